@@ -0,0 +1,78 @@
+//! Compares opening a database that has attachments with opening the same database without them.
+//! keepass does not tell which entry an attachment belongs to, so the XML of a database with
+//! attachments is decrypted once more, which derives its key a second time. Run with
+//! `cargo bench`.
+
+extern crate keepass;
+extern crate keepass_diff;
+
+use keepass::db::{Entry, HeaderAttachment, Node, Value};
+use keepass::{Database, DatabaseKey};
+use keepass_diff::diff::DiffOptions;
+use keepass_diff::{open_database, KeyfileFormat};
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const PASSWORD: &str = "demopass";
+const ENTRIES: usize = 100;
+const RUNS: u32 = 5;
+
+/// Writes a database with a few entries, and an attachment if `with_attachment` is set
+fn write_database(path: &Path, with_attachment: bool) {
+    let mut db = Database::new(Default::default());
+    for index in 0..ENTRIES {
+        let mut entry = Entry::new();
+        entry.fields.insert(
+            "Title".to_owned(),
+            Value::Unprotected(format!("Entry {}", index)),
+        );
+        entry.fields.insert(
+            "Password".to_owned(),
+            Value::Protected("secret".as_bytes().into()),
+        );
+        db.root.children.push(Node::Entry(entry));
+    }
+    if with_attachment {
+        db.header_attachments.push(HeaderAttachment {
+            flags: 1,
+            content: b"attached".to_vec(),
+        });
+    }
+    let key = DatabaseKey::new().with_password(PASSWORD);
+    db.save(&mut std::fs::File::create(path).unwrap(), key)
+        .unwrap();
+}
+
+/// The average time it takes to open the database
+fn measure(path: &Path) -> Duration {
+    let file = path.to_str().unwrap();
+    let options = DiffOptions::default();
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        open_database(file, Some(PASSWORD), None, KeyfileFormat::Auto, &options).unwrap();
+    }
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    let dir: PathBuf = std::env::temp_dir().join(format!(
+        "keepass-diff-bench-attachments-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (without, with) = (dir.join("without.kdbx"), dir.join("with.kdbx"));
+    write_database(&without, false);
+    write_database(&with, true);
+
+    let without_attachments = measure(&without);
+    let with_attachments = measure(&with);
+    println!(
+        "{} entries: {:?} without attachments, {:?} with an attachment ({:.2}x)",
+        ENTRIES,
+        without_attachments,
+        with_attachments,
+        with_attachments.as_secs_f64() / without_attachments.as_secs_f64()
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,89 @@
+//! Compares opening two databases one after another with opening them at the same time, like
+//! `--jobs 1` and the default do. Run with `cargo bench`.
+
+extern crate keepass;
+extern crate keepass_diff;
+
+use keepass::db::{Entry, Group, Node, Value};
+use keepass::{Database, DatabaseKey};
+use keepass_diff::diff::{Diff, DiffOptions};
+use keepass_diff::{open_database, KeyfileFormat};
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const PASSWORD: &str = "demopass";
+const GROUPS: usize = 50;
+const ENTRIES_PER_GROUP: usize = 60;
+const RUNS: u32 = 5;
+
+/// Writes a database with a few thousand entries, `changed` of them with another password
+fn write_database(path: &Path, changed: usize) {
+    let mut db = Database::new(Default::default());
+    for g in 0..GROUPS {
+        let mut group = Group::new(&format!("Group {}", g));
+        for e in 0..ENTRIES_PER_GROUP {
+            let mut entry = Entry::new();
+            let index = g * ENTRIES_PER_GROUP + e;
+            let password = if index < changed { "changed" } else { "secret" };
+            entry.fields.insert(
+                "Title".to_owned(),
+                Value::Unprotected(format!("Entry {}", index)),
+            );
+            entry.fields.insert(
+                "Password".to_owned(),
+                Value::Protected(password.as_bytes().into()),
+            );
+            group.children.push(Node::Entry(entry));
+        }
+        db.root.children.push(Node::Group(group));
+    }
+    let key = DatabaseKey::new().with_password(PASSWORD);
+    db.save(&mut std::fs::File::create(path).unwrap(), key)
+        .unwrap();
+}
+
+fn open(path: &Path) -> keepass_diff::diff::group::Group {
+    let file = path.to_str().unwrap();
+    let options = DiffOptions::default();
+    open_database(file, Some(PASSWORD), None, KeyfileFormat::Auto, &options)
+        .unwrap()
+        .1
+}
+
+/// The average time it takes to open and diff both databases
+fn measure(a: &Path, b: &Path, concurrently: bool) -> Duration {
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        let (db_a, db_b) = if concurrently {
+            std::thread::scope(|scope| {
+                let db_b = scope.spawn(|| open(b));
+                (open(a), db_b.join().unwrap())
+            })
+        } else {
+            (open(a), open(b))
+        };
+        assert!(db_a.diff(&db_b).has_changes());
+    }
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    let dir: PathBuf =
+        std::env::temp_dir().join(format!("keepass-diff-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (a, b) = (dir.join("a.kdbx"), dir.join("b.kdbx"));
+    write_database(&a, 0);
+    write_database(&b, 100);
+
+    let one_after_another = measure(&a, &b, false);
+    let at_the_same_time = measure(&a, &b, true);
+    println!(
+        "{} entries: {:?} one after another, {:?} at the same time ({:.2}x)",
+        GROUPS * ENTRIES_PER_GROUP,
+        one_after_another,
+        at_the_same_time,
+        one_after_another.as_secs_f64() / at_the_same_time.as_secs_f64()
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
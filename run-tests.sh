@@ -30,15 +30,31 @@
     fi
   }
 
+  # keepass-diff exits with 1 if the databases differ, which most tests expect
+  keepass_diff() {
+    cargo run --release -- "$@" || [ $? -eq 1 ]
+  }
+
+  exit_code_of() {
+    set +e
+    cargo run --release -- "$@" >/dev/null 2>&1
+    echo $?
+    set -e
+  }
+
+  stderr_of() {
+    cargo run --quiet --release -- "$@" 2>&1 >/dev/null || true
+  }
+
   echo "### Preparing tests"
   echo "# Creating temporary directory for test results"
   mkdir -p "$PWD/tmp-tests"
 
   echo "### Running verbose equality tests, depending on order"
   echo "# Run a <diff> b"
-  cargo run --release -- "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --no-color --verbose >"$PWD/tmp-tests/test-result-01.txt"
+  keepass_diff "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --no-color --verbose >"$PWD/tmp-tests/test-result-01.txt"
   echo "# Run b <diff> a"
-  cargo run --release -- "$PWD/test/test2.kdbx" "$PWD/test/test.kdbx" --passwords demopass --no-color --verbose >"$PWD/tmp-tests/test-result-02.txt"
+  keepass_diff "$PWD/test/test2.kdbx" "$PWD/test/test.kdbx" --passwords demopass --no-color --verbose >"$PWD/tmp-tests/test-result-02.txt"
 
   lines_of_run_01=$(wc -l <"$PWD/tmp-tests/test-result-01.txt")
   lines_of_run_02=$(wc -l <"$PWD/tmp-tests/test-result-02.txt")
@@ -65,9 +81,9 @@
 
   echo "### Running regular equality tests, depending on order"
   echo "# Run a <diff> b"
-  cargo run --release -- "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --no-color >"$PWD/tmp-tests/test-result-03.txt"
+  keepass_diff "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --no-color >"$PWD/tmp-tests/test-result-03.txt"
   echo "# Run b <diff> a"
-  cargo run --release -- "$PWD/test/test2.kdbx" "$PWD/test/test.kdbx" --passwords demopass --no-color >"$PWD/tmp-tests/test-result-04.txt"
+  keepass_diff "$PWD/test/test2.kdbx" "$PWD/test/test.kdbx" --passwords demopass --no-color >"$PWD/tmp-tests/test-result-04.txt"
 
   lines_of_run_03=$(wc -l <"$PWD/tmp-tests/test-result-03.txt")
   lines_of_run_04=$(wc -l <"$PWD/tmp-tests/test-result-04.txt")
@@ -94,9 +110,9 @@
 
   echo "### Running test to open KDBX 3.1 files"
   echo "# Run a <diff> b"
-  cargo run --release -- "$PWD/test/issue-24-kdbx-3.1/Test1.kdbx" "$PWD/test/issue-24-kdbx-3.1/Test2.kdbx" --password-a Test1 --password-b Test2 --no-color >"$PWD/tmp-tests/test-result-05.txt"
+  keepass_diff "$PWD/test/issue-24-kdbx-3.1/Test1.kdbx" "$PWD/test/issue-24-kdbx-3.1/Test2.kdbx" --password-a Test1 --password-b Test2 --no-color >"$PWD/tmp-tests/test-result-05.txt"
   echo "# Run b <diff> a"
-  cargo run --release -- "$PWD/test/issue-24-kdbx-3.1/Test2.kdbx" "$PWD/test/issue-24-kdbx-3.1/Test1.kdbx" --password-a Test2 --password-b Test1 --no-color >"$PWD/tmp-tests/test-result-06.txt"
+  keepass_diff "$PWD/test/issue-24-kdbx-3.1/Test2.kdbx" "$PWD/test/issue-24-kdbx-3.1/Test1.kdbx" --password-a Test2 --password-b Test1 --no-color >"$PWD/tmp-tests/test-result-06.txt"
 
   lines_of_run_05=$(wc -l <"$PWD/tmp-tests/test-result-05.txt")
   lines_of_run_06=$(wc -l <"$PWD/tmp-tests/test-result-06.txt")
@@ -121,6 +137,198 @@
   test_equal "first run should have same amount of plus lines as second run has minus lines" "$amount_of_plus_05" "$amount_of_minus_06"
   test_equal "first run should have same amount of minus lines as second run has plus lines" "$amount_of_minus_05" "$amount_of_plus_06"
 
+  echo "# Run a <diff> b reversed"
+  keepass_diff "$PWD/test/issue-24-kdbx-3.1/Test1.kdbx" "$PWD/test/issue-24-kdbx-3.1/Test2.kdbx" --password-a Test1 --password-b Test2 --no-color --reverse >"$PWD/tmp-tests/test-result-07.txt"
+  test_equal "reversed run should equal the second run" "$(cat "$PWD/tmp-tests/test-result-07.txt")" "$(cat "$PWD/tmp-tests/test-result-06.txt")"
+
+  echo "### Running exit code tests"
+  test_equal "should exit with 0 for identical databases" "$(exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test.kdbx" --passwords demopass)" 0
+  test_equal "should exit with 1 for different databases" "$(exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass)" 1
+  test_equal "should exit with 2 for a wrong password" "$(exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords wrongpass)" 2
+  test_equal "should exit with 2 for a missing file" "$(exit_code_of "$PWD/test/test.kdbx" "$PWD/test/does-not-exist.kdbx" --passwords demopass)" 2
+  test_equal "should only count the shown changes in the summary" "$(keepass_diff "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --no-color --summary-only --only-removals)" "0 groups added, 0 removed, 0 changed, 0 unchanged; 0 entries added, 1 removed, 0 changed, 0 unchanged"
+
+  echo "### Running output file tests"
+  keepass_diff "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --output "$PWD/tmp-tests/test-result-07.txt"
+  keepass_diff "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --no-color >"$PWD/tmp-tests/test-result-08.txt"
+  test_equal "should write the differences to a file without colors" "$(cat "$PWD/tmp-tests/test-result-07.txt")" "$(cat "$PWD/tmp-tests/test-result-08.txt")"
+  test_equal "should not color output to a pipe by default" "$(keepass_diff "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass | cat)" "$(cat "$PWD/tmp-tests/test-result-08.txt")"
+
+  echo "### Running symbolic link tests"
+  links="$(mktemp -d)"
+  ln -s "$PWD/test/test.kdbx" "$links/latest.kdbx"
+  ln -s "$links/missing.kdbx" "$links/broken.kdbx"
+  test_equal "should follow symbolic links to databases" "$(stderr_of "$links/latest.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --verbose --check | head -n 1)" "Database A: $links/latest.kdbx links to $PWD/test/test.kdbx"
+  test_equal "should report broken symbolic links" "$(stderr_of "$links/broken.kdbx" "$PWD/test/test2.kdbx" --passwords demopass)" "Error opening database A: File not found (broken symlink to $links/missing.kdbx)"
+  rm -rf "$links"
+
+  echo "### Running field rule tests"
+  test_equal "should reject unknown field rules" "$(stderr_of "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --field-rule URL=upper | head -n 1)" "error: invalid value 'URL=upper' for '--field-rule <FIELD=RULES>': unknown rule upper (expected trim, lowercase, collapse-whitespace, strip-trailing-slash)"
+
+  echo "### Running missing database tests"
+  test_equal "should compare a missing database as an empty one" "$(keepass_diff "$PWD/test/test.kdbx" "$PWD/test/missing.kdbx" --password-a demopass --treat-missing-as-empty --no-color --summary-only)" "0 groups added, 4 removed, 1 changed, 0 unchanged; 0 entries added, 5 removed, 0 changed, 0 unchanged"
+  test_equal "should still fail for a wrong password with a missing database" "$(stderr_of "$PWD/test/missing.kdbx" "$PWD/test/test.kdbx" --password-b wrongpass --treat-missing-as-empty)" "Error opening database B: Incorrect password"
+  test_equal "should fail if both databases are missing" "$(exit_code_of "$PWD/test/missing.kdbx" "$PWD/test/missing.kdbx" --no-passwords --treat-missing-as-empty)" 2
+
+  echo "### Running quiet mode tests"
+  test_equal "should print nothing in quiet mode" "$(keepass_diff "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --quiet)" ""
+  test_equal "should exit with 1 in quiet mode if the databases differ" "$(exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass -q)" 1
+  test_equal "should exit with 0 in quiet mode if the databases are identical" "$(exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test.kdbx" --passwords demopass -q)" 0
+  test_equal "should still report errors in quiet mode" "$(stderr_of "$PWD/test/test.kdbx" "$PWD/test/missing.kdbx" --passwords demopass -q)" "Error opening database B: Cannot read the database file: No such file or directory (os error 2)"
+  test_equal "should only count changed passwords in quiet mode with --changed-passwords-only" "$(exit_code_of "$PWD/test/__fixtures__/custom-fields.kdbx" "$PWD/test/__fixtures__/custom-fields-2.kdbx" --passwords demopass --changed-passwords-only -q)" 0
+
+  echo "### Running count tests"
+  test_equal "should print the number of changes" "$(keepass_diff "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --count)" 5
+  test_equal "should print 0 for identical databases" "$(keepass_diff "$PWD/test/test.kdbx" "$PWD/test/test.kdbx" --passwords demopass --count)" 0
+  test_equal "should exit with 1 when counting differences" "$(exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --count)" 1
+
+  echo "### Running manifest tests"
+  test_equal "should list the entries of a single database" "$(keepass_diff "$PWD/test/test.kdbx" --passwords demopass --manifest | grep -c '"kind": "entry"')" 5
+  test_equal "should list both databases" "$(keepass_diff "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --manifest | grep -c '"database"')" 2
+  test_equal "should not list any value" "$(keepass_diff "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --manifest | grep -c 'plank')" 0
+  test_equal "should exit with 0 after listing databases" "$(exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --manifest)" 0
+
+  echo "### Running git textconv tests"
+  test_equal "should list a single file for git diff" "$(KEEPASS_DIFF_PASSWORD=demopass keepass_diff --git-textconv "$PWD/test/test.kdbx" | grep Walked)" "[Root, Some group, Sub-Group 2 of group, Walked]
+[Root, Some group, Sub-Group 2 of group, Walked, Password = line]
+[Root, Some group, Sub-Group 2 of group, Walked, Title = Walked]
+[Root, Some group, Sub-Group 2 of group, Walked, UserName = the]"
+  test_equal "should not ask for a password when listing a file for git diff" "$(exit_code_of --git-textconv "$PWD/test/test.kdbx" </dev/null)" 2
+
+  echo "### Running group filter tests"
+  test_equal "should only compare included groups" "$(keepass_diff "$PWD/test/__fixtures__/moved-entries.kdbx" "$PWD/test/__fixtures__/moved-entries-2.kdbx" --passwords demopass --no-color --include-group 'Work/*' | head -n 1)" "+ [Root, Work, Email, Mailbox]"
+  test_equal "should leave out excluded groups inside included groups" "$(exit_code_of "$PWD/test/__fixtures__/moved-entries.kdbx" "$PWD/test/__fixtures__/moved-entries-2.kdbx" --passwords demopass --include-group 'Work/*' --exclude-group '**/Email')" 0
+  test_equal "should only compare the entry at the given path" "$(keepass_diff "$PWD/test/__fixtures__/moved-entries.kdbx" "$PWD/test/__fixtures__/moved-entries-2.kdbx" --passwords demopass --no-color --only 'Work/Email/Mailbox')" "+ [Root, Work, Email, Mailbox]
+
+0 groups added, 0 removed, 3 changed, 0 unchanged; 1 entry added, 0 removed, 0 changed, 0 unchanged"
+  test_equal "should warn about paths that match nothing" "$(stderr_of "$PWD/test/__fixtures__/moved-entries.kdbx" "$PWD/test/__fixtures__/moved-entries-2.kdbx" --passwords demopass --only 'Work/Nope')" "Warning: --only Work/Nope matches no group or entry in either database"
+
+  echo "### Running encryption banner tests"
+  test_equal "should show the encryption of both databases in verbose mode" "$(stderr_of "$PWD/test/test.kdbx" "$PWD/test/__fixtures__/tagged.kdbx" --passwords demopass --verbose --check)" "Database A: KDBX 3.1, AES-KDF (100000 rounds), AES-256
+Database B: KDBX 4.0, Argon2d (2 iterations, 64 KiB, 1 thread), AES-256
+Warning: the databases use a different key derivation function
+Warning: database A is KDBX 3.1 and database B is KDBX 4.0, so some differences may come from the formats rather than the content"
+  test_equal "should warn about databases of different KDBX versions" "$(stderr_of "$PWD/test/test.kdbx" "$PWD/test/__fixtures__/tagged.kdbx" --passwords demopass)" "Warning: database A is KDBX 3.1 and database B is KDBX 4.0, so some differences may come from the formats rather than the content"
+  test_equal "should not warn about different KDBX versions when quiet" "$(stderr_of "$PWD/test/test.kdbx" "$PWD/test/__fixtures__/tagged.kdbx" --passwords demopass --quiet)" ""
+  test_equal "should not show the encryption without verbose mode" "$(stderr_of "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass)" ""
+
+  echo "### Running merge tests"
+  merged="$(mktemp -d)/merged.kdbx"
+  test_equal "should report entries changed in both files as conflicts" "$(stderr_of "$PWD/test/__fixtures__/three-way-a.kdbx" "$PWD/test/__fixtures__/three-way-b.kdbx" --passwords demopass --merge-into "$merged" --merge-password mergepass)" "Conflict: [Root, Work, VPN] was changed in both files, kept the version of A"
+  rm -f "$merged"
+  test_equal "should take the additions of the second file" "$(exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --merge-into "$merged" --merge-password mergepass)" 1
+  test_equal "should keep the removals of the second file" "$(keepass_diff "$merged" "$PWD/test/test2.kdbx" --password-a mergepass --password-b demopass --no-color | head -n 1)" "- [Root, Some group, In a group]"
+  rm -f "$merged"
+  test_equal "should still merge with --quiet" "$(exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --merge-into "$merged" --merge-password mergepass --quiet; ls "$merged")" "1
+$merged"
+  rm -f "$merged"
+  test_equal "should not merge databases with attachments" "$(exit_code_of "$PWD/test/__fixtures__/large-withfile.kdbx" "$PWD/test/__fixtures__/large-withfile2.kdbx" --passwords samplepassword --merge-into "$merged" --merge-password mergepass)" 2
+
+  echo "### Running duplicate entry tests"
+  test_equal "should warn about entries paired up by the same title" "$(stderr_of "$PWD/test/__fixtures__/multiple-entries.kdbx" "$PWD/test/__fixtures__/empty.kdbx" --passwords demopass --warn-duplicates)" "Warning: database A has 2 entries [Root, duplicated] with the same title, which are paired up in order"
+  test_equal "should not warn about entries with different UUIDs" "$(stderr_of "$PWD/test/__fixtures__/multiple-entries.kdbx" "$PWD/test/__fixtures__/empty.kdbx" --passwords demopass --warn-duplicates --match-by uuid)" ""
+
+  echo "### Running config file tests"
+  printf 'ignore-field = ["Password"]\nmask-passwords = true\n' >"$PWD/tmp-tests/config.toml"
+  test_equal "should leave out the fields ignored in the config file" "$(keepass_diff "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --no-color --config "$PWD/tmp-tests/config.toml" --ignore-field that | grep -c '= ')" 0
+  test_equal "should reject ignored fields of the config file with --fields" "$(stderr_of "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --config "$PWD/tmp-tests/config.toml" --fields URL)" "Error reading the config file $PWD/tmp-tests/config.toml: ignore-field cannot be combined with --fields given on the command line"
+  printf 'match-by = "url"\n' >"$PWD/tmp-tests/config.toml"
+  test_equal "should fail for an invalid config file" "$(stderr_of "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --config "$PWD/tmp-tests/config.toml")" "Error reading the config file $PWD/tmp-tests/config.toml: Line 1: match-by must be one of title, uuid, title+username, title+url"
+  rm -f "$PWD/tmp-tests/config.toml"
+
+  echo "### Running path separator tests"
+  test_equal "should end the records of parseable output with NUL" "$(keepass_diff "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --mask-passwords --format parseable | tr '\0\037' '\n|' | sed -n 2p)" "changed|Root/Some group/Sub-Group 2 of group/Walked|Password|***|***"
+  test_equal "should join the groups of moved entries with the separator" "$(keepass_diff "$PWD/test/__fixtures__/moved-entries.kdbx" "$PWD/test/__fixtures__/moved-entries-2.kdbx" --passwords demopass --detect-moves --format csv --path-separator '|' | sed -n 2p)" "Root|Work|Mailbox,moved,,Root|Work,Root|Work|Email"
+  test_equal "should reject a separator with a backslash" "$(exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --path-separator '\\')" 2
+
+  echo "### Running three-way comparison tests"
+  three_way=("$PWD/test/__fixtures__/three-way-a.kdbx" "$PWD/test/__fixtures__/three-way-b.kdbx" --passwords demopass --no-color --base "$PWD/test/__fixtures__/three-way-base.kdbx")
+  test_equal "should count the changes of each side and the conflicts" "$(keepass_diff "${three_way[@]}" | tail -n 1)" "0 changes only in A, 2 only in B, 1 in both; 3 conflicts"
+  test_equal "should list both sides of conflicts" "$(keepass_diff "${three_way[@]}" | grep -c '^!')" 4
+  test_equal "should not merge conflicting masked passwords" "$(keepass_diff "${three_way[@]}" --mask-passwords | grep -c '^!B ~ \[Root, Work, VPN')" 1
+  test_equal "should exit with 1 if either side changed" "$(exit_code_of "${three_way[@]}")" 1
+  test_equal "should exit with 0 if neither side changed" "$(exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test.kdbx" --passwords demopass --base "$PWD/test/test.kdbx")" 0
+
+  echo "### Running credential tests"
+  test_equal "should read passwords from environment variables" "$(KPDIFF_TEST_PW=demopass exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test.kdbx" --password-a-env KPDIFF_TEST_PW --password-b-env KPDIFF_TEST_PW)" 0
+  test_equal "should fail for an unset password environment variable" "$(exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test.kdbx" --password-a-env KPDIFF_UNSET_PW --passwords demopass)" 2
+  test_equal "should read passwords from standard input" "$(printf 'demopass\ndemopass\n' | exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test.kdbx" --password-a-stdin --password-b-stdin)" 0
+  test_equal "should read a shared password from standard input" "$(echo demopass | exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test.kdbx" --passwords -)" 0
+  test_equal "should fail if standard input has no password" "$(exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test.kdbx" --password-a - --password-b demopass </dev/null)" 2
+  printf 'demopass\n' >"$PWD/tmp-tests/password.txt"
+  chmod 600 "$PWD/tmp-tests/password.txt"
+  printf '\377\376d\000e\000m\000o\000p\000a\000s\000s\000\r\000\n\000' >"$PWD/tmp-tests/utf16-password.txt"
+  chmod 600 "$PWD/tmp-tests/utf16-password.txt"
+  printf 'demopass\r\n' >"$PWD/tmp-tests/readable-password.txt"
+  chmod 644 "$PWD/tmp-tests/readable-password.txt"
+  test_equal "should read passwords from files" "$(exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test.kdbx" --password-a-file "$PWD/tmp-tests/password.txt" --password-b demopass)" 0
+  test_equal "should read a shared password from a file" "$(exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test.kdbx" --passwords-file "$PWD/tmp-tests/password.txt")" 0
+  test_equal "should read password files saved as UTF-16" "$(exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test.kdbx" --passwords-file "$PWD/tmp-tests/utf16-password.txt")" 0
+  test_equal "should reject password files that others can read" "$(exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test.kdbx" --passwords-file "$PWD/tmp-tests/readable-password.txt")" 2
+  test_equal "should read password files that others can read when asked to" "$(exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test.kdbx" --passwords-file "$PWD/tmp-tests/readable-password.txt" --insecure-password-file)" 0
+  test_equal "should open the files one after another with --jobs 1" "$(exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --jobs 1)" 1
+  test_equal "should tell how long each phase took with --timing" "$(stderr_of "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --timing | sed 's/ took .*//')" "$(printf 'Timing: opening A\nTiming: opening B\nTiming: comparing\nTiming: writing the differences')"
+  test_equal "should report the error of the first file when opening both at the same time" "$(stderr_of "$PWD/test/missing.kdbx" "$PWD/test/corrupt.kdbx" --passwords demopass --jobs 2)" "Error opening database A: Cannot read the database file: No such file or directory (os error 2)"
+  test_equal "should decode hex key files" "$(exit_code_of "$PWD/test/hex-keyfile.kdbx" "$PWD/test/hex-keyfile.kdbx" --passwords demopass --keyfiles "$PWD/test/hex-keyfile.key")" 0
+  test_equal "should report an incorrect password" "$(stderr_of "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --password-a wrongpass --password-b demopass)" "Error opening database A: Incorrect password"
+  test_equal "should report an incorrect password or key file" "$(stderr_of "$PWD/test/test.kdbx" "$PWD/test/hex-keyfile.kdbx" --passwords demopass --keyfile-b "$PWD/test/keyfile.key")" "Error opening database B: Incorrect password or key file"
+  test_equal "should report a missing key file" "$(stderr_of "$PWD/test/hex-keyfile.kdbx" "$PWD/test/hex-keyfile.kdbx" --passwords demopass --keyfiles "$PWD/test/missing.key")" "Error opening database A: Cannot read the key file: No such file or directory (os error 2)"
+  test_equal "should open databases locked by a key file only" "$(exit_code_of "$PWD/test/keyfile-only.kdbx" "$PWD/test/keyfile-only.kdbx" --no-passwords --keyfiles "$PWD/test/keyfile.key")" 0
+  test_equal "should read key files given as base64" "$(exit_code_of "$PWD/test/test2_file.kdbx" "$PWD/test/test2_file.kdbx" --passwords demopass --keyfiles-base64 "$(base64 "$PWD/test/keyfile.key")")" 0
+  test_equal "should report invalid base64 of --keyfiles-base64 as an error of the flag" "$(stderr_of "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --keyfiles-base64 'bad!')" "Error in --keyfiles-base64: Cannot read the key file: invalid base64: Invalid byte 33, offset 3."
+  test_equal "should report key files that are no base64" "$(stderr_of "$PWD/test/test2_file.kdbx" "$PWD/test/test2_file.kdbx" --passwords demopass --keyfile-b-base64 '!!')" "Error opening database B: Cannot read the key file: invalid base64: Invalid byte 33, offset 0."
+  test_equal "should report a corrupt database" "$(stderr_of "$PWD/test/corrupt.kdbx" "$PWD/test/test.kdbx" --passwords demopass)" "Error opening database A: The database is corrupt: Block hash mismatch for block 0"
+  test_equal "should exit with 2 for a corrupt database" "$(exit_code_of "$PWD/test/corrupt.kdbx" "$PWD/test/test.kdbx" --passwords demopass)" 2
+  test_equal "should name the entry with a malformed value" "$(stderr_of "$PWD/test/corrupt-entry-export.xml" "$PWD/test/test2-export.xml")" "Error opening database A: The database is corrupt at the LastModificationTime of entry [Root, One more]: Invalid byte 45, offset 4."
+  test_equal "should report a missing database file" "$(stderr_of "$PWD/test/test.kdbx" "$PWD/test/missing.kdbx" --passwords demopass)" "Error opening database B: Cannot read the database file: No such file or directory (os error 2)"
+  test_equal "should draw the tree with box-drawing characters in UTF-8 locales" "$(LC_ALL=C.UTF-8 keepass_diff "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --no-color --tree | sed -n 2p)" "├─ ~ Another group"
+  test_equal "should draw the tree with ASCII characters in other locales" "$(LC_ALL=C keepass_diff "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --no-color --tree | sed -n 2p)" "|- ~ Another group"
+  test_equal "should list unchanged groups and entries with --show-unchanged" "$(keepass_diff "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --no-color --show-unchanged | sed -n 3p)" "  [Root, Another group, Here]"
+  test_equal "should compare entries regardless of their groups with --flatten" "$(keepass_diff "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --no-color --flatten | head -n 2)" "$(printf -- '- [Root, In a group (in Some group)]\n+ [Root, In another group (in Another group)]')"
+  test_equal "should mark moved entries with ASCII characters in other locales" "$(LC_ALL=C keepass_diff "$PWD/test/__fixtures__/moved-entries.kdbx" "$PWD/test/__fixtures__/moved-entries-2.kdbx" --passwords demopass --no-color --detect-moves | tail -n 3 | head -n 1)" "-> Wiki moved from [Root] to [Root, Work]"
+  test_equal "should read a database from standard input" "$(keepass_diff - "$PWD/test/test2.kdbx" --passwords demopass --no-color < "$PWD/test/test.kdbx" | head -n 1)" "+ [Root, Another group, In another group]"
+  test_equal "should not read both databases from standard input" "$(exit_code_of - - --passwords demopass)" 2
+  test_equal "should not watch a database read from standard input" "$(exit_code_of - "$PWD/test/test2.kdbx" --passwords demopass --watch < "$PWD/test/test.kdbx")" 2
+  test_equal "should exit with 1 for differences left out by --limit" "$(exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --limit 0)" 1
+  test_equal "should only open the databases with --check" "$(keepass_diff "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --passwords demopass --check; echo $?)" 0
+  test_equal "should report an incorrect password with --check" "$(stderr_of "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --password-a demopass --password-b wrongpass --check)" "Error opening database B: Incorrect password"
+  test_equal "should not ask for passwords with --no-prompt" "$(stderr_of "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --password-a demopass --no-prompt)" "No password provided for file B and prompting is disabled"
+  test_equal "should exit with 2 without a password and prompt" "$(exit_code_of "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --no-prompt)" 2
+  test_equal "should read hex key files as binary when asked to" "$(exit_code_of "$PWD/test/hex-keyfile.kdbx" "$PWD/test/hex-keyfile.kdbx" --passwords demopass --keyfiles "$PWD/test/hex-keyfile.key" --keyfile-a-format binary)" 2
+
+  test_equal "should report errors as JSON" "$(stderr_of "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" --password-a demopass --password-b wrongpass --json-errors)" '{"error":"auth","database":"B","file":"'"$PWD"'/test/test2.kdbx","message":"Error opening database B: Incorrect password"}'
+  test_equal "should report errors as JSON for JSON output" "$(stderr_of "$PWD/test/test.kdbx" "$PWD/test/missing.kdbx" --passwords demopass --json)" '{"error":"io","database":"B","file":"'"$PWD"'/test/missing.kdbx","message":"Error opening database B: Cannot read the database file: No such file or directory (os error 2)"}'
+
+  mkdir -p "$PWD/tmp-tests/backup-a" "$PWD/tmp-tests/backup-b"
+  cp "$PWD/test/test2_file.kdbx" "$PWD/test/keyfile.key" "$PWD/tmp-tests/backup-a/"
+  cp "$PWD/test/test2.kdbx" "$PWD/tmp-tests/backup-b/"
+  test_equal "should open the database and key file in a directory" "$(exit_code_of "$PWD/tmp-tests/backup-a" "$PWD/test/test2_file.kdbx" --passwords demopass --keyfile-b "$PWD/test/keyfile.key")" 0
+  test_equal "should open the database without a key file in a directory" "$(exit_code_of "$PWD/tmp-tests/backup-b" "$PWD/test/test2.kdbx" --passwords demopass)" 0
+  mkdir -p "$PWD/tmp-tests/backup-two"
+  cp "$PWD/test/test.kdbx" "$PWD/test/test2.kdbx" "$PWD/tmp-tests/backup-two/"
+  test_equal "should report directories with several databases" "$(stderr_of "$PWD/tmp-tests/backup-two" "$PWD/test/test.kdbx" --passwords demopass)" "Error opening database A: The directory contains more than one database or key file: test.kdbx, test2.kdbx"
+
+  echo "### Running KeePass 1.x tests"
+  test_equal "should read KeePass 1.x databases like their migrated versions" "$(exit_code_of "$PWD/test/keepass1.kdb" "$PWD/test/keepass1-migrated.kdbx" --passwords demopass)" 0
+  test_equal "should report a wrong key for KeePass 1.x databases" "$(stderr_of "$PWD/test/keepass1.kdb" "$PWD/test/keepass1-migrated.kdbx" --passwords wrongpass)" "Error opening database A: Incorrect password"
+  test_equal "should report files that are not databases" "$(stderr_of "$PWD/README.md" "$PWD/test/test.kdbx" --passwords demopass)" "Error opening database A: Not a KeePass database (expected a KeePass 2.x .kdbx or KeePass 1.x .kdb file)"
+
+  echo "### Running XML export tests"
+  test_equal "should read XML exports like the database they were exported from" "$(exit_code_of "$PWD/test/__fixtures__/test2.kdbx" "$PWD/test/test2-export.xml" --password-a demopass)" 0
+  test_equal "should compare XML exports like databases" "$(keepass_diff "$PWD/test/__fixtures__/test.kdbx" "$PWD/test/test2-export.xml" --password-a demopass --no-color --verbose)" "$(keepass_diff "$PWD/test/__fixtures__/test.kdbx" "$PWD/test/__fixtures__/test2.kdbx" --passwords demopass --no-color --verbose)"
+  test_equal "should not ask for the password of XML exports" "$(exit_code_of "$PWD/test/test2-export.xml" "$PWD/test/test2-export.xml" </dev/null)" 0
+  sed 's#<Name>Root</Name>#<Name>Passwords</Name>#' "$PWD/test/test2-export.xml" >"$PWD/tmp-tests/renamed-root-export.xml"
+  test_equal "should compare roots with other names by their contents" "$(exit_code_of "$PWD/test/test2-export.xml" "$PWD/tmp-tests/renamed-root-export.xml")" 0
+  test_equal "should note roots with other names" "$(stderr_of "$PWD/test/test2-export.xml" "$PWD/tmp-tests/renamed-root-export.xml")" "Note: the root group is named Root in database A and Passwords in database B, so their contents are compared with each other"
+
+  echo "### Running root name tests"
+  test_equal "should note roots of databases with other names" "$(stderr_of "$PWD/test/__fixtures__/renamed-root.kdbx" "$PWD/test/__fixtures__/renamed-root-2.kdbx" --passwords demopass)" "Note: the root group is named Root in database A and Passwords in database B, so their contents are compared with each other"
+  test_equal "should pair the contents of roots with other names" "$(keepass_diff "$PWD/test/__fixtures__/renamed-root.kdbx" "$PWD/test/__fixtures__/renamed-root-2.kdbx" --passwords demopass --no-color 2>/dev/null)" "- [Root, Email, Mailbox, Password = secret]
++ [Root, Email, Mailbox, Password = changed]
++ [Root, VPN]
+
+0 groups added, 0 removed, 2 changed, 0 unchanged; 1 entry added, 0 removed, 1 changed, 1 unchanged"
+
   echo "### Testing snapshots against fixtures"
   for dir in test/test-*; do
     IFS='_' read -r -a files <<<"$(basename "$dir" | cut -c6-)"
@@ -132,7 +340,8 @@
 
       mkdir -p "res/$dir"
       test_result_name="$PWD/tmp-tests/snapshot-result-$(basename "$dir").txt"
-      cargo run --release -- "test/__fixtures__/${file_a}.kdbx" "test/__fixtures__/${file_b}.kdbx" "${args[@]}" >"$test_result_name"
+      # Snapshots are taken in a UTF-8 locale, other locales are written with ASCII characters
+      LC_ALL=C.UTF-8 keepass_diff "test/__fixtures__/${file_a}.kdbx" "test/__fixtures__/${file_b}.kdbx" "${args[@]}" >"$test_result_name"
       echo "# Run $snapshot"
       if diff "$test_result_name" "$snapshot"; then
         echo "✅ $snapshot"
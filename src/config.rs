@@ -0,0 +1,258 @@
+//! Settings read from a config file, so the same comparison can be repeated without giving all
+//! of its options again
+
+use clap::ValueEnum;
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::diff::MatchBy;
+use crate::error::ConfigError;
+
+/// The config file that is read from the current directory if no other file is given
+pub const DEFAULT_FILE: &str = ".keepass-diff.toml";
+
+/// The settings of a config file. Keys are named like the options they stand for:
+///
+/// ```toml
+/// ignore-field = ["Notes", "URL"]
+/// exclude-group = ["Archive/*"]
+/// match-by = "uuid"
+/// mask-passwords = true
+/// mask-fields = ["UserName"]
+/// ```
+///
+/// The file is read as TOML, of which only keys with strings, booleans and arrays of strings are
+/// supported, as there are no other settings.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Config {
+    pub ignore_fields: Vec<String>,
+    pub exclude_groups: Vec<String>,
+    pub match_by: Option<MatchBy>,
+    pub mask_passwords: Option<bool>,
+    pub mask_fields: Vec<String>,
+}
+
+impl Config {
+    pub fn read(path: &str) -> Result<Config, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(ConfigError::File)?;
+        Config::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        let mut parser = Parser {
+            chars: text.chars().peekable(),
+            line: 1,
+        };
+        while let Some((line, key, value)) = parser.pair()? {
+            let invalid = |message: String| ConfigError::Invalid { line, message };
+            let expected = |kind: &str| invalid(format!("{} must be {}", key, kind));
+            match (key.as_str(), value) {
+                ("ignore-field", Value::Array(names)) => config.ignore_fields = names,
+                ("exclude-group", Value::Array(globs)) => config.exclude_groups = globs,
+                ("mask-fields", Value::Array(names)) => config.mask_fields = names,
+                ("ignore-field" | "exclude-group" | "mask-fields", _) => {
+                    return Err(expected("an array of strings"))
+                }
+                ("match-by", Value::String(name)) => {
+                    let match_by = MatchBy::from_str(&name, false).map_err(|_| {
+                        let names: Vec<_> = MatchBy::value_variants()
+                            .iter()
+                            .filter_map(|v| v.to_possible_value())
+                            .map(|v| v.get_name().to_owned())
+                            .collect();
+                        expected(&format!("one of {}", names.join(", ")))
+                    })?;
+                    config.match_by = Some(match_by);
+                }
+                ("match-by", _) => return Err(expected("a string")),
+                ("mask-passwords", Value::Bool(mask)) => config.mask_passwords = Some(mask),
+                ("mask-passwords", _) => return Err(expected("true or false")),
+                (key, _) => return Err(invalid(format!("unknown setting {}", key))),
+            }
+        }
+        Ok(config)
+    }
+}
+
+enum Value {
+    String(String),
+    Bool(bool),
+    Array(Vec<String>),
+}
+
+/// Reads the `key = value` pairs of a config file, one per line
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    /// The line that is read, counted from 1
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// The next pair with the line it is on, or `None` at the end of the file
+    fn pair(&mut self) -> Result<Option<(usize, String, Value)>, ConfigError> {
+        self.skip_blank(true);
+        let line = self.line;
+        let key = match self.chars.peek() {
+            None => return Ok(None),
+            Some('"') | Some('\'') => self.string()?,
+            Some('[') => return Err(self.invalid("tables are not supported")),
+            Some(_) => {
+                let mut key = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                        break;
+                    }
+                    key.push(c);
+                    self.chars.next();
+                }
+                key
+            }
+        };
+        if key.is_empty() {
+            return Err(self.invalid("expected a key"));
+        }
+        self.skip_blank(false);
+        if self.chars.next() != Some('=') {
+            return Err(self.invalid("expected = after the key"));
+        }
+        self.skip_blank(false);
+        let value = self.value()?;
+        self.skip_blank(false);
+        match self.chars.peek() {
+            None | Some('\n') | Some('\r') => Ok(Some((line, key, value))),
+            Some(_) => Err(self.invalid("expected the end of the line after the value")),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, ConfigError> {
+        match self.chars.peek() {
+            Some('"') | Some('\'') => self.string().map(Value::String),
+            Some('[') => {
+                self.chars.next();
+                let mut values = Vec::new();
+                loop {
+                    self.skip_blank(true);
+                    match self.chars.peek() {
+                        Some(']') => break,
+                        Some('"') | Some('\'') => values.push(self.string()?),
+                        _ => return Err(self.invalid("expected a string or ] in the array")),
+                    }
+                    self.skip_blank(true);
+                    match self.chars.next() {
+                        Some(',') => {}
+                        Some(']') => return Ok(Value::Array(values)),
+                        _ => return Err(self.invalid("expected , or ] after a value")),
+                    }
+                }
+                self.chars.next();
+                Ok(Value::Array(values))
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = self.chars.peek().filter(|c| c.is_ascii_alphabetic()) {
+                    word.push(c);
+                    self.chars.next();
+                }
+                match word.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    _ => Err(self.invalid("expected a string, true, false or an array")),
+                }
+            }
+        }
+    }
+
+    /// A string in double quotes, with escapes like `\"`, or a literal string in single quotes
+    fn string(&mut self) -> Result<String, ConfigError> {
+        let quote = self.chars.next();
+        let mut string = String::new();
+        loop {
+            match self.chars.next() {
+                None | Some('\n') => return Err(self.invalid("the string is not closed")),
+                c if c == quote => return Ok(string),
+                Some('\\') if quote == Some('"') => match self.chars.next() {
+                    Some('"') => string.push('"'),
+                    Some('\\') => string.push('\\'),
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    _ => return Err(self.invalid("unknown escape in the string")),
+                },
+                Some(c) => string.push(c),
+            }
+        }
+    }
+
+    /// Skips spaces and comments, and line breaks as well if `lines` is set
+    fn skip_blank(&mut self, lines: bool) {
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                ' ' | '\t' | '\r' => {}
+                '\n' if lines => self.line += 1,
+                '#' => {
+                    while self.chars.peek().is_some_and(|&c| c != '\n') {
+                        self.chars.next();
+                    }
+                    continue;
+                }
+                _ => return,
+            }
+            self.chars.next();
+        }
+    }
+
+    fn invalid(&self, message: &str) -> ConfigError {
+        ConfigError::Invalid {
+            line: self.line,
+            message: message.to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_the_settings() {
+        let config = Config::parse(
+            "# Shared by the team\n\
+             ignore-field = [\"Notes\", 'URL'] # not reviewed\n\
+             exclude-group = [\n  \"Archive/*\",\n]\n\n\
+             match-by = \"title+username\"\n\
+             mask-passwords = true\n",
+        )
+        .unwrap();
+        assert_eq!(
+            Config {
+                ignore_fields: vec!["Notes".to_owned(), "URL".to_owned()],
+                exclude_groups: vec!["Archive/*".to_owned()],
+                match_by: Some(MatchBy::TitleUsername),
+                mask_passwords: Some(true),
+                mask_fields: vec![],
+            },
+            config
+        );
+    }
+
+    #[test]
+    fn tells_the_line_of_invalid_settings() {
+        let message = |text| match Config::parse(text) {
+            Err(ConfigError::Invalid { line, message }) => format!("{}: {}", line, message),
+            other => panic!("Expected an error, got {:?}", other),
+        };
+        assert_eq!(
+            "2: unknown setting ignore-fields",
+            message("\nignore-fields = []")
+        );
+        assert_eq!(
+            "1: match-by must be one of title, uuid, title+username, title+url",
+            message("match-by = \"url\"")
+        );
+        assert_eq!(
+            "3: expected , or ] after a value",
+            message("mask-fields = [\n\"UserName\"\n\"URL\"]")
+        );
+    }
+}
@@ -0,0 +1,244 @@
+//! Locating the group or entry that keeps a database from being read, as keepass only tells what
+//! is wrong with its XML, not where
+
+use base64::{engine::general_purpose, Engine as _};
+use chrono::NaiveDateTime;
+use xml::reader::{EventReader, XmlEvent};
+
+use std::fmt;
+
+use crate::xml_tags::inside;
+
+/// A group or entry of a database with a value that cannot be read
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Corruption {
+    /// Names of the groups containing the item, starting at the root, and the title of the
+    /// entry if it is one
+    pub path: Vec<String>,
+    pub is_entry: bool,
+    /// The tag of the value, like `UUID` or `LastModificationTime`
+    pub tag: String,
+}
+
+impl fmt::Display for Corruption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let item = if self.is_entry { "entry" } else { "group" };
+        write!(f, "{} of {} [{}]", self.tag, item, self.path.join(", "))
+    }
+}
+
+/// A value that cannot be read, of the entry or group that begins at `depth` in the open tags
+struct Pending {
+    tag: String,
+    depth: usize,
+    is_entry: bool,
+}
+
+/// Finds the first group or entry in the XML of a database with a value that keepass cannot
+/// read, like a UUID that is no base64-encoded UUID or a time in neither format, or at which the
+/// XML is malformed. Returns `None` if the values of all groups and entries look valid, like when
+/// what is wrong is elsewhere.
+///
+/// The title of an entry or the name of a group may come after the value, so the item is read
+/// to its end before it is reported.
+pub fn locate_corruption(xml: &[u8]) -> Option<Corruption> {
+    let mut tags: Vec<String> = Vec::new();
+    let mut groups: Vec<String> = Vec::new();
+    let mut entry: Option<usize> = None;
+    let mut title: Option<String> = None;
+    let mut key = String::new();
+    let mut protected = false;
+    let mut pending: Option<Pending> = None;
+
+    let resolve = |pending: Pending, groups: &[String], title: &Option<String>| {
+        let mut path = groups.to_vec();
+        if pending.is_entry {
+            path.push(title.clone().unwrap_or_else(|| "(untitled)".to_owned()));
+        }
+        Corruption {
+            path,
+            is_entry: pending.is_entry,
+            tag: pending.tag,
+        }
+    };
+
+    for event in EventReader::new(xml) {
+        let event = match event {
+            Ok(event) => event,
+            Err(_) if groups.is_empty() => return None,
+            Err(_) => {
+                let tag = tags.last().cloned().unwrap_or_default();
+                let pending = pending.unwrap_or_else(|| invalid(tag, &tags, entry));
+                return Some(resolve(pending, &groups, &title));
+            }
+        };
+        match event {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                let tag = name.local_name;
+                if tag == "Group" || tag == "Entry" {
+                    if let Some(p) = pending.take_if(|p| !p.is_entry) {
+                        return Some(resolve(p, &groups, &title));
+                    }
+                }
+                if tag == "Group" {
+                    groups.push(String::new());
+                } else if tag == "Entry" && entry.is_none() {
+                    entry = Some(tags.len());
+                    title = None;
+                } else if tag == "Value" && !groups.is_empty() {
+                    protected = attributes.iter().any(|a| {
+                        a.name.local_name == "Protected" && a.value.eq_ignore_ascii_case("true")
+                    });
+                    let is_binary = inside(&tags, &["Entry", "Binary"]);
+                    if is_binary && !attributes.iter().any(|a| a.name.local_name == "Ref") {
+                        pending.get_or_insert_with(|| invalid(tag.clone(), &tags, entry));
+                    }
+                }
+                tags.push(tag);
+            }
+            XmlEvent::Characters(text) if !groups.is_empty() => {
+                let in_history = tags.iter().any(|t| t == "History");
+                if inside(&tags, &["Group", "Name"]) {
+                    *groups.last_mut().expect("The group is open") = text.clone();
+                    if let Some(p) = pending.take_if(|p| !p.is_entry) {
+                        return Some(resolve(p, &groups, &title));
+                    }
+                } else if !in_history && inside(&tags, &["Entry", "String", "Key"]) {
+                    key = text.clone();
+                } else if !in_history
+                    && inside(&tags, &["Entry", "String", "Value"])
+                    && key == "Title"
+                    && !protected
+                {
+                    title = Some(text.clone());
+                }
+                if pending.is_none() && !is_valid(&tags, &text, protected) {
+                    let tag = tags.last().cloned().unwrap_or_default();
+                    pending = Some(invalid(tag, &tags, entry));
+                }
+            }
+            XmlEvent::EndElement { .. } => {
+                let tag = tags.pop().unwrap_or_default();
+                let ends_item = pending
+                    .as_ref()
+                    .is_some_and(|p| p.depth == tags.len() && (p.is_entry || tag == "Group"));
+                if ends_item {
+                    let pending = pending.take().expect("A value cannot be read");
+                    return Some(resolve(pending, &groups, &title));
+                }
+                if tag == "Group" {
+                    groups.pop();
+                } else if tag == "Entry" && entry == Some(tags.len()) {
+                    entry = None;
+                }
+                protected = false;
+            }
+            _ => {}
+        }
+    }
+    pending.map(|p| resolve(p, &groups, &title))
+}
+
+/// The value of `tag`, which belongs to the open entry that begins at `entry` in the open tags
+/// if there is one, or else to the innermost open group
+fn invalid(tag: String, tags: &[String], entry: Option<usize>) -> Pending {
+    match entry {
+        Some(depth) => Pending {
+            tag,
+            depth,
+            is_entry: true,
+        },
+        None => Pending {
+            tag,
+            depth: tags.iter().rposition(|t| t == "Group").unwrap_or_default(),
+            is_entry: false,
+        },
+    }
+}
+
+/// Whether keepass can read the text of the innermost open tag, which is checked for the values
+/// that keepass parses rather than keeps as text
+fn is_valid(tags: &[String], text: &str, protected: bool) -> bool {
+    let tag = tags.last().map_or("", String::as_str);
+    let parent = tags.len().checked_sub(2).map_or("", |i| tags[i].as_str());
+    match (parent, tag) {
+        ("Group" | "Entry", "UUID" | "CustomIconUUID") | ("Group", "LastTopVisibleEntry") => {
+            general_purpose::STANDARD
+                .decode(text)
+                .is_ok_and(|uuid| uuid.len() == 16)
+        }
+        ("Group" | "Entry", "IconID") | ("Times", "UsageCount") => text.parse::<usize>().is_ok(),
+        ("Group", "IsExpanded") | ("Times", "Expires") | ("AutoType", "Enabled") => {
+            text.to_lowercase().parse::<bool>().is_ok()
+        }
+        ("Times", _) => is_time(text),
+        ("Entry", "ForegroundColor" | "BackgroundColor") => {
+            text.len() == 7 && text.starts_with('#') && u32::from_str_radix(&text[1..], 16).is_ok()
+        }
+        ("String", "Value") if protected => general_purpose::STANDARD.decode(text).is_ok(),
+        _ => true,
+    }
+}
+
+/// Times are either ISO 8601 timestamps, or base64-encoded seconds like in KDBX 4 databases
+fn is_time(text: &str) -> bool {
+    NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%SZ").is_ok()
+        || general_purpose::STANDARD
+            .decode(text)
+            .is_ok_and(|seconds| seconds.len() == 8)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn document(entry: &str) -> String {
+        format!(
+            "<KeePassFile><Root><Group><UUID>MZE3GigUqlhhWb+uWeMNQw==</UUID><Name>Root</Name>\
+             <Group><UUID>nE7UJ1yu73Q4etSzVASfSg==</UUID><Name>Work</Name>{}</Group>\
+             </Group></Root></KeePassFile>",
+            entry
+        )
+    }
+
+    #[test]
+    fn names_the_entry_with_a_value_that_cannot_be_read() {
+        let entry = "<Entry><UUID>not a uuid</UUID><Times><Expires>False</Expires></Times>\
+                     <String><Key>Title</Key><Value>github</Value></String></Entry>";
+        assert_eq!(
+            Some(Corruption {
+                path: vec!["Root".to_owned(), "Work".to_owned(), "github".to_owned()],
+                is_entry: true,
+                tag: "UUID".to_owned(),
+            }),
+            locate_corruption(document(entry).as_bytes())
+        );
+
+        let entry = "<Entry><String><Key>Title</Key><Value>github</Value></String>\
+                     <Times><Expires>maybe</Expires></Times></Entry>";
+        let corruption = locate_corruption(document(entry).as_bytes()).unwrap();
+        assert_eq!(
+            "Expires of entry [Root, Work, github]",
+            corruption.to_string()
+        );
+    }
+
+    #[test]
+    fn names_the_group_where_the_xml_is_malformed() {
+        let xml = "<KeePassFile><Root><Group><Name>Root</Name><Group><Name>Work</Name>\
+                   <IconID>1</Icon";
+        let corruption = locate_corruption(xml.as_bytes()).unwrap();
+        assert_eq!("IconID of group [Root, Work]", corruption.to_string());
+    }
+
+    #[test]
+    fn finds_nothing_in_valid_databases() {
+        let entry = "<Entry><UUID>nE7UJ1yu73Q4etSzVASfSg==</UUID>\
+                     <Times><LastModificationTime>2018-10-04T22:00:29Z</LastModificationTime>\
+                     <Expires>False</Expires></Times>\
+                     <String><Key>Title</Key><Value>github</Value></String></Entry>";
+        assert_eq!(None, locate_corruption(document(entry).as_bytes()));
+    }
+}
@@ -0,0 +1,190 @@
+use base64::{engine::general_purpose, Engine as _};
+use sha2::{Digest, Sha256};
+use xml::reader::{EventReader, XmlEvent};
+
+use std::collections::HashMap;
+
+use crate::diff::{Diff, DiffItem, DiffResult, ItemKind};
+use crate::xml_tags::inside;
+
+/// Number of hex digits of the content hash that are shown in the text output
+pub(crate) const SHORT_HASH_LENGTH: usize = 12;
+
+/// A file attached to an entry. Attachments are compared by a hash of their content, so binary
+/// content never ends up in the output.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attachment {
+    pub name: String,
+    /// Hex-encoded SHA-256 hash of the content
+    pub sha256: String,
+    pub use_verbose: bool,
+}
+
+/// The attachments of all entries of a database, keyed by `entry_key`
+pub type Attachments = HashMap<String, Vec<Attachment>>;
+
+impl Attachment {
+    pub fn new(name: &str, content: &[u8], use_verbose: bool) -> Self {
+        let sha256 = Sha256::digest(content)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        Attachment {
+            name: name.to_owned(),
+            sha256,
+            use_verbose,
+        }
+    }
+
+    fn short_hash(&self) -> &str {
+        &self.sha256[..SHORT_HASH_LENGTH]
+    }
+}
+
+/// The key of an entry in `Attachments`, which is its UUID as written in the database XML
+pub fn entry_key(uuid: &[u8]) -> String {
+    general_purpose::STANDARD.encode(uuid)
+}
+
+/// Reads the attachments of all entries from the XML of a database. `pool` maps the references
+/// used in the XML to the content of the binaries in the database. Attachments of historical
+/// versions of an entry are skipped.
+pub fn read_attachments(
+    xml: &[u8],
+    pool: &HashMap<String, &[u8]>,
+    use_verbose: bool,
+) -> Result<Attachments, xml::reader::Error> {
+    let mut attachments = Attachments::new();
+    let mut tags: Vec<String> = Vec::new();
+    let mut uuid = String::new();
+    let mut current: Vec<Attachment> = Vec::new();
+    let mut name = String::new();
+    let mut reference = String::new();
+
+    for event in EventReader::new(xml) {
+        let in_history = tags.iter().any(|t| t == "History");
+        match event? {
+            XmlEvent::StartElement {
+                name: tag,
+                attributes,
+                ..
+            } => {
+                if !in_history && tag.local_name == "Value" && inside(&tags, &["Entry", "Binary"]) {
+                    if let Some(r) = attributes.iter().find(|a| a.name.local_name == "Ref") {
+                        reference = r.value.clone();
+                    }
+                }
+                tags.push(tag.local_name);
+            }
+            XmlEvent::Characters(text) if !in_history => {
+                if inside(&tags, &["Entry", "UUID"]) {
+                    uuid = text;
+                } else if inside(&tags, &["Entry", "Binary", "Key"]) {
+                    name = text;
+                }
+            }
+            XmlEvent::EndElement { .. } => {
+                if !in_history && inside(&tags, &["Entry", "Binary"]) {
+                    let content = pool.get(&reference).copied().unwrap_or_default();
+                    current.push(Attachment::new(&name, content, use_verbose));
+                } else if !in_history && inside(&tags, &["Entry"]) && !current.is_empty() {
+                    attachments.insert(uuid.clone(), std::mem::take(&mut current));
+                }
+                tags.pop();
+            }
+            _ => {}
+        }
+    }
+
+    Ok(attachments)
+}
+
+impl Diff for Attachment {
+    fn diff<'a>(&'a self, other: &'a Self) -> DiffResult<'a, Self> {
+        if self.sha256 == other.sha256 {
+            DiffResult::Identical {
+                left: self,
+                right: other,
+            }
+        } else {
+            DiffResult::Changed {
+                left: self,
+                right: other,
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Attachment {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.use_verbose {
+            write!(
+                f,
+                "Attachment '{}' (sha256 {})",
+                self.name,
+                self.short_hash()
+            )
+        } else {
+            write!(f, "Attachment {} (sha256 {})", self.name, self.short_hash())
+        }
+    }
+}
+
+impl DiffItem for Attachment {
+    fn item_kind(&self) -> ItemKind {
+        ItemKind::Attachment
+    }
+
+    fn item_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn item_value(&self) -> Option<String> {
+        Some(self.sha256.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const XML: &str = r#"<KeePassFile><Root><Group>
+        <Entry>
+            <UUID>AAAAAAAAAAAAAAAAAAAAAQ==</UUID>
+            <Binary><Key>id_rsa.pub</Key><Value Ref="0"/></Binary>
+            <History><Entry>
+                <UUID>AAAAAAAAAAAAAAAAAAAAAQ==</UUID>
+                <Binary><Key>old.pub</Key><Value Ref="1"/></Binary>
+            </Entry></History>
+        </Entry>
+        <Entry><UUID>AAAAAAAAAAAAAAAAAAAAAg==</UUID></Entry>
+    </Group></Root></KeePassFile>"#;
+
+    #[test]
+    fn reads_attachments_of_current_entries() {
+        let pool: HashMap<String, &[u8]> = vec![
+            ("0".to_owned(), b"ssh-rsa AAAA".as_slice()),
+            ("1".to_owned(), b"ssh-rsa BBBB".as_slice()),
+        ]
+        .into_iter()
+        .collect();
+        let attachments = read_attachments(XML.as_bytes(), &pool, false).unwrap();
+
+        assert_eq!(1, attachments.len());
+        let entry_attachments = &attachments[&entry_key(&1u128.to_be_bytes())];
+        assert_eq!(
+            vec![Attachment::new("id_rsa.pub", b"ssh-rsa AAAA", false)],
+            *entry_attachments
+        );
+    }
+
+    #[test]
+    fn compares_attachments_by_content() {
+        let a = Attachment::new("id_rsa.pub", b"ssh-rsa AAAA", false);
+        let b = Attachment::new("id_rsa.pub", b"ssh-rsa BBBB", false);
+
+        assert!(!a.diff(&a.clone()).has_changes());
+        assert!(a.diff(&b).has_changes());
+        assert!(!format!("{}", b).contains("BBBB"));
+    }
+}
@@ -0,0 +1,99 @@
+//! Detection of entries within one database that are easily confused with each other when the
+//! entries of both databases are paired up
+
+use crate::diff::entry::Entry;
+use crate::diff::group::Group;
+
+/// Entries of the same group that are paired up by the same key, or that have the same fields
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Duplicates {
+    /// Names of the groups containing the entries, starting at the root
+    pub path: Vec<String>,
+    /// The title of the first of the entries
+    pub title: String,
+    pub count: usize,
+    /// Whether the entries have the same fields. Otherwise they only have the same key, like the
+    /// same title, so they are paired up with the entries of the other database in their order.
+    pub identical: bool,
+}
+
+/// Finds the entries of each group that have the same key or the same fields, in the order of
+/// the database. Fields left out of the comparison are not compared.
+pub fn find_duplicates(root: &Group) -> Vec<Duplicates> {
+    let mut found = Vec::new();
+    collect_duplicates(root, &mut vec![root.name.clone()], &mut found);
+    found
+}
+
+fn collect_duplicates(group: &Group, path: &mut Vec<String>, found: &mut Vec<Duplicates>) {
+    let buckets: Vec<&Vec<Entry>> = group
+        .entry_keys
+        .iter()
+        .filter_map(|key| group.entries.get(key))
+        .collect();
+    let duplicates = |entries: &[&Entry], identical| Duplicates {
+        path: path.clone(),
+        title: entries[0].display_title().into_owned(),
+        count: entries.len(),
+        identical,
+    };
+    for bucket in buckets.iter().filter(|bucket| bucket.len() > 1) {
+        let entries: Vec<&Entry> = bucket.iter().collect();
+        let identical = entries.iter().all(|e| e.fields == entries[0].fields);
+        found.push(duplicates(&entries, identical));
+    }
+
+    // Entries with different keys may still be the same, like with different UUIDs
+    let firsts: Vec<&Entry> = buckets.iter().map(|bucket| &bucket[0]).collect();
+    let mut reported = vec![false; firsts.len()];
+    for (i, entry) in firsts.iter().enumerate() {
+        if reported[i] {
+            continue;
+        }
+        let same: Vec<usize> = (i..firsts.len())
+            .filter(|&j| firsts[j].fields == entry.fields)
+            .collect();
+        if same.len() > 1 {
+            same.iter().for_each(|&j| reported[j] = true);
+            let entries: Vec<&Entry> = same.iter().map(|&j| firsts[j]).collect();
+            found.push(duplicates(&entries, true));
+        }
+    }
+
+    for key in &group.group_keys {
+        for child in group.child_groups.get(key).into_iter().flatten() {
+            path.push(child.name.clone());
+            collect_duplicates(child, path, found);
+            path.pop();
+        }
+    }
+}
+
+/// Finds the entries without a title, in the order of the database, by the names of the groups
+/// containing them and their displayed title like `(untitled 1a2b3c4d)`, with whether they have
+/// a UUID. They are paired up by their UUID instead, or in their order if they have none.
+pub fn find_untitled(root: &Group) -> Vec<(Vec<String>, bool)> {
+    let mut found = Vec::new();
+    collect_untitled(root, &mut vec![root.name.clone()], &mut found);
+    found
+}
+
+fn collect_untitled(group: &Group, path: &mut Vec<String>, found: &mut Vec<(Vec<String>, bool)>) {
+    let entries = group
+        .entry_keys
+        .iter()
+        .filter_map(|key| group.entries.get(key))
+        .flatten();
+    for entry in entries.filter(|e| e.title().is_empty()) {
+        let mut entry_path = path.clone();
+        entry_path.push(entry.display_title().into_owned());
+        found.push((entry_path, entry.uuid.is_some()));
+    }
+    for key in &group.group_keys {
+        for child in group.child_groups.get(key).into_iter().flatten() {
+            path.push(child.name.clone());
+            collect_untitled(child, path, found);
+            path.pop();
+        }
+    }
+}
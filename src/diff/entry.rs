@@ -1,67 +1,315 @@
 use base64::{engine::general_purpose, Engine as _};
 use keepass::db::Value;
+use std::borrow::Cow;
 use std::collections::HashMap;
+use zeroize::Zeroizing;
 
-use crate::diff::field::{Field, ValueType};
-use crate::diff::{Diff, DiffResult, DiffResultFormat};
+use crate::diff::attachment::{entry_key, Attachment, Attachments};
+use crate::diff::field::{is_otp_field, lossy_length, undecodable_digest, Field, ValueType};
+use crate::diff::group::describe_icon;
+use crate::diff::history::{diff_history, HistoryVersion};
+use crate::diff::moves::Move;
+use crate::diff::strength;
+use crate::diff::tag::{tags_of, Tag};
+use crate::diff::times::Times;
+use crate::diff::{Diff, DiffItem, DiffOptions, DiffResult, DiffResultFormat, ItemKind, SortBy};
 
+/// How many digits of the UUID tell untitled entries apart, like `1a2b3c4d`
+const UUID_PREFIX: usize = 8;
+
+/// An entry of a database with all of its fields, keyed by field name
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Entry {
+    /// The title of the entry, kept even if the Title field is left out of the comparison
+    title: String,
     pub fields: HashMap<String, Field>,
+    /// Whether each field is protected, as a `<name> protection` field that is `protected` or
+    /// `unprotected`, for fields that are not binary. Only compared for fields both entries have.
+    pub protection: HashMap<String, Field>,
+    pub attachments: HashMap<String, Attachment>,
+    /// Tags of the entry, keyed by their name. Not read with `DiffOptions::ignore_metadata`.
+    pub tags: HashMap<String, Tag>,
+    /// The icon of the entry as an `Icon` field, only read if
+    /// `DiffOptions::compares_entry_icons`
+    pub icon: Option<Field>,
+    /// Prior versions of the entry, oldest first. Only read with `DiffOptions::with_history`.
+    pub history: Vec<HistoryVersion>,
+    /// The UUID of the entry, unless it has none like entries of KeePass 1.x databases
+    pub uuid: Option<String>,
+    /// Where the entry moved, if `detect_moves` found that it did
+    pub moved: Option<Move>,
+    /// The names of the groups below the root that the entry was in before its database was
+    /// flattened, see `Group::flatten`
+    pub origin: Vec<String>,
+    pub times: Times,
+    compare_times: bool,
+    /// See `DiffOptions::newer_wins`
+    newer_wins: bool,
     use_verbose: bool,
     mask_passwords: bool,
 }
 
 impl Entry {
-    pub fn from_keepass(e: &keepass::db::Entry, use_verbose: bool, mask_passwords: bool) -> Self {
+    pub fn from_keepass(
+        e: &keepass::db::Entry,
+        options: &DiffOptions,
+        attachments: &Attachments,
+    ) -> Self {
+        let use_verbose = options.use_verbose;
+        let mask_passwords = options.mask_passwords;
+
         // username, password, etc. are just fields
-        let fields = e
+        let fields: HashMap<String, Field> = e
             .fields
             .iter()
+            .filter(|(k, _)| !options.ignores_field(k))
             .map(|(k, v)| {
-                (
-                    k.to_owned(),
-                    Field {
-                        name: k.to_owned(),
-                        value: match v {
-                            Value::Bytes(b) => general_purpose::STANDARD_NO_PAD.encode(b),
-                            Value::Unprotected(v) => v.to_owned(),
-                            Value::Protected(p) => String::from_utf8(p.unsecure().to_owned())
-                                .unwrap()
-                                .to_owned(),
-                        },
-                        kind: match v {
-                            Value::Bytes(_) => ValueType::Binary,
-                            Value::Unprotected(_) => ValueType::Unprotected,
-                            Value::Protected(_) => ValueType::Protected,
-                        },
-                        use_verbose,
-                        mask_passwords,
+                let mut field = Field {
+                    name: k.to_owned(),
+                    value: Zeroizing::default(),
+                    undecodable: None,
+                    kind: match v {
+                        Value::Bytes(_) => ValueType::Binary,
+                        Value::Unprotected(_) => ValueType::Unprotected,
+                        Value::Protected(_) => ValueType::Protected,
                     },
-                )
+                    ignore_case: options.ignores_case_of(k),
+                    normalizers: options.normalizers_of(k),
+                    otp: is_otp_field(k),
+                    use_verbose,
+                    mask_passwords,
+                    masked: options.masks_field(k),
+                    mask: options.mask.clone(),
+                    length: 0,
+                    truncate: options.truncate,
+                    strength: None,
+                    ascii: options.ascii,
+                };
+                let encoded;
+                let raw = match v {
+                    Value::Bytes(b) => {
+                        encoded = general_purpose::STANDARD_NO_PAD.encode(b);
+                        encoded.as_bytes()
+                    }
+                    Value::Unprotected(v) => v.as_bytes(),
+                    Value::Protected(p) => p.unsecure(),
+                };
+                // Counted and scored without copying the value, unless it is not valid UTF-8
+                if options.mask.show_length {
+                    field.length = lossy_length(raw);
+                }
+                if options.password_strength && k == "Password" {
+                    field.strength = Some(match std::str::from_utf8(raw) {
+                        Ok(text) => strength::score(text),
+                        Err(_) => strength::score(&Zeroizing::new(
+                            String::from_utf8_lossy(raw).into_owned(),
+                        )),
+                    });
+                }
+                // Protected values are only copied out of the database if they are shown
+                field.value = field.keep(raw);
+                field.undecodable = undecodable_digest(raw);
+                (k.to_owned(), field)
+            })
+            .collect();
+
+        let protection = fields
+            .values()
+            .filter_map(|field| {
+                let protection = match field.kind {
+                    ValueType::Protected => "protected",
+                    ValueType::Unprotected => "unprotected",
+                    ValueType::Binary => return None,
+                };
+                let name = format!("{} protection", field.name);
+                let attribute = Field::attribute(&name, protection, options);
+                Some((field.name.clone(), attribute))
             })
             .collect();
 
+        let attachments = attachments
+            .get(&entry_key(e.uuid.as_bytes()))
+            .into_iter()
+            .flatten()
+            .map(|a| (a.name.clone(), a.clone()))
+            .collect();
+
+        // Attachments of prior versions are not read, so versions are compared by their fields
+        let history = match &e.history {
+            Some(history) if options.with_history => history
+                .get_entries()
+                .iter()
+                .enumerate()
+                .map(|(index, version)| HistoryVersion {
+                    number: index + 1,
+                    entry: Entry::from_keepass(version, options, &Attachments::new()),
+                    use_verbose,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let tags = if options.ignore_metadata {
+            HashMap::new()
+        } else {
+            tags_of(e, use_verbose)
+        };
+
+        let icon = describe_icon(e.icon_id, &e.custom_icon_uuid)
+            .filter(|_| options.compares_entry_icons())
+            .map(|icon| Field::attribute("Icon", &icon, options));
+
         Entry {
+            title: e.get("Title").unwrap_or_default().to_owned(),
             fields,
+            protection,
+            attachments,
+            tags,
+            icon,
+            history,
+            uuid: Some(e.uuid.to_string()).filter(|_| !e.uuid.is_nil()),
+            moved: None,
+            origin: Vec::new(),
+            times: Times::from_keepass(&e.times, options),
+            compare_times: options.compare_times,
+            newer_wins: options.newer_wins,
             use_verbose,
             mask_passwords,
         }
     }
 }
 
+impl Entry {
+    /// The title of the entry, or an empty string if it has none
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// The title of the entry, or `(untitled <uuid-prefix>)` with the first eight digits of its
+    /// UUID if it has none, so untitled entries can be told apart. Entries of a flattened
+    /// database are followed by the groups they were in, like `Mailbox (in Work/Email)`.
+    pub fn display_title(&self) -> Cow<'_, str> {
+        let title = match (self.title.as_str(), &self.uuid) {
+            ("", Some(uuid)) => Cow::Owned(format!("(untitled {})", &uuid[..UUID_PREFIX])),
+            ("", None) => Cow::Borrowed("(untitled)"),
+            (title, _) => Cow::Borrowed(title),
+        };
+        if self.origin.is_empty() {
+            title
+        } else {
+            Cow::Owned(format!("{} (in {})", title, self.origin.join("/")))
+        }
+    }
+
+    /// Whether the entry carries at least one of the given tags
+    pub fn has_any_tag(&self, tags: &[String]) -> bool {
+        tags.iter().any(|tag| self.tags.contains_key(tag))
+    }
+}
+
+impl Entry {
+    /// Whether the password is the only field that differs from `other`, and the attachments
+    /// and tags are the same. History and times, which change along with the password, are not
+    /// taken into account.
+    pub fn only_password_differs(&self, other: &Entry) -> bool {
+        let (_, fields) = crate::diff::diff_entry(&self.fields, &other.fields);
+        let mut changed = fields.iter().filter(|dr| dr.has_changes());
+        let password_changed = matches!(
+            (changed.next(), changed.next()),
+            (Some(DiffResult::Changed { left, .. }), None) if left.name == "Password"
+        );
+        password_changed
+            && diff_protection(&self.protection, &other.protection).is_empty()
+            && !crate::diff::diff_entry(&self.attachments, &other.attachments).0
+            && !crate::diff::diff_entry(&self.tags, &other.tags).0
+            && diff_icon(&self.icon, &other.icon).is_none()
+    }
+}
+
+/// How the icons of two entries differ, if they do
+fn diff_icon<'a>(a: &'a Option<Field>, b: &'a Option<Field>) -> Option<DiffResult<'a, Field>> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.diff(b)).filter(|dr| dr.has_changes()),
+        (Some(left), None) => Some(DiffResult::OnlyLeft { left }),
+        (None, Some(right)) => Some(DiffResult::OnlyRight { right }),
+        (None, None) => None,
+    }
+}
+
+/// How the protection of the fields that both entries have differs, like a password that is no
+/// longer protected
+fn diff_protection<'a>(
+    a: &'a HashMap<String, Field>,
+    b: &'a HashMap<String, Field>,
+) -> Vec<DiffResult<'a, Field>> {
+    let (_, differences) = crate::diff::diff_entry(a, b);
+    differences
+        .into_iter()
+        .filter(|dr| matches!(dr, DiffResult::Changed { .. }))
+        .collect()
+}
+
 impl Diff for Entry {
     fn diff<'a>(&'a self, other: &'a Self) -> DiffResult<'a, Self> {
-        let (has_differences, field_differences) =
+        let (has_differences_fields, field_differences) =
             crate::diff::diff_entry(&self.fields, &other.fields);
 
-        if has_differences {
+        let (has_differences_attachments, attachment_differences) =
+            crate::diff::diff_entry(&self.attachments, &other.attachments);
+
+        let (has_differences_tags, tag_differences) =
+            crate::diff::diff_entry(&self.tags, &other.tags);
+
+        let (has_differences_history, history_differences) =
+            diff_history(&self.history, &other.history);
+
+        let has_differences_times = self.compare_times && self.times != other.times;
+
+        let protection_differences = diff_protection(&self.protection, &other.protection);
+
+        let icon_difference = diff_icon(&self.icon, &other.icon);
+
+        if has_differences_fields
+            || !protection_differences.is_empty()
+            || icon_difference.is_some()
+            || has_differences_attachments
+            || has_differences_tags
+            || has_differences_history
+            || has_differences_times
+        {
             let mut inner_differences: Vec<Box<dyn DiffResultFormat>> = Vec::new();
 
+            if has_differences_times {
+                inner_differences.push(Box::new(DiffResult::Changed {
+                    left: &self.times,
+                    right: &other.times,
+                }))
+            }
+
             for dr in field_differences {
                 inner_differences.push(Box::new(dr))
             }
 
+            for dr in protection_differences {
+                inner_differences.push(Box::new(dr))
+            }
+
+            if let Some(dr) = icon_difference {
+                inner_differences.push(Box::new(dr))
+            }
+
+            for dr in attachment_differences {
+                inner_differences.push(Box::new(dr))
+            }
+
+            for dr in tag_differences {
+                inner_differences.push(Box::new(dr))
+            }
+
+            for dr in history_differences {
+                inner_differences.push(Box::new(dr))
+            }
+
             DiffResult::InnerDifferences {
                 left: self,
                 right: other,
@@ -78,22 +326,47 @@ impl Diff for Entry {
 
 impl std::fmt::Display for Entry {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let name = self
-            .fields
-            .get("Title")
-            .unwrap_or(&Field {
-                name: "Title".to_string(),
-                value: "".to_string(),
-                kind: ValueType::Unprotected,
-                use_verbose: self.use_verbose,
-                mask_passwords: self.mask_passwords,
-            })
-            .value
-            .clone();
         if self.use_verbose {
-            write!(f, "Entry '{}'", name)
+            write!(f, "Entry '{}'", self.display_title())
         } else {
-            write!(f, "{}", name)
+            write!(f, "{}", self.display_title())
+        }
+    }
+}
+
+impl DiffItem for Entry {
+    fn item_kind(&self) -> ItemKind {
+        ItemKind::Entry
+    }
+
+    fn item_name(&self) -> String {
+        self.display_title().into_owned()
+    }
+
+    fn annotation(&self) -> Option<String> {
+        self.times.describe()
+    }
+
+    /// Tells which database modified the entry last, like `(B is newer)`, for `newer_wins`
+    fn change_note(&self, new: &Self) -> Option<String> {
+        if !self.newer_wins {
+            return None;
+        }
+        let note = match (self.times.modified, new.times.modified) {
+            (Some(a), Some(b)) if a > b => "(A is newer)",
+            (Some(a), Some(b)) if a < b => "(B is newer)",
+            _ => "(same/unknown time)",
+        };
+        Some(note.to_owned())
+    }
+
+    fn sort_key(&self, sort_by: SortBy) -> String {
+        match sort_by {
+            SortBy::Username => {
+                let user_name = self.fields.get("UserName").map(|f| f.value.as_str());
+                format!("{}\0{}", user_name.unwrap_or_default(), self.title())
+            }
+            _ => self.title().to_owned(),
         }
     }
 }
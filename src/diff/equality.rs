@@ -0,0 +1,146 @@
+//! Comparison of the entries of both databases that only tells whether their fields are the same,
+//! without showing any of their values
+
+use std::collections::BTreeSet;
+
+use crate::diff::entry::Entry;
+use crate::diff::field::Field;
+use crate::diff::group::Group;
+
+/// Whether a field, or an entry, is the same in both databases
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Equality {
+    Match,
+    Differs,
+    OnlyInA,
+    OnlyInB,
+}
+
+/// How an entry, or one of its fields, compares between both databases
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Comparison {
+    /// Names of the groups containing the entry, starting at the root, and its title
+    pub path: Vec<String>,
+    /// The name of the field, or `None` for an entry that only one database has
+    pub field: Option<String>,
+    pub equality: Equality,
+}
+
+/// Compares the fields of the entries of both databases, paired up like they are diffed, in the
+/// order of their paths and then of the names of their fields. Values are compared in constant
+/// time, so how long a comparison takes does not tell how much of a value matches. Entries that
+/// only one database has are listed without their fields.
+///
+/// Values are only compared as they are kept, so masking all fields with
+/// `DiffOptions::compare_only` leaves only their digests to compare.
+pub fn compare(a: &Group, b: &Group) -> Vec<Comparison> {
+    let mut found = Vec::new();
+    compare_groups(Some(a), Some(b), &mut vec![a.name.clone()], &mut found);
+    found
+}
+
+/// Compares the entries of groups with the same path, of which only one database may have one
+fn compare_groups<'a>(
+    a: Option<&'a Group>,
+    b: Option<&'a Group>,
+    path: &mut Vec<String>,
+    found: &mut Vec<Comparison>,
+) {
+    let entries = |group: Option<&'a Group>, key| list(group.and_then(|g| g.entries.get(key)));
+    let keys: BTreeSet<&String> = a
+        .into_iter()
+        .chain(b)
+        .flat_map(|g| g.entries.keys())
+        .collect();
+    for key in keys {
+        let (entries_a, entries_b) = (entries(a, key), entries(b, key));
+        for i in 0..entries_a.len().max(entries_b.len()) {
+            match (entries_a.get(i), entries_b.get(i)) {
+                (Some(a), Some(b)) => compare_entries(a, b, path, found),
+                (Some(only), None) => found.push(only_in(only, path, Equality::OnlyInA)),
+                (None, Some(only)) => found.push(only_in(only, path, Equality::OnlyInB)),
+                (None, None) => {}
+            }
+        }
+    }
+
+    let groups = |group: Option<&'a Group>, key| list(group.and_then(|g| g.child_groups.get(key)));
+    let keys: BTreeSet<&String> = a
+        .into_iter()
+        .chain(b)
+        .flat_map(|g| g.child_groups.keys())
+        .collect();
+    for key in keys {
+        let (groups_a, groups_b) = (groups(a, key), groups(b, key));
+        for i in 0..groups_a.len().max(groups_b.len()) {
+            let (group_a, group_b) = (groups_a.get(i), groups_b.get(i));
+            let group = group_a
+                .or(group_b)
+                .expect("One of the databases has the group");
+            path.push(group.name.clone());
+            compare_groups(group_a, group_b, path, found);
+            path.pop();
+        }
+    }
+}
+
+fn list<T>(items: Option<&Vec<T>>) -> &[T] {
+    items.map_or(&[], Vec::as_slice)
+}
+
+fn only_in(entry: &Entry, path: &[String], equality: Equality) -> Comparison {
+    let mut path = path.to_vec();
+    path.push(entry.display_title().into_owned());
+    Comparison {
+        path,
+        field: None,
+        equality,
+    }
+}
+
+fn compare_entries(a: &Entry, b: &Entry, path: &[String], found: &mut Vec<Comparison>) {
+    let mut entry_path = path.to_vec();
+    entry_path.push(a.display_title().into_owned());
+    let names: BTreeSet<&String> = a.fields.keys().chain(b.fields.keys()).collect();
+    for name in names {
+        let equality = match (a.fields.get(name), b.fields.get(name)) {
+            (Some(a), Some(b)) if is_same(a, b) => Equality::Match,
+            (Some(_), Some(_)) => Equality::Differs,
+            (Some(_), None) => Equality::OnlyInA,
+            (None, _) => Equality::OnlyInB,
+        };
+        found.push(Comparison {
+            path: entry_path.clone(),
+            field: Some(name.clone()),
+            equality,
+        });
+    }
+}
+
+fn is_same(a: &Field, b: &Field) -> bool {
+    let (value_a, value_b) = (a.compared_value(), b.compared_value());
+    if a.ignore_case && !a.is_hidden() {
+        // Hidden values are case folded before they are digested already
+        caseless::default_caseless_match_str(&value_a, &value_b)
+    } else {
+        constant_time_eq(value_a.as_bytes(), value_b.as_bytes())
+    }
+}
+
+/// Whether the bytes are the same, comparing all of them even after the first difference
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compares_all_bytes_in_constant_time() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secrets"));
+        assert!(constant_time_eq(b"", b""));
+    }
+}
@@ -1,5 +1,123 @@
-use crate::diff::{Diff, DiffResult};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
 
+use std::borrow::Cow;
+use std::cmp::Ordering;
+
+use crate::diff::normalize::{normalize, Normalizer};
+use crate::diff::{Diff, DiffItem, DiffOptions, DiffResult, ItemKind};
+use crate::output::Glyphs;
+
+/// Shown instead of protected values when passwords are masked, and of masked fields
+pub const MASK: &str = "***";
+
+/// The SHA-256 digest of `value` if it is not valid UTF-8, so values that only differ in their
+/// invalid bytes are not the same once they are decoded lossily
+pub fn undecodable_digest(value: &[u8]) -> Option<String> {
+    match std::str::from_utf8(value) {
+        Ok(_) => None,
+        Err(_) => Some(hex_digest(value).to_string()),
+    }
+}
+
+/// The number of characters of `value` once it is decoded lossily, counted without decoding it
+/// into a copy. Each invalid sequence counts as the one replacement character it is decoded as.
+pub(crate) fn lossy_length(value: &[u8]) -> usize {
+    value
+        .utf8_chunks()
+        .map(|chunk| chunk.valid().chars().count() + usize::from(!chunk.invalid().is_empty()))
+        .sum()
+}
+
+/// The SHA-256 digest of `value` in hexadecimal
+fn hex_digest(value: &[u8]) -> Zeroizing<String> {
+    let digest = Sha256::digest(value);
+    Zeroizing::new(digest.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// How hidden values are shown instead of their value
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mask {
+    /// Shown instead of the value, `***` by default
+    pub text: String,
+    /// Whether the text is padded with its last character to the length of the value, so a
+    /// short value that became a long one can be told without showing either
+    pub show_length: bool,
+}
+
+impl Default for Mask {
+    fn default() -> Self {
+        Mask {
+            text: MASK.to_owned(),
+            show_length: false,
+        }
+    }
+}
+
+impl Mask {
+    /// The text shown instead of a value of `length` characters. Values shorter than the text
+    /// are shown as the text alone.
+    pub fn apply(&self, length: usize) -> Cow<'_, str> {
+        let padding = length.saturating_sub(self.text.chars().count());
+        match self.text.chars().last() {
+            Some(last) if self.show_length && padding > 0 => {
+                let mut text = self.text.clone();
+                text.extend(std::iter::repeat_n(last, padding));
+                Cow::Owned(text)
+            }
+            _ => Cow::Borrowed(&self.text),
+        }
+    }
+}
+
+/// Names of the fields that KeePassXC and older plugins keep OTP secrets in
+const OTP_FIELDS: &[&str] = &["otp", "TOTP Seed"];
+
+/// Prefixes of the fields that KeePass keeps OTP secrets in, like `TimeOtp-Secret-Base32`
+const OTP_FIELD_PREFIXES: &[&str] = &["TimeOtp-Secret", "HmacOtp-Secret"];
+
+/// Whether the field with the given name holds the secret of a one-time password
+pub fn is_otp_field(name: &str) -> bool {
+    OTP_FIELDS.contains(&name) || OTP_FIELD_PREFIXES.iter().any(|p| name.starts_with(p))
+}
+
+/// How long and multiline values are shown in text output. This only changes how values are
+/// shown, not how they are compared.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Truncate {
+    /// Shows values on a single line, with line breaks marked as `↵` or `\n` in ASCII output
+    #[default]
+    Collapse,
+    /// Collapses values like `Collapse` and shortens them to this many characters
+    Length(usize),
+    /// Shows values as they are, with the lines after the first indented below it
+    Off,
+}
+
+impl Truncate {
+    /// Shortens a value for showing it, marking line breaks and the end of shortened values with
+    /// the given glyphs
+    pub fn apply(&self, value: &str, glyphs: &Glyphs) -> String {
+        let collapsed = || {
+            value
+                .replace("\r\n", "\n")
+                .replace(['\n', '\r'], glyphs.line_break)
+        };
+        match *self {
+            Truncate::Off => value.replace("\r\n", "\n"),
+            Truncate::Collapse => collapsed(),
+            Truncate::Length(max) => {
+                let collapsed = collapsed();
+                match collapsed.char_indices().nth(max) {
+                    Some((end, _)) => format!("{}{}", &collapsed[..end], glyphs.ellipsis),
+                    None => collapsed,
+                }
+            }
+        }
+    }
+}
+
+/// How a field value is stored in the database
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ValueType {
     Binary,
@@ -7,18 +125,144 @@ pub enum ValueType {
     Protected,
 }
 
+/// A single named value of an entry, like its title or password
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Field {
     pub name: String,
-    pub value: String,
+    /// The value, wiped from memory once the field is dropped. Values that are never shown are
+    /// kept as a digest instead, see [`Field::keep`].
+    pub value: Zeroizing<String>,
+    /// The SHA-256 digest of the raw value if it is not valid UTF-8, which is compared instead
+    /// of the value, as that is decoded lossily for showing it, see [`undecodable_digest`]
+    pub undecodable: Option<String>,
     pub kind: ValueType,
+    /// Whether values that only differ in their case are the same
+    pub ignore_case: bool,
+    /// How the value is normalized before it is compared, see `DiffOptions::field_rules`
+    pub normalizers: Vec<Normalizer>,
+    /// Whether the field holds an OTP secret, which is masked like a password
+    pub otp: bool,
     pub use_verbose: bool,
     pub mask_passwords: bool,
+    /// Whether the value is masked regardless of how it is stored, see `DiffOptions::mask_fields`
+    pub masked: bool,
+    /// How the value is shown if it is hidden
+    pub mask: Mask,
+    /// The number of characters of the value for `Mask::show_length`, as hidden values are only
+    /// kept as a digest
+    pub length: usize,
+    /// How the value is shortened in text output
+    pub truncate: Truncate,
+    /// The estimated strength of a password from 0 to 4, see `strength::score`, if it is shown
+    pub strength: Option<u8>,
+    /// Whether the value is shown with ASCII characters only, see `DiffOptions::ascii`
+    pub ascii: bool,
+}
+
+impl Field {
+    /// A field for an attribute that is not stored as a field, like the icon of a group or an
+    /// entry, with the given value
+    pub(crate) fn attribute(name: &str, value: &str, options: &DiffOptions) -> Field {
+        let mut field = Field {
+            name: name.to_owned(),
+            value: Default::default(),
+            undecodable: None,
+            kind: ValueType::Unprotected,
+            ignore_case: options.ignores_case_of(name),
+            normalizers: options.normalizers_of(name),
+            otp: false,
+            use_verbose: options.use_verbose,
+            mask_passwords: options.mask_passwords,
+            masked: options.masks_field(name),
+            mask: options.mask.clone(),
+            length: value.chars().count(),
+            truncate: options.truncate,
+            strength: None,
+            ascii: options.ascii,
+        };
+        field.value = field.keep(value.as_bytes());
+        field
+    }
+
+    /// Whether the value is masked in all output, so it is only compared
+    pub fn is_hidden(&self) -> bool {
+        match (self.mask_passwords, self.kind) {
+            _ if self.masked => true,
+            (true, ValueType::Protected) => true,
+            (true, _) => self.otp,
+            (false, _) => false,
+        }
+    }
+
+    /// The value to keep of the field from the raw `value` in the database. The value of hidden
+    /// fields is replaced by its SHA-256 digest, so their plaintext is not copied out of the
+    /// database. Values compared regardless of their case are case folded before, and all are
+    /// normalized before. Hidden values that are not valid UTF-8 are digested as they are.
+    pub fn keep(&self, value: &[u8]) -> Zeroizing<String> {
+        if !self.is_hidden() {
+            return Zeroizing::new(String::from_utf8_lossy(value).into_owned());
+        }
+        let value = match std::str::from_utf8(value) {
+            Ok(value) => Zeroizing::new(normalize(value, &self.normalizers).into_owned()),
+            Err(_) => return hex_digest(value),
+        };
+        if self.ignore_case {
+            hex_digest(Zeroizing::new(caseless::default_case_fold_str(&value)).as_bytes())
+        } else {
+            hex_digest(value.as_bytes())
+        }
+    }
+
+    /// The value as it is compared, normalized like `normalizers` tell. Hidden values were
+    /// normalized before they were digested.
+    pub(crate) fn compared_value(&self) -> Cow<'_, str> {
+        if let Some(digest) = &self.undecodable {
+            Cow::Borrowed(digest.as_str())
+        } else if self.is_hidden() {
+            Cow::Borrowed(self.value.as_str())
+        } else {
+            normalize(&self.value, &self.normalizers)
+        }
+    }
+
+    /// The value as it should be shown to the user, masked if requested
+    pub fn display_value(&self) -> Cow<'_, str> {
+        if self.is_hidden() {
+            self.mask.apply(self.length)
+        } else {
+            Cow::Borrowed(self.value.as_str())
+        }
+    }
+
+    /// The name the field is shown with, like `Field 'Notes'`
+    fn display_name(&self) -> String {
+        match (self.use_verbose, self.otp) {
+            (true, true) => format!("OTP secret '{}'", self.name),
+            (true, false) => format!("Field '{}'", self.name),
+            (false, true) => format!("OTP secret ({})", self.name),
+            (false, false) => self.name.clone(),
+        }
+    }
+
+    /// The displayed text before the value, the displayed value and the text after it
+    fn display_parts(&self) -> (String, Zeroizing<String>, &'static str) {
+        let tail = if self.use_verbose { "'" } else { "" };
+        let head = format!("{} = {}", self.display_name(), tail);
+        let glyphs = Glyphs::new(self.ascii);
+        let value = Zeroizing::new(self.truncate.apply(&self.display_value(), glyphs));
+        (head, value, tail)
+    }
 }
 
 impl Diff for Field {
     fn diff<'a>(&'a self, other: &'a Self) -> DiffResult<'a, Self> {
-        if self.value == other.value {
+        let (value, other_value) = (self.compared_value(), other.compared_value());
+        let same_value = if self.ignore_case {
+            caseless::default_caseless_match_str(&value, &other_value)
+        } else {
+            value == other_value
+        };
+        if same_value {
             DiffResult::Identical {
                 left: self,
                 right: other,
@@ -34,26 +278,234 @@ impl Diff for Field {
 
 impl std::fmt::Display for Field {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        if self.use_verbose {
-            write!(
-                f,
-                "Field '{}' = '{}'",
-                self.name,
-                match (self.mask_passwords, self.kind) {
-                    (true, ValueType::Protected) => "***".to_owned(),
-                    _ => self.value.to_owned(),
-                }
-            )
-        } else {
-            write!(
-                f,
-                "{} = {}",
-                self.name,
-                match (self.mask_passwords, self.kind) {
-                    (true, ValueType::Protected) => "***".to_owned(),
-                    _ => self.value.to_owned(),
-                }
-            )
+        let (head, value, tail) = self.display_parts();
+        write!(f, "{}{}{}", head, *value, tail)
+    }
+}
+
+impl DiffItem for Field {
+    fn item_kind(&self) -> ItemKind {
+        ItemKind::Field
+    }
+
+    fn item_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn item_value(&self) -> Option<String> {
+        Some(self.display_value().into_owned())
+    }
+
+    fn value_parts(&self) -> Option<(String, String, String)> {
+        if self.is_hidden() || self.kind == ValueType::Binary {
+            return None;
         }
+        let (head, value, tail) = self.display_parts();
+        Some((head, value.to_string(), tail.to_owned()))
+    }
+
+    fn value_lines(&self) -> Option<(String, Vec<String>)> {
+        if self.is_hidden() || self.kind == ValueType::Binary {
+            return None;
+        }
+        let lines = self.value.replace("\r\n", "\n");
+        let lines = lines
+            .split(['\n', '\r'])
+            .map(|line| self.truncate.apply(line, Glyphs::new(self.ascii)));
+        Some((self.display_name(), lines.collect()))
+    }
+
+    /// Tells how the strength of a password changed, like `(strength 3→1, weaker!)`
+    fn change_note(&self, new: &Self) -> Option<String> {
+        let (old, new) = (self.strength?, new.strength?);
+        let change = match old.cmp(&new) {
+            Ordering::Greater => ", weaker!",
+            Ordering::Less => ", stronger",
+            Ordering::Equal => "",
+        };
+        let arrow = Glyphs::new(self.ascii).arrow;
+        Some(format!("(strength {}{}{}{})", old, arrow, new, change))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn otp_secret(mask_passwords: bool) -> Field {
+        Field {
+            name: "otp".to_owned(),
+            value: "otpauth://totp/Example?secret=JBSWY3DPEHPK3PXP"
+                .to_owned()
+                .into(),
+            undecodable: None,
+            kind: ValueType::Unprotected,
+            ignore_case: false,
+            normalizers: Vec::new(),
+            otp: is_otp_field("otp"),
+            use_verbose: false,
+            mask_passwords,
+            masked: false,
+            mask: Mask::default(),
+            length: 0,
+            truncate: Truncate::Collapse,
+            strength: None,
+            ascii: false,
+        }
+    }
+
+    #[test]
+    fn recognizes_otp_fields() {
+        assert!(is_otp_field("TOTP Seed"));
+        assert!(is_otp_field("TimeOtp-Secret-Base32"));
+        assert!(!is_otp_field("TOTP Settings"));
+        assert!(!is_otp_field("Password"));
+    }
+
+    #[test]
+    fn masks_otp_secrets_like_passwords() {
+        assert_eq!("OTP secret (otp) = ***", otp_secret(true).to_string());
+        assert!(otp_secret(false).to_string().contains("JBSWY3DPEHPK3PXP"));
+    }
+
+    #[test]
+    fn masks_masked_fields_regardless_of_their_protection() {
+        let url = Field {
+            name: "URL".to_owned(),
+            value: "https://intranet.example.com".to_owned().into(),
+            otp: false,
+            masked: true,
+            ..otp_secret(false)
+        };
+        assert_eq!("URL = ***", url.to_string());
+        assert_eq!(Some(MASK.to_owned()), url.item_value());
+        assert_eq!(None, url.value_parts());
+    }
+
+    #[test]
+    fn pads_the_mask_to_the_length_of_the_value() {
+        let field = |text: &str, length| Field {
+            mask: Mask {
+                text: text.to_owned(),
+                show_length: true,
+            },
+            length,
+            ..otp_secret(true)
+        };
+        assert_eq!("OTP secret (otp) = ********", field("***", 8).to_string());
+        assert_eq!("OTP secret (otp) = ***", field("***", 2).to_string());
+        assert_eq!(
+            Some("<hidden>>>>".to_owned()),
+            field("<hidden>", 11).item_value()
+        );
+    }
+
+    #[test]
+    fn keeps_only_a_digest_of_hidden_values() {
+        let hidden = otp_secret(true);
+        let value = hidden.keep(b"JBSWY3DPEHPK3PXP");
+        assert_eq!(64, value.len());
+        assert!(!value.contains("JBSWY3DPEHPK3PXP"));
+        assert_eq!(value, hidden.keep(b"JBSWY3DPEHPK3PXP"));
+        assert_ne!(value, hidden.keep(b"jbswy3dpehpk3pxp"));
+
+        let ignore_case = Field {
+            ignore_case: true,
+            ..hidden
+        };
+        assert_eq!(ignore_case.keep(b"Secret"), ignore_case.keep(b"sECRET"));
+        assert_eq!(
+            "JBSWY3DPEHPK3PXP",
+            *otp_secret(false).keep(b"JBSWY3DPEHPK3PXP")
+        );
+    }
+
+    #[test]
+    fn compares_values_that_are_not_utf8_by_their_bytes() {
+        let raw = |value: &[u8], mask_passwords| {
+            let mut field = Field {
+                kind: ValueType::Protected,
+                ..otp_secret(mask_passwords)
+            };
+            field.value = field.keep(value);
+            field.undecodable = undecodable_digest(value);
+            field
+        };
+        for mask_passwords in [false, true] {
+            let (first, second) = (raw(b"a\xff", mask_passwords), raw(b"a\xfe", mask_passwords));
+            let same = raw(b"a\xff", mask_passwords);
+            assert!(matches!(first.diff(&second), DiffResult::Changed { .. }));
+            assert!(matches!(first.diff(&same), DiffResult::Identical { .. }));
+        }
+        assert_eq!("a\u{fffd}", *raw(b"a\xff", false).value);
+        assert_eq!(None, undecodable_digest("a\u{fffd}".as_bytes()));
+    }
+
+    #[test]
+    fn counts_the_length_of_values_as_they_are_decoded() {
+        for value in [&b"p\xc3\xa4ss"[..], b"a\xff\xfeb", b"\xe2\x82", b""] {
+            let decoded = String::from_utf8_lossy(value).chars().count();
+            assert_eq!(decoded, lossy_length(value));
+        }
+    }
+
+    #[test]
+    fn notes_how_the_strength_of_passwords_changed() {
+        let password = |strength| Field {
+            name: "Password".to_owned(),
+            kind: ValueType::Protected,
+            otp: false,
+            strength,
+            ..otp_secret(true)
+        };
+        let note = |old, new| password(old).change_note(&password(new));
+        assert_eq!(
+            Some("(strength 3→1, weaker!)".to_owned()),
+            note(Some(3), Some(1))
+        );
+        assert_eq!(
+            Some("(strength 1→4, stronger)".to_owned()),
+            note(Some(1), Some(4))
+        );
+        assert_eq!(Some("(strength 2→2)".to_owned()), note(Some(2), Some(2)));
+        assert_eq!(None, note(None, None));
+    }
+
+    #[test]
+    fn truncates_only_the_shown_value() {
+        let notes = Field {
+            name: "Notes".to_owned(),
+            value: "first line\r\nsecond line".to_owned().into(),
+            otp: false,
+            ..otp_secret(false)
+        };
+        assert_eq!("Notes = first line↵second line", notes.to_string());
+        let shortened = Field {
+            truncate: Truncate::Length(8),
+            ..notes.clone()
+        };
+        assert_eq!("Notes = first li…", shortened.to_string());
+        let ascii = Field {
+            ascii: true,
+            ..shortened.clone()
+        };
+        assert_eq!("Notes = first li...", ascii.to_string());
+        assert_eq!(
+            "Notes = first line\\nsecond line",
+            Field {
+                truncate: Truncate::Collapse,
+                ..ascii
+            }
+            .to_string()
+        );
+        assert_eq!(
+            Some("first line\r\nsecond line".to_owned()),
+            shortened.item_value()
+        );
+        let full = Field {
+            truncate: Truncate::Off,
+            ..notes
+        };
+        assert_eq!("Notes = first line\nsecond line", full.to_string());
     }
 }
@@ -0,0 +1,168 @@
+//! Pairing of entries whose titles changed slightly, for databases that do not share UUIDs
+
+use crate::diff::entry::Entry;
+use crate::diff::field::Field;
+use crate::diff::group::Group;
+
+/// How similar entries must be to be paired up by `match_fuzzy`
+#[derive(Clone, Debug, PartialEq)]
+pub struct FuzzyMatch {
+    /// The least similarity of the titles, and of the other fields on average, from 0 (anything)
+    /// to 1 (the same)
+    pub threshold: f64,
+    /// Names of the fields besides the title that entries must be similar in
+    pub fields: Vec<String>,
+}
+
+impl FuzzyMatch {
+    /// How similar two entries are, which is the similarity of their titles if the other fields
+    /// are similar enough, or `None` if the entries are not similar enough. Hidden fields, which
+    /// are only kept as digests, are either the same or entirely different.
+    fn score(&self, a: &Entry, b: &Entry) -> Option<f64> {
+        let title = similarity(a.title(), b.title());
+        let field = |name: &String| {
+            let (field_a, field_b) = (a.fields.get(name), b.fields.get(name));
+            match (field_a, field_b) {
+                (None, None) => None,
+                // Hidden values are kept as digests, which are only the same or not
+                (Some(x), Some(y)) if x.is_hidden() || y.is_hidden() => {
+                    Some(if x.value == y.value { 1.0 } else { 0.0 })
+                }
+                (Some(hidden), None) | (None, Some(hidden)) if hidden.is_hidden() => Some(0.0),
+                (x, y) => Some(similarity(value(x), value(y))),
+            }
+        };
+        let fields: Vec<f64> = self.fields.iter().filter_map(field).collect();
+        let fields = match fields.len() {
+            0 => 1.0,
+            n => fields.iter().sum::<f64>() / n as f64,
+        };
+        Some(title).filter(|_| title >= self.threshold && fields >= self.threshold)
+    }
+}
+
+/// Pairs up entries that are only in one of two paired groups, like "AWS prod" and "AWS
+/// Production", if they are similar enough, so diffing `a` with `b` shows them as renamed instead
+/// of as removed and added. The most similar entries are paired first. Only entries whose title
+/// is unique in their group and groups that exist in both databases are considered.
+pub fn match_fuzzy(a: &Group, b: &mut Group, options: &FuzzyMatch) {
+    let only_in = |group: &Group, other: &Group| -> Vec<String> {
+        let mut keys: Vec<String> = group
+            .entries
+            .iter()
+            .filter(|(key, entries)| entries.len() == 1 && !other.entries.contains_key(*key))
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.sort();
+        keys
+    };
+    let (removed, added) = (only_in(a, b), only_in(b, a));
+
+    let mut candidates = Vec::new();
+    for r in &removed {
+        for d in &added {
+            if let Some(score) = options.score(&a.entries[r][0], &b.entries[d][0]) {
+                candidates.push((score, r, d));
+            }
+        }
+    }
+    // Stable, so equally similar entries are paired in the order of their keys
+    candidates.sort_by(|(x, _, _), (y, _, _)| y.total_cmp(x));
+    let mut paired: Vec<(&String, &String)> = Vec::new();
+    for (_, r, d) in candidates {
+        if !paired.iter().any(|&(pr, pd)| pr == r || pd == d) {
+            paired.push((r, d));
+        }
+    }
+    for (r, d) in paired {
+        b.rekey_entries(d, r);
+    }
+
+    for (key, groups_a) in &a.child_groups {
+        if let Some(groups_b) = b.child_groups.get_mut(key) {
+            for (group_a, group_b) in groups_a.iter().zip(groups_b.iter_mut()) {
+                match_fuzzy(group_a, group_b, options);
+            }
+        }
+    }
+}
+
+/// The value of a field, or nothing if the entry does not have it
+fn value(field: Option<&Field>) -> &str {
+    field.map_or("", |f| f.value.as_str())
+}
+
+/// How similar two texts are regardless of their case, from 0 to 1, based on the Levenshtein
+/// distance: the share of characters that stay the same
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = caseless::default_case_fold_str(a).chars().collect();
+    let b: Vec<char> = caseless::default_case_fold_str(b).chars().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    1.0 - levenshtein(&a, &b) as f64 / longest as f64
+}
+
+/// The number of characters that need to be inserted, removed or replaced to turn `a` into `b`
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(ca != cb);
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::diff::attachment::Attachments;
+    use crate::diff::DiffOptions;
+
+    #[test]
+    fn rates_the_similarity_of_texts() {
+        assert_eq!(1.0, similarity("AWS prod", "aws PROD"));
+        assert_eq!(0.0, similarity("abc", "xyz"));
+        assert_eq!(0.75, similarity("Mail", "Main"));
+        assert!(similarity("AWS prod", "AWS Production") > 0.5);
+        assert_eq!(1.0, similarity("", ""));
+    }
+
+    #[test]
+    fn hidden_fields_are_only_the_same_or_not() {
+        let entry = |title: &str, password: &str| {
+            let mut entry = keepass::db::Entry::new();
+            entry.fields.insert(
+                "Title".to_owned(),
+                keepass::db::Value::Unprotected(title.to_owned()),
+            );
+            entry.fields.insert(
+                "Password".to_owned(),
+                keepass::db::Value::Protected(password.as_bytes().into()),
+            );
+            let options = DiffOptions {
+                mask_passwords: true,
+                ..Default::default()
+            };
+            Entry::from_keepass(&entry, &options, &Attachments::new())
+        };
+        let options = FuzzyMatch {
+            threshold: 0.5,
+            fields: vec!["Password".to_owned()],
+        };
+        let prod = entry("AWS prod", "secret");
+
+        assert!(options
+            .score(&prod, &entry("AWS Production", "secret"))
+            .is_some());
+        assert_eq!(
+            None,
+            options.score(&prod, &entry("AWS Production", "secreT"))
+        );
+    }
+}
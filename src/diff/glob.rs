@@ -0,0 +1,66 @@
+//! Glob patterns for the paths of groups, as given to --include-group and --exclude-group
+
+/// Whether a glob pattern matches the path of a group, given as the names of the groups leading
+/// to it below the root. Names in the pattern are separated by `/`, where `*` matches any part of
+/// a name, `?` a single character and `**` any number of nested groups.
+pub fn matches(pattern: &str, path: &[String]) -> bool {
+    let segments: Vec<&str> = pattern.split('/').filter(|s| !s.is_empty()).collect();
+    matches_segments(&segments, path)
+}
+
+/// Whether any of the glob patterns matches the path of a group, see [`matches`]
+pub fn matches_any(patterns: &[String], path: &[String]) -> bool {
+    patterns.iter().any(|pattern| matches(pattern, path))
+}
+
+fn matches_segments(pattern: &[&str], path: &[String]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| matches_segments(rest, &path[skip..])),
+        Some((segment, rest)) => match path.split_first() {
+            Some((name, path)) => {
+                let segment: Vec<char> = segment.chars().collect();
+                let name: Vec<char> = name.chars().collect();
+                matches_name(&segment, &name) && matches_segments(rest, path)
+            }
+            None => false,
+        },
+    }
+}
+
+fn matches_name(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| matches_name(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && matches_name(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && matches_name(rest, &name[1..]),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn path(path: &str) -> Vec<String> {
+        path.split('/').map(|name| name.to_owned()).collect()
+    }
+
+    #[test]
+    fn matches_names_and_parts_of_names() {
+        assert!(matches("Imported", &path("Imported")));
+        assert!(!matches("Imported", &path("Work/Imported")));
+        assert!(matches("Work/*", &path("Work/Email")));
+        assert!(!matches("Work/*", &path("Work")));
+        assert!(!matches("Work/*", &path("Work/Email/Old")));
+        assert!(matches("Im?orted 20*", &path("Imported 2023")));
+    }
+
+    #[test]
+    fn matches_any_number_of_groups_with_two_stars() {
+        assert!(matches("**/Imported", &path("Imported")));
+        assert!(matches("**/Imported", &path("Work/Old/Imported")));
+        assert!(matches("Work/**", &path("Work")));
+        assert!(matches("Work/**", &path("Work/Email/Old")));
+        assert!(!matches("Work/**/Old", &path("Private/Old")));
+    }
+}
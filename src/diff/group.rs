@@ -1,55 +1,358 @@
+use crate::diff::attachment::Attachments;
 use crate::diff::entry::Entry;
-use crate::diff::{Diff, DiffResult, DiffResultFormat};
+use crate::diff::field::Field;
+use crate::diff::moves::resolve_moves;
+use crate::diff::references::{self, Unresolved};
+use crate::diff::times::Times;
+use crate::diff::{
+    ChangeKind, Diff, DiffItem, DiffOptions, DiffResult, DiffResultFormat, ItemKind, MatchBy,
+    Pairing, SortBy,
+};
+use crate::security::Security;
 
 use std::collections::HashMap;
 
 /// Corresponds to a sorted Vec of KdbxEntry objects that can be diffed
 #[derive(Debug)]
 pub struct Group {
-    name: String,
-    child_groups: HashMap<String, Vec<Group>>,
-    entries: HashMap<String, Vec<Entry>>,
+    pub(crate) name: String,
+    /// Notes, icon and expiry of the group itself, compared like the fields of an entry
+    pub(crate) attributes: HashMap<String, Field>,
+    pub(crate) child_groups: HashMap<String, Vec<Group>>,
+    pub(crate) entries: HashMap<String, Vec<Entry>>,
+    /// Keys of `child_groups` and `entries` in the order of the database
+    pub(crate) group_keys: Vec<String>,
+    pub(crate) entry_keys: Vec<String>,
     use_verbose: bool,
+    sort_by: SortBy,
+    /// Whether the entries of the groups below were moved into this one, see `Group::flatten`
+    flattened: bool,
+    /// Whether the group is nested beyond `DiffOptions::max_depth`, so only whether it changed is
+    /// reported
+    opaque: bool,
+    /// See `DiffOptions::tags`
+    tags: Vec<String>,
+    /// See `DiffOptions::changed_passwords_only`
+    changed_passwords_only: bool,
+    /// The format and encryption of the database, only known for the root group of a database
+    /// that is no XML export
+    pub(crate) security: Option<Security>,
+    /// Field references that could not be resolved, only known for the root group of a database
+    /// compared with `follow_references`
+    unresolved_references: Vec<Unresolved>,
 }
 
 impl Group {
     /// Create an entries list from a keepass::Group
     pub fn from_keepass(
         group: &keepass::db::Group,
-        use_verbose: bool,
-        mask_passwords: bool,
+        options: &DiffOptions,
+        attachments: &Attachments,
+    ) -> Self {
+        if !options.follow_references {
+            return Group::from_keepass_at(group, options, attachments, 0);
+        }
+        let mut resolved = group.clone();
+        let unresolved_references = references::resolve(&mut resolved);
+        Group {
+            unresolved_references,
+            ..Group::from_keepass_at(&resolved, options, attachments, 0)
+        }
+    }
+
+    /// Create an entries list from a keepass::Group nested `depth` levels below the root
+    fn from_keepass_at(
+        group: &keepass::db::Group,
+        options: &DiffOptions,
+        attachments: &Attachments,
+        depth: usize,
     ) -> Self {
         let name = group.name.to_owned();
 
         let mut child_groups: HashMap<String, Vec<Group>> = HashMap::new();
+        let mut group_keys = Vec::new();
         for node in group.children.iter() {
             if let keepass::db::Node::Group(g) = node {
+                if !child_groups.contains_key(&g.name) {
+                    group_keys.push(g.name.clone());
+                }
                 child_groups
                     .entry(g.name.clone())
                     .or_default()
-                    .push(Group::from_keepass(g, use_verbose, mask_passwords))
+                    .push(Group::from_keepass_at(g, options, attachments, depth + 1))
             }
         }
 
         let mut entries: HashMap<String, Vec<Entry>> = HashMap::new();
+        let mut entry_keys = Vec::new();
         for node in group.children.iter() {
             if let keepass::db::Node::Entry(e) = node {
+                let key = match_key(e, options);
+                if !entries.contains_key(&key) {
+                    entry_keys.push(key.clone());
+                }
                 entries
-                    .entry(e.get("Title").unwrap_or_default().to_owned())
+                    .entry(key)
                     .or_default()
-                    .push(Entry::from_keepass(e, use_verbose, mask_passwords))
+                    .push(Entry::from_keepass(e, options, attachments))
+            }
+        }
+
+        // Entries with the same key are paired up in order, so order them consistently on both sides
+        if options.sort_by == SortBy::Username {
+            for bucket in entries.values_mut() {
+                bucket.sort_by_cached_key(|e| e.sort_key(SortBy::Username));
             }
         }
 
         Group {
             name,
+            attributes: group_attributes(group, options),
             child_groups,
             entries,
-            use_verbose,
+            group_keys,
+            entry_keys,
+            use_verbose: options.use_verbose,
+            sort_by: options.sort_by,
+            flattened: false,
+            opaque: options.max_depth.is_some_and(|max_depth| depth > max_depth),
+            tags: options.tags.clone(),
+            changed_passwords_only: options.changed_passwords_only,
+            security: None,
+            unresolved_references: Vec::new(),
+        }
+    }
+
+    /// The group with its attributes but none of its groups and entries, like the root of an
+    /// empty database that stands in for a missing one
+    pub fn without_children(&self) -> Group {
+        Group {
+            name: self.name.clone(),
+            attributes: self.attributes.clone(),
+            child_groups: HashMap::new(),
+            entries: HashMap::new(),
+            group_keys: Vec::new(),
+            entry_keys: Vec::new(),
+            use_verbose: self.use_verbose,
+            sort_by: self.sort_by,
+            flattened: self.flattened,
+            opaque: self.opaque,
+            tags: self.tags.clone(),
+            changed_passwords_only: self.changed_passwords_only,
+            security: None,
+            unresolved_references: Vec::new(),
+        }
+    }
+
+    /// Moves the entries of all groups below this one into it and leaves out the groups, so
+    /// entries are paired up by their key wherever they are, like when one database is nested and
+    /// the other one flat. Entries with the same key follow each other in the order of their
+    /// groups, and each one keeps the names of the groups it was in as its `origin`.
+    pub fn flatten(&mut self) {
+        self.flattened = true;
+        let keys = std::mem::take(&mut self.group_keys);
+        for key in keys {
+            for mut group in self.child_groups.remove(&key).into_iter().flatten() {
+                group.flatten();
+                let Group {
+                    name,
+                    mut entries,
+                    entry_keys,
+                    ..
+                } = group;
+                for key in entry_keys {
+                    if !self.entries.contains_key(&key) {
+                        self.entry_keys.push(key.clone());
+                    }
+                    let mut moved = entries.remove(&key).unwrap_or_default();
+                    for entry in &mut moved {
+                        entry.origin.insert(0, name.clone());
+                    }
+                    self.entries.entry(key).or_default().extend(moved);
+                }
+            }
+        }
+    }
+
+    /// The name of the group. The roots of two databases are compared with each other whatever
+    /// their names are.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The format and encryption of the database the group is the root of, if it was opened with
+    /// [`crate::open_database`] and is no XML export
+    pub fn security(&self) -> Option<&Security> {
+        self.security.as_ref()
+    }
+
+    /// The field references of the group's entries that could not be resolved, if it is the root
+    /// of a database compared with `follow_references`
+    pub fn unresolved_references(&self) -> &[Unresolved] {
+        &self.unresolved_references
+    }
+
+    /// Whether the group contains a group or an entry at the given path below it, made of the
+    /// names of the groups leading to it and its name or title
+    pub fn contains_path(&self, path: &[String]) -> bool {
+        match path {
+            [] => true,
+            [name] if self.entries.values().flatten().any(|e| e.title() == name) => true,
+            [name, rest @ ..] => self
+                .child_groups
+                .get(name)
+                .into_iter()
+                .flatten()
+                .any(|g| g.contains_path(rest)),
+        }
+    }
+
+    /// Whether the group or one of its subgroups contains an entry with one of the given tags
+    fn contains_tagged_entry(&self, tags: &[String]) -> bool {
+        self.entries.values().flatten().any(|e| e.has_any_tag(tags))
+            || self
+                .child_groups
+                .values()
+                .flatten()
+                .any(|g| g.contains_tagged_entry(tags))
+    }
+
+    /// Files the entries with the key `from` under the key `to`, so they are paired with the
+    /// entries of the other group that have that key
+    pub(crate) fn rekey_entries(&mut self, from: &str, to: &str) {
+        if let Some(entries) = self.entries.remove(from) {
+            self.entries
+                .entry(to.to_owned())
+                .or_default()
+                .extend(entries);
+            for key in self.entry_keys.iter_mut().filter(|key| *key == from) {
+                to.clone_into(key);
+            }
         }
     }
 }
 
+/// The attributes of a group that can change besides its name: its notes, icon and when it
+/// expires, if it does
+fn group_attributes(group: &keepass::db::Group, options: &DiffOptions) -> HashMap<String, Field> {
+    let icon = describe_icon(group.icon_id, &group.custom_icon_uuid);
+    let expires = Times::from_keepass(&group.times, options).describe_expiry();
+    let notes = group.notes.clone().filter(|notes| !notes.is_empty());
+    vec![("Notes", notes), ("Icon", icon), ("Expires", expires)]
+        .into_iter()
+        .filter(|(name, _)| !options.ignores_attribute(name))
+        .filter_map(|(name, value)| {
+            let value = value?;
+            Some((name.to_owned(), Field::attribute(name, &value, options)))
+        })
+        .collect()
+}
+
+/// The icon of a group or entry, like `48` for one of the built-in icons or `custom <uuid>`
+/// for an icon stored in the database, which is compared by its UUID rather than its image
+pub(crate) fn describe_icon(icon_id: Option<usize>, custom: &Option<uuid::Uuid>) -> Option<String> {
+    match (custom, icon_id) {
+        (Some(uuid), _) => Some(format!("custom {}", uuid)),
+        (None, icon_id) => icon_id.map(|id| id.to_string()),
+    }
+}
+
+/// Leaves out the entries that carry none of `tags` in either database, and the groups if none of
+/// their entries carries one of them in either database
+fn filter_tagged<'a>(
+    groups: Vec<DiffResult<'a, Group>>,
+    entries: Vec<DiffResult<'a, Entry>>,
+    tags: &[String],
+) -> (Vec<DiffResult<'a, Group>>, Vec<DiffResult<'a, Entry>>) {
+    let groups = groups
+        .into_iter()
+        .filter(|dr| match dr {
+            DiffResult::OnlyLeft { left: group } | DiffResult::OnlyRight { right: group } => {
+                group.contains_tagged_entry(tags)
+            }
+            DiffResult::Identical { left, right }
+            | DiffResult::Changed { left, right }
+            | DiffResult::InnerDifferences { left, right, .. }
+            | DiffResult::Moved { left, right, .. } => {
+                left.contains_tagged_entry(tags) || right.contains_tagged_entry(tags)
+            }
+        })
+        .collect();
+    let entries = entries
+        .into_iter()
+        .filter(|dr| match dr {
+            DiffResult::OnlyLeft { left: entry } | DiffResult::OnlyRight { right: entry } => {
+                entry.has_any_tag(tags)
+            }
+            DiffResult::Identical { left, right }
+            | DiffResult::Changed { left, right }
+            | DiffResult::InnerDifferences { left, right, .. }
+            | DiffResult::Moved { left, right, .. } => {
+                left.has_any_tag(tags) || right.has_any_tag(tags)
+            }
+        })
+        .collect();
+    (groups, entries)
+}
+
+/// Leaves out the groups and entries that only exist in one database, and the entries whose
+/// password is not all that changed
+fn filter_changed_passwords<'a>(
+    groups: Vec<DiffResult<'a, Group>>,
+    entries: Vec<DiffResult<'a, Entry>>,
+) -> (Vec<DiffResult<'a, Group>>, Vec<DiffResult<'a, Entry>>) {
+    let groups = groups
+        .into_iter()
+        .filter(|dr| {
+            !matches!(
+                dr,
+                DiffResult::OnlyLeft { .. } | DiffResult::OnlyRight { .. }
+            )
+        })
+        .collect();
+    let entries = entries
+        .into_iter()
+        .filter(|dr| match dr {
+            DiffResult::Identical { .. } => true,
+            DiffResult::InnerDifferences { left, right, .. } => left.only_password_differs(right),
+            _ => false,
+        })
+        .collect();
+    (groups, entries)
+}
+
+/// The key under which an entry is paired with the entry of the other database
+fn match_key(entry: &keepass::db::Entry, options: &DiffOptions) -> String {
+    let field = |name| {
+        let value = entry.get(name).unwrap_or_default();
+        if options.ignores_case_of(name) {
+            caseless::default_case_fold_str(value)
+        } else {
+            value.to_owned()
+        }
+    };
+    let title = field("Title");
+    // Untitled entries would all be paired up by the same empty title, so their UUID is used
+    if title.is_empty() && !entry.uuid.is_nil() {
+        return format!("uuid:{}", entry.uuid);
+    }
+    // Titles may contain any printable character, so the second field is set apart by a NUL
+    match options.match_by {
+        MatchBy::Uuid if !entry.uuid.is_nil() => format!("uuid:{}", entry.uuid),
+        MatchBy::Uuid => format!("title:{}", title),
+        MatchBy::Title => title,
+        MatchBy::TitleUsername => format!("{}\0{}", title, field("UserName")),
+        MatchBy::TitleUrl => format!("{}\0{}", title, field("URL")),
+    }
+}
+
+/// The keys of the first database in its order, followed by the keys only in the second database
+fn database_order<'a>(a: &'a [String], b: &'a [String]) -> Vec<&'a String> {
+    let mut keys: Vec<&String> = a.iter().collect();
+    keys.extend(b.iter().filter(|key| !a.contains(key)));
+    keys
+}
+
 impl std::fmt::Display for Group {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if self.use_verbose {
@@ -60,25 +363,188 @@ impl std::fmt::Display for Group {
     }
 }
 
+impl DiffItem for Group {
+    fn item_kind(&self) -> ItemKind {
+        ItemKind::Group
+    }
+
+    fn item_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn item_value(&self) -> Option<String> {
+        if self.opaque {
+            let (groups, entries) = self.nested_nodes();
+            Some(format!("{} groups, {} entries", groups, entries))
+        } else {
+            None
+        }
+    }
+
+    /// Entries left out by `tags` are not counted, nor are groups without any tagged entry
+    fn nested_nodes(&self) -> (usize, usize) {
+        let tagged = |entry: &&Entry| self.tags.is_empty() || entry.has_any_tag(&self.tags);
+        let mut groups = 0;
+        let mut entries = self.entries.values().flatten().filter(tagged).count();
+        for group in self.child_groups.values().flatten() {
+            if !self.tags.is_empty() && !group.contains_tagged_entry(&self.tags) {
+                continue;
+            }
+            let (nested_groups, nested_entries) = group.nested_nodes();
+            groups += 1 + nested_groups;
+            entries += nested_entries;
+        }
+        (groups, entries)
+    }
+
+    fn nested_entries(&self) -> Vec<(Vec<String>, String)> {
+        let mut nested = Vec::new();
+        let sort_key = |item: &dyn DiffItem| item.sort_key(self.sort_by);
+        let mut groups: Vec<&Group> = self.child_groups.values().flatten().collect();
+        groups.sort_by_cached_key(|g| sort_key(*g));
+        for group in groups {
+            for (mut path, name) in group.nested_entries() {
+                path.insert(0, self.name.clone());
+                nested.push((path, name));
+            }
+        }
+        let mut entries: Vec<&Entry> = self.entries.values().flatten().collect();
+        entries.sort_by_cached_key(|e| sort_key(*e));
+        for entry in entries {
+            nested.push((vec![self.name.clone()], entry.item_name()));
+        }
+        nested
+    }
+
+    fn nested_results(&self, kind: Option<ChangeKind>) -> Vec<Box<dyn DiffResultFormat + '_>> {
+        fn result<T>(item: &T, kind: Option<ChangeKind>) -> DiffResult<'_, T> {
+            match kind {
+                Some(ChangeKind::Added) => DiffResult::OnlyRight { right: item },
+                Some(ChangeKind::Removed) => DiffResult::OnlyLeft { left: item },
+                _ => DiffResult::Identical {
+                    left: item,
+                    right: item,
+                },
+            }
+        }
+        // Ordered like the differences of the group, see `diff`
+        let rank = |is_entry: bool| is_entry && self.sort_by != SortBy::Path;
+        let mut sorted: Vec<((bool, String), Box<dyn DiffResultFormat>)> = Vec::new();
+        let groups = self
+            .group_keys
+            .iter()
+            .filter_map(|key| self.child_groups.get(key))
+            .flatten();
+        for group in groups {
+            let key = (rank(false), group.sort_key(self.sort_by));
+            sorted.push((key, Box::new(result(group, kind))));
+        }
+        let entries = self
+            .entry_keys
+            .iter()
+            .filter_map(|key| self.entries.get(key))
+            .flatten();
+        for entry in entries {
+            let key = (rank(true), entry.sort_key(self.sort_by));
+            sorted.push((key, Box::new(result(entry, kind))));
+        }
+        if self.sort_by != SortBy::None {
+            sorted.sort_by(|(a, _), (b, _)| a.cmp(b));
+        }
+        sorted.into_iter().map(|(_, result)| result).collect()
+    }
+}
+
 /// Groups can be diffed.
 impl Diff for Group {
     fn diff<'a>(&'a self, other: &'a Group) -> DiffResult<'a, Self> {
-        let (has_differences_groups, acc_groups) =
-            crate::diff::diff_hashmap(&self.child_groups, &other.child_groups);
-
-        let (has_differences_entries, acc_entries) =
-            crate::diff::diff_hashmap(&self.entries, &other.entries);
+        // Entries with the same key come from different groups once flattened, so their order
+        // does not tell which of them belong together
+        let pairing = if self.flattened {
+            Pairing::IdenticalFirst
+        } else {
+            Pairing::ByPosition
+        };
+        let ((has_differences_groups, acc_groups), (_, acc_entries)) =
+            if self.sort_by == SortBy::None {
+                (
+                    crate::diff::diff_hashmap_in_order(
+                        &self.child_groups,
+                        &other.child_groups,
+                        database_order(&self.group_keys, &other.group_keys),
+                        Pairing::ByPosition,
+                    ),
+                    crate::diff::diff_hashmap_in_order(
+                        &self.entries,
+                        &other.entries,
+                        database_order(&self.entry_keys, &other.entry_keys),
+                        pairing,
+                    ),
+                )
+            } else {
+                (
+                    crate::diff::diff_hashmap(
+                        &self.child_groups,
+                        &other.child_groups,
+                        Pairing::ByPosition,
+                    ),
+                    crate::diff::diff_hashmap(&self.entries, &other.entries, pairing),
+                )
+            };
+        let (has_differences_groups, acc_groups, acc_entries) = if self.tags.is_empty() {
+            (has_differences_groups, acc_groups, acc_entries)
+        } else {
+            let (acc_groups, acc_entries) = filter_tagged(acc_groups, acc_entries, &self.tags);
+            let has_differences = acc_groups.iter().any(DiffResult::has_changes);
+            (has_differences, acc_groups, acc_entries)
+        };
+        let (has_differences_groups, acc_groups, acc_entries) = if self.changed_passwords_only {
+            let (acc_groups, acc_entries) = filter_changed_passwords(acc_groups, acc_entries);
+            let has_differences = acc_groups.iter().any(DiffResult::has_changes);
+            (has_differences, acc_groups, acc_entries)
+        } else {
+            (has_differences_groups, acc_groups, acc_entries)
+        };
+        let acc_entries = resolve_moves(acc_entries);
+        let has_differences_entries = acc_entries.iter().any(DiffResult::has_changes);
+        // Only entries are compared when filtering by tags or changed passwords
+        let (has_differences_attributes, acc_attributes) =
+            if self.tags.is_empty() && !self.changed_passwords_only {
+                crate::diff::diff_entry(&self.attributes, &other.attributes)
+            } else {
+                (false, Vec::new())
+            };
+        let has_differences_groups = has_differences_groups || has_differences_attributes;
 
-        if has_differences_groups || has_differences_entries {
-            let mut inner_differences: Vec<Box<dyn DiffResultFormat>> = Vec::new();
+        if self.opaque && (has_differences_groups || has_differences_entries) {
+            DiffResult::Changed {
+                left: self,
+                right: other,
+            }
+        } else if has_differences_groups || has_differences_entries {
+            // Groups come first unless sorted by path, which mixes them with the entries
+            let rank = |is_entry: bool| is_entry && self.sort_by != SortBy::Path;
+            let mut sorted: Vec<((bool, String), Box<dyn DiffResultFormat>)> = Vec::new();
 
             for dr in acc_groups {
-                inner_differences.push(Box::new(dr));
+                let key = (rank(false), dr.item().sort_key(self.sort_by));
+                sorted.push((key, Box::new(dr)));
             }
 
             for dr in acc_entries {
+                let key = (rank(true), dr.item().sort_key(self.sort_by));
+                sorted.push((key, Box::new(dr)));
+            }
+
+            if self.sort_by != SortBy::None {
+                sorted.sort_by(|(a, _), (b, _)| a.cmp(b));
+            }
+            // The attributes of the group itself come before its contents
+            let mut inner_differences: Vec<Box<dyn DiffResultFormat>> = Vec::new();
+            for dr in acc_attributes {
                 inner_differences.push(Box::new(dr));
             }
+            inner_differences.extend(sorted.into_iter().map(|(_, dr)| dr));
 
             DiffResult::InnerDifferences {
                 left: self,
@@ -92,4 +558,14 @@ impl Diff for Group {
             }
         }
     }
+
+    fn differs(&self, other: &Group) -> bool {
+        // Filtered and flattened groups only tell their differences once they are paired up
+        if self.flattened || self.changed_passwords_only || !self.tags.is_empty() {
+            return self.diff(other).has_changes();
+        }
+        crate::diff::differs_hashmap(&self.entries, &other.entries)
+            || crate::diff::differs_hashmap(&self.child_groups, &other.child_groups)
+            || crate::diff::diff_entry(&self.attributes, &other.attributes).0
+    }
 }
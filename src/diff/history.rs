@@ -0,0 +1,163 @@
+use crate::diff::entry::Entry;
+use crate::diff::{Diff, DiffItem, DiffResult, ItemKind};
+
+/// A prior version of an entry, as kept in the history of the entry
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistoryVersion {
+    /// Position of the version in the history, starting at 1 for the oldest version
+    pub number: usize,
+    pub entry: Entry,
+    pub use_verbose: bool,
+}
+
+impl Diff for HistoryVersion {
+    fn diff<'a>(&'a self, other: &'a Self) -> DiffResult<'a, Self> {
+        match self.entry.diff(&other.entry) {
+            DiffResult::InnerDifferences {
+                inner_differences, ..
+            } => DiffResult::InnerDifferences {
+                left: self,
+                right: other,
+                inner_differences,
+            },
+            DiffResult::Identical { .. } => DiffResult::Identical {
+                left: self,
+                right: other,
+            },
+            _ => DiffResult::Changed {
+                left: self,
+                right: other,
+            },
+        }
+    }
+}
+
+/// Diffs the histories of two entries. Versions are paired up by their position, so a version
+/// that exists on one side only shows up as added or removed.
+pub(crate) fn diff_history<'a>(
+    a: &'a [HistoryVersion],
+    b: &'a [HistoryVersion],
+) -> (bool, Vec<DiffResult<'a, HistoryVersion>>) {
+    let mut acc = Vec::new();
+    let mut has_differences = false;
+
+    for index in 0..a.len().max(b.len()) {
+        let dr = match (a.get(index), b.get(index)) {
+            (Some(v_a), Some(v_b)) => v_a.diff(v_b),
+            (Some(v_a), None) => DiffResult::OnlyLeft { left: v_a },
+            (None, Some(v_b)) => DiffResult::OnlyRight { right: v_b },
+            (None, None) => unreachable!("index is below the length of one of the histories"),
+        };
+        has_differences |= dr.has_changes();
+        acc.push(dr);
+    }
+
+    (has_differences, acc)
+}
+
+impl std::fmt::Display for HistoryVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.use_verbose {
+            write!(
+                f,
+                "History version {} '{}'",
+                self.number,
+                self.entry.display_title()
+            )
+        } else {
+            write!(f, "History version {}", self.number)
+        }
+    }
+}
+
+impl DiffItem for HistoryVersion {
+    fn item_kind(&self) -> ItemKind {
+        ItemKind::History
+    }
+
+    fn item_name(&self) -> String {
+        format!("History version {}", self.number)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use diff::attachment::Attachments;
+    use diff::{collect_changes, ChangeKind, DiffOptions};
+    use keepass::db::{History, Value};
+
+    fn keepass_entry_with_history(old_passwords: &[&str]) -> keepass::db::Entry {
+        let mut history = History::default();
+        for password in old_passwords {
+            let mut version = keepass::db::Entry::new();
+            version.fields.insert(
+                "Password".to_owned(),
+                Value::Protected(password.as_bytes().into()),
+            );
+            history.add_entry(version);
+        }
+        let mut entry = keepass::db::Entry::new();
+        entry
+            .fields
+            .insert("Title".to_owned(), Value::Unprotected("github".to_owned()));
+        entry.history = Some(history);
+        entry
+    }
+
+    #[test]
+    fn diffs_versions_by_position() {
+        let options = DiffOptions {
+            mask_passwords: true,
+            with_history: true,
+            ..Default::default()
+        };
+        let a = Entry::from_keepass(
+            &keepass_entry_with_history(&["old"]),
+            &options,
+            &Attachments::new(),
+        );
+        let b = Entry::from_keepass(
+            &keepass_entry_with_history(&["older", "new"]),
+            &options,
+            &Attachments::new(),
+        );
+        let changes = collect_changes(&a.diff(&b));
+
+        let summary: Vec<_> = changes
+            .iter()
+            .map(|c| (c.path.join("/"), c.item, c.kind))
+            .collect();
+        assert_eq!(
+            vec![
+                ("".to_owned(), ItemKind::Entry, ChangeKind::Changed),
+                ("github".to_owned(), ItemKind::History, ChangeKind::Changed),
+                (
+                    "github/History version 1".to_owned(),
+                    ItemKind::Field,
+                    ChangeKind::Changed
+                ),
+                ("github".to_owned(), ItemKind::History, ChangeKind::Added),
+            ],
+            summary
+        );
+        assert_eq!(Some("***".to_owned()), changes[2].old);
+    }
+
+    #[test]
+    fn ignores_history_by_default() {
+        let a = Entry::from_keepass(
+            &keepass_entry_with_history(&["old"]),
+            &DiffOptions::default(),
+            &Attachments::new(),
+        );
+        let b = Entry::from_keepass(
+            &keepass_entry_with_history(&["new"]),
+            &DiffOptions::default(),
+            &Attachments::new(),
+        );
+
+        assert!(a.history.is_empty());
+        assert!(!a.diff(&b).has_changes());
+    }
+}
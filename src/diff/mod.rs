@@ -1,11 +1,35 @@
+//! Diffing of the groups, entries and fields of a KeePass database
+
+use std::cell::Cell;
 use std::collections::{HashMap, HashSet};
-use termcolor::Color;
+use std::ops::ControlFlow;
+use termcolor::{Buffer, ColorSpec, WriteColor};
 
-use stack::Stack;
+use diff::field::Mask;
+use diff::normalize::{FieldRule, Normalizer};
+use diff::summary::Summary;
+use output::theme::Theme;
+use output::Glyphs;
+use stack::{join_path, split_path, Stack};
 
+pub mod attachment;
+pub mod duplicates;
 pub mod entry;
+pub mod equality;
 pub mod field;
+pub mod fuzzy;
+pub mod glob;
 pub mod group;
+pub mod history;
+pub mod moves;
+pub mod normalize;
+pub mod references;
+pub mod strength;
+pub mod summary;
+pub mod tag;
+pub mod three_way;
+pub mod times;
+pub mod words;
 
 /// The possible outcomes of diffing two objects against another
 #[derive(Debug)]
@@ -24,6 +48,52 @@ pub enum DiffResult<'a, T> {
     OnlyLeft { left: &'a T },
     /// Only the right object exists
     OnlyRight { right: &'a T },
+    /// The object moved to another group, see `moves::detect_moves`
+    Moved {
+        left: &'a T,
+        right: &'a T,
+        /// Names of the groups containing the right object, starting at the root
+        to: &'a [String],
+        /// How the right object differs from the left object
+        difference: Box<dyn DiffResultFormat + 'a>,
+    },
+}
+
+impl<'a, T> DiffResult<'a, T> {
+    /// Whether the compared objects differ in any way, including any of their children
+    pub fn has_changes(&self) -> bool {
+        !matches!(self, DiffResult::Identical { .. })
+    }
+
+    /// The compared object of the first database, or of the second if it only exists there
+    pub fn item(&self) -> &'a T {
+        match self {
+            DiffResult::Identical { left, .. }
+            | DiffResult::Changed { left, .. }
+            | DiffResult::InnerDifferences { left, .. }
+            | DiffResult::OnlyLeft { left }
+            | DiffResult::Moved { left, .. } => left,
+            DiffResult::OnlyRight { right } => right,
+        }
+    }
+}
+
+impl<'a, T> DiffResult<'a, T>
+where
+    Self: DiffResultFormat,
+{
+    /// Whether any difference of this object or its children passes the filter of `options`
+    fn shows_any(&self, path: &Stack<&String>, options: &DisplayOptions) -> bool {
+        // Stops at the first change shown
+        let found = self.visit_changes(path, &mut |c| {
+            if options.filter.shows(c.item, c.kind) {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        found.is_break()
+    }
 }
 
 /// Denotes that an object can be diffed
@@ -32,181 +102,1103 @@ where
     Self: Sized,
 {
     fn diff<'a>(&'a self, other: &'a Self) -> DiffResult<'a, Self>;
+
+    /// Whether the objects differ at all, like `diff` tells with `has_changes`. Objects that
+    /// can tell this without collecting all of their differences stop at the first one.
+    fn differs(&self, other: &Self) -> bool {
+        self.diff(other).has_changes()
+    }
+}
+
+/// How entries of both databases are paired up before they are diffed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum MatchBy {
+    /// Entries with the same title are the same entry
+    #[default]
+    Title,
+    /// Entries with the same UUID are the same entry, falling back to the title without a UUID
+    Uuid,
+    /// Entries with the same title and user name are the same entry
+    #[value(name = "title+username")]
+    TitleUsername,
+    /// Entries with the same title and URL are the same entry
+    #[value(name = "title+url")]
+    TitleUrl,
+}
+
+/// How the groups and entries of a group are ordered
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SortBy {
+    /// Groups by their name, then entries by their title
+    #[default]
+    Title,
+    /// Groups by their name, then entries by their user name and title
+    Username,
+    /// Groups and entries together by their name, so paths are in alphabetical order
+    Path,
+    /// Groups, then entries, as ordered in the first database, followed by those only in the
+    /// second database
+    None,
+}
+
+/// User-specified settings for reading a database into groups and entries that can be diffed
+#[derive(Clone, Debug, Default)]
+pub struct DiffOptions {
+    pub use_verbose: bool,
+    /// Whether protected values, OTP secrets and the password are masked
+    pub mask_passwords: bool,
+    /// Names of fields whose values are masked, whether they are protected or not
+    pub mask_fields: Vec<String>,
+    /// How masked values are shown
+    pub mask: Mask,
+    pub match_by: MatchBy,
+    /// Names of fields that are left out of the comparison
+    pub ignore_fields: Vec<String>,
+    /// Whether `ignore_fields` match field names regardless of their case
+    pub ignore_fields_case_insensitive: bool,
+    /// Names of the only fields that are compared, if there are any. All other fields are left
+    /// out like those in `ignore_fields`.
+    pub only_fields: Vec<String>,
+    /// Whether the histories of entries are compared as well
+    pub with_history: bool,
+    /// Names of fields whose values are compared regardless of their case. Entries are paired up
+    /// regardless of the case of their title if "Title" is one of them.
+    pub ignore_case_fields: Vec<String>,
+    /// How the values of fields are normalized before they are compared, by the name of the field
+    pub field_rules: Vec<FieldRule>,
+    /// Whether the recycle bin group and everything in it is compared as well
+    pub include_recycle_bin: bool,
+    /// Glob patterns (see [`glob::matches`]) of groups to compare. If there are any, only the
+    /// matching groups and their subgroups are compared.
+    pub include_groups: Vec<String>,
+    /// Glob patterns of groups that are left out of the comparison with their subgroups
+    pub exclude_groups: Vec<String>,
+    /// Paths below the root of the groups and entries that are compared, split into the names
+    /// of their groups and the title of the entry, if there are any. Everything else is left out.
+    pub only_paths: Vec<Vec<String>>,
+    pub sort_by: SortBy,
+    /// Groups nested more levels below the root than this are only reported as changed, without
+    /// their differences, so 0 only compares the children of the root
+    pub max_depth: Option<usize>,
+    /// Whether entries whose creation, modification or expiry time differs are changed. The
+    /// times are only shown in verbose output otherwise.
+    pub compare_times: bool,
+    /// Whether times are left out of the comparison, even when groups expire
+    pub ignore_times: bool,
+    /// Whether metadata other than times is left out of the comparison, like the icons of groups
+    /// and the tags of entries
+    pub ignore_metadata: bool,
+    /// Tags that entries must carry in either database to be compared, if there are any. Groups
+    /// are only compared by the entries that carry one of them.
+    pub tags: Vec<String>,
+    /// Whether only the entries whose password is all that changed are compared, like after a
+    /// password rotation
+    pub changed_passwords_only: bool,
+    pub time_zone: times::TimeZone,
+    /// How long and multiline values are shown in text output
+    pub truncate: field::Truncate,
+    /// Whether changed passwords are annotated with how their estimated strength changed
+    pub password_strength: bool,
+    /// Whether changed entries are annotated with which database modified them last
+    pub newer_wins: bool,
+    /// Whether the icons of entries are compared, which they are in verbose output as well
+    pub show_icons: bool,
+    /// Whether field references like `{REF:P@I:<UUID>}` are compared by the values they refer to
+    pub follow_references: bool,
+    /// Whether all fields are masked, so only whether they match is told, see [`equality`]
+    pub compare_only: bool,
+    /// Whether values are shown with ASCII characters only in text output, see [`Glyphs`]
+    pub ascii: bool,
+}
+
+impl DiffOptions {
+    /// Whether the field with the given name is left out of the comparison
+    pub fn ignores_field(&self, name: &str) -> bool {
+        if !self.only_fields.is_empty() && !self.only_fields.iter().any(|f| f == name) {
+            true
+        } else if self.ignore_fields_case_insensitive {
+            self.ignore_fields
+                .iter()
+                .any(|f| caseless::default_caseless_match_str(f, name))
+        } else {
+            self.ignore_fields.iter().any(|f| f == name)
+        }
+    }
+
+    /// Whether the attribute of a group with the given name, like its notes, icon or expiry, is
+    /// left out of the comparison, like a field or as part of the times or the metadata
+    pub fn ignores_attribute(&self, name: &str) -> bool {
+        match name {
+            "Expires" if self.ignore_times => true,
+            "Icon" if self.ignore_metadata => true,
+            name => self.ignores_field(name),
+        }
+    }
+
+    /// Whether the icons of entries are compared, unless all metadata or the `Icon` field is
+    /// left out
+    pub fn compares_entry_icons(&self) -> bool {
+        (self.show_icons || self.use_verbose) && !self.ignores_attribute("Icon")
+    }
+
+    /// Whether values of the field with the given name are masked: those in `mask_fields`, the
+    /// password if passwords are masked, and all of them with `compare_only`
+    pub fn masks_field(&self, name: &str) -> bool {
+        self.compare_only
+            || (self.mask_passwords && name == "Password")
+            || self.mask_fields.iter().any(|f| f == name)
+    }
+
+    /// Whether values of the field with the given name are compared regardless of their case
+    pub fn ignores_case_of(&self, name: &str) -> bool {
+        self.ignore_case_fields.iter().any(|f| f == name)
+    }
+
+    /// The normalizers of all `field_rules` for the field with the given name, in their order
+    pub fn normalizers_of(&self, name: &str) -> Vec<Normalizer> {
+        self.field_rules
+            .iter()
+            .filter(|rule| rule.field == name)
+            .flat_map(|rule| rule.normalizers.iter().copied())
+            .collect()
+    }
+}
+
+/// The kind of object a change refers to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ItemKind {
+    Group,
+    Entry,
+    Field,
+    Attachment,
+    /// A prior version of an entry
+    History,
+    /// A tag of an entry, which can only be added or removed
+    Tag,
+}
+
+impl ItemKind {
+    /// Whether the item is a group or an entry, rather than a part of an entry
+    pub fn is_node(&self) -> bool {
+        matches!(self, ItemKind::Group | ItemKind::Entry)
+    }
+
+    /// Whether the item carries a value, like a field or an attachment
+    pub fn has_value(&self) -> bool {
+        matches!(self, ItemKind::Field | ItemKind::Attachment)
+    }
+}
+
+/// How an object differs between the two databases
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// The object only exists in the second database
+    Added,
+    /// The object only exists in the first database
+    Removed,
+    /// The object exists in both databases, but its value or children differ
+    Changed,
+    /// The object moved to another group, which is its new value
+    Moved,
+}
+
+/// A single difference, flattened out of a DiffResult tree
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    /// Names of the groups (and entry) containing the object, starting at the root
+    pub path: Vec<String>,
+    pub item: ItemKind,
+    pub name: String,
+    pub kind: ChangeKind,
+    /// The value in the first database, if the object carries a value
+    pub old: Option<String>,
+    /// The value in the second database, if the object carries a value
+    pub new: Option<String>,
+}
+
+impl Change {
+    /// Whether the change only holds the changes within a changed group or entry that follow it,
+    /// rather than being a change of its own
+    pub fn is_container(&self) -> bool {
+        let is_node = self.item.is_node() || self.item == ItemKind::History;
+        self.kind == ChangeKind::Changed && is_node && self.old.is_none()
+    }
+
+    /// The path of the changed group or entry, or of the entry containing the changed value,
+    /// joined with `separator` like `Root/Work/Email`
+    pub fn location(&self, separator: &str) -> String {
+        if self.item.is_node() || self.item == ItemKind::History {
+            let mut path = self.path.clone();
+            path.push(self.name.clone());
+            join_path(&path, separator)
+        } else {
+            join_path(&self.path, separator)
+        }
+    }
+
+    /// The old and new value, with the groups a moved object was moved from and to joined with
+    /// `separator` instead of `/`
+    pub fn values(&self, separator: &str) -> (Option<String>, Option<String>) {
+        let rejoin = |path: &Option<String>| match self.kind {
+            ChangeKind::Moved => path
+                .as_ref()
+                .map(|path| join_path(&split_path(path, "/"), separator)),
+            _ => path.clone(),
+        };
+        (rejoin(&self.old), rejoin(&self.new))
+    }
+}
+
+#[cfg(test)]
+impl Change {
+    /// A change of the object at `path`, without values
+    pub(crate) fn new(path: &[&str], item: ItemKind, name: &str, kind: ChangeKind) -> Change {
+        Change {
+            path: path.iter().map(|p| p.to_string()).collect(),
+            item,
+            name: name.to_owned(),
+            kind,
+            old: None,
+            new: None,
+        }
+    }
+
+    /// The change with the given values, leaving out the old value of an addition and the new
+    /// value of a removal
+    pub(crate) fn with_values(self, old: &str, new: &str) -> Change {
+        Change {
+            old: Some(old.to_owned()).filter(|_| self.kind != ChangeKind::Added),
+            new: Some(new.to_owned()).filter(|_| self.kind != ChangeKind::Removed),
+            ..self
+        }
+    }
+}
+
+/// Denotes that an object can be described by a Change
+pub trait DiffItem {
+    fn item_kind(&self) -> ItemKind;
+    fn item_name(&self) -> String;
+    /// The displayable value of the object, already masked if requested
+    fn item_value(&self) -> Option<String> {
+        None
+    }
+    /// The number of groups and entries nested in the object
+    fn nested_nodes(&self) -> (usize, usize) {
+        (0, 0)
+    }
+    /// The entries nested in the object, each with the names of the groups leading to it,
+    /// starting with the object itself
+    fn nested_entries(&self) -> Vec<(Vec<String>, String)> {
+        Vec::new()
+    }
+    /// The groups and entries directly in the object, in the order they are shown, as unchanged
+    /// if `kind` is `None`, or else as added or removed like the object, so they can be shown in
+    /// full with [`DisplayOptions::show_unchanged`]
+    fn nested_results(&self, _kind: Option<ChangeKind>) -> Vec<Box<dyn DiffResultFormat + '_>> {
+        Vec::new()
+    }
+    /// Further details shown below the object in verbose output, like the times of an entry
+    fn annotation(&self) -> Option<String> {
+        None
+    }
+    /// The key the object is ordered by among its siblings
+    fn sort_key(&self, _sort_by: SortBy) -> String {
+        self.item_name()
+    }
+    /// The displayed text before a value, the value and the text after it, if changes within the
+    /// value can be highlighted
+    fn value_parts(&self) -> Option<(String, String, String)> {
+        None
+    }
+    /// The name the object is shown with, like `Field 'Notes'`, and the lines of its value, each
+    /// shortened like the value would be, if changes of the value can be shown line by line
+    fn value_lines(&self) -> Option<(String, Vec<String>)> {
+        None
+    }
+    /// A note on how the object changed into `new`, shown after its new value in text output,
+    /// like how the strength of a password changed
+    fn change_note(&self, _new: &Self) -> Option<String>
+    where
+        Self: Sized,
+    {
+        None
+    }
+}
+
+/// Restricts which differences are shown
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Filter {
+    /// Shows all differences
+    #[default]
+    All,
+    /// Shows only groups and entries that exist in the second database only
+    OnlyAdditions,
+    /// Shows only groups and entries that exist in the first database only
+    OnlyRemovals,
+}
+
+impl Filter {
+    /// Whether a change of the given item should be shown
+    pub fn shows(&self, item: ItemKind, kind: ChangeKind) -> bool {
+        match self {
+            Filter::All => true,
+            Filter::OnlyAdditions => kind == ChangeKind::Added && item.is_node(),
+            Filter::OnlyRemovals => kind == ChangeKind::Removed && item.is_node(),
+        }
+    }
+}
+
+/// User-specified settings for rendering a DiffResult as text
+#[derive(Clone, Debug, Default)]
+pub struct DisplayOptions {
+    pub use_color: bool,
+    pub use_verbose: bool,
+    pub mask_passwords: bool,
+    pub filter: Filter,
+    pub theme: Theme,
+    /// How many unchanged groups and entries are shown before and after each changed sibling,
+    /// like the context lines of `diff`
+    pub context: usize,
+    /// How many changes are shown at most, if there is a limit
+    pub limit: Option<Limit>,
+    /// Joins the names of paths with this separator instead of listing them in brackets
+    pub path_separator: Option<String>,
+    /// Marks moves and left out changes with ASCII characters only, see [`Glyphs`]
+    pub ascii: bool,
+    /// Whether all groups and entries are shown, with the unchanged ones indented like context
+    /// and the contents of added and removed groups marked like their group
+    pub show_unchanged: bool,
+}
+
+/// The most changes that are shown, not counting the groups and entries that only contain
+/// changes, and how many were shown already
+#[derive(Clone, Debug)]
+pub struct Limit {
+    max: usize,
+    shown: Cell<usize>,
+}
+
+impl Limit {
+    pub fn new(max: usize) -> Self {
+        Limit {
+            max,
+            shown: Cell::new(0),
+        }
+    }
+
+    fn is_reached(&self) -> bool {
+        self.shown.get() >= self.max
+    }
+}
+
+impl DisplayOptions {
+    /// Whether no more changes are shown, as the limit of changes is reached
+    fn is_limit_reached(&self) -> bool {
+        self.limit.as_ref().is_some_and(Limit::is_reached)
+    }
+
+    /// The names of a path, listed like `[Root, Work, Email]` or joined with the path separator
+    fn path_string(&self, path: &Stack<&String>) -> String {
+        match &self.path_separator {
+            None => path.mk_string("[", ", ", "]"),
+            Some(separator) => path.to_path(separator),
+        }
+    }
+
+    /// The path of an item with the item itself. Only the names of groups and entries are
+    /// escaped when joining them, values are written as they are.
+    fn path_of<E: std::fmt::Display + DiffItem>(&self, path: &Stack<&String>, item: &E) -> String {
+        match &self.path_separator {
+            Some(_) if !item.item_kind().is_node() => {
+                let item = indent_lines(path, &item.to_string());
+                format!("{}{}{}", self.path_start(path), item, self.path_end())
+            }
+            _ => self.path_string(&path.append(&indent_lines(path, &item.to_string()))),
+        }
+    }
+
+    /// The start of a path, up to where the name of the item in it follows
+    fn path_start(&self, path: &Stack<&String>) -> String {
+        match &self.path_separator {
+            None if path.is_empty() => "[".to_owned(),
+            None => path.mk_string("[", ", ", ", "),
+            Some(_) if path.is_empty() => String::new(),
+            Some(separator) => format!("{}{}", path.to_path(separator), separator),
+        }
+    }
+
+    /// The end of a path, after the name of the item in it
+    fn path_end(&self) -> &'static str {
+        match self.path_separator {
+            None => "]",
+            Some(_) => "",
+        }
+    }
 }
 
 /// Denotes that an object can be formatted as a DiffResult
 pub trait DiffResultFormat: std::fmt::Debug {
     fn diff_result_format(
         &self,
-        f: &mut std::fmt::Formatter<'_>,
+        f: &mut dyn WriteColor,
+        path: &Stack<&String>,
+        options: &DisplayOptions,
+    ) -> std::io::Result<()>;
+
+    /// Writes the object as a line of context if it is an unchanged group or entry, or an
+    /// unchanged value if `with_values` is set, see [`DisplayOptions::context`]
+    fn context_format(
+        &self,
+        f: &mut dyn WriteColor,
         path: &Stack<&String>,
-        use_color: bool,
-        use_verbose: bool,
-        mask_passwords: bool,
-    ) -> std::fmt::Result;
+        options: &DisplayOptions,
+        with_values: bool,
+    ) -> std::io::Result<()>;
+
+    /// Whether any difference of this object or its children is shown with `options`
+    fn shows_changes(&self, path: &Stack<&String>, options: &DisplayOptions) -> bool;
+
+    /// Passes all differences of this object and its children to `visit`, parents before
+    /// children, one at a time as they are found. Stops as soon as `visit` breaks, and tells
+    /// whether it did.
+    fn visit_changes(&self, path: &Stack<&String>, visit: &mut Visit) -> ControlFlow<()>;
+
+    /// Passes the added, removed, changed and moved entries of this object and its children to
+    /// `visit` like `visit_changes`, including those of added and removed groups, but neither
+    /// groups nor the changes within entries
+    fn visit_entry_changes(&self, path: &Stack<&String>, visit: &mut Visit) -> ControlFlow<()>;
+
+    /// Counts this object and its children into `summary`
+    fn summarize(&self, summary: &mut Summary);
+}
+
+/// Receives the changes of a DiffResult tree one at a time, see
+/// [`DiffResultFormat::visit_changes`]
+pub type Visit<'v> = dyn FnMut(Change) -> ControlFlow<()> + 'v;
+
+/// Flattens a DiffResult tree into the list of its differences, parents before children
+pub fn collect_changes(delta: &dyn DiffResultFormat) -> Vec<Change> {
+    let mut acc = Vec::new();
+    let _ = delta.visit_changes(&Stack::empty(), &mut |change| {
+        acc.push(change);
+        ControlFlow::Continue(())
+    });
+    acc
+}
+
+/// Flattens a DiffResult tree into the list of its added, removed, changed and moved entries, see
+/// [`DiffResultFormat::visit_entry_changes`]
+pub fn collect_entry_changes(delta: &dyn DiffResultFormat) -> Vec<Change> {
+    let mut acc = Vec::new();
+    let _ = delta.visit_entry_changes(&Stack::empty(), &mut |change| {
+        acc.push(change);
+        ControlFlow::Continue(())
+    });
+    acc
+}
+
+/// Counts the added, removed, changed and unchanged groups and entries of a DiffResult tree.
+/// Everything in an added, removed or unchanged group is counted as added, removed or unchanged.
+pub fn summarize(delta: &dyn DiffResultFormat) -> Summary {
+    let mut summary = Summary::default();
+    delta.summarize(&mut summary);
+    summary
 }
 
 /// Helper wrapper to impl Display for a DiffResult with user-specified settings
 pub struct DiffDisplay<'a, T: DiffResultFormat> {
     pub inner: T,
     pub path: Stack<&'a String>,
-    pub use_color: bool,
-    pub use_verbose: bool,
-    pub mask_passwords: bool,
+    pub options: DisplayOptions,
+}
+
+impl<'a, T: DiffResultFormat> DiffDisplay<'a, T> {
+    /// Writes the differences to `out`, which decides how colors are rendered, if at all. They
+    /// are written line by line while the differences are walked, so large differences are not
+    /// held in memory, unlike with `to_string`. With a limit, the differences stop after that
+    /// many changes, followed by how many were left out.
+    pub fn write_to(&self, out: &mut dyn WriteColor) -> std::io::Result<()> {
+        self.inner
+            .diff_result_format(out, &self.path, &self.options)?;
+        if self.options.use_color {
+            crate::reset_color(out)?;
+        }
+        if let Some(limit) = self.options.limit.as_ref().filter(|l| l.is_reached()) {
+            // Only counted, so the changes left out are not held in memory either
+            let mut total = 0;
+            let _ = self.inner.visit_changes(&self.path, &mut |c| {
+                if !c.is_container() && self.options.filter.shows(c.item, c.kind) {
+                    total += 1;
+                }
+                ControlFlow::Continue(())
+            });
+            if total > limit.max {
+                let more = total - limit.max;
+                let noun = if more == 1 { "change" } else { "changes" };
+                let ellipsis = Glyphs::new(self.options.ascii).ellipsis;
+                writeln!(out, "{} and {} more {}", ellipsis, more, noun)?;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<'a, T: DiffResultFormat> std::fmt::Display for DiffDisplay<'a, T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let result = self.inner.diff_result_format(
-            f,
-            &self.path,
-            self.use_color,
-            self.use_verbose,
-            self.mask_passwords,
-        );
-        if self.use_color {
-            crate::reset_color();
+        let mut buffer = if self.options.use_color {
+            Buffer::ansi()
+        } else {
+            Buffer::no_color()
+        };
+        self.write_to(&mut buffer).map_err(|_| std::fmt::Error)?;
+        f.write_str(&String::from_utf8_lossy(buffer.as_slice()))
+    }
+}
+
+/// The stack of the given path, starting at the root
+fn stack_of(path: &[String]) -> Stack<&String> {
+    path.iter()
+        .fold(Stack::empty(), |stack, name| stack.append(name))
+}
+
+/// Indents the lines after the first of an object shown with a value of several lines, see
+/// [`field::Truncate::Off`], so they show as a block below the line of the object
+fn indent_lines(path: &Stack<&String>, text: &str) -> String {
+    text.replace('\n', &format!("\n  {}", "  ".repeat(path.len() + 1)))
+}
+
+/// Writes the annotations of an object below it in verbose output: the one of the side it exists
+/// on, the one of both sides if they are the same, or both of them, one per line
+fn write_annotations(
+    f: &mut dyn WriteColor,
+    path: &Stack<&String>,
+    options: &DisplayOptions,
+    left: Option<String>,
+    right: Option<String>,
+) -> std::io::Result<()> {
+    let lines = match (left, right) {
+        (Some(left), Some(right)) if left != right => {
+            vec![format!("A: {}", left), format!("B: {}", right)]
+        }
+        (left, right) => left.or(right).into_iter().collect(),
+    };
+    for line in lines {
+        if options.use_color {
+            f.set_color(&options.theme.unchanged_spec())?;
+        }
+        writeln!(f, "  {}{}", "  ".repeat(path.len() + 1), line)?;
+    }
+    Ok(())
+}
+
+/// Writes the groups and entries in `item` and everything in them, see
+/// [`DisplayOptions::show_unchanged`]
+fn write_nested<E: std::fmt::Display + DiffItem>(
+    f: &mut dyn WriteColor,
+    path: &Stack<&String>,
+    options: &DisplayOptions,
+    item: &E,
+    kind: Option<ChangeKind>,
+) -> std::io::Result<()> {
+    let name = item.to_string();
+    let inner_path = path.append(&name);
+    for nested in item.nested_results(kind) {
+        nested.diff_result_format(f, &inner_path, options)?;
+    }
+    Ok(())
+}
+
+/// Writes a line with a changed value, highlighting the words that changed and dimming the ones
+/// both values have in common. The line break is left to the caller.
+fn write_highlighted(
+    f: &mut dyn WriteColor,
+    sign: &str,
+    kind: ChangeKind,
+    path: &Stack<&String>,
+    options: &DisplayOptions,
+    (head, _, tail): &(String, String, String),
+    words: &[words::Segment],
+) -> std::io::Result<()> {
+    let color = options.theme.color_of(kind);
+    crate::set_fg(f, Some(color))?;
+    if options.use_verbose {
+        write!(f, "{} {}{}", sign, "  ".repeat(path.len()), head)?;
+    } else {
+        write!(f, "{} {}{}", sign, options.path_start(path), head)?;
+    }
+    for word in words {
+        let mut spec = ColorSpec::new();
+        spec.set_fg(Some(color))
+            .set_bold(word.changed)
+            .set_dimmed(!word.changed);
+        f.set_color(&spec)?;
+        write!(f, "{}", indent_lines(path, word.text))?;
+    }
+    crate::set_fg(f, Some(color))?;
+    if options.use_verbose {
+        write!(f, "{}", tail)
+    } else {
+        write!(f, "{}{}", tail, options.path_end())
+    }
+}
+
+/// Writes a changed value of several lines as a line with its name, followed by the lines that
+/// were removed and added, indented below it like `diff` shows them
+fn write_lines(
+    f: &mut dyn WriteColor,
+    path: &Stack<&String>,
+    options: &DisplayOptions,
+    name: &str,
+    old: &[String],
+    new: &[String],
+    note: &str,
+) -> std::io::Result<()> {
+    if options.use_color {
+        crate::set_fg(f, Some(options.theme.changed))?;
+    }
+    if options.use_verbose {
+        writeln!(f, "~ {}{}{}", "  ".repeat(path.len()), name, note)?;
+    } else {
+        let end = options.path_end();
+        writeln!(f, "~ {}{}{}{}", options.path_start(path), name, end, note)?;
+    }
+    let indent = "  ".repeat(path.len() + 1);
+    for line in words::diff_lines(old, new) {
+        let (sign, kind, text) = match line {
+            words::Line::Unchanged(_) => continue,
+            words::Line::Removed(text) => ("-", ChangeKind::Removed, text),
+            words::Line::Added(text) => ("+", ChangeKind::Added, text),
+        };
+        if options.use_color {
+            crate::set_fg(f, Some(options.theme.color_of(kind)))?;
         }
-        result
+        writeln!(f, "{} {}{}", sign, indent, text)?;
     }
+    Ok(())
 }
 
 /// Format functionality for deep recursion
 impl<'a, E> DiffResultFormat for DiffResult<'a, E>
 where
-    E: std::fmt::Display + std::fmt::Debug,
+    E: std::fmt::Display + std::fmt::Debug + DiffItem,
 {
     fn diff_result_format(
         &self,
-        f: &mut std::fmt::Formatter<'_>,
+        f: &mut dyn WriteColor,
         path: &Stack<&String>,
-        use_color: bool,
-        use_verbose: bool,
-        mask_passwords: bool,
-    ) -> std::fmt::Result {
-        let _ = match self {
+        options: &DisplayOptions,
+    ) -> std::io::Result<()> {
+        if options.is_limit_reached() {
+            return Ok(());
+        }
+        let shows = |item: &E, kind| options.filter.shows(item.item_kind(), kind);
+        let is_change = match self {
+            DiffResult::Identical { .. } | DiffResult::InnerDifferences { .. } => false,
+            DiffResult::Changed { left, .. } => shows(left, ChangeKind::Changed),
+            DiffResult::OnlyLeft { left } => shows(left, ChangeKind::Removed),
+            DiffResult::OnlyRight { right } => shows(right, ChangeKind::Added),
+            DiffResult::Moved { left, .. } => shows(left, ChangeKind::Moved),
+        };
+        if let (true, Some(limit)) = (is_change, &options.limit) {
+            limit.shown.set(limit.shown.get() + 1);
+        }
+
+        let use_color = options.use_color;
+        let use_verbose = options.use_verbose;
+        match self {
+            DiffResult::Identical { left, .. }
+                if options.show_unchanged && left.item_kind().is_node() =>
+            {
+                self.context_format(f, path, options, false)?;
+                write_nested(f, path, options, *left, None)
+            }
             DiffResult::Identical { .. } => Ok(()),
-            DiffResult::Changed { left, right } => {
+            DiffResult::Changed { left, .. }
+                if !options.filter.shows(left.item_kind(), ChangeKind::Changed) =>
+            {
+                Ok(())
+            }
+            DiffResult::Changed { left, .. } if left.item_kind() == ItemKind::Group => {
                 if use_color {
-                    crate::set_fg(Some(Color::Red));
+                    crate::set_fg(f, Some(options.theme.changed))?;
                 }
                 if use_verbose {
                     let indent = "  ".repeat(path.len());
-                    writeln!(f, "- {}{}", indent, left)?;
+                    writeln!(f, "~ {}{}", indent, left)
                 } else {
-                    writeln!(
+                    writeln!(f, "~ {}", options.path_of(path, *left))
+                }
+            }
+            DiffResult::Changed { left, right } => {
+                let note = left
+                    .change_note(right)
+                    .map(|note| format!(" {}", note))
+                    .unwrap_or_default();
+                if let (Some((name, old)), Some((_, new))) =
+                    (left.value_lines(), right.value_lines())
+                {
+                    if old.len() > 1 || new.len() > 1 {
+                        return write_lines(f, path, options, &name, &old, &new, &note);
+                    }
+                }
+                if let (true, Some(old), Some(new)) =
+                    (use_color, left.value_parts(), right.value_parts())
+                {
+                    let (old_words, new_words) = words::diff_words(&old.1, &new.1);
+                    write_highlighted(
                         f,
-                        "- {}",
-                        path.append(&format!("{}", left)).mk_string("[", ", ", "]")
+                        "-",
+                        ChangeKind::Removed,
+                        path,
+                        options,
+                        &old,
+                        &old_words,
                     )?;
+                    writeln!(f)?;
+                    write_highlighted(f, "+", ChangeKind::Added, path, options, &new, &new_words)?;
+                    return writeln!(f, "{}", note);
                 }
                 if use_color {
-                    crate::set_fg(Some(Color::Green));
+                    crate::set_fg(f, Some(options.theme.removed))?;
                 }
                 if use_verbose {
                     let indent = "  ".repeat(path.len());
-                    writeln!(f, "+ {}{}", indent, right)
+                    writeln!(f, "- {}{}", indent, indent_lines(path, &left.to_string()))?;
                 } else {
-                    writeln!(
-                        f,
-                        "+ {}",
-                        path.append(&format!("{}", right)).mk_string("[", ", ", "]")
-                    )
+                    writeln!(f, "- {}", options.path_of(path, *left))?;
+                }
+                if use_color {
+                    crate::set_fg(f, Some(options.theme.added))?;
+                }
+                if use_verbose {
+                    let indent = "  ".repeat(path.len());
+                    let right = indent_lines(path, &right.to_string());
+                    writeln!(f, "+ {}{}{}", indent, right, note)
+                } else {
+                    writeln!(f, "+ {}{}", options.path_of(path, *right), note)
                 }
             }
             DiffResult::InnerDifferences {
                 left,
+                right,
                 inner_differences,
-                ..
             } => {
+                let hidden = options.filter != Filter::All
+                    && (left.item_kind() != ItemKind::Group || !self.shows_any(path, options));
+                if hidden {
+                    return Ok(());
+                }
+                let note = left.change_note(right);
                 if use_verbose {
                     if use_color {
-                        crate::set_fg(Some(Color::Yellow));
+                        crate::set_fg(f, Some(options.theme.changed))?;
                     }
                     let indent = "  ".repeat(path.len());
-                    writeln!(f, "~ {}{}", indent, left)?;
+                    let note = note.map(|note| format!(" {}", note)).unwrap_or_default();
+                    writeln!(f, "~ {}{}{}", indent, left, note)?;
+                    write_annotations(f, path, options, left.annotation(), right.annotation())?;
+                } else if let Some(note) = note {
+                    if use_color {
+                        crate::set_fg(f, Some(options.theme.changed))?;
+                    }
+                    writeln!(f, "~ {} {}", options.path_of(path, *left), note)?;
+                } else if options.show_unchanged && left.item_kind().is_node() {
+                    if use_color {
+                        crate::set_fg(f, Some(options.theme.changed))?;
+                    }
+                    writeln!(f, "~ {}", options.path_of(path, *left))?;
                 }
-                for id in inner_differences {
-                    id.diff_result_format(
-                        f,
-                        &path.append(&format!("{}", left)),
-                        use_color,
-                        use_verbose,
-                        mask_passwords,
-                    )?;
+                let name = format!("{}", left);
+                let inner_path = path.append(&name);
+                if options.context == 0 {
+                    for id in inner_differences {
+                        id.diff_result_format(f, &inner_path, options)?;
+                    }
+                    return Ok(());
+                }
+                // Unchanged values only show around the changed values of an entry
+                let with_values = use_verbose && left.item_kind() != ItemKind::Group;
+                let shown: Vec<usize> = (0..inner_differences.len())
+                    .filter(|&i| inner_differences[i].shows_changes(&inner_path, options))
+                    .collect();
+                for (i, id) in inner_differences.iter().enumerate() {
+                    let is_near = |&j: &usize| i.abs_diff(j) <= options.context;
+                    if shown.contains(&i) {
+                        id.diff_result_format(f, &inner_path, options)?;
+                    } else if shown.iter().any(is_near) {
+                        id.context_format(f, &inner_path, options, with_values)?;
+                    }
                 }
                 Ok(())
             }
+            DiffResult::OnlyLeft { left }
+                if !options.filter.shows(left.item_kind(), ChangeKind::Removed) =>
+            {
+                Ok(())
+            }
             DiffResult::OnlyLeft { left } => {
                 if use_color {
-                    crate::set_fg(Some(Color::Red));
+                    crate::set_fg(f, Some(options.theme.removed))?;
                 }
                 if use_verbose {
                     let indent = "  ".repeat(path.len());
-                    writeln!(f, "- {}{}", indent, left)
+                    writeln!(f, "- {}{}", indent, indent_lines(path, &left.to_string()))?;
+                    write_annotations(f, path, options, left.annotation(), None)?;
                 } else {
-                    writeln!(
-                        f,
-                        "- {}",
-                        path.append(&format!("{}", left)).mk_string("[", ", ", "]")
-                    )
+                    writeln!(f, "- {}", options.path_of(path, *left))?;
+                }
+                if options.show_unchanged {
+                    write_nested(f, path, options, *left, Some(ChangeKind::Removed))?;
+                }
+                Ok(())
+            }
+            DiffResult::Moved { left, .. }
+                if !options.filter.shows(left.item_kind(), ChangeKind::Moved) =>
+            {
+                Ok(())
+            }
+            DiffResult::Moved {
+                left,
+                to,
+                difference,
+                ..
+            } => {
+                if use_color {
+                    crate::set_fg(f, Some(options.theme.moved))?;
+                }
+                let to_path = options.path_string(&stack_of(to));
+                let arrow = Glyphs::new(options.ascii).arrow;
+                if use_verbose {
+                    let indent = "  ".repeat(path.len());
+                    writeln!(f, "{} {}{} moved to {}", arrow, indent, left, to_path)?;
+                } else {
+                    let from = options.path_string(path);
+                    writeln!(f, "{} {} moved from {} to {}", arrow, left, from, to_path)?;
                 }
+                difference.diff_result_format(f, &stack_of(to), options)
+            }
+            DiffResult::OnlyRight { right }
+                if !options.filter.shows(right.item_kind(), ChangeKind::Added) =>
+            {
+                Ok(())
             }
             DiffResult::OnlyRight { right } => {
                 if use_color {
-                    crate::set_fg(Some(Color::Green));
+                    crate::set_fg(f, Some(options.theme.added))?;
                 }
                 if use_verbose {
                     let indent = "  ".repeat(path.len());
-                    writeln!(f, "+ {}{}", indent, right)
+                    writeln!(f, "+ {}{}", indent, indent_lines(path, &right.to_string()))?;
+                    write_annotations(f, path, options, None, right.annotation())?;
                 } else {
-                    writeln!(
-                        f,
-                        "+ {}",
-                        path.append(&format!("{}", right)).mk_string("[", ", ", "]")
-                    )
+                    writeln!(f, "+ {}", options.path_of(path, *right))?;
+                }
+                if options.show_unchanged {
+                    write_nested(f, path, options, *right, Some(ChangeKind::Added))?;
                 }
+                Ok(())
             }
-        };
+        }
+    }
 
+    fn context_format(
+        &self,
+        f: &mut dyn WriteColor,
+        path: &Stack<&String>,
+        options: &DisplayOptions,
+        with_values: bool,
+    ) -> std::io::Result<()> {
+        let left = match self {
+            DiffResult::Identical { left, .. } if left.item_kind().is_node() => left,
+            DiffResult::Identical { left, .. } if with_values && left.item_kind().has_value() => {
+                left
+            }
+            _ => return Ok(()),
+        };
+        if options.is_limit_reached() {
+            return Ok(());
+        }
+        if options.use_color {
+            f.set_color(&options.theme.unchanged_spec())?;
+        }
+        if options.use_verbose {
+            let line = indent_lines(path, &left.to_string());
+            writeln!(f, "  {}{}", "  ".repeat(path.len()), line)?;
+        } else {
+            let line = options.path_of(path, *left);
+            writeln!(f, "  {}", line)?;
+        }
         Ok(())
     }
-}
-
-/// Compare to HashMaps of the same value type with each other, returning a bool indicating whether
-/// there are any differences and a Vec<DiffResult<A>> listing all differences
-pub fn diff_entry<'a, A>(
-    a: &'a HashMap<String, A>,
-    b: &'a HashMap<String, A>,
-) -> (bool, Vec<DiffResult<'a, A>>)
-where
-    A: Diff,
-{
-    let mut keys = HashSet::new();
-    keys.extend(a.keys());
-    keys.extend(b.keys());
-
-    let mut keys: Vec<_> = keys.iter().collect();
-    keys.sort();
-
-    let mut acc: Vec<DiffResult<A>> = Vec::new();
-
-    let mut has_differences = false;
 
-    for key in keys {
-        let el_a: Option<&A> = a.get(*key);
-        let el_b: Option<&A> = b.get(*key);
+    fn shows_changes(&self, path: &Stack<&String>, options: &DisplayOptions) -> bool {
+        self.shows_any(path, options)
+    }
 
-        match (el_a, el_b) {
-            // both a and b have the key
-            (Some(v_a), Some(v_b)) => {
-                let dr: DiffResult<A> = v_a.diff(v_b);
+    fn visit_changes(&self, path: &Stack<&String>, visit: &mut Visit) -> ControlFlow<()> {
+        let change = |item: &E, kind, old, new| Change {
+            path: path.to_vec().into_iter().cloned().collect(),
+            item: item.item_kind(),
+            name: item.item_name(),
+            kind,
+            old,
+            new,
+        };
+        match self {
+            DiffResult::Identical { .. } => ControlFlow::Continue(()),
+            DiffResult::Changed { left, right } => visit(change(
+                left,
+                ChangeKind::Changed,
+                left.item_value(),
+                right.item_value(),
+            )),
+            DiffResult::InnerDifferences {
+                left,
+                inner_differences,
+                ..
+            } => {
+                visit(change(left, ChangeKind::Changed, None, None))?;
+                let name = left.item_name();
+                for id in inner_differences {
+                    id.visit_changes(&path.append(&name), visit)?;
+                }
+                ControlFlow::Continue(())
+            }
+            DiffResult::OnlyLeft { left } => {
+                visit(change(left, ChangeKind::Removed, left.item_value(), None))
+            }
+            DiffResult::OnlyRight { right } => {
+                visit(change(right, ChangeKind::Added, None, right.item_value()))
+            }
+            DiffResult::Moved {
+                left,
+                to,
+                difference,
+                ..
+            } => {
+                let from = path.to_vec().into_iter().cloned().collect::<Vec<_>>();
+                visit(change(
+                    left,
+                    ChangeKind::Moved,
+                    Some(join_path(&from, "/")),
+                    Some(join_path(to, "/")),
+                ))?;
+                difference.visit_changes(&stack_of(to), visit)
+            }
+        }
+    }
 
-                if let DiffResult::Identical { .. } = dr {
-                } else {
-                    has_differences = true;
+    fn visit_entry_changes(&self, path: &Stack<&String>, visit: &mut Visit) -> ControlFlow<()> {
+        let entry_change = |item: &E, kind| Change {
+            path: path.to_vec().into_iter().cloned().collect(),
+            item: ItemKind::Entry,
+            name: item.item_name(),
+            kind,
+            old: None,
+            new: None,
+        };
+        // The entries nested in an added or removed group
+        let nested_changes = |item: &E, kind, visit: &mut Visit| {
+            if item.item_kind() == ItemKind::Entry {
+                visit(entry_change(item, kind))?;
+            }
+            for (nested_path, name) in item.nested_entries() {
+                let mut change_path: Vec<String> = path.to_vec().into_iter().cloned().collect();
+                change_path.extend(nested_path);
+                visit(Change {
+                    path: change_path,
+                    item: ItemKind::Entry,
+                    name,
+                    kind,
+                    old: None,
+                    new: None,
+                })?;
+            }
+            ControlFlow::Continue(())
+        };
+        match self {
+            DiffResult::Identical { .. } => ControlFlow::Continue(()),
+            DiffResult::Changed { left, .. } | DiffResult::InnerDifferences { left, .. }
+                if left.item_kind() == ItemKind::Entry =>
+            {
+                visit(entry_change(left, ChangeKind::Changed))
+            }
+            // Groups compared beyond the maximum depth do not tell which entries changed
+            DiffResult::Changed { .. } => ControlFlow::Continue(()),
+            DiffResult::InnerDifferences {
+                left,
+                inner_differences,
+                ..
+            } => {
+                let name = left.item_name();
+                for id in inner_differences {
+                    id.visit_entry_changes(&path.append(&name), visit)?;
                 }
+                ControlFlow::Continue(())
+            }
+            DiffResult::OnlyLeft { left } => nested_changes(left, ChangeKind::Removed, visit),
+            DiffResult::OnlyRight { right } => nested_changes(right, ChangeKind::Added, visit),
+            DiffResult::Moved { left, to, .. } => {
+                let from = path.to_vec().into_iter().cloned().collect::<Vec<_>>();
+                visit(Change {
+                    old: Some(join_path(&from, "/")),
+                    new: Some(join_path(to, "/")),
+                    ..entry_change(left, ChangeKind::Moved)
+                })
+            }
+        }
+    }
 
+    fn summarize(&self, summary: &mut Summary) {
+        match self {
+            DiffResult::Identical { left, .. } => summary.count(*left, |t| &mut t.unchanged),
+            DiffResult::Changed { left, .. } => summary.count_one(*left, |t| &mut t.changed),
+            DiffResult::InnerDifferences {
+                left,
+                inner_differences,
+                ..
+            } => {
+                summary.count_one(*left, |t| &mut t.changed);
+                for id in inner_differences {
+                    id.summarize(summary);
+                }
+            }
+            DiffResult::OnlyLeft { left } => summary.count(*left, |t| &mut t.removed),
+            DiffResult::OnlyRight { right } => summary.count(*right, |t| &mut t.added),
+            DiffResult::Moved { left, .. } => summary.count_one(*left, |t| &mut t.changed),
+        }
+    }
+}
+
+/// Compare to HashMaps of the same value type with each other, returning a bool indicating whether
+/// there are any differences and a Vec<DiffResult<A>> listing all differences
+pub(crate) fn diff_entry<'a, A>(
+    a: &'a HashMap<String, A>,
+    b: &'a HashMap<String, A>,
+) -> (bool, Vec<DiffResult<'a, A>>)
+where
+    A: Diff,
+{
+    let mut keys = HashSet::new();
+    keys.extend(a.keys());
+    keys.extend(b.keys());
+
+    let mut keys: Vec<_> = keys.iter().collect();
+    keys.sort();
+
+    let mut acc: Vec<DiffResult<A>> = Vec::new();
+
+    let mut has_differences = false;
+
+    for key in keys {
+        let el_a: Option<&A> = a.get(*key);
+        let el_b: Option<&A> = b.get(*key);
+
+        match (el_a, el_b) {
+            // both a and b have the key
+            (Some(v_a), Some(v_b)) => {
+                let dr: DiffResult<A> = v_a.diff(v_b);
+                has_differences |= dr.has_changes();
                 acc.push(dr);
             }
 
@@ -230,9 +1222,22 @@ where
     (has_differences, acc)
 }
 
-pub fn diff_hashmap<'a, A>(
+/// How the values of a key are paired up when both maps have several of them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Pairing {
+    /// The first value of one map with the first of the other, and so on
+    ByPosition,
+    /// Each value with the first identical value of the other map, and the others by their
+    /// position, like the entries of different groups of flattened databases
+    IdenticalFirst,
+}
+
+/// Compare two HashMaps of Vecs with each other, pairing up the values of a key like `pairing`
+/// tells, returning a bool indicating whether there are any differences and a Vec<DiffResult<A>>
+pub(crate) fn diff_hashmap<'a, A>(
     a: &'a HashMap<String, Vec<A>>,
     b: &'a HashMap<String, Vec<A>>,
+    pairing: Pairing,
 ) -> (bool, Vec<DiffResult<'a, A>>)
 where
     A: Diff,
@@ -241,41 +1246,55 @@ where
     keys.extend(a.keys());
     keys.extend(b.keys());
 
-    let mut keys: Vec<_> = keys.iter().collect();
+    let mut keys: Vec<_> = keys.into_iter().collect();
     keys.sort();
 
+    diff_hashmap_in_order(a, b, keys, pairing)
+}
+
+/// Like `diff_hashmap`, but diffs the objects in the order of the given keys
+pub(crate) fn diff_hashmap_in_order<'a, A>(
+    a: &'a HashMap<String, Vec<A>>,
+    b: &'a HashMap<String, Vec<A>>,
+    keys: Vec<&String>,
+    pairing: Pairing,
+) -> (bool, Vec<DiffResult<'a, A>>)
+where
+    A: Diff,
+{
     let mut acc: Vec<DiffResult<A>> = Vec::new();
 
     let mut has_differences = false;
 
     for key in keys {
-        let el_a: Option<&Vec<A>> = a.get(*key);
-        let el_b: Option<&Vec<A>> = b.get(*key);
+        let el_a: Option<&Vec<A>> = a.get(key);
+        let el_b: Option<&Vec<A>> = b.get(key);
 
         match (el_a, el_b) {
             // both a and b have the key
             (Some(v_a), Some(v_b)) => {
-                v_a.iter()
-                    .enumerate()
-                    .for_each(|(index, value_a)| match v_b.get(index) {
+                let (pairs, rest) = match pairing {
+                    Pairing::ByPosition => (vec![None; v_a.len()], v_b.iter().collect()),
+                    Pairing::IdenticalFirst => pair_identical(v_a, v_b),
+                };
+                let mut rest = rest.into_iter();
+                for (value_a, paired) in v_a.iter().zip(pairs) {
+                    let value_b = paired.or_else(|| rest.next());
+                    match value_b {
                         Some(value_b) => {
                             let dr: DiffResult<A> = value_a.diff(value_b);
-                            if let DiffResult::Identical { .. } = dr {
-                            } else {
-                                has_differences = true;
-                            }
+                            has_differences |= dr.has_changes();
                             acc.push(dr);
                         }
                         None => {
                             has_differences = true;
                             acc.push(DiffResult::OnlyLeft { left: value_a })
                         }
-                    });
-                if v_a.len() < v_b.len() {
+                    }
+                }
+                for value_b in rest {
                     has_differences = true;
-                    v_b[v_a.len()..]
-                        .iter()
-                        .for_each(|value_b| acc.push(DiffResult::OnlyRight { right: value_b }));
+                    acc.push(DiffResult::OnlyRight { right: value_b });
                 }
             }
 
@@ -300,18 +1319,1213 @@ where
     (has_differences, acc)
 }
 
+/// Whether any objects of `a` and `b` with the same key differ, or only one of them has a key,
+/// like `diff_hashmap` tells, stopping at the first difference
+pub(crate) fn differs_hashmap<A: Diff>(
+    a: &HashMap<String, Vec<A>>,
+    b: &HashMap<String, Vec<A>>,
+) -> bool {
+    a.len() != b.len()
+        || a.iter().any(|(key, v_a)| match b.get(key) {
+            Some(v_b) => v_a.len() != v_b.len() || v_a.iter().zip(v_b).any(|(a, b)| a.differs(b)),
+            None => true,
+        })
+}
+
+/// Pairs each value of `a` with the first value of `b` it is identical to, so values with the same
+/// key are not shown as changed just because they are in another order. Values are only compared
+/// with each other if the key has several of them. Returns the value of `b` paired with each value
+/// of `a`, if any, and the values of `b` left unpaired, in their order.
+fn pair_identical<'a, A: Diff>(a: &'a [A], b: &'a [A]) -> (Vec<Option<&'a A>>, Vec<&'a A>) {
+    let mut rest: Vec<Option<&A>> = b.iter().map(Some).collect();
+    let pairs = if a.len() > 1 || b.len() > 1 {
+        a.iter()
+            .map(|value_a| {
+                let identical = rest
+                    .iter()
+                    .position(|value_b| value_b.is_some_and(|value_b| !value_a.differs(value_b)))?;
+                rest[identical].take()
+            })
+            .collect()
+    } else {
+        vec![None; a.len()]
+    };
+    (pairs, rest.into_iter().flatten().collect())
+}
+
 #[cfg(test)]
 mod test {
 
     use super::*;
+    use diff::attachment::Attachments;
     use diff::group::Group;
+    use diff::summary::Tally;
+    use keepass::db::{Entry, Node, Value};
+
+    fn keepass_entry(title: &str, password: &str) -> Entry {
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Title".to_owned(), Value::Unprotected(title.to_string()));
+        entry.fields.insert(
+            "Password".to_owned(),
+            Value::Protected(password.as_bytes().into()),
+        );
+        entry
+    }
+
+    fn keepass_group(passwords: &[(&str, &str)]) -> keepass::db::Group {
+        let mut group = keepass::db::Group::new("Root");
+        for (title, password) in passwords {
+            group
+                .children
+                .push(Node::Entry(keepass_entry(title, password)));
+        }
+        group
+    }
 
     #[test]
     fn diff_empty_groups() {
         let a = HashMap::<String, Vec<Group>>::new();
         let b = HashMap::<String, Vec<Group>>::new();
-        let (has_differences, _) = diff_hashmap(&a, &b);
+        let (has_differences, _) = diff_hashmap(&a, &b, Pairing::ByPosition);
+
+        assert!(!has_differences);
+    }
+
+    #[test]
+    fn collect_changes_of_identical_groups() {
+        let a = Group::from_keepass(
+            &keepass_group(&[("github", "pw")]),
+            &DiffOptions::default(),
+            &Attachments::new(),
+        );
+        let b = Group::from_keepass(
+            &keepass_group(&[("github", "pw")]),
+            &DiffOptions::default(),
+            &Attachments::new(),
+        );
+
+        assert_eq!(Vec::<Change>::new(), collect_changes(&a.diff(&b)));
+    }
+
+    #[test]
+    fn collect_changes_lists_parents_before_children() {
+        let a = Group::from_keepass(
+            &keepass_group(&[("github", "old")]),
+            &DiffOptions {
+                mask_passwords: true,
+                ..Default::default()
+            },
+            &Attachments::new(),
+        );
+        let b = Group::from_keepass(
+            &keepass_group(&[("github", "new"), ("gitlab", "pw")]),
+            &DiffOptions {
+                mask_passwords: true,
+                ..Default::default()
+            },
+            &Attachments::new(),
+        );
+        let changes = collect_changes(&a.diff(&b));
+
+        let summary: Vec<_> = changes
+            .iter()
+            .map(|c| (c.path.join("/"), c.item, c.name.as_str(), c.kind))
+            .collect();
+        assert_eq!(
+            vec![
+                ("".to_owned(), ItemKind::Group, "Root", ChangeKind::Changed),
+                (
+                    "Root".to_owned(),
+                    ItemKind::Entry,
+                    "github",
+                    ChangeKind::Changed
+                ),
+                (
+                    "Root/github".to_owned(),
+                    ItemKind::Field,
+                    "Password",
+                    ChangeKind::Changed
+                ),
+                (
+                    "Root".to_owned(),
+                    ItemKind::Entry,
+                    "gitlab",
+                    ChangeKind::Added
+                ),
+            ],
+            summary
+        );
+        assert_eq!(Some("***".to_owned()), changes[2].old);
+        assert_eq!(Some("***".to_owned()), changes[2].new);
+    }
+
+    #[test]
+    fn sort_by_username_pairs_entries_with_the_same_title() {
+        let group = |users: &[&str]| {
+            let mut group = keepass::db::Group::new("Root");
+            for user in users {
+                let mut entry = keepass_entry("mail", "pw");
+                entry
+                    .fields
+                    .insert("UserName".to_owned(), Value::Unprotected(user.to_string()));
+                group.children.push(Node::Entry(entry));
+            }
+            group
+        };
+        let options = |sort_by| DiffOptions {
+            sort_by,
+            ..Default::default()
+        };
+        let diff = |sort_by| {
+            let a = Group::from_keepass(
+                &group(&["bob", "alice"]),
+                &options(sort_by),
+                &Attachments::new(),
+            );
+            let b = Group::from_keepass(
+                &group(&["alice", "bob"]),
+                &options(sort_by),
+                &Attachments::new(),
+            );
+            let has_changes = a.diff(&b).has_changes();
+            has_changes
+        };
+
+        assert!(diff(SortBy::None));
+        assert!(!diff(SortBy::Username));
+    }
+
+    #[test]
+    fn detect_moves_pairs_entries_of_different_groups() {
+        let mut keepass_a = keepass_group(&[("github", "pw"), ("gitlab", "pw")]);
+        keepass_a
+            .children
+            .push(Node::Group(keepass::db::Group::new("Work")));
+        let mut keepass_b = keepass_group(&[("gitlab", "pw")]);
+        let mut work = keepass::db::Group::new("Work");
+        if let Some(Node::Entry(github)) = keepass_a.children.first() {
+            work.children.push(Node::Entry(github.clone()));
+        }
+        keepass_b.children.push(Node::Group(work));
+
+        let group =
+            |keepass| Group::from_keepass(keepass, &DiffOptions::default(), &Attachments::new());
+        let (mut a, mut b) = (group(&keepass_a), group(&keepass_b));
+        moves::detect_moves(&mut a, &mut b);
+        let changes = collect_changes(&a.diff(&b));
+
+        let entries: Vec<_> = changes
+            .iter()
+            .filter(|c| c.item == ItemKind::Entry)
+            .map(|c| (c.name.as_str(), c.kind, c.old.as_deref(), c.new.as_deref()))
+            .collect();
+        assert_eq!(
+            vec![("github", ChangeKind::Moved, Some("Root"), Some("Root/Work"))],
+            entries
+        );
+    }
+
+    #[test]
+    fn max_depth_reports_nested_groups_as_changed() {
+        let nested = |password| {
+            let mut inner = keepass_group(&[("github", password)]);
+            inner.name = "Inner".to_owned();
+            let mut outer = keepass::db::Group::new("Outer");
+            outer.children.push(Node::Group(inner));
+            let mut root = keepass_group(&[]);
+            root.children.push(Node::Group(outer));
+            root
+        };
+        let options = DiffOptions {
+            max_depth: Some(0),
+            ..Default::default()
+        };
+        let a = Group::from_keepass(&nested("old"), &options, &Attachments::new());
+        let b = Group::from_keepass(&nested("new"), &options, &Attachments::new());
+        let changes = collect_changes(&a.diff(&b));
+
+        let names: Vec<_> = changes.iter().map(|c| (c.name.as_str(), c.kind)).collect();
+        assert_eq!(
+            vec![
+                ("Root", ChangeKind::Changed),
+                ("Outer", ChangeKind::Changed)
+            ],
+            names
+        );
+        assert_eq!(Some("1 groups, 1 entries"), changes[1].old.as_deref());
+    }
+
+    #[test]
+    fn collect_entry_changes_lists_the_entries_of_added_groups() {
+        let mut added = keepass_group(&[("github", "pw"), ("gitlab", "pw")]);
+        added.name = "Work".to_owned();
+        let mut keepass_b = keepass_group(&[("mail", "new")]);
+        keepass_b.children.push(Node::Group(added));
+        keepass_b
+            .children
+            .push(Node::Group(keepass::db::Group::new("empty")));
+
+        let group =
+            |keepass| Group::from_keepass(keepass, &DiffOptions::default(), &Attachments::new());
+        let keepass_a = keepass_group(&[("mail", "old")]);
+        let (a, b) = (group(&keepass_a), group(&keepass_b));
+        let changes = collect_entry_changes(&a.diff(&b));
+
+        let entries: Vec<_> = changes
+            .iter()
+            .map(|c| (c.path.join("/"), c.item, c.name.as_str(), c.kind))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    "Root/Work".to_owned(),
+                    ItemKind::Entry,
+                    "github",
+                    ChangeKind::Added
+                ),
+                (
+                    "Root/Work".to_owned(),
+                    ItemKind::Entry,
+                    "gitlab",
+                    ChangeKind::Added
+                ),
+                (
+                    "Root".to_owned(),
+                    ItemKind::Entry,
+                    "mail",
+                    ChangeKind::Changed
+                ),
+            ],
+            entries
+        );
+    }
+
+    #[test]
+    fn has_changes_ignores_identical_nested_groups() {
+        let mut keepass_a = keepass_group(&[]);
+        keepass_a
+            .children
+            .push(Node::Group(keepass::db::Group::new("empty")));
+        let mut keepass_b = keepass_group(&[]);
+        keepass_b
+            .children
+            .push(Node::Group(keepass::db::Group::new("empty")));
+        let a = Group::from_keepass(&keepass_a, &DiffOptions::default(), &Attachments::new());
+        let b = Group::from_keepass(&keepass_b, &DiffOptions::default(), &Attachments::new());
+
+        assert!(!a.diff(&b).has_changes());
+        assert!(a
+            .diff(&Group::from_keepass(
+                &keepass_group(&[]),
+                &DiffOptions::default(),
+                &Attachments::new()
+            ))
+            .has_changes());
+    }
+
+    #[test]
+    fn match_by_uuid_pairs_renamed_entries() {
+        let entry = keepass_entry("AWS prod", "pw");
+        let mut renamed = entry.clone();
+        renamed.fields.insert(
+            "Title".to_owned(),
+            Value::Unprotected("AWS Production".to_owned()),
+        );
+        let mut keepass_a = keepass_group(&[]);
+        keepass_a.children.push(Node::Entry(entry));
+        let mut keepass_b = keepass_group(&[]);
+        keepass_b.children.push(Node::Entry(renamed));
+
+        let a = Group::from_keepass(
+            &keepass_a,
+            &DiffOptions {
+                match_by: MatchBy::Uuid,
+                ..Default::default()
+            },
+            &Attachments::new(),
+        );
+        let b = Group::from_keepass(
+            &keepass_b,
+            &DiffOptions {
+                match_by: MatchBy::Uuid,
+                ..Default::default()
+            },
+            &Attachments::new(),
+        );
+        let changes = collect_changes(&a.diff(&b));
+        let summary: Vec<_> = changes.iter().map(|c| (c.item, c.kind)).collect();
+        assert_eq!(
+            vec![
+                (ItemKind::Group, ChangeKind::Changed),
+                (ItemKind::Entry, ChangeKind::Changed),
+                (ItemKind::Field, ChangeKind::Changed),
+            ],
+            summary
+        );
+        assert_eq!("Title", changes[2].name);
+
+        let a = Group::from_keepass(&keepass_a, &DiffOptions::default(), &Attachments::new());
+        let b = Group::from_keepass(&keepass_b, &DiffOptions::default(), &Attachments::new());
+        let kinds: Vec<_> = collect_changes(&a.diff(&b))
+            .iter()
+            .map(|c| c.kind)
+            .collect();
+        assert_eq!(
+            vec![ChangeKind::Changed, ChangeKind::Added, ChangeKind::Removed],
+            kinds
+        );
+    }
+
+    #[test]
+    fn match_by_title_and_username_keeps_same_titles_apart() {
+        let admin = |user_name: &str, password: &str| {
+            let mut entry = keepass_entry("admin", password);
+            entry.fields.insert(
+                "UserName".to_owned(),
+                Value::Unprotected(user_name.to_owned()),
+            );
+            Node::Entry(entry)
+        };
+        let mut keepass_a = keepass_group(&[]);
+        keepass_a.children = vec![admin("alice", "pw"), admin("bob", "old")];
+        let mut keepass_b = keepass_group(&[]);
+        keepass_b.children = vec![admin("bob", "new"), admin("alice", "pw")];
+        let changed_fields = |match_by| {
+            let options = DiffOptions {
+                match_by,
+                sort_by: SortBy::None,
+                ..Default::default()
+            };
+            let a = Group::from_keepass(&keepass_a, &options, &Attachments::new());
+            let b = Group::from_keepass(&keepass_b, &options, &Attachments::new());
+            let fields: Vec<_> = collect_changes(&a.diff(&b))
+                .into_iter()
+                .filter(|c| c.item == ItemKind::Field)
+                .map(|c| c.name)
+                .collect();
+            fields
+        };
+
+        assert_eq!(
+            vec!["Password", "UserName", "Password", "UserName"],
+            changed_fields(MatchBy::Title)
+        );
+        assert_eq!(vec!["Password"], changed_fields(MatchBy::TitleUsername));
+    }
+
+    #[test]
+    fn ignored_fields_are_not_compared() {
+        let mut entry = keepass_entry("github", "pw");
+        entry
+            .fields
+            .insert("Notes".to_owned(), Value::Unprotected("old".to_owned()));
+        let mut changed = entry.clone();
+        changed
+            .fields
+            .insert("Notes".to_owned(), Value::Unprotected("new".to_owned()));
+        let mut keepass_a = keepass_group(&[]);
+        keepass_a.children.push(Node::Entry(entry));
+        let mut keepass_b = keepass_group(&[]);
+        keepass_b.children.push(Node::Entry(changed));
+
+        let diff_with = |options: &DiffOptions| {
+            let a = Group::from_keepass(&keepass_a, options, &Attachments::new());
+            let b = Group::from_keepass(&keepass_b, options, &Attachments::new());
+            let has_changes = a.diff(&b).has_changes();
+            has_changes
+        };
+        let ignore = |name: &str, ignore_fields_case_insensitive| DiffOptions {
+            ignore_fields: vec![name.to_owned()],
+            ignore_fields_case_insensitive,
+            ..Default::default()
+        };
+        assert!(diff_with(&DiffOptions::default()));
+        assert!(!diff_with(&ignore("Notes", false)));
+        assert!(diff_with(&ignore("notes", false)));
+        assert!(!diff_with(&ignore("notes", true)));
+    }
+
+    #[test]
+    fn only_the_given_fields_are_compared() {
+        let keepass_a = keepass_group(&[("github", "old")]);
+        let keepass_b = keepass_group(&[("github", "new")]);
+        let changes_with = |fields: &[&str]| {
+            let options = DiffOptions {
+                only_fields: fields.iter().map(|f| f.to_string()).collect(),
+                ..Default::default()
+            };
+            let a = Group::from_keepass(&keepass_a, &options, &Attachments::new());
+            let b = Group::from_keepass(&keepass_b, &options, &Attachments::new());
+            let changes = collect_changes(&a.diff(&b));
+            changes
+        };
+
+        assert!(changes_with(&["URL", "Notes"]).is_empty());
+        // Entries keep their title even if it is not compared
+        let changes = changes_with(&["Password"]);
+        let names: Vec<&str> = changes.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(vec!["Root", "github", "Password"], names);
+    }
+
+    #[test]
+    fn group_notes_and_icons_are_compared() {
+        let mut keepass_a = keepass_group(&[("github", "pw")]);
+        keepass_a.notes = Some("Old notes".to_owned());
+        keepass_a.icon_id = Some(48);
+        let mut keepass_b = keepass_group(&[("github", "pw")]);
+        keepass_b.icon_id = Some(49);
+        let a = Group::from_keepass(&keepass_a, &DiffOptions::default(), &Attachments::new());
+        let b = Group::from_keepass(&keepass_b, &DiffOptions::default(), &Attachments::new());
+        let changes = collect_changes(&a.diff(&b));
+
+        let icon = Change {
+            path: vec!["Root".to_owned()],
+            item: ItemKind::Field,
+            name: "Icon".to_owned(),
+            kind: ChangeKind::Changed,
+            old: Some("48".to_owned()),
+            new: Some("49".to_owned()),
+        };
+        let notes = Change {
+            name: "Notes".to_owned(),
+            kind: ChangeKind::Removed,
+            old: Some("Old notes".to_owned()),
+            new: None,
+            ..icon.clone()
+        };
+        assert_eq!(&changes[1..], &[icon, notes]);
+    }
+
+    #[test]
+    fn ignoring_times_and_metadata_leaves_out_group_attributes() {
+        let mut keepass_a = keepass_group(&[]);
+        keepass_a.icon_id = Some(48);
+        let mut keepass_b = keepass_group(&[]);
+        keepass_b.icon_id = Some(49);
+        keepass_b.times.expires = true;
+        let expiry = chrono::NaiveDate::from_ymd_opt(2030, 1, 1).unwrap();
+        keepass_b
+            .times
+            .set_expiry(expiry.and_hms_opt(0, 0, 0).unwrap());
+        let changed_attributes = |options: &DiffOptions| {
+            let a = Group::from_keepass(&keepass_a, options, &Attachments::new());
+            let b = Group::from_keepass(&keepass_b, options, &Attachments::new());
+            let changes = collect_changes(&a.diff(&b));
+            let names: Vec<String> = changes.into_iter().skip(1).map(|c| c.name).collect();
+            names
+        };
+        let ignore_times = DiffOptions {
+            ignore_times: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            vec!["Expires", "Icon"],
+            changed_attributes(&DiffOptions::default())
+        );
+        assert_eq!(vec!["Icon"], changed_attributes(&ignore_times));
+        assert!(changed_attributes(&DiffOptions {
+            ignore_metadata: true,
+            ..ignore_times
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn tags_are_compared_and_filter_entries() {
+        let mut keepass_a = keepass_group(&[("github", "pw"), ("gitlab", "old")]);
+        let mut keepass_b = keepass_group(&[("github", "pw"), ("gitlab", "new")]);
+        let tag = |group: &mut keepass::db::Group, tags: &str| match &mut group.children[0] {
+            Node::Entry(entry) => entry.tags = vec![tags.to_owned()],
+            Node::Group(_) => unreachable!(),
+        };
+        tag(&mut keepass_a, "legacy;prod");
+        tag(&mut keepass_b, "prod,web");
+        let changes = |options: &DiffOptions| {
+            let a = Group::from_keepass(&keepass_a, options, &Attachments::new());
+            let b = Group::from_keepass(&keepass_b, options, &Attachments::new());
+            let changes: Vec<_> = collect_changes(&a.diff(&b))
+                .into_iter()
+                .filter(|c| !c.item.is_node())
+                .map(|c| (c.kind, c.item, c.name))
+                .collect();
+            changes
+        };
+        let tag_changes = vec![
+            (ChangeKind::Removed, ItemKind::Tag, "legacy".to_owned()),
+            (ChangeKind::Added, ItemKind::Tag, "web".to_owned()),
+        ];
+
+        let all = changes(&DiffOptions::default());
+        assert_eq!(tag_changes[..], all[..2]);
+        assert_eq!(3, all.len());
+        assert_eq!(
+            tag_changes,
+            changes(&DiffOptions {
+                tags: vec!["prod".to_owned()],
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn summarize_leaves_out_untagged_entries() {
+        let mut keepass_a = keepass_group(&[("github", "pw"), ("gitlab", "pw")]);
+        let mut other = keepass::db::Group::new("other");
+        other
+            .children
+            .push(Node::Entry(keepass_entry("bitbucket", "pw")));
+        keepass_a.children.push(Node::Group(other));
+        match &mut keepass_a.children[0] {
+            Node::Entry(entry) => entry.tags = vec!["prod".to_owned()],
+            Node::Group(_) => unreachable!(),
+        }
+        let summary = |tag: &str| {
+            let options = DiffOptions {
+                tags: vec![tag.to_owned()],
+                ..Default::default()
+            };
+            let a = Group::from_keepass(&keepass_a, &options, &Attachments::new());
+            let b = Group::from_keepass(&keepass_a, &options, &Attachments::new());
+            let summary = summarize(&a.diff(&b));
+            summary
+        };
+
+        let tagged = summary("prod");
+        assert_eq!((1, 1), (tagged.groups.unchanged, tagged.entries.unchanged));
+        let untagged = summary("nomatch");
+        assert_eq!(
+            (1, 0),
+            (untagged.groups.unchanged, untagged.entries.unchanged)
+        );
+    }
+
+    #[test]
+    fn limit_stops_after_the_given_number_of_changes() {
+        let keepass_a = keepass_group(&[("github", "old"), ("gitlab", "old"), ("sourcehut", "pw")]);
+        let keepass_b = keepass_group(&[("github", "new"), ("gitlab", "new")]);
+        let a = Group::from_keepass(&keepass_a, &DiffOptions::default(), &Attachments::new());
+        let b = Group::from_keepass(&keepass_b, &DiffOptions::default(), &Attachments::new());
+        let render_with = |limit, ascii| {
+            let display = DiffDisplay {
+                inner: a.diff(&b),
+                path: Stack::empty(),
+                options: DisplayOptions {
+                    limit: Some(Limit::new(limit)),
+                    ascii,
+                    ..Default::default()
+                },
+            };
+            display.to_string()
+        };
+        let render = |limit| render_with(limit, false);
+
+        assert_eq!(
+            "- [Root, github, Password = old]\n\
+             + [Root, github, Password = new]\n\
+             … and 2 more changes\n",
+            render(1)
+        );
+        assert!(!render(3).contains("more"));
+        assert!(render_with(2, true).ends_with("\n... and 1 more change\n"));
+    }
+
+    /// Records the size of the largest write, to tell whether the output is written in pieces
+    #[derive(Default)]
+    struct LargestWrite {
+        largest: usize,
+        total: usize,
+    }
+
+    impl std::io::Write for LargestWrite {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.largest = self.largest.max(buf.len());
+            self.total += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_to_streams_large_differences() {
+        let titles: Vec<String> = (0..5000).map(|i| format!("entry {}", i)).collect();
+        let passwords = |password| -> Vec<(&str, &str)> {
+            titles.iter().map(|t| (t.as_str(), password)).collect()
+        };
+        let a = Group::from_keepass(
+            &keepass_group(&passwords("old")),
+            &DiffOptions::default(),
+            &Attachments::new(),
+        );
+        let b = Group::from_keepass(
+            &keepass_group(&passwords("new")),
+            &DiffOptions::default(),
+            &Attachments::new(),
+        );
+        let display = DiffDisplay {
+            inner: a.diff(&b),
+            path: Stack::empty(),
+            options: DisplayOptions::default(),
+        };
+        let mut out = termcolor::NoColor::new(LargestWrite::default());
+        display.write_to(&mut out).unwrap();
+
+        let written = out.into_inner();
+        assert!(written.total > 5000 * "+ [Root, entry 0, Password = new]\n".len());
+        assert!(
+            written.largest < 100,
+            "wrote {} bytes at once",
+            written.largest
+        );
+    }
+
+    #[test]
+    fn verbose_context_shows_unchanged_fields_of_changed_entries() {
+        let options = DiffOptions {
+            use_verbose: true,
+            ..Default::default()
+        };
+        let a = Group::from_keepass(
+            &keepass_group(&[("github", "old")]),
+            &options,
+            &Attachments::new(),
+        );
+        let b = Group::from_keepass(
+            &keepass_group(&[("github", "new")]),
+            &options,
+            &Attachments::new(),
+        );
+        let display = DiffDisplay {
+            inner: a.diff(&b),
+            path: Stack::empty(),
+            options: DisplayOptions {
+                use_verbose: true,
+                context: 1,
+                ..Default::default()
+            },
+        };
+
+        let output = display.to_string();
+        let lines: Vec<&str> = output.lines().skip(3).collect();
+
+        assert_eq!(
+            vec![
+                "-     Field 'Password' = 'old'",
+                "+     Field 'Password' = 'new'",
+                "      Field 'Title' = 'github'",
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn show_unchanged_lists_every_group_and_entry() {
+        let database = |password: &str, added: bool| {
+            let mut work = keepass_group(&[("github", password)]);
+            work.name = "Work".to_owned();
+            let mut root = keepass_group(&[("wiki", "hunter2")]);
+            root.children.push(Node::Group(work));
+            if added {
+                let mut mail = keepass_group(&[("inbox", "secret")]);
+                mail.name = "Mail".to_owned();
+                root.children.push(Node::Group(mail));
+            }
+            let options = DiffOptions {
+                mask_passwords: true,
+                ..Default::default()
+            };
+            Group::from_keepass(&root, &options, &Attachments::new())
+        };
+        let (a, b) = (database("old", false), database("new", true));
+        let display = DiffDisplay {
+            inner: a.diff(&b),
+            path: Stack::empty(),
+            options: DisplayOptions {
+                show_unchanged: true,
+                ..Default::default()
+            },
+        };
+
+        let output = display.to_string();
+        assert_eq!(
+            vec![
+                "~ [Root]",
+                "+ [Root, Mail]",
+                "+ [Root, Mail, inbox]",
+                "~ [Root, Work]",
+                "~ [Root, Work, github]",
+                "- [Root, Work, github, Password = ***]",
+                "+ [Root, Work, github, Password = ***]",
+                "  [Root, wiki]",
+            ],
+            output.lines().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn multiline_values_are_diffed_line_by_line() {
+        let group = |notes: &str| {
+            let mut entry = keepass_entry("server", "pw");
+            entry
+                .fields
+                .insert("Notes".to_owned(), Value::Unprotected(notes.to_owned()));
+            let mut group = keepass::db::Group::new("Root");
+            group.children.push(Node::Entry(entry));
+            Group::from_keepass(&group, &DiffOptions::default(), &Attachments::new())
+        };
+        let a = group("Host: db1\r\nPort: 5432\r\nUser: admin");
+        let b = group("Host: db1\r\nPort: 6543\r\nUser: admin\r\nBackup: nightly");
+        let display = DiffDisplay {
+            inner: a.diff(&b),
+            path: Stack::empty(),
+            options: DisplayOptions::default(),
+        };
+
+        assert_eq!(
+            "~ [Root, server, Notes]\n\
+             -       Port: 5432\n\
+             +       Port: 6543\n\
+             +       Backup: nightly\n",
+            display.to_string()
+        );
+    }
+
+    #[test]
+    fn reports_fields_that_are_no_longer_protected() {
+        let a = keepass_group(&[("github", "pw")]);
+        let mut b = a.clone();
+        if let Node::Entry(e) = &mut b.children[0] {
+            e.fields
+                .insert("Password".to_owned(), Value::Unprotected("pw".to_owned()));
+        }
+        let a = Group::from_keepass(&a, &DiffOptions::default(), &Attachments::new());
+        let b = Group::from_keepass(&b, &DiffOptions::default(), &Attachments::new());
+        let display = DiffDisplay {
+            inner: a.diff(&b),
+            path: Stack::empty(),
+            options: DisplayOptions::default(),
+        };
+
+        assert_eq!(
+            "- [Root, github, Password protection = protected]\n\
+             + [Root, github, Password protection = unprotected]\n",
+            display.to_string()
+        );
+    }
+
+    #[test]
+    fn compares_the_icons_of_entries_if_asked_to() {
+        let mut a = keepass_group(&[("github", "pw")]);
+        if let Node::Entry(e) = &mut a.children[0] {
+            e.icon_id = Some(42);
+        }
+        let mut b = a.clone();
+        if let Node::Entry(e) = &mut b.children[0] {
+            e.icon_id = Some(48);
+            e.custom_icon_uuid = None;
+        }
+        let changes = |show_icons| {
+            let options = DiffOptions {
+                show_icons,
+                ..Default::default()
+            };
+            let a = Group::from_keepass(&a, &options, &Attachments::new());
+            let b = Group::from_keepass(&b, &options, &Attachments::new());
+            let changes = collect_changes(&a.diff(&b));
+            changes
+                .into_iter()
+                .map(|c| (c.name, c.old, c.new))
+                .collect::<Vec<_>>()
+        };
+
+        assert!(changes(false).is_empty());
+        assert_eq!(
+            (
+                "Icon".to_owned(),
+                Some("42".to_owned()),
+                Some("48".to_owned())
+            ),
+            changes(true)[2]
+        );
+    }
+
+    #[test]
+    fn tells_which_database_modified_an_entry_last() {
+        let time = |s: &str| chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap();
+        let options = DiffOptions {
+            newer_wins: true,
+            ..Default::default()
+        };
+        let entry = |modified: Option<&str>| {
+            let mut e = keepass_entry("github", "pw");
+            e.times = keepass::db::Times::default();
+            if let Some(modified) = modified {
+                e.times.set_last_modification(time(modified));
+            }
+            entry::Entry::from_keepass(&e, &options, &Attachments::new())
+        };
+        let note = |a, b| entry(a).change_note(&entry(b));
+
+        let (older, newer) = (Some("2023-06-01 10:00"), Some("2024-01-31 23:59"));
+        assert_eq!(Some("(B is newer)".to_owned()), note(older, newer));
+        assert_eq!(Some("(A is newer)".to_owned()), note(newer, older));
+        assert_eq!(Some("(same/unknown time)".to_owned()), note(newer, newer));
+        assert_eq!(Some("(same/unknown time)".to_owned()), note(None, newer));
+    }
+
+    #[test]
+    fn changed_passwords_only_shows_entries_with_only_a_new_password() {
+        let keepass_a = keepass_group(&[("github", "old"), ("gitlab", "old"), ("wiki", "pw")]);
+        let mut keepass_b = keepass_group(&[("github", "new"), ("gitlab", "new"), ("mail", "pw")]);
+        if let Node::Entry(gitlab) = &mut keepass_b.children[1] {
+            gitlab.fields.insert(
+                "UserName".to_owned(),
+                Value::Unprotected("admin".to_owned()),
+            );
+        }
+        let options = DiffOptions {
+            changed_passwords_only: true,
+            ..Default::default()
+        };
+        let a = Group::from_keepass(&keepass_a, &options, &Attachments::new());
+        let b = Group::from_keepass(&keepass_b, &options, &Attachments::new());
+
+        let changes: Vec<_> = collect_changes(&a.diff(&b))
+            .into_iter()
+            .filter(|c| c.item != ItemKind::Group)
+            .map(|c| (c.name, c.kind))
+            .collect();
+        assert_eq!(
+            vec![
+                ("github".to_owned(), ChangeKind::Changed),
+                ("Password".to_owned(), ChangeKind::Changed)
+            ],
+            changes
+        );
+    }
+
+    #[test]
+    fn summarize_counts_nested_nodes() {
+        let mut keepass_a = keepass_group(&[("github", "old"), ("gitlab", "pw")]);
+        let mut removed = keepass::db::Group::new("removed");
+        removed
+            .children
+            .push(Node::Entry(keepass_entry("bitbucket", "pw")));
+        removed
+            .children
+            .push(Node::Group(keepass::db::Group::new("nested")));
+        keepass_a.children.push(Node::Group(removed));
+        let keepass_b = keepass_group(&[("github", "new"), ("gitlab", "pw"), ("gitea", "pw")]);
+
+        let a = Group::from_keepass(&keepass_a, &DiffOptions::default(), &Attachments::new());
+        let b = Group::from_keepass(&keepass_b, &DiffOptions::default(), &Attachments::new());
+        let summary = summarize(&a.diff(&b));
+
+        assert_eq!(
+            Tally {
+                added: 0,
+                removed: 2,
+                changed: 1,
+                unchanged: 0
+            },
+            summary.groups
+        );
+        assert_eq!(
+            Tally {
+                added: 1,
+                removed: 1,
+                changed: 1,
+                unchanged: 1
+            },
+            summary.entries
+        );
+        assert_eq!(
+            "0 groups added, 2 removed, 1 changed, 0 unchanged; \
+             1 entry added, 1 removed, 1 changed, 1 unchanged",
+            summary.to_string()
+        );
+    }
+
+    #[test]
+    fn ignore_case_pairs_entries_with_differently_cased_titles() {
+        let keepass_entry_of = |title: &str, username: &str, password: &str| {
+            let mut entry = keepass_entry(title, password);
+            entry.fields.insert(
+                "UserName".to_owned(),
+                Value::Unprotected(username.to_owned()),
+            );
+            let mut group = keepass_group(&[]);
+            group.children.push(Node::Entry(entry));
+            group
+        };
+        let options = DiffOptions {
+            ignore_case_fields: vec!["Title".to_owned(), "UserName".to_owned()],
+            ..Default::default()
+        };
+        let diff_with = |a: &keepass::db::Group, b: &keepass::db::Group| {
+            let a = Group::from_keepass(a, &options, &Attachments::new());
+            let b = Group::from_keepass(b, &options, &Attachments::new());
+            let changes = collect_changes(&a.diff(&b));
+            changes
+                .iter()
+                .map(|c| (c.item, c.name.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        let a = keepass_entry_of("Straße", "Ärger", "pw");
+        assert!(diff_with(&a, &keepass_entry_of("STRASSE", "ärger", "pw")).is_empty());
+        assert_eq!(
+            vec![
+                (ItemKind::Group, "Root".to_owned()),
+                (ItemKind::Entry, "Straße".to_owned()),
+                (ItemKind::Field, "Password".to_owned()),
+            ],
+            diff_with(&a, &keepass_entry_of("strasse", "ÄRGER", "PW"))
+        );
+    }
+
+    #[test]
+    fn field_rules_normalize_values_before_comparing_them() {
+        let keepass_entry_of = |url: &str, password: &str| {
+            let mut entry = keepass_entry("github", password);
+            entry
+                .fields
+                .insert("URL".to_owned(), Value::Unprotected(url.to_owned()));
+            let mut group = keepass_group(&[]);
+            group.children.push(Node::Entry(entry));
+            group
+        };
+        let options = DiffOptions {
+            mask_passwords: true,
+            field_rules: vec![
+                "URL=trim,lowercase".parse().unwrap(),
+                "URL=strip-trailing-slash".parse().unwrap(),
+                "Password=trim".parse().unwrap(),
+            ],
+            ..Default::default()
+        };
+        let diff_with = |a: &keepass::db::Group, b: &keepass::db::Group| {
+            let a = Group::from_keepass(a, &options, &Attachments::new());
+            let b = Group::from_keepass(b, &options, &Attachments::new());
+            let changes = collect_changes(&a.diff(&b));
+            changes
+                .iter()
+                .map(|c| (c.name.clone(), c.new.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        let a = keepass_entry_of("https://github.com", "secret");
+        let b = keepass_entry_of(" HTTPS://GitHub.com/", "secret ");
+        assert!(diff_with(&a, &b).is_empty());
+        let b = keepass_entry_of("https://github.com/login", "secret");
+        assert_eq!(
+            Some("https://github.com/login".to_owned()),
+            diff_with(&a, &b).last().and_then(|(_, new)| new.clone())
+        );
+    }
+
+    #[test]
+    fn pairs_up_untitled_entries_by_their_uuid() {
+        let a = keepass_group(&[("", "first"), ("", "second")]);
+        let mut b = a.clone();
+        b.children.reverse();
+        if let Node::Entry(e) = &mut b.children[0] {
+            e.fields.insert(
+                "Password".to_owned(),
+                Value::Protected("changed".as_bytes().into()),
+            );
+        }
+        let second = match &a.children[1] {
+            Node::Entry(e) => e.uuid.simple().to_string(),
+            _ => unreachable!(),
+        };
+
+        let options = DiffOptions::default();
+        let a = Group::from_keepass(&a, &options, &Attachments::new());
+        let b = Group::from_keepass(&b, &options, &Attachments::new());
+        let changes = collect_changes(&a.diff(&b));
+        assert_eq!(
+            vec![
+                (ItemKind::Group, "Root".to_owned()),
+                (ItemKind::Entry, format!("(untitled {})", &second[..8])),
+                (ItemKind::Field, "Password".to_owned()),
+            ],
+            changes
+                .iter()
+                .map(|c| (c.item, c.name.clone()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(2, duplicates::find_untitled(&a).len());
+    }
+
+    #[test]
+    fn finds_entries_with_the_same_key_or_fields() {
+        let group = keepass_group(&[
+            ("github", "a"),
+            ("wiki", "pw"),
+            ("github", "b"),
+            ("wiki", "pw"),
+            ("mail", "pw"),
+        ]);
+        let duplicates = |match_by| {
+            let options = DiffOptions {
+                match_by,
+                ..Default::default()
+            };
+            let group = Group::from_keepass(&group, &options, &Attachments::new());
+            duplicates::find_duplicates(&group)
+                .into_iter()
+                .map(|d| (d.title, d.count, d.identical))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            vec![
+                ("github".to_owned(), 2, false),
+                ("wiki".to_owned(), 2, true)
+            ],
+            duplicates(MatchBy::Title)
+        );
+        // Entries with different UUIDs are not paired up, but still the same
+        assert_eq!(
+            vec![("wiki".to_owned(), 2, true)],
+            duplicates(MatchBy::Uuid)
+        );
+    }
+
+    #[test]
+    fn follows_field_references() {
+        let target = keepass_entry("github", "secret");
+        let reference = format!("{{REF:P@I:{}}}", target.uuid.simple());
+        let a = keepass_group(&[("github", "secret"), ("mirror", "secret")]);
+        let mut b = keepass::db::Group::new("Root");
+        b.children.push(Node::Entry(target));
+        b.children
+            .push(Node::Entry(keepass_entry("mirror", &reference)));
+        b.children
+            .push(Node::Entry(keepass_entry("broken", "{REF:P@T:missing}")));
+        let options = DiffOptions {
+            follow_references: true,
+            ..Default::default()
+        };
+        let a = Group::from_keepass(&a, &options, &Attachments::new());
+        let b = Group::from_keepass(&b, &options, &Attachments::new());
+
+        let changes: Vec<_> = collect_changes(&a.diff(&b))
+            .into_iter()
+            .filter(|c| c.item == ItemKind::Entry)
+            .map(|c| (c.name, c.kind))
+            .collect();
+        assert_eq!(vec![("broken".to_owned(), ChangeKind::Added)], changes);
+        assert_eq!(
+            vec![references::Unresolved {
+                path: vec!["Root".to_owned(), "broken".to_owned()],
+                field: "Password".to_owned(),
+                reference: "{REF:P@T:missing}".to_owned(),
+            }],
+            b.unresolved_references()
+        );
+    }
+
+    #[test]
+    fn flattened_databases_are_compared_regardless_of_their_groups() {
+        let options = DiffOptions {
+            match_by: MatchBy::TitleUsername,
+            ..Default::default()
+        };
+        let group = |name: &str, passwords: &[(&str, &str)]| {
+            let mut group = keepass_group(passwords);
+            group.name = name.to_owned();
+            group
+        };
+        let mut nested = keepass_group(&[]);
+        let mut work = group("Work", &[("github", "secret")]);
+        work.children
+            .push(Node::Group(group("Email", &[("mail", "hunter2")])));
+        nested.children.push(Node::Group(work));
+        nested
+            .children
+            .push(Node::Group(group("Personal", &[("wiki", "old")])));
+        let flat = keepass_group(&[("wiki", "new"), ("github", "secret"), ("bank", "1234")]);
+        let mut a = Group::from_keepass(&nested, &options, &Attachments::new());
+        let mut b = Group::from_keepass(&flat, &options, &Attachments::new());
+        a.flatten();
+        b.flatten();
+
+        let changes: Vec<_> = collect_changes(&a.diff(&b))
+            .into_iter()
+            .filter(|c| !c.is_container())
+            .map(|c| (c.path.join("/"), c.name, c.kind))
+            .collect();
+        assert_eq!(
+            vec![
+                ("Root".to_owned(), "bank".to_owned(), ChangeKind::Added),
+                (
+                    "Root".to_owned(),
+                    "mail (in Work/Email)".to_owned(),
+                    ChangeKind::Removed
+                ),
+                (
+                    "Root/wiki (in Personal)".to_owned(),
+                    "Password".to_owned(),
+                    ChangeKind::Changed
+                ),
+            ],
+            changes
+        );
+    }
+
+    #[test]
+    fn flattened_entries_with_the_same_key_are_paired_with_identical_ones() {
+        let group = |name: &str, password: &str| {
+            let mut group = keepass_group(&[("x", password)]);
+            group.name = name.to_owned();
+            Node::Group(group)
+        };
+        let mut keepass_a = keepass_group(&[("x", "root")]);
+        keepass_a.children.push(group("G1", "p"));
+        keepass_a.children.push(group("G2", "q"));
+        let mut keepass_b = keepass_group(&[("x", "root")]);
+        keepass_b.children.push(group("G1", "q"));
+        keepass_b.children.push(group("G3", "p"));
+        let flattened = |keepass: &keepass::db::Group| {
+            let mut group =
+                Group::from_keepass(keepass, &DiffOptions::default(), &Attachments::new());
+            group.flatten();
+            group
+        };
+        let (a, b) = (flattened(&keepass_a), flattened(&keepass_b));
+        assert!(!a.diff(&b).has_changes());
+
+        keepass_b.children.push(group("G4", "r"));
+        let b = flattened(&keepass_b);
+        let changes: Vec<_> = collect_changes(&a.diff(&b))
+            .into_iter()
+            .filter(|c| !c.is_container())
+            .map(|c| (c.name, c.kind))
+            .collect();
+        assert_eq!(vec![("x (in G4)".to_owned(), ChangeKind::Added)], changes);
+    }
+
+    #[test]
+    fn compares_roots_with_other_names_by_their_contents() {
+        let database = |root: &str| {
+            let mut work = keepass_group(&[("github", "secret")]);
+            work.name = "Work".to_owned();
+            let mut group = keepass_group(&[("wiki", "hunter2")]);
+            group.name = root.to_owned();
+            group.children.push(Node::Group(work));
+            Group::from_keepass(&group, &DiffOptions::default(), &Attachments::new())
+        };
+        let (mut a, mut b) = (database("Database"), database("Passwords"));
 
-        assert_eq!(false, has_differences);
+        assert!(!a.diff(&b).has_changes());
+        moves::detect_moves(&mut a, &mut b);
+        assert!(collect_changes(&a.diff(&b)).is_empty());
     }
 }
@@ -0,0 +1,158 @@
+//! Detection of entries that moved to another group between both databases
+
+use crate::diff::entry::Entry;
+use crate::diff::group::Group;
+use crate::diff::{Diff, DiffResult};
+
+/// Where an entry moved, as found by `detect_moves`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Move {
+    /// The entry of the first database moved to the group with the given path in the second
+    /// database, where it is `entry`
+    To {
+        path: Vec<String>,
+        entry: Box<Entry>,
+    },
+    /// The entry of the second database moved here from another group
+    From,
+}
+
+/// An entry that only exists in one of two paired groups
+struct Unpaired {
+    /// Keys and indices of the groups leading to the entry, starting below the root
+    location: Vec<(String, usize)>,
+    key: String,
+    index: usize,
+    /// Names of the groups containing the entry, starting at the root
+    path: Vec<String>,
+    entry: Entry,
+}
+
+/// Marks the entries that disappeared from one group and appeared in another group, so diffing
+/// `a` with `b` lists them as moved instead of as removed and added. Entries move if they have the
+/// same UUID or the same fields and attachments. Only groups that exist in both databases are
+/// considered.
+pub fn detect_moves(a: &mut Group, b: &mut Group) {
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    collect_unpaired(
+        a,
+        b,
+        &mut Vec::new(),
+        &mut vec![a.name.clone()],
+        &mut vec![b.name.clone()],
+        &mut removed,
+        &mut added,
+    );
+
+    // Entries are collected in the arbitrary order of hash maps, so order them by their location
+    let order = |u: &Unpaired| (u.location.clone(), u.key.clone(), u.index);
+    removed.sort_by_cached_key(order);
+    added.sort_by_cached_key(order);
+
+    let mut moves = Vec::new();
+    let same_uuid = |r: &Entry, a: &Entry| r.uuid.is_some() && r.uuid == a.uuid;
+    let same_content =
+        |r: &Entry, a: &Entry| r.fields == a.fields && r.attachments == a.attachments;
+    for matches in [&same_uuid as &dyn Fn(&Entry, &Entry) -> bool, &same_content] {
+        for (r_index, r) in removed.iter().enumerate() {
+            if moves.iter().any(|&(taken, _)| taken == r_index) {
+                continue;
+            }
+            let destination = added.iter().enumerate().find(|&(a_index, a)| {
+                !moves.iter().any(|&(_, taken)| taken == a_index)
+                    && a.path[1..] != r.path[1..]
+                    && matches(&r.entry, &a.entry)
+            });
+            if let Some((a_index, _)) = destination {
+                moves.push((r_index, a_index));
+            }
+        }
+    }
+
+    for (r_index, a_index) in moves {
+        let (r, d) = (&removed[r_index], &added[a_index]);
+        entry_at(b, d).moved = Some(Move::From);
+        entry_at(a, r).moved = Some(Move::To {
+            path: d.path.clone(),
+            entry: Box::new(d.entry.clone()),
+        });
+    }
+}
+
+/// Replaces the removed entries that moved by their moves and leaves out the added entries that
+/// moved here, as marked by `detect_moves`
+pub(crate) fn resolve_moves(differences: Vec<DiffResult<'_, Entry>>) -> Vec<DiffResult<'_, Entry>> {
+    differences
+        .into_iter()
+        .filter_map(|dr| match dr {
+            DiffResult::OnlyLeft { left } => match &left.moved {
+                Some(Move::To { path, entry }) => Some(DiffResult::Moved {
+                    left,
+                    right: entry,
+                    to: path,
+                    difference: Box::new(left.diff(entry)),
+                }),
+                _ => Some(dr),
+            },
+            DiffResult::OnlyRight { right } if right.moved == Some(Move::From) => None,
+            _ => Some(dr),
+        })
+        .collect()
+}
+
+/// Collects the entries of paired groups that are not paired up with an entry of the other group
+fn collect_unpaired(
+    a: &Group,
+    b: &Group,
+    location: &mut Vec<(String, usize)>,
+    path_a: &mut Vec<String>,
+    path_b: &mut Vec<String>,
+    removed: &mut Vec<Unpaired>,
+    added: &mut Vec<Unpaired>,
+) {
+    let unpaired = |key: &String, index: usize, path: &[String], entry: &Entry| Unpaired {
+        location: location.clone(),
+        key: key.clone(),
+        index,
+        path: path.to_vec(),
+        entry: entry.clone(),
+    };
+    let empty = Vec::new();
+    for (key, entries) in &a.entries {
+        let paired = b.entries.get(key).unwrap_or(&empty).len();
+        for (index, entry) in entries.iter().enumerate().skip(paired) {
+            removed.push(unpaired(key, index, path_a, entry));
+        }
+    }
+    for (key, entries) in &b.entries {
+        let paired = a.entries.get(key).unwrap_or(&empty).len();
+        for (index, entry) in entries.iter().enumerate().skip(paired) {
+            added.push(unpaired(key, index, path_b, entry));
+        }
+    }
+
+    for (key, groups_a) in &a.child_groups {
+        let groups_b = match b.child_groups.get(key) {
+            Some(groups_b) => groups_b,
+            None => continue,
+        };
+        for (index, (group_a, group_b)) in groups_a.iter().zip(groups_b).enumerate() {
+            location.push((key.clone(), index));
+            path_a.push(group_a.name.clone());
+            path_b.push(group_b.name.clone());
+            collect_unpaired(group_a, group_b, location, path_a, path_b, removed, added);
+            location.pop();
+            path_a.pop();
+            path_b.pop();
+        }
+    }
+}
+
+/// The entry at the location of `unpaired` below `group`
+fn entry_at<'a>(group: &'a mut Group, unpaired: &Unpaired) -> &'a mut Entry {
+    let mut group = group;
+    for (key, index) in &unpaired.location {
+        group = &mut group.child_groups.get_mut(key).unwrap()[*index];
+    }
+    &mut group.entries.get_mut(&unpaired.key).unwrap()[unpaired.index]
+}
@@ -0,0 +1,100 @@
+//! Normalization of the values of fields before they are compared, as given with `--field-rule`
+
+use clap::ValueEnum;
+
+use std::borrow::Cow;
+use std::str::FromStr;
+
+/// A transformation of a value before it is compared. The value is still shown as it is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Normalizer {
+    /// Leaves out whitespace at the start and the end
+    Trim,
+    /// Converts all letters to lowercase
+    Lowercase,
+    /// Replaces each run of whitespace by a single space, and leaves it out at the start and the
+    /// end
+    CollapseWhitespace,
+    /// Leaves out one slash at the end, as in `https://example.com/`
+    StripTrailingSlash,
+}
+
+impl Normalizer {
+    fn apply(self, value: &str) -> String {
+        match self {
+            Normalizer::Trim => value.trim().to_owned(),
+            Normalizer::Lowercase => value.to_lowercase(),
+            Normalizer::CollapseWhitespace => {
+                value.split_whitespace().collect::<Vec<_>>().join(" ")
+            }
+            Normalizer::StripTrailingSlash => value.strip_suffix('/').unwrap_or(value).to_owned(),
+        }
+    }
+}
+
+/// The normalizers applied to the values of a field, like `URL=trim,strip-trailing-slash`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldRule {
+    pub field: String,
+    pub normalizers: Vec<Normalizer>,
+}
+
+impl FromStr for FieldRule {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let (field, normalizers) = rule
+            .split_once('=')
+            .ok_or_else(|| format!("expected FIELD=RULES like URL=trim, got {}", rule))?;
+        if field.is_empty() {
+            return Err(format!("the field of {} has no name", rule));
+        }
+        let normalizers = normalizers
+            .split(',')
+            .map(|name| {
+                Normalizer::from_str(name.trim(), true).map_err(|_| {
+                    let known: Vec<String> = Normalizer::value_variants()
+                        .iter()
+                        .filter_map(|n| n.to_possible_value())
+                        .map(|n| n.get_name().to_owned())
+                        .collect();
+                    format!("unknown rule {} (expected {})", name, known.join(", "))
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(FieldRule {
+            field: field.to_owned(),
+            normalizers,
+        })
+    }
+}
+
+/// The value with the normalizers applied in their order
+pub fn normalize<'a>(value: &'a str, normalizers: &[Normalizer]) -> Cow<'a, str> {
+    normalizers
+        .iter()
+        .fold(Cow::Borrowed(value), |value, n| Cow::Owned(n.apply(&value)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_and_applies_rules() {
+        let rule: FieldRule = "URL=trim,lowercase,strip-trailing-slash".parse().unwrap();
+        assert_eq!("URL", rule.field);
+        assert_eq!(
+            "https://example.com",
+            normalize("  HTTPS://Example.com/ ", &rule.normalizers)
+        );
+        let rule: FieldRule = "Phone=collapse-whitespace".parse().unwrap();
+        assert_eq!(
+            "+1 555 0100",
+            normalize(" +1  555\t0100", &rule.normalizers)
+        );
+
+        assert!("URL".parse::<FieldRule>().is_err());
+        assert!("URL=uppercase".parse::<FieldRule>().is_err());
+    }
+}
@@ -0,0 +1,206 @@
+//! Resolution of KeePass field references like `{REF:P@I:<UUID>}`, which stand for the value of
+//! a field of another entry, so entries are compared by the values they stand for
+
+use keepass::db::{Entry, Group, History, Node, Value};
+use secstr::SecStr;
+use zeroize::Zeroizing;
+
+use std::borrow::Cow;
+
+/// Starts a field reference, which KeePass matches regardless of its case
+const REFERENCE_START: &str = "{REF:";
+
+/// How many references are followed from a value, so references that refer to each other stop
+const MAX_DEPTH: usize = 10;
+
+/// Fields of an entry that stand for themselves, all others are searched with `O`
+const STANDARD_FIELDS: &[&str] = &["Title", "UserName", "Password", "URL", "Notes"];
+
+/// A field reference that could not be resolved, which is compared as it is
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Unresolved {
+    /// Names of the groups containing the entry, starting at the root, and its title
+    pub path: Vec<String>,
+    pub field: String,
+    pub reference: String,
+}
+
+/// Replaces the field references in the entries of `root` and in their history with the values
+/// they refer to. KeePass references look like `{REF:P@I:46C9B1FFBD4ABC4BBB260C6190BAD20C}`: the
+/// password (`P`) of the entry whose UUID (`I`) is the given one. Entries can be referred to by a
+/// text in their title (`T`), user name (`U`), password (`P`), URL (`A`), notes (`N`) or other
+/// fields (`O`) as well, and their title, user name, password, URL, notes or UUID taken. The first
+/// entry of the database that matches is taken.
+///
+/// Returns the references of the entries that could not be resolved, which are left as they are.
+pub fn resolve(root: &mut Group) -> Vec<Unresolved> {
+    let mut entries = Vec::new();
+    collect_entries(root, &mut entries);
+    let mut unresolved = Vec::new();
+    let mut path = vec![root.name.clone()];
+    resolve_group(root, &entries, &mut path, &mut unresolved);
+    unresolved
+}
+
+fn collect_entries(group: &Group, entries: &mut Vec<Entry>) {
+    for node in &group.children {
+        match node {
+            Node::Group(g) => collect_entries(g, entries),
+            Node::Entry(e) => entries.push(e.clone()),
+        }
+    }
+}
+
+fn resolve_group(
+    group: &mut Group,
+    entries: &[Entry],
+    path: &mut Vec<String>,
+    unresolved: &mut Vec<Unresolved>,
+) {
+    for node in group.children.iter_mut() {
+        match node {
+            Node::Group(g) => {
+                path.push(g.name.clone());
+                resolve_group(g, entries, path, unresolved);
+                path.pop();
+            }
+            Node::Entry(e) => {
+                let mut failed = Vec::new();
+                resolve_entry(e, entries, &mut failed);
+                // Prior versions are compared by their fields as well, but only reported once
+                if let Some(history) = e.history.as_mut() {
+                    // History only adds versions in front, so they are added back last first
+                    let mut resolved = History::default();
+                    for version in history.get_entries().iter().rev() {
+                        let mut version = version.clone();
+                        resolve_entry(&mut version, entries, &mut Vec::new());
+                        resolved.add_entry(version);
+                    }
+                    *history = resolved;
+                }
+                let mut entry_path = path.clone();
+                entry_path.push(e.get_title().unwrap_or_default().to_owned());
+                unresolved.extend(failed.into_iter().map(|(field, reference)| Unresolved {
+                    path: entry_path.clone(),
+                    field,
+                    reference,
+                }));
+            }
+        }
+    }
+}
+
+/// Resolves the references in the fields of an entry, keeping whether they are protected. Adds
+/// the names of the fields and the references that could not be resolved to `failed`.
+fn resolve_entry(entry: &mut Entry, entries: &[Entry], failed: &mut Vec<(String, String)>) {
+    for (name, value) in entry.fields.iter_mut() {
+        let resolved = match value {
+            Value::Unprotected(text) if has_reference(text) => {
+                let mut references = Vec::new();
+                let resolved = resolve_value(text, entries, 0, &mut references);
+                failed.extend(references.into_iter().map(|r| (name.clone(), r)));
+                Value::Unprotected(resolved.to_string())
+            }
+            Value::Protected(secret) => match std::str::from_utf8(secret.unsecure()) {
+                Ok(text) if has_reference(text) => {
+                    let mut references = Vec::new();
+                    let resolved = resolve_value(text, entries, 0, &mut references);
+                    failed.extend(references.into_iter().map(|r| (name.clone(), r)));
+                    Value::Protected(SecStr::from(resolved.as_str()))
+                }
+                _ => continue,
+            },
+            _ => continue,
+        };
+        *value = resolved;
+    }
+}
+
+fn has_reference(text: &str) -> bool {
+    text.to_ascii_uppercase().contains(REFERENCE_START)
+}
+
+/// The value with its references replaced by the values they refer to, which are resolved as
+/// well. The references that cannot be resolved are kept and added to `unresolved`.
+fn resolve_value(
+    value: &str,
+    entries: &[Entry],
+    depth: usize,
+    unresolved: &mut Vec<String>,
+) -> Zeroizing<String> {
+    let mut resolved = Zeroizing::new(String::new());
+    let mut rest = value;
+    // Upper-casing ASCII keeps the length, so positions in it are positions in the value
+    while let Some(start) = rest.to_ascii_uppercase().find(REFERENCE_START) {
+        resolved.push_str(&rest[..start]);
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end + 1,
+            None => break,
+        };
+        let reference = &rest[start..end];
+        let target = match depth {
+            MAX_DEPTH => None,
+            _ => referred_value(reference, entries),
+        };
+        match target {
+            Some(target) => {
+                resolved.push_str(&resolve_value(&target, entries, depth + 1, unresolved))
+            }
+            None => {
+                unresolved.push(reference.to_owned());
+                resolved.push_str(reference);
+            }
+        }
+        rest = &rest[end..];
+    }
+    resolved.push_str(rest);
+    resolved
+}
+
+/// The value that a reference like `{REF:P@I:<UUID>}` refers to, if the reference is valid and
+/// an entry matches it
+fn referred_value<'a>(reference: &str, entries: &'a [Entry]) -> Option<Cow<'a, str>> {
+    let inner = &reference[REFERENCE_START.len()..reference.len() - 1];
+    let mut chars = inner.chars();
+    let wanted = chars.next()?.to_ascii_uppercase();
+    let at = chars.next()?;
+    let search_in = chars.next()?.to_ascii_uppercase();
+    if at != '@' || chars.next()? != ':' {
+        return None;
+    }
+    let text = chars.as_str();
+    let entry = entries.iter().find(|e| is_match(e, search_in, text))?;
+    match wanted {
+        // KeePass writes UUIDs as 32 uppercase hex digits
+        'I' => Some(Cow::Owned(entry.uuid.simple().to_string().to_uppercase())),
+        wanted => entry.get(field_name(wanted)?).map(Cow::Borrowed),
+    }
+}
+
+/// Whether the entry is the one referred to by `text` in the field given by `search_in`
+fn is_match(entry: &Entry, search_in: char, text: &str) -> bool {
+    let contains = |value: &str| value.to_lowercase().contains(&text.to_lowercase());
+    match search_in {
+        'I' => entry.uuid.simple().to_string().eq_ignore_ascii_case(text),
+        'O' => entry
+            .fields
+            .keys()
+            .filter(|name| !STANDARD_FIELDS.contains(&name.as_str()))
+            .any(|name| entry.get(name).is_some_and(contains)),
+        search_in => field_name(search_in)
+            .and_then(|name| entry.get(name))
+            .is_some_and(contains),
+    }
+}
+
+/// The field of an entry that the letter stands for in a reference
+fn field_name(letter: char) -> Option<&'static str> {
+    match letter {
+        'T' => Some("Title"),
+        'U' => Some("UserName"),
+        'P' => Some("Password"),
+        'A' => Some("URL"),
+        'N' => Some("Notes"),
+        _ => None,
+    }
+}
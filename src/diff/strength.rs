@@ -0,0 +1,78 @@
+//! Estimates of how hard passwords are to guess, to tell whether a changed password got weaker
+
+/// The least entropy in bits for the scores 1 to 4
+const SCORE_BITS: [f64; 4] = [28.0, 36.0, 60.0, 128.0];
+
+/// Number of characters in each class of characters, see `entropy`
+const LOWERCASE: u32 = 26;
+const UPPERCASE: u32 = 26;
+const DIGITS: u32 = 10;
+const SYMBOLS: u32 = 33;
+/// Counted for any character that is not ASCII, like letters with accents
+const OTHERS: u32 = 100;
+
+/// Scores a password from 0 (very weak) to 4 (very strong) by its estimated entropy, with 28, 36,
+/// 60 and 128 bits for the scores 1, 2, 3 and 4
+pub fn score(password: &str) -> u8 {
+    let bits = entropy(password);
+    SCORE_BITS.iter().filter(|&&least| bits >= least).count() as u8
+}
+
+/// Estimates the entropy of a password in bits from the classes of characters it uses, like
+/// lowercase letters and digits, as if each character was picked from all classes it uses.
+/// Characters that repeat the one before or continue a sequence like `abc` or `321` count for one
+/// bit only, as they are easily guessed.
+pub fn entropy(password: &str) -> f64 {
+    let chars: Vec<char> = password.chars().collect();
+    let uses = |class: fn(&char) -> bool, size| {
+        if chars.iter().any(class) {
+            size
+        } else {
+            0
+        }
+    };
+    let pool = uses(char::is_ascii_lowercase, LOWERCASE)
+        + uses(char::is_ascii_uppercase, UPPERCASE)
+        + uses(char::is_ascii_digit, DIGITS)
+        + uses(|c| c.is_ascii_punctuation() || *c == ' ', SYMBOLS)
+        + uses(
+            |c| !c.is_ascii_alphanumeric() && !c.is_ascii_punctuation() && *c != ' ',
+            OTHERS,
+        );
+    if pool == 0 {
+        return 0.0;
+    }
+    let bits_per_char = f64::from(pool).log2();
+    chars
+        .iter()
+        .enumerate()
+        .map(
+            |(i, &c)| match i.checked_sub(1).map(|before| chars[before]) {
+                Some(before) if continues(before, c) => 1.0,
+                _ => bits_per_char,
+            },
+        )
+        .sum()
+}
+
+/// Whether the character repeats the one before, or is next to it like `b` after `a` or `c`
+fn continues(before: char, c: char) -> bool {
+    let (before, c) = (before as u32, c as u32);
+    before.abs_diff(c) <= 1
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scores_passwords_by_their_entropy() {
+        assert_eq!(0, score(""));
+        assert_eq!(0, score("aaaaaaaaaaaaaaaa"));
+        assert_eq!(0, score("abcdefgh12345678"));
+        assert_eq!(1, score("wlnkpqxe"));
+        assert_eq!(2, score("Tr0ub4dor"));
+        assert_eq!(3, score("Tr0ub4dor&3x"));
+        assert_eq!(4, score("correct horse battery staple"));
+    }
+}
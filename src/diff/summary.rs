@@ -0,0 +1,86 @@
+use crate::diff::{DiffItem, Filter, ItemKind};
+
+/// Number of objects of one kind per kind of change
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+    pub unchanged: usize,
+}
+
+/// Number of added, removed, changed and unchanged groups and entries of a diff
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub groups: Tally,
+    pub entries: Tally,
+}
+
+impl Summary {
+    /// Counts `item` and everything nested in it into the tally that `pick` selects
+    pub(crate) fn count<E: DiffItem>(&mut self, item: &E, pick: fn(&mut Tally) -> &mut usize) {
+        self.count_one(item, pick);
+        let (groups, entries) = item.nested_nodes();
+        *pick(&mut self.groups) += groups;
+        *pick(&mut self.entries) += entries;
+    }
+
+    /// Counts only `item` itself into the tally that `pick` selects
+    pub(crate) fn count_one<E: DiffItem>(&mut self, item: &E, pick: fn(&mut Tally) -> &mut usize) {
+        match item.item_kind() {
+            ItemKind::Group => *pick(&mut self.groups) += 1,
+            ItemKind::Entry => *pick(&mut self.entries) += 1,
+            _ => {}
+        }
+    }
+
+    /// Only the counts of the changes that `filter` shows, so the summary of filtered differences
+    /// does not count the ones that were left out
+    pub fn filtered(self, filter: Filter) -> Summary {
+        Summary {
+            groups: self.groups.filtered(filter),
+            entries: self.entries.filtered(filter),
+        }
+    }
+}
+
+impl Tally {
+    fn filtered(self, filter: Filter) -> Tally {
+        match filter {
+            Filter::All => self,
+            Filter::OnlyAdditions => Tally {
+                added: self.added,
+                ..Tally::default()
+            },
+            Filter::OnlyRemovals => Tally {
+                removed: self.removed,
+                ..Tally::default()
+            },
+        }
+    }
+
+    fn format(
+        &self,
+        f: &mut std::fmt::Formatter,
+        singular: &str,
+        plural: &str,
+    ) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} added, {} removed, {} changed, {} unchanged",
+            self.added,
+            if self.added == 1 { singular } else { plural },
+            self.removed,
+            self.changed,
+            self.unchanged
+        )
+    }
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.groups.format(f, "group", "groups")?;
+        write!(f, "; ")?;
+        self.entries.format(f, "entry", "entries")
+    }
+}
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+use crate::diff::{Diff, DiffItem, DiffResult, ItemKind};
+
+/// A tag of an entry. Tags are a set, so a tag is either added or removed, never changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tag {
+    pub name: String,
+    pub use_verbose: bool,
+}
+
+/// Reads the tags of an entry, keyed by their name. KeePass separates them with semicolons or
+/// commas, blanks around them are left out.
+pub fn tags_of(entry: &keepass::db::Entry, use_verbose: bool) -> HashMap<String, Tag> {
+    entry
+        .tags
+        .iter()
+        .flat_map(|tags| tags.split([';', ',']))
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            let tag = Tag {
+                name: name.to_owned(),
+                use_verbose,
+            };
+            (name.to_owned(), tag)
+        })
+        .collect()
+}
+
+impl Diff for Tag {
+    fn diff<'a>(&'a self, other: &'a Self) -> DiffResult<'a, Self> {
+        DiffResult::Identical {
+            left: self,
+            right: other,
+        }
+    }
+}
+
+impl std::fmt::Display for Tag {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.use_verbose {
+            write!(f, "Tag '{}'", self.name)
+        } else {
+            write!(f, "tag:{}", self.name)
+        }
+    }
+}
+
+impl DiffItem for Tag {
+    fn item_kind(&self) -> ItemKind {
+        ItemKind::Tag
+    }
+
+    fn item_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_tags_as_a_set() {
+        let mut entry = keepass::db::Entry::new();
+        entry.tags = vec!["prod".to_owned(), " legacy; prod,".to_owned()];
+        let mut names: Vec<String> = tags_of(&entry, false).into_keys().collect();
+        names.sort();
+
+        assert_eq!(vec!["legacy", "prod"], names);
+    }
+}
@@ -0,0 +1,214 @@
+//! Three-way comparison of two databases that both descend from a common base database
+
+use crate::diff::{Change, ChangeKind};
+
+/// Which of the two databases a change from the base was made in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    /// Only the first database changed
+    A,
+    /// Only the second database changed
+    B,
+    /// Both databases made the same change
+    Both,
+    /// Both databases changed the object, but differently, or one of them removed a group or
+    /// entry that the other one changed
+    Conflict,
+}
+
+/// A change from the base database, with the side it was made in
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ThreeWayChange {
+    pub side: Side,
+    /// The change from the base to the first database, if it made one
+    pub a: Option<Change>,
+    /// The change from the base to the second database, if it made one
+    pub b: Option<Change>,
+}
+
+/// Pairs up the changes from the base to the first database with the changes from the base to
+/// the second database. Both databases made the same change if `a_to_b`, the changes from the
+/// first to the second database, leaves the object alone, which also holds for masked values.
+/// Changed groups and entries are left out as they only contain the changes of their children.
+/// Objects are paired by their path below the root, as the root of the first database may be
+/// named other than that of the base. The result is ordered by path.
+pub fn three_way(
+    base_to_a: &[Change],
+    base_to_b: &[Change],
+    a_to_b: &[Change],
+) -> Vec<ThreeWayChange> {
+    let is_leaf = |c: &&Change| !(c.kind == ChangeKind::Changed && c.old.is_none());
+    let below_root = |c: &Change| c.path.get(1..).unwrap_or_default().to_vec();
+    let same_object = |a: &Change, b: &Change| {
+        below_root(a) == below_root(b) && a.item == b.item && a.name == b.name
+    };
+
+    let changes_b: Vec<&Change> = base_to_b.iter().filter(is_leaf).collect();
+    let mut paired_b = vec![false; changes_b.len()];
+    let mut result = Vec::new();
+    for a in base_to_a.iter().filter(is_leaf) {
+        let b = changes_b
+            .iter()
+            .enumerate()
+            .find(|(index, b)| !paired_b[*index] && same_object(a, b));
+        result.push(match b {
+            Some((index, b)) => {
+                paired_b[index] = true;
+                let same = a.kind == b.kind && !a_to_b.iter().any(|c| same_object(a, c));
+                ThreeWayChange {
+                    side: if same { Side::Both } else { Side::Conflict },
+                    a: Some(a.clone()),
+                    b: Some((*b).clone()),
+                }
+            }
+            None => ThreeWayChange {
+                side: Side::A,
+                a: Some(a.clone()),
+                b: None,
+            },
+        });
+    }
+    for (b, _) in changes_b.iter().zip(paired_b).filter(|(_, paired)| !paired) {
+        result.push(ThreeWayChange {
+            side: Side::B,
+            a: None,
+            b: Some((*b).clone()),
+        });
+    }
+
+    mark_removal_conflicts(&mut result);
+    result.sort_by_cached_key(|c| full_path(c.change()));
+    result
+}
+
+/// Marks the removals of groups and entries on one side as conflicts if the other side changed
+/// something in them, and marks those changes as conflicts as well
+fn mark_removal_conflicts(changes: &mut [ThreeWayChange]) {
+    let removals = |side: Side| -> Vec<Vec<String>> {
+        changes
+            .iter()
+            .filter(|c| c.side == side && c.change().kind == ChangeKind::Removed)
+            .map(|c| full_path(c.change()))
+            .collect()
+    };
+    let (removed_in_a, removed_in_b) = (removals(Side::A), removals(Side::B));
+    let inside = |change: &Change, removed: &[Vec<String>]| {
+        removed.iter().any(|r| full_path(change).starts_with(r))
+    };
+
+    let mut conflicting = Vec::new();
+    for (index, c) in changes.iter().enumerate() {
+        let removed_by_other = match c.side {
+            Side::A => &removed_in_b,
+            Side::B => &removed_in_a,
+            Side::Both | Side::Conflict => continue,
+        };
+        if inside(c.change(), removed_by_other) {
+            conflicting.push(index);
+            for (other, o) in changes.iter().enumerate() {
+                if o.side != c.side && removed_by_other.contains(&full_path(o.change())) {
+                    conflicting.push(other);
+                }
+            }
+        }
+    }
+    for index in conflicting {
+        changes[index].side = Side::Conflict;
+    }
+}
+
+impl ThreeWayChange {
+    /// The change from the base to the first database, or to the second if only it changed
+    pub fn change(&self) -> &Change {
+        self.a
+            .as_ref()
+            .or(self.b.as_ref())
+            .expect("A three-way change has at least one side")
+    }
+}
+
+/// The names of the groups leading to the changed object, followed by its own name
+fn full_path(change: &Change) -> Vec<String> {
+    let mut path = change.path.clone();
+    path.push(change.name.clone());
+    path
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::diff::ItemKind;
+
+    fn change(path: &[&str], item: ItemKind, name: &str, kind: ChangeKind, new: &str) -> Change {
+        Change::new(path, item, name, kind).with_values("base", new)
+    }
+
+    fn password(new: &str) -> Change {
+        let path = ["Root", "Walked"];
+        change(&path, ItemKind::Field, "Password", ChangeKind::Changed, new)
+    }
+
+    #[test]
+    fn tells_apart_one_sided_shared_and_conflicting_changes() {
+        let url = change(
+            &["Root", "Walked"],
+            ItemKind::Field,
+            "URL",
+            ChangeKind::Changed,
+            "x",
+        );
+        let notes = change(
+            &["Root", "Ran"],
+            ItemKind::Field,
+            "Notes",
+            ChangeKind::Changed,
+            "y",
+        );
+        let base_to_a = vec![password("plank"), url.clone()];
+        let base_to_b = vec![password("board"), url, notes.clone()];
+        let a_to_b = vec![password("board"), notes];
+
+        let sides: Vec<_> = three_way(&base_to_a, &base_to_b, &a_to_b)
+            .iter()
+            .map(|c| (c.change().name.clone(), c.side))
+            .collect();
+        assert_eq!(
+            vec![
+                ("Notes".to_owned(), Side::B),
+                ("Password".to_owned(), Side::Conflict),
+                ("URL".to_owned(), Side::Both),
+            ],
+            sides
+        );
+    }
+
+    #[test]
+    fn changes_to_removed_entries_conflict() {
+        let removed = change(
+            &["Root"],
+            ItemKind::Entry,
+            "Walked",
+            ChangeKind::Removed,
+            "",
+        );
+        let changes = three_way(&[removed], &[password("board")], &[]);
+
+        assert!(changes.iter().all(|c| c.side == Side::Conflict));
+        assert_eq!(2, changes.len());
+    }
+
+    #[test]
+    fn pairs_changes_below_roots_with_other_names() {
+        let renamed = |c: Change| Change {
+            path: vec!["Passwords".to_owned(), "Walked".to_owned()],
+            ..c
+        };
+        let a_to_b = vec![renamed(password("board"))];
+        let changes = three_way(&[password("plank")], &[password("board")], &a_to_b);
+
+        assert_eq!(
+            vec![Side::Conflict],
+            changes.iter().map(|c| c.side).collect::<Vec<_>>()
+        );
+    }
+}
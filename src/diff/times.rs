@@ -0,0 +1,114 @@
+use chrono::{Local, NaiveDateTime, TimeZone as _};
+
+use crate::diff::{DiffItem, DiffOptions, ItemKind};
+
+/// How timestamps are shown. KeePass stores them in UTC.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum TimeZone {
+    #[default]
+    Utc,
+    /// The time zone of this computer
+    Local,
+}
+
+/// When an entry was created, last modified and when it expires, if it does
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Times {
+    pub created: Option<NaiveDateTime>,
+    pub modified: Option<NaiveDateTime>,
+    pub expires: Option<NaiveDateTime>,
+    time_zone: TimeZone,
+    use_verbose: bool,
+}
+
+impl Times {
+    pub fn from_keepass(times: &keepass::db::Times, options: &DiffOptions) -> Self {
+        Times {
+            created: times.get_creation().copied(),
+            modified: times.get_last_modification().copied(),
+            expires: times.get_expiry().copied().filter(|_| times.expires),
+            time_zone: options.time_zone,
+            use_verbose: options.use_verbose,
+        }
+    }
+
+    /// The timestamps in ISO 8601 form, like `created 2023-06-01T10:00:00Z, modified ...`, or
+    /// `None` if there are none
+    pub fn describe(&self) -> Option<String> {
+        let times: Vec<String> = [
+            ("created", self.created),
+            ("modified", self.modified),
+            ("expires", self.expires),
+        ]
+        .iter()
+        .filter_map(|(label, time)| time.map(|time| format!("{} {}", label, self.format(time))))
+        .collect();
+        Some(times.join(", ")).filter(|times| !times.is_empty())
+    }
+
+    /// When the entry or group expires in ISO 8601 form, or `None` if it does not
+    pub fn describe_expiry(&self) -> Option<String> {
+        self.expires.map(|time| self.format(time))
+    }
+
+    fn format(&self, time: NaiveDateTime) -> String {
+        match self.time_zone {
+            TimeZone::Utc => time.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            TimeZone::Local => Local
+                .from_utc_datetime(&time)
+                .format("%Y-%m-%dT%H:%M:%S%:z")
+                .to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for Times {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let times = self.describe().unwrap_or_default();
+        if self.use_verbose {
+            write!(f, "Times '{}'", times)
+        } else {
+            write!(f, "Times = {}", times)
+        }
+    }
+}
+
+impl DiffItem for Times {
+    fn item_kind(&self) -> ItemKind {
+        ItemKind::Field
+    }
+
+    fn item_name(&self) -> String {
+        "Times".to_owned()
+    }
+
+    fn item_value(&self) -> Option<String> {
+        self.describe()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn describes_times_in_iso_8601() {
+        let mut keepass_times = keepass::db::Times::default();
+        let time = |s| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap();
+        keepass_times.set_creation(time("2023-06-01 10:00:00"));
+        keepass_times.set_last_modification(time("2024-01-31 23:59:59"));
+        keepass_times.set_expiry(time("2025-01-01 00:00:00"));
+        let mut times = |expires| {
+            keepass_times.expires = expires;
+            Times::from_keepass(&keepass_times, &DiffOptions::default()).describe()
+        };
+
+        assert_eq!(
+            Some("created 2023-06-01T10:00:00Z, modified 2024-01-31T23:59:59Z"),
+            times(false).as_deref()
+        );
+        assert!(times(true)
+            .unwrap()
+            .ends_with(", expires 2025-01-01T00:00:00Z"));
+    }
+}
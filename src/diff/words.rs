@@ -0,0 +1,254 @@
+//! Word-level and line-level diffing of changed values, used to highlight what changed within a
+//! value
+
+/// Values with more pairs of tokens or lines than this are not diffed word by word or line by
+/// line, as the table of common subsequences grows with the product of their lengths
+const MAX_TOKEN_PAIRS: usize = 1_000_000;
+
+/// A piece of a value that is either shared by both values or only part of one of them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Segment<'a> {
+    pub text: &'a str,
+    pub changed: bool,
+}
+
+/// A line of a value of several lines, as diffed by `diff_lines`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Line<'a> {
+    Unchanged(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// How two sequences line up along their longest common subsequence, one step per item
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Step {
+    /// The next items of both sequences are part of the common subsequence
+    Both,
+    /// The next item of the first sequence is not in the second one
+    Old,
+    /// The next item of the second sequence is not in the first one
+    New,
+}
+
+/// The steps along the longest common subsequence of `a` and `b`, with the items only `a` has
+/// before those only `b` has, or `None` if the sequences are too long to be compared
+fn align<T: PartialEq>(a: &[T], b: &[T]) -> Option<Vec<Step>> {
+    if a.len().saturating_mul(b.len()) > MAX_TOKEN_PAIRS {
+        return None;
+    }
+
+    // common[i][j] is the length of the longest common subsequence of a[i..] and b[j..]
+    let mut common = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            common[i][j] = if a[i] == b[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut steps = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            steps.push(Step::Both);
+            i += 1;
+            j += 1;
+        } else if j == b.len() || (i < a.len() && common[i + 1][j] >= common[i][j + 1]) {
+            steps.push(Step::Old);
+            i += 1;
+        } else {
+            steps.push(Step::New);
+            j += 1;
+        }
+    }
+    Some(steps)
+}
+
+/// Splits a value into words, runs of whitespace and single other characters, each with its
+/// offset in the value
+fn tokenize(value: &str) -> Vec<(usize, &str)> {
+    let class = |c: char| {
+        if c.is_alphanumeric() {
+            0
+        } else if c.is_whitespace() {
+            1
+        } else {
+            2
+        }
+    };
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut previous = None;
+    for (index, c) in value.char_indices() {
+        let current = class(c);
+        if index > start && (previous != Some(current) || current == 2) {
+            tokens.push((start, &value[start..index]));
+            start = index;
+        }
+        previous = Some(current);
+    }
+    if start < value.len() {
+        tokens.push((start, &value[start..]));
+    }
+    tokens
+}
+
+/// Appends a token of `value` to its segments, merging it into the last segment if that has the
+/// same state. Tokens are pushed in order, so the last segment ends where the token starts.
+fn push<'a>(
+    segments: &mut Vec<Segment<'a>>,
+    value: &'a str,
+    (start, token): (usize, &'a str),
+    changed: bool,
+) {
+    match segments.last_mut() {
+        Some(last) if last.changed == changed => {
+            last.text = &value[start - last.text.len()..start + token.len()];
+        }
+        _ => segments.push(Segment {
+            text: token,
+            changed,
+        }),
+    }
+}
+
+/// Splits both values into segments, marking the words that are not part of their longest common
+/// subsequence of words as changed
+pub fn diff_words<'a>(old: &'a str, new: &'a str) -> (Vec<Segment<'a>>, Vec<Segment<'a>>) {
+    let a = tokenize(old);
+    let b = tokenize(new);
+    let texts = |tokens: &[(usize, &'a str)]| tokens.iter().map(|t| t.1).collect::<Vec<_>>();
+    let steps = match align(&texts(&a), &texts(&b)) {
+        Some(steps) => steps,
+        None => {
+            let whole = |text| {
+                vec![Segment {
+                    text,
+                    changed: true,
+                }]
+            };
+            return (whole(old), whole(new));
+        }
+    };
+
+    let (mut old_segments, mut new_segments) = (Vec::new(), Vec::new());
+    let (mut i, mut j) = (0, 0);
+    for step in steps {
+        match step {
+            Step::Both => {
+                push(&mut old_segments, old, a[i], false);
+                push(&mut new_segments, new, b[j], false);
+                i += 1;
+                j += 1;
+            }
+            Step::Old => {
+                push(&mut old_segments, old, a[i], true);
+                i += 1;
+            }
+            Step::New => {
+                push(&mut new_segments, new, b[j], true);
+                j += 1;
+            }
+        }
+    }
+    (old_segments, new_segments)
+}
+
+/// Diffs two values of several lines line by line, like `diff` does for files. The lines that
+/// are not part of their longest common subsequence of lines are removed or added, each run of
+/// removed lines followed by the lines added in their place. Values with too many lines are
+/// replaced as a whole.
+pub fn diff_lines<'a>(old: &'a [String], new: &'a [String]) -> Vec<Line<'a>> {
+    let steps = match align(old, new) {
+        Some(steps) => steps,
+        None => {
+            let removed = old.iter().map(|line| Line::Removed(line));
+            return removed
+                .chain(new.iter().map(|line| Line::Added(line)))
+                .collect();
+        }
+    };
+
+    let mut lines = Vec::with_capacity(steps.len());
+    let (mut i, mut j) = (0, 0);
+    for step in steps {
+        match step {
+            Step::Both => {
+                lines.push(Line::Unchanged(&new[j]));
+                i += 1;
+                j += 1;
+            }
+            Step::Old => {
+                lines.push(Line::Removed(&old[i]));
+                i += 1;
+            }
+            Step::New => {
+                lines.push(Line::Added(&new[j]));
+                j += 1;
+            }
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn segment(text: &str, changed: bool) -> Segment<'_> {
+        Segment { text, changed }
+    }
+
+    #[test]
+    fn marks_only_the_changed_words() {
+        let (old, new) = diff_words(
+            "Call Bob at 555-1234, not Alice",
+            "Call Bob at 555-9876, not Alice",
+        );
+
+        assert_eq!(
+            vec![
+                segment("Call Bob at 555-", false),
+                segment("1234", true),
+                segment(", not Alice", false),
+            ],
+            old
+        );
+        assert_eq!(segment("9876", true), new[1]);
+        assert_eq!(3, new.len());
+    }
+
+    #[test]
+    fn marks_added_and_removed_words() {
+        let (old, new) = diff_words("one two", "one two three");
+
+        assert_eq!(vec![segment("one two", false)], old);
+        assert_eq!(
+            vec![segment("one two", false), segment(" three", true)],
+            new
+        );
+    }
+
+    #[test]
+    fn diffs_values_line_by_line() {
+        let lines = |text: &str| text.split('\n').map(|l| l.to_owned()).collect::<Vec<_>>();
+        let old = lines("Server: db1\nPort: 5432\nUser: admin\nNotes end");
+        let new = lines("Server: db1\nPort: 6543\nUser: admin\nBackup: nightly\nNotes end");
+
+        assert_eq!(
+            vec![
+                Line::Unchanged("Server: db1"),
+                Line::Removed("Port: 5432"),
+                Line::Added("Port: 6543"),
+                Line::Unchanged("User: admin"),
+                Line::Added("Backup: nightly"),
+                Line::Unchanged("Notes end"),
+            ],
+            diff_lines(&old, &new)
+        );
+    }
+}
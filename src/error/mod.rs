@@ -0,0 +1,204 @@
+//! Errors upon opening a database for diffing, upon writing a merged database, and upon reading
+//! a config file
+
+use keepass::config::DatabaseVersion;
+use keepass::error::{DatabaseKeyError, DatabaseOpenError, DatabaseSaveError};
+
+use std::fmt;
+
+use crate::corruption::Corruption;
+
+#[derive(Debug)]
+pub enum OpenError {
+    /// The file does not start with the signature of a KeePass database
+    NotADatabase,
+    /// The file is a KeePass database in a version that cannot be read
+    UnsupportedVersion(DatabaseVersion),
+    /// The database file could not be read
+    File(std::io::Error),
+    /// The database file is a symbolic link to the given file, which does not exist
+    BrokenSymlink(String),
+    /// The key file could not be read
+    Keyfile(std::io::Error),
+    /// The database could not be decrypted with the given password and key file, or with none
+    /// if neither was given
+    IncorrectKey { password: bool, keyfile: bool },
+    /// The database could not be read or decrypted
+    Database(DatabaseOpenError),
+    /// The database could not be read because of a value of the given group or entry
+    CorruptItem {
+        corruption: Box<Corruption>,
+        error: DatabaseOpenError,
+    },
+    /// The directory given as a database contains no database
+    NoDatabaseInDirectory,
+    /// The directory given as a database contains several databases or several key files, which
+    /// are named
+    SeveralFilesInDirectory(Vec<String>),
+}
+
+impl OpenError {
+    /// What went wrong, for tools that handle errors differently: `auth` for incorrect
+    /// credentials, `io` for files that cannot be read, `corrupt` for files that are no
+    /// database or cannot be decrypted, and `unsupported` for versions that cannot be read
+    pub fn category(&self) -> &'static str {
+        match self {
+            OpenError::IncorrectKey { .. } | OpenError::Database(DatabaseOpenError::Key(_)) => {
+                "auth"
+            }
+            OpenError::File(_)
+            | OpenError::BrokenSymlink(_)
+            | OpenError::Keyfile(_)
+            | OpenError::NoDatabaseInDirectory
+            | OpenError::SeveralFilesInDirectory(_)
+            | OpenError::Database(DatabaseOpenError::Io(_)) => "io",
+            OpenError::UnsupportedVersion(_)
+            | OpenError::Database(DatabaseOpenError::UnsupportedVersion) => "unsupported",
+            OpenError::NotADatabase | OpenError::Database(_) | OpenError::CorruptItem { .. } => {
+                "corrupt"
+            }
+        }
+    }
+}
+
+impl fmt::Display for OpenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OpenError::NotADatabase => write!(
+                f,
+                "Not a KeePass database (expected a KeePass 2.x .kdbx or KeePass 1.x .kdb file)"
+            ),
+            OpenError::UnsupportedVersion(version) => write!(
+                f,
+                "{} databases (KeePass 2.x pre-releases) are not supported",
+                version.to_string()
+            ),
+            OpenError::File(e) => write!(f, "Cannot read the database file: {}", e),
+            OpenError::BrokenSymlink(target) => {
+                write!(f, "File not found (broken symlink to {})", target)
+            }
+            OpenError::Keyfile(e) => write!(f, "Cannot read the key file: {}", e),
+            OpenError::IncorrectKey { password, keyfile } => match (password, keyfile) {
+                (true, false) => write!(f, "Incorrect password"),
+                (false, true) => write!(f, "Incorrect key file"),
+                (true, true) => write!(f, "Incorrect password or key file"),
+                (false, false) => write!(f, "The database needs a password or a key file"),
+            },
+            OpenError::Database(DatabaseOpenError::DatabaseIntegrity(e)) => {
+                write!(f, "The database is corrupt: {}", e)
+            }
+            OpenError::CorruptItem { corruption, error } => match error {
+                DatabaseOpenError::DatabaseIntegrity(e) => {
+                    write!(f, "The database is corrupt at the {}: {}", corruption, e)
+                }
+                e => write!(f, "{} at the {}", e, corruption),
+            },
+            OpenError::Database(DatabaseOpenError::Key(DatabaseKeyError::IncorrectKey)) => {
+                write!(f, "Incorrect password or key file")
+            }
+            OpenError::Database(e) => write!(f, "{}", e),
+            OpenError::NoDatabaseInDirectory => write!(
+                f,
+                "The directory contains no KeePass database (a .kdbx or .kdb file)"
+            ),
+            OpenError::SeveralFilesInDirectory(files) => write!(
+                f,
+                "The directory contains more than one database or key file: {}",
+                files.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OpenError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            OpenError::File(e) | OpenError::Keyfile(e) => Some(e),
+            OpenError::Database(e) | OpenError::CorruptItem { error: e, .. } => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<DatabaseOpenError> for OpenError {
+    fn from(e: DatabaseOpenError) -> Self {
+        OpenError::Database(e)
+    }
+}
+
+impl From<std::io::Error> for OpenError {
+    fn from(e: std::io::Error) -> Self {
+        OpenError::Database(e.into())
+    }
+}
+
+/// Why the merged database could not be written
+#[derive(Debug)]
+pub enum MergeError {
+    /// One of the databases has attachments, which keepass would lose when writing the merge
+    Attachments,
+    /// The file for the merged database could not be created
+    File(std::io::Error),
+    /// The merged database could not be written
+    Save(DatabaseSaveError),
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MergeError::Attachments => write!(
+                f,
+                "Databases with attachments cannot be merged, as the attachments would be lost"
+            ),
+            MergeError::File(e) => write!(f, "Cannot create the file: {}", e),
+            MergeError::Save(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for MergeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MergeError::File(e) => Some(e),
+            MergeError::Save(e) => Some(e),
+            MergeError::Attachments => None,
+        }
+    }
+}
+
+/// Why the config file could not be read
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file could not be read
+    File(std::io::Error),
+    /// The line of the config file is no valid setting
+    Invalid { line: usize, message: String },
+    /// The setting of the config file conflicts with the option given on the command line
+    Conflict {
+        setting: &'static str,
+        option: &'static str,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::File(e) => write!(f, "Cannot read the file: {}", e),
+            ConfigError::Invalid { line, message } => write!(f, "Line {}: {}", line, message),
+            ConfigError::Conflict { setting, option } => write!(
+                f,
+                "{} cannot be combined with --{} given on the command line",
+                setting, option
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::File(e) => Some(e),
+            ConfigError::Invalid { .. } | ConfigError::Conflict { .. } => None,
+        }
+    }
+}
@@ -0,0 +1,987 @@
+//! Reads two KeePass (.kdbx) databases and computes their differences.
+//!
+//! The binary is a thin wrapper around this library. Open both databases with [`open_database`]
+//! (or [`kdbx_to_group`] and a key from [`get_database_key`]), call [`diff::Diff::diff`] on the
+//! groups they are converted to and either render the result with [`diff::DiffDisplay`] or
+//! inspect it with [`diff::collect_changes`]:
+//!
+//! ```
+//! use keepass_diff::diff::{collect_changes, Diff, DiffOptions};
+//! use keepass_diff::KeyfileFormat;
+//!
+//! let open = |file| {
+//!     let options = DiffOptions {
+//!         mask_passwords: true,
+//!         ..Default::default()
+//!     };
+//!     keepass_diff::open_database(file, Some("demopass"), None, KeyfileFormat::Auto, &options)
+//! };
+//! let (_, a) = open("test/__fixtures__/test.kdbx").unwrap();
+//! let (_, b) = open("test/__fixtures__/test2.kdbx").unwrap();
+//!
+//! let delta = a.diff(&b);
+//! assert!(delta.has_changes());
+//! for change in collect_changes(&delta) {
+//!     println!("{:?} {} in {}", change.kind, change.name, change.path.join("/"));
+//! }
+//! ```
+
+extern crate base64;
+extern crate caseless;
+extern crate chrono;
+extern crate clap;
+extern crate flate2;
+extern crate keepass;
+extern crate secstr;
+extern crate serde_json;
+extern crate sha2;
+extern crate termcolor;
+extern crate uuid;
+extern crate xml;
+extern crate zeroize;
+
+pub mod config;
+pub mod corruption;
+pub mod diff;
+pub mod error;
+pub mod merge;
+pub mod output;
+pub mod security;
+pub mod stack;
+pub mod xml_export;
+mod xml_tags;
+
+use corruption::locate_corruption;
+use diff::attachment::{read_attachments, Attachments};
+use diff::{glob, group::Group, DiffOptions};
+use error::OpenError;
+use keepass::config::DatabaseVersion;
+use keepass::db::{Group as KeepassGroup, Node};
+use keepass::error::{
+    CryptographyError, DatabaseIntegrityError, DatabaseKeyError, DatabaseOpenError, XmlParseError,
+};
+use keepass::{Database, DatabaseKey};
+use security::Security;
+
+use termcolor::{Color, ColorSpec, WriteColor};
+use zeroize::Zeroizing;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+
+/// Opens a KeePass database with a password and a key file, either of which may be omitted, and
+/// converts its root group into a diffable Group. The database is returned as well, as keepass
+/// reads it, like the databases that are merged with [`merge::merge`].
+///
+/// Unlike [`kdbx_to_group`], this tells whether the password or the key file is incorrect, as far
+/// as that can be told from which of them were given.
+pub fn open_database(
+    file: &str,
+    password: Option<&str>,
+    keyfile: Option<Keyfile>,
+    keyfile_format: KeyfileFormat,
+    options: &DiffOptions,
+) -> Result<(Database, Group), OpenError> {
+    let db_key = get_database_key(password, keyfile, keyfile_format)?;
+    kdbx_to_group(file, db_key, options).map_err(|e| incorrect_key_error(e, password, keyfile))
+}
+
+/// Like [`open_database`], but reads the database from `data` instead of a file, like a
+/// database that was piped to standard input
+pub fn open_database_from(
+    data: &[u8],
+    password: Option<&str>,
+    keyfile: Option<Keyfile>,
+    keyfile_format: KeyfileFormat,
+    options: &DiffOptions,
+) -> Result<(Database, Group), OpenError> {
+    let db_key = get_database_key(password, keyfile, keyfile_format)?;
+    data_to_group(data, db_key, options).map_err(|e| incorrect_key_error(e, password, keyfile))
+}
+
+/// Tells which of the password and the key file may be incorrect, if the key is
+fn incorrect_key_error(
+    error: OpenError,
+    password: Option<&str>,
+    keyfile: Option<Keyfile>,
+) -> OpenError {
+    match error {
+        OpenError::Database(DatabaseOpenError::Key(DatabaseKeyError::IncorrectKey)) => {
+            OpenError::IncorrectKey {
+                password: password.is_some(),
+                keyfile: keyfile.is_some(),
+            }
+        }
+        e => e,
+    }
+}
+
+/// Opens a KeePass database with the given key (see [`get_database_key`]) and converts its root
+/// group into a diffable Group, returning both
+///
+/// Besides KDBX files, this reads KeePass 1.x (.kdb) databases, so they can be diffed against
+/// their migrated versions, and plaintext XML exports (see [`xml_export`]), which need no key.
+pub fn kdbx_to_group(
+    file: &str,
+    db_key: DatabaseKey,
+    options: &DiffOptions,
+) -> Result<(Database, Group), OpenError> {
+    let mut data = Vec::new();
+    File::open(file)
+        .and_then(|mut f| f.read_to_end(&mut data))
+        .map_err(|e| file_error(file, e))?;
+    data_to_group(&data, db_key, options)
+}
+
+/// The error for a database file that cannot be read, telling symbolic links to a file that does
+/// not exist from other missing files
+pub fn file_error(file: &str, error: std::io::Error) -> OpenError {
+    match std::fs::read_link(file) {
+        Ok(target) if error.kind() == std::io::ErrorKind::NotFound => {
+            OpenError::BrokenSymlink(target.to_string_lossy().into_owned())
+        }
+        _ => OpenError::File(error),
+    }
+}
+
+/// The file that a path given as a database stands for if it is a symbolic link, followed
+/// through all links in between, or `None` for other paths. Links to a file that does not exist
+/// are rejected with [`OpenError::BrokenSymlink`].
+pub fn resolve_symlink(path: &str) -> Result<Option<String>, OpenError> {
+    let is_symlink = std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink());
+    if !is_symlink {
+        return Ok(None);
+    }
+    let resolved = std::fs::canonicalize(path).map_err(|e| file_error(path, e))?;
+    Ok(Some(resolved.to_string_lossy().into_owned()))
+}
+
+/// Like [`kdbx_to_group`], but reads the database from `data` instead of a file
+pub fn data_to_group(
+    data: &[u8],
+    db_key: DatabaseKey,
+    options: &DiffOptions,
+) -> Result<(Database, Group), OpenError> {
+    let is_export = xml_export::is_xml_export(data);
+    let db = read_database(data, db_key.clone())?;
+    // The database is returned as it was read, so the groups left out are removed from a copy
+    let mut root = Cow::Borrowed(&db.root);
+    if !options.include_recycle_bin && db.meta.recyclebin_enabled != Some(false) {
+        if let Some(recycle_bin) = db.meta.recyclebin_uuid {
+            remove_groups(root.to_mut(), &|g| g.uuid == recycle_bin);
+        }
+    }
+    if !options.include_groups.is_empty() || !options.exclude_groups.is_empty() {
+        let included = options.include_groups.is_empty();
+        filter_groups(root.to_mut(), &mut Vec::new(), included, options);
+    }
+    if !options.only_paths.is_empty() {
+        clear_attributes(root.to_mut());
+        filter_paths(root.to_mut(), &mut Vec::new(), &options.only_paths);
+    }
+
+    // keepass does not keep track of which entry an attachment belongs to, so this needs another
+    // look at the XML. Decrypting it derives the key again, as keepass does not hand out the
+    // derived key to reuse, which about doubles the time to open (see benches/attachments.rs).
+    // So only do it when necessary.
+    let pool = binary_pool(&db);
+    let attachments = if pool.is_empty() {
+        Attachments::new()
+    } else {
+        let xml = if is_export {
+            data.to_vec()
+        } else {
+            Database::get_xml(&mut &data[..], db_key)?
+        };
+        read_attachments(&xml, &pool, options.use_verbose)
+            .map_err(|e| DatabaseOpenError::from(XmlParseError::from(e)))?
+    };
+
+    let mut group = Group::from_keepass(&root, options, &attachments);
+    if !is_export {
+        group.security = Some(Security::of(&db.config));
+    }
+    Ok((db, group))
+}
+
+/// Reads a database or XML export from `data` as keepass reads it, without converting it to a
+/// diffable Group
+fn read_database(data: &[u8], db_key: DatabaseKey) -> Result<Database, OpenError> {
+    if xml_export::is_xml_export(data) {
+        return xml_export::read_xml_export(data)
+            .map_err(|e| locate_xml_error(DatabaseOpenError::from(e).into(), data, db_key))?
+            .ok_or(OpenError::NotADatabase);
+    }
+    match database_version(data)? {
+        version @ DatabaseVersion::KDB2(_) => Err(OpenError::UnsupportedVersion(version)),
+        DatabaseVersion::KDB(_) => {
+            let mut db = Database::open(&mut &data[..], db_key).map_err(kdb_open_error)?;
+            normalize_kdb(&mut db.root);
+            Ok(db)
+        }
+        DatabaseVersion::KDB3(_) => Database::open(&mut &data[..], db_key.clone())
+            .map_err(kdb_open_error)
+            .map_err(|e| locate_xml_error(e, data, db_key)),
+        _ => Database::open(&mut &data[..], db_key.clone())
+            .map_err(|e| locate_xml_error(e.into(), data, db_key)),
+    }
+}
+
+/// keepass only tells what is wrong with the XML of a database it cannot read, so this looks at
+/// the XML again for the group or entry at fault. Decrypting it costs another key derivation,
+/// which only happens after the XML turned out to be broken.
+fn locate_xml_error(e: OpenError, data: &[u8], db_key: DatabaseKey) -> OpenError {
+    let e = match e {
+        OpenError::Database(
+            e @ DatabaseOpenError::DatabaseIntegrity(DatabaseIntegrityError::Xml(_)),
+        ) => e,
+        e => return e,
+    };
+    let xml = if xml_export::is_xml_export(data) {
+        Ok(data.to_vec())
+    } else {
+        Database::get_xml(&mut &data[..], db_key)
+    };
+    match xml.ok().as_deref().and_then(locate_corruption) {
+        Some(corruption) => OpenError::CorruptItem {
+            corruption: Box::new(corruption),
+            error: e,
+        },
+        None => e.into(),
+    }
+}
+
+/// Extensions of the files that `find_in_directory` takes for databases
+const DATABASE_EXTENSIONS: &[&str] = &["kdbx", "kdb"];
+/// Extensions of the files that `find_in_directory` takes for key files
+const KEYFILE_EXTENSIONS: &[&str] = &["key", "keyx"];
+
+/// Finds the database and, if there is one, the key file in a directory, like the snapshot
+/// directories that some backup tools write. Other paths are returned as they are, without a key
+/// file.
+///
+/// Databases are `.kdbx` and `.kdb` files, key files are `.key` and `.keyx` files. Directories
+/// with no database, several databases or several key files are rejected. Subdirectories are not
+/// searched.
+pub fn find_in_directory(path: &str) -> Result<(String, Option<String>), OpenError> {
+    if !Path::new(path).is_dir() {
+        return Ok((path.to_owned(), None));
+    }
+    let (mut databases, mut keyfiles) = (Vec::new(), Vec::new());
+    for entry in std::fs::read_dir(path).map_err(OpenError::File)? {
+        let file = entry.map_err(OpenError::File)?.path();
+        if !file.is_file() {
+            continue;
+        }
+        let extension = file
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_lowercase);
+        match extension.as_deref() {
+            Some(e) if DATABASE_EXTENSIONS.contains(&e) => databases.push(file),
+            Some(e) if KEYFILE_EXTENSIONS.contains(&e) => keyfiles.push(file),
+            _ => {}
+        }
+    }
+    let names = |files: &[PathBuf]| {
+        let mut names: Vec<String> = files
+            .iter()
+            .filter_map(|f| f.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    };
+    if databases.len() > 1 {
+        return Err(OpenError::SeveralFilesInDirectory(names(&databases)));
+    }
+    if keyfiles.len() > 1 {
+        return Err(OpenError::SeveralFilesInDirectory(names(&keyfiles)));
+    }
+    let to_string = |file: PathBuf| file.to_string_lossy().into_owned();
+    match databases.pop() {
+        Some(database) => Ok((to_string(database), keyfiles.pop().map(to_string))),
+        None => Err(OpenError::NoDatabaseInDirectory),
+    }
+}
+
+/// Number of bytes needed to tell the version of a database: the signature, followed by the
+/// version numbers
+const VERSION_HEADER_LENGTH: usize = 12;
+
+/// Detects the version of a database from its signature
+pub fn database_version(data: &[u8]) -> Result<DatabaseVersion, OpenError> {
+    if data.len() < VERSION_HEADER_LENGTH {
+        return Err(OpenError::NotADatabase);
+    }
+    DatabaseVersion::parse(data).map_err(|e| match e {
+        DatabaseIntegrityError::InvalidKDBXIdentifier => OpenError::NotADatabase,
+        e => OpenError::Database(e.into()),
+    })
+}
+
+/// KeePass 1.x and KDBX 3 databases are decrypted before their key is checked, so a wrong key
+/// usually shows up as garbage after decryption, which keepass reports as a padding error.
+fn kdb_open_error(e: DatabaseOpenError) -> OpenError {
+    match e {
+        DatabaseOpenError::DatabaseIntegrity(DatabaseIntegrityError::Cryptography(
+            CryptographyError::Unpadding(_),
+        )) => DatabaseOpenError::Key(DatabaseKeyError::IncorrectKey).into(),
+        e => e.into(),
+    }
+}
+
+/// Title, user name and URL of the entries KeePass 1.x uses to store its settings ("meta streams")
+const KDB_META_STREAM: (&str, &str, &str) = ("Meta-Info", "SYSTEM", "$");
+
+/// Brings a KeePass 1.x database into the shape it gets when migrating it to KDBX: meta stream
+/// entries are dropped, the notes field is renamed from "Additional" to "Notes", and empty fields
+/// are left out like keepass does for KDBX files.
+fn normalize_kdb(group: &mut KeepassGroup) {
+    group.children.retain(|node| match node {
+        Node::Entry(e) => {
+            let field = |name| e.get(name).unwrap_or_default();
+            (field("Title"), field("UserName"), field("URL")) != KDB_META_STREAM
+        }
+        Node::Group(_) => true,
+    });
+    for node in group.children.iter_mut() {
+        match node {
+            Node::Entry(e) => {
+                if let Some(notes) = e.fields.remove("Additional") {
+                    e.fields.insert("Notes".to_owned(), notes);
+                }
+                e.fields.retain(|_, value| !value.is_empty());
+            }
+            Node::Group(g) => normalize_kdb(g),
+        }
+    }
+}
+
+/// Removes all groups that match `predicate` from `group` and its subgroups
+fn remove_groups(group: &mut KeepassGroup, predicate: &dyn Fn(&KeepassGroup) -> bool) {
+    group.children.retain(|node| match node {
+        Node::Group(g) => !predicate(g),
+        Node::Entry(_) => true,
+    });
+    for node in group.children.iter_mut() {
+        if let Node::Group(g) = node {
+            remove_groups(g, predicate);
+        }
+    }
+}
+
+/// Leaves out the subgroups of `group` that match --exclude-group and, if --include-group is
+/// given, those that neither match it nor contain a matching group. `path` leads to `group` from
+/// below the root, and `included` tells whether `group` is part of an included group, otherwise
+/// its entries are left out as well.
+fn filter_groups(
+    group: &mut KeepassGroup,
+    path: &mut Vec<String>,
+    included: bool,
+    options: &DiffOptions,
+) {
+    group.children.retain_mut(|node| match node {
+        Node::Entry(_) => included,
+        Node::Group(g) => {
+            path.push(g.name.clone());
+            let keep = if glob::matches_any(&options.exclude_groups, path) {
+                false
+            } else {
+                let included = included || glob::matches_any(&options.include_groups, path);
+                filter_groups(g, path, included, options);
+                included || !g.children.is_empty()
+            };
+            path.pop();
+            keep
+        }
+    });
+}
+
+/// Leaves out all groups and entries of `group` except those at one of the `paths` given with
+/// --only, with everything they contain, and the groups leading to them. Those groups are kept
+/// even if the path does not exist in this database, so an entry that only exists in the other
+/// database shows as added, but without their attributes, see [`clear_attributes`].
+/// Groups that lead to none of the paths are not looked into. `path` leads to `group` from below
+/// the root.
+fn filter_paths(group: &mut KeepassGroup, path: &mut Vec<String>, paths: &[Vec<String>]) {
+    group.children.retain_mut(|node| {
+        let name = match node {
+            Node::Group(g) => g.name.clone(),
+            Node::Entry(e) => e.get_title().unwrap_or_default().to_owned(),
+        };
+        path.push(name);
+        let keep = if paths.iter().any(|only| path.starts_with(only)) {
+            true
+        } else if let Node::Group(g) = node {
+            let leads_to_path = paths.iter().any(|only| only.starts_with(path));
+            if leads_to_path {
+                clear_attributes(g);
+                filter_paths(g, path, paths);
+            }
+            leads_to_path
+        } else {
+            false
+        };
+        path.pop();
+        keep
+    });
+}
+
+/// Removes the notes, icon and expiry of a group, so they are not compared
+fn clear_attributes(group: &mut KeepassGroup) {
+    group.notes = None;
+    group.icon_id = None;
+    group.custom_icon_uuid = None;
+    group.times.expires = false;
+}
+
+/// Maps the references used by entries in the database XML to the content of the binaries.
+/// KDBX 4 keeps binaries in the inner header and references them by index, KDBX 3 keeps them in
+/// the metadata and references them by their ID.
+fn binary_pool(db: &Database) -> HashMap<String, &[u8]> {
+    if !db.header_attachments.is_empty() {
+        db.header_attachments
+            .iter()
+            .enumerate()
+            .map(|(index, a)| (index.to_string(), a.content.as_slice()))
+            .collect()
+    } else {
+        db.meta
+            .binaries
+            .binaries
+            .iter()
+            .enumerate()
+            .map(|(index, b)| {
+                let id = b.identifier.clone().unwrap_or_else(|| index.to_string());
+                (id, b.content.as_slice())
+            })
+            .collect()
+    }
+}
+
+/// Where the key file of a database is read from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Keyfile<'a> {
+    /// The key file at the given path
+    Path(&'a str),
+    /// The content of a key file, like one given as base64, which is never written to a file
+    Contents(&'a [u8]),
+}
+
+/// How the content of a key file is turned into key material
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum KeyfileFormat {
+    /// Hex if the file is named `*.hex` or consists of exactly 64 hex digits, binary otherwise
+    Auto,
+    /// 64 hex digits encoding a 32 byte key
+    Hex,
+    /// Used as is: an XML key file, a 32 byte key or any other file, which gets hashed
+    Binary,
+}
+
+/// Number of hex digits in a hex key file
+const HEX_KEYFILE_LENGTH: usize = 64;
+
+/// Builds the key to open a database from an optional password and an optional key file
+///
+/// The password and the key file are combined into the key, as KeePass does. Either of them may be
+/// omitted for databases that are not locked with it.
+pub fn get_database_key(
+    password: Option<&str>,
+    keyfile: Option<Keyfile>,
+    keyfile_format: KeyfileFormat,
+) -> Result<DatabaseKey, OpenError> {
+    let db_key = DatabaseKey::new();
+    let db_key = match password {
+        Some(pwd) => db_key.with_password(pwd),
+        _ => db_key,
+    };
+    let format = match (keyfile_format, keyfile) {
+        (KeyfileFormat::Auto, Some(Keyfile::Path(path)))
+            if path.to_lowercase().ends_with(".hex") =>
+        {
+            KeyfileFormat::Hex
+        }
+        (format, _) => format,
+    };
+    let contents = match keyfile {
+        None => return Ok(db_key),
+        Some(Keyfile::Path(path)) => {
+            read_keyfile(path).and_then(|data| keyfile_contents(&data, format))
+        }
+        Some(Keyfile::Contents(contents)) => keyfile_contents(contents, format),
+    };
+    contents
+        .and_then(|contents| db_key.with_keyfile(&mut Cursor::new(&contents[..])))
+        .map_err(OpenError::Keyfile)
+}
+
+/// Reads the key file at `path` into a buffer that is wiped once it is dropped. The buffer is
+/// sized to the file up front, so it is not grown and copied while reading it.
+fn read_keyfile(path: &str) -> Result<Zeroizing<Vec<u8>>, std::io::Error> {
+    let mut file = File::open(path)?;
+    let length = file.metadata().map_or(0, |m| m.len() as usize);
+    let mut data = Zeroizing::new(Vec::with_capacity(length + 1));
+    file.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Converts the content of a key file into what keepass expects. keepass takes a 32 byte file as
+/// the raw key and hashes any other file that is not XML, so hex key files are decoded first.
+///
+/// XML key files saved as UTF-16, like by some Windows editors, are converted to UTF-8, as
+/// KeePass reads them by their byte order mark. Any other key file with a byte order mark is
+/// hashed as it is, like KeePass does, unless it is read as hex.
+fn keyfile_contents(
+    data: &[u8],
+    format: KeyfileFormat,
+) -> Result<Zeroizing<Vec<u8>>, std::io::Error> {
+    match format {
+        KeyfileFormat::Binary => Ok(Zeroizing::new(data.to_vec())),
+        KeyfileFormat::Auto => match utf16_xml_keyfile(data) {
+            Some(xml) => Ok(xml),
+            None => Ok(Zeroizing::new(
+                decode_hex_key(data).unwrap_or_else(|| data.to_vec()),
+            )),
+        },
+        KeyfileFormat::Hex => {
+            let digits = Zeroizing::new(
+                decode_text(data).unwrap_or_else(|| String::from_utf8_lossy(data).into_owned()),
+            );
+            decode_hex_key(digits.trim().as_bytes())
+                .map(Zeroizing::new)
+                .ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!(
+                            "hex key file must contain exactly {} hex digits",
+                            HEX_KEYFILE_LENGTH
+                        ),
+                    )
+                })
+        }
+    }
+}
+
+/// The UTF-8 text of an XML key file saved as UTF-16. Its XML declaration is left out, as it
+/// would still name UTF-16 as the encoding.
+fn utf16_xml_keyfile(data: &[u8]) -> Option<Zeroizing<Vec<u8>>> {
+    if !data.starts_with(UTF16_LE_BOM) && !data.starts_with(UTF16_BE_BOM) {
+        return None;
+    }
+    let text = Zeroizing::new(decode_text(data)?);
+    let xml = text.trim_start();
+    if !xml.starts_with('<') {
+        return None;
+    }
+    let xml = match xml.strip_prefix("<?xml") {
+        Some(declared) => &declared[declared.find("?>")? + 2..],
+        None => xml,
+    };
+    Some(Zeroizing::new(xml.as_bytes().to_vec()))
+}
+
+/// Byte order marks of UTF-16 text, in little and big endian
+const UTF16_LE_BOM: &[u8] = b"\xff\xfe";
+const UTF16_BE_BOM: &[u8] = b"\xfe\xff";
+
+/// Decodes text that starts with a UTF-16 byte order mark as UTF-16 and any other as UTF-8,
+/// without the byte order mark. Returns `None` if it is not valid in its encoding.
+///
+/// Passwords are used as the bytes of their UTF-8 text, without normalizing them to any Unicode
+/// form, as KeePass and KeePassXC do not either, so only the encoding is converted.
+pub fn decode_text(data: &[u8]) -> Option<String> {
+    let utf16 = |data: &[u8], from_bytes: fn([u8; 2]) -> u16| {
+        let units = data
+            .chunks(2)
+            .map(|pair| (pair.len() == 2).then(|| from_bytes([pair[0], pair[1]])));
+        String::from_utf16(&units.collect::<Option<Vec<u16>>>()?).ok()
+    };
+    if let Some(data) = data.strip_prefix(UTF16_LE_BOM) {
+        utf16(data, u16::from_le_bytes)
+    } else if let Some(data) = data.strip_prefix(UTF16_BE_BOM) {
+        utf16(data, u16::from_be_bytes)
+    } else {
+        let data = data
+            .strip_prefix(xml_export::BYTE_ORDER_MARK)
+            .unwrap_or(data);
+        String::from_utf8(data.to_vec()).ok()
+    }
+}
+
+/// Decodes exactly 64 hex digits into a 32 byte key
+fn decode_hex_key(digits: &[u8]) -> Option<Vec<u8>> {
+    if digits.len() != HEX_KEYFILE_LENGTH {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).ok()?;
+            u8::from_str_radix(pair, 16).ok()
+        })
+        .collect()
+}
+
+/// Sets the foreground color of `out` for everything written afterwards
+pub fn set_fg(out: &mut dyn WriteColor, color: Option<Color>) -> std::io::Result<()> {
+    out.set_color(ColorSpec::new().set_fg(color))
+}
+
+/// Resets `out` to its default colors
+pub fn reset_color(out: &mut dyn WriteColor) -> std::io::Result<()> {
+    out.reset()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const HEX_KEY: &[u8] = b"00112233445566778899aabbccddeeff00112233445566778899AABBCCDDEEFF";
+
+    #[test]
+    fn auto_decodes_hex_keyfiles() {
+        let key = keyfile_contents(HEX_KEY, KeyfileFormat::Auto).unwrap();
+        assert_eq!(32, key.len());
+        assert_eq!(&[0x00, 0x11, 0x22], &key[..3]);
+        assert_eq!(0xff, key[31]);
+    }
+
+    #[test]
+    fn auto_keeps_other_keyfiles() {
+        let mut not_hex = HEX_KEY.to_vec();
+        not_hex[0] = b'x';
+        assert_eq!(
+            not_hex,
+            *keyfile_contents(&not_hex, KeyfileFormat::Auto).unwrap()
+        );
+        assert_eq!(
+            HEX_KEY.to_vec(),
+            *keyfile_contents(HEX_KEY, KeyfileFormat::Binary).unwrap()
+        );
+    }
+
+    #[test]
+    fn decodes_text_by_its_byte_order_mark() {
+        assert_eq!(
+            Some("pässword"),
+            decode_text(b"\xef\xbb\xbfp\xc3\xa4ssword").as_deref()
+        );
+        let utf16: Vec<u8> = "pässword"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        assert_eq!(
+            Some("pässword"),
+            decode_text(&[b"\xff\xfe", &utf16[..]].concat()).as_deref()
+        );
+        assert_eq!(None, decode_text(b"\xff\xfep"));
+    }
+
+    #[test]
+    fn converts_utf16_xml_keyfiles() {
+        let xml = std::fs::read_to_string("test/keyfile.key").unwrap();
+        let utf16: Vec<u8> = xml.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        let key = keyfile_contents(&[b"\xfe\xff", &utf16[..]].concat(), KeyfileFormat::Auto);
+        let key = String::from_utf8(key.unwrap().to_vec()).unwrap();
+        assert!(key.starts_with("<KeyFile>"));
+        assert!(xml.ends_with(&key));
+    }
+
+    #[test]
+    fn hex_rejects_invalid_keyfiles() {
+        let with_newline = [HEX_KEY, b"\n"].concat();
+        assert!(keyfile_contents(&with_newline, KeyfileFormat::Hex).is_ok());
+        assert!(keyfile_contents(&HEX_KEY[1..], KeyfileFormat::Hex).is_err());
+    }
+
+    #[test]
+    fn tells_which_credential_is_incorrect() {
+        let open = |password, keyfile| {
+            let options = DiffOptions::default();
+            open_database(
+                "test/test.kdbx",
+                password,
+                keyfile,
+                KeyfileFormat::Auto,
+                &options,
+            )
+        };
+
+        assert!(matches!(
+            open(Some("wrongpass"), None),
+            Err(OpenError::IncorrectKey {
+                password: true,
+                keyfile: false
+            })
+        ));
+        assert!(matches!(
+            open(Some("demopass"), Some(Keyfile::Path("test/missing.key"))),
+            Err(OpenError::Keyfile(_))
+        ));
+        assert!(open(Some("demopass"), None).is_ok());
+    }
+
+    #[test]
+    fn reads_key_files_from_memory() {
+        let contents = std::fs::read("test/keyfile.key").unwrap();
+        let open = |keyfile| {
+            let options = DiffOptions::default();
+            open_database(
+                "test/test2_file.kdbx",
+                Some("demopass"),
+                Some(keyfile),
+                KeyfileFormat::Auto,
+                &options,
+            )
+        };
+
+        assert!(open(Keyfile::Contents(&contents)).is_ok());
+        assert!(matches!(
+            open(Keyfile::Contents(b"not the key")),
+            Err(OpenError::IncorrectKey { .. })
+        ));
+    }
+
+    #[test]
+    fn opens_databases_read_into_memory() {
+        let data = std::fs::read("test/test.kdbx").unwrap();
+        let open = |password| {
+            let options = DiffOptions::default();
+            open_database_from(&data, Some(password), None, KeyfileFormat::Auto, &options)
+        };
+
+        assert!(open("demopass").is_ok());
+        assert!(matches!(
+            open("wrongpass"),
+            Err(OpenError::IncorrectKey { .. })
+        ));
+    }
+
+    #[test]
+    fn opens_databases_locked_by_a_key_file_only() {
+        let keyfile = std::fs::read("test/keyfile.key").unwrap();
+        let mut db = Database::new(keepass::config::DatabaseConfig {
+            kdf_config: keepass::config::KdfConfig::Aes { rounds: 100 },
+            ..Default::default()
+        });
+        db.root.name = "Root".to_owned();
+        let key = get_database_key(None, Some(Keyfile::Contents(&keyfile)), KeyfileFormat::Auto);
+        let mut data = Vec::new();
+        db.save(&mut data, key.unwrap()).unwrap();
+        let open = |password, keyfile| {
+            let options = DiffOptions::default();
+            open_database_from(&data, password, keyfile, KeyfileFormat::Auto, &options)
+        };
+
+        assert!(open(None, Some(Keyfile::Contents(&keyfile))).is_ok());
+        assert!(matches!(
+            open(Some("demopass"), Some(Keyfile::Contents(&keyfile))),
+            Err(OpenError::IncorrectKey {
+                password: true,
+                keyfile: true
+            })
+        ));
+        assert!(open(None, None).is_err());
+    }
+
+    #[test]
+    fn categorizes_errors() {
+        let open = |file, password| {
+            let options = DiffOptions::default();
+            open_database(file, Some(password), None, KeyfileFormat::Auto, &options)
+                .unwrap_err()
+                .category()
+        };
+
+        assert_eq!("auth", open("test/test.kdbx", "wrongpass"));
+        assert_eq!("io", open("test/missing.kdbx", "demopass"));
+        assert_eq!("corrupt", open("README.md", "demopass"));
+    }
+
+    #[test]
+    fn finds_the_database_and_key_file_in_directories() {
+        let dir = std::env::temp_dir().join(format!("keepass-diff-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let find = || find_in_directory(&dir.to_string_lossy());
+
+        assert!(matches!(find(), Err(OpenError::NoDatabaseInDirectory)));
+        for file in ["vault.kdbx", "vault.key", "notes.txt"].iter() {
+            std::fs::write(path(file), b"").unwrap();
+        }
+        assert_eq!(
+            (path("vault.kdbx"), Some(path("vault.key"))),
+            find().unwrap()
+        );
+        std::fs::write(path("old.kdb"), b"").unwrap();
+        let several = find();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        match several {
+            Err(OpenError::SeveralFilesInDirectory(files)) => {
+                assert_eq!(vec!["old.kdb", "vault.kdbx"], files)
+            }
+            result => panic!("expected several databases, got {:?}", result),
+        }
+        assert_eq!(
+            ("test/test.kdbx".to_owned(), None),
+            find_in_directory("test/test.kdbx").unwrap()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolves_symbolic_links_to_databases() {
+        let dir = std::env::temp_dir().join(format!("keepass-diff-links-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let database = std::fs::canonicalize("test/test.kdbx").unwrap();
+        std::os::unix::fs::symlink(&database, path("latest.kdbx")).unwrap();
+        std::os::unix::fs::symlink(path("missing.kdbx"), path("broken.kdbx")).unwrap();
+        let resolved = resolve_symlink(&path("latest.kdbx"));
+        let broken = resolve_symlink(&path("broken.kdbx"));
+        let opened = kdbx_to_group(
+            &path("broken.kdbx"),
+            get_database_key(Some("demopass"), None, KeyfileFormat::Auto).unwrap(),
+            &DiffOptions::default(),
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            Some(database.to_string_lossy().into_owned()),
+            resolved.unwrap()
+        );
+        assert!(resolve_symlink("test/test.kdbx").unwrap().is_none());
+        assert!(
+            matches!(broken, Err(OpenError::BrokenSymlink(target)) if target == path("missing.kdbx"))
+        );
+        assert!(matches!(opened, Err(OpenError::BrokenSymlink(_))));
+    }
+
+    #[test]
+    fn detects_files_that_are_no_database() {
+        assert!(matches!(
+            database_version(b""),
+            Err(OpenError::NotADatabase)
+        ));
+        assert!(matches!(
+            database_version(b"<KeePassFile></KeePassFile>"),
+            Err(OpenError::NotADatabase)
+        ));
+    }
+
+    #[test]
+    fn normalizes_keepass_1_entries() {
+        let mut entry = keepass::db::Entry::new();
+        entry.fields.insert(
+            "Additional".to_owned(),
+            keepass::db::Value::Unprotected("PIN".to_owned()),
+        );
+        entry.fields.insert(
+            "BinaryData".to_owned(),
+            keepass::db::Value::Bytes(Vec::new()),
+        );
+        let mut meta_stream = keepass::db::Entry::new();
+        for &(name, value) in &[("Title", "Meta-Info"), ("UserName", "SYSTEM"), ("URL", "$")] {
+            meta_stream.fields.insert(
+                name.to_owned(),
+                keepass::db::Value::Unprotected(value.to_owned()),
+            );
+        }
+        let mut root = KeepassGroup::new("Root");
+        root.children.push(Node::Entry(entry));
+        root.children.push(Node::Entry(meta_stream));
+
+        normalize_kdb(&mut root);
+
+        assert_eq!(1, root.children.len());
+        match &root.children[0] {
+            Node::Entry(e) => {
+                assert_eq!(vec!["Notes"], e.fields.keys().collect::<Vec<_>>());
+                assert_eq!(Some("PIN"), e.get("Notes"));
+            }
+            Node::Group(_) => panic!("expected an entry"),
+        }
+    }
+
+    #[test]
+    fn removes_nested_groups() {
+        let mut nested = KeepassGroup::new("Recycle Bin");
+        nested.children.push(Node::Entry(keepass::db::Entry::new()));
+        let mut general = KeepassGroup::new("General");
+        general.children.push(Node::Group(nested));
+        let mut root = KeepassGroup::new("Root");
+        root.children.push(Node::Group(general));
+        root.children
+            .push(Node::Group(KeepassGroup::new("Recycle Bin")));
+
+        remove_groups(&mut root, &|g| g.name == "Recycle Bin");
+
+        assert_eq!(1, root.children.len());
+        match &root.children[0] {
+            Node::Group(g) => assert!(g.children.is_empty()),
+            Node::Entry(_) => panic!("expected a group"),
+        }
+    }
+
+    #[test]
+    fn keeps_only_the_given_paths() {
+        let entry = |title: &str| {
+            let mut entry = keepass::db::Entry::new();
+            entry.fields.insert(
+                "Title".to_owned(),
+                keepass::db::Value::Unprotected(title.to_owned()),
+            );
+            Node::Entry(entry)
+        };
+        let mut email = KeepassGroup::new("Email");
+        email.children.push(entry("GitHub"));
+        email.children.push(entry("GitLab"));
+        let mut work = KeepassGroup::new("Work");
+        work.notes = Some("Not compared".to_owned());
+        work.children.push(Node::Group(email));
+        work.children.push(entry("VPN"));
+        let mut servers = KeepassGroup::new("Servers");
+        servers.children.push(entry("db1"));
+        let mut root = KeepassGroup::new("Root");
+        root.children.push(Node::Group(work));
+        root.children.push(Node::Group(servers));
+        root.children.push(Node::Group(KeepassGroup::new("Empty")));
+
+        let path = |path: &str| path.split('/').map(str::to_owned).collect::<Vec<_>>();
+        filter_paths(
+            &mut root,
+            &mut Vec::new(),
+            &[path("Work/Email/GitHub"), path("Servers")],
+        );
+
+        fn names(group: &KeepassGroup) -> Vec<String> {
+            group
+                .children
+                .iter()
+                .map(|node| match node {
+                    Node::Group(g) => g.name.clone(),
+                    Node::Entry(e) => e.get_title().unwrap_or_default().to_owned(),
+                })
+                .collect()
+        }
+        fn child(group: &KeepassGroup, index: usize) -> &KeepassGroup {
+            match &group.children[index] {
+                Node::Group(g) => g,
+                Node::Entry(_) => panic!("expected a group"),
+            }
+        }
+        assert_eq!(vec!["Work", "Servers"], names(&root));
+        assert_eq!(None, child(&root, 0).notes);
+        let work = child(&root, 0);
+        assert_eq!(vec!["Email"], names(work));
+        assert_eq!(vec!["GitHub"], names(child(work, 0)));
+        let servers = child(&root, 1);
+        assert_eq!(vec!["db1"], names(servers));
+    }
+}
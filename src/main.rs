@@ -1,46 +1,537 @@
 extern crate base64;
 extern crate clap;
 extern crate keepass;
+extern crate keepass_diff;
+extern crate notify_debouncer_mini;
 extern crate rpassword;
+extern crate serde_json;
 extern crate termcolor;
+extern crate zeroize;
 
-pub mod diff;
-pub mod stack;
+use base64::{engine::general_purpose, Engine as _};
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, ValueEnum};
+use keepass_diff::config::{self, Config};
+use keepass_diff::diff::field::{Mask, Truncate};
+use keepass_diff::diff::group::Group;
+use keepass_diff::diff::normalize::FieldRule;
+use keepass_diff::diff::times::TimeZone;
+use keepass_diff::diff::{Diff, DiffDisplay, DiffOptions, DisplayOptions, Filter, MatchBy, SortBy};
+use keepass_diff::error::{ConfigError, MergeError, OpenError};
+use keepass_diff::merge::{self, Prefer};
+use keepass_diff::output::ordered::OrderBy;
+use keepass_diff::output::theme::Theme;
+use keepass_diff::output::{Format, Glyphs};
+use keepass_diff::xml_export::{is_xml_export, is_xml_export_file};
+use keepass_diff::{decode_text, resolve_symlink};
+use keepass_diff::{diff, find_in_directory, open_database, open_database_from, output, stack};
+use keepass_diff::{get_database_key, Keyfile, KeyfileFormat};
 
-use clap::Parser;
-use diff::{group::Group, Diff, DiffDisplay};
-use keepass::{error::DatabaseOpenError, Database, DatabaseKey};
+use notify_debouncer_mini::notify::{self, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebouncedEventKind};
+use serde_json::json;
 
-use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
-
-use std::borrow::Cow;
 use std::fs::File;
+use std::io::{BufRead, BufWriter, IsTerminal, Read, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use termcolor::{Ansi, BufferedStandardStream, Color, ColorChoice, NoColor, WriteColor};
+use zeroize::Zeroizing;
 
 type Str = Box<str>;
 
+/// Exit code when the databases differ, like GNU diff
+const EXIT_DIFFERENCES: u8 = 1;
+/// Exit code when the databases could not be compared, e.g. because one could not be opened
+const EXIT_ERROR: u8 = 2;
+
+/// Password that stands for a line of standard input
+const STDIN_PASSWORD: &str = "-";
+
+/// The input that stands for a database read from standard input
+const STDIN_DATABASE: &str = "-";
+
+/// The environment variable that --git-textconv reads the password from, as git runs it with
+/// no way to give one
+const TEXTCONV_PASSWORD_ENV: &str = "KEEPASS_DIFF_PASSWORD";
+
+/// How long the spinner waits before it shows up and between its frames
+const SPINNER_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long the databases have to stop changing before --watch compares them again, as saving
+/// may take several writes
+const WATCH_SETTLE: Duration = Duration::from_millis(500);
+
+/// Fields that --ignore-case applies to
+const IGNORE_CASE_DEFAULT_FIELDS: &[&str] = &["Title", "UserName"];
+
+/// When to color the output, like `ls` and `git` do
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum ColorWhen {
+    /// Colors the output if it goes to a terminal
+    Auto,
+    Always,
+    Never,
+}
+
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
-    /// Sets the first file
+    /// Sets the first file, or a directory with the file and its key file (- reads the file from
+    /// standard input)
     #[clap(name = "INPUT-A", index = 1)]
     input_a: Str,
 
-    /// Sets the second file
-    #[clap(name = "INPUT-B", index = 2)]
-    input_b: Str,
+    /// Sets the second file, or a directory with the file and its key file (- reads the file from
+    /// standard input)
+    #[clap(name = "INPUT-B", index = 2, required_unless_present_any = ["git-textconv", "manifest"])]
+    input_b: Option<Str>,
+
+    /// Lists the groups, entries and values of INPUT-A one per line instead of comparing two
+    /// files, for git diff to compare as a textconv driver. Reads the password from
+    /// KEEPASS_DIFF_PASSWORD unless it is given otherwise, and never asks for it.
+    #[clap(
+        name = "git-textconv",
+        long,
+        conflicts_with_all = ["INPUT-B", "base", "merge-into", "format", "json", "summary-only", "count", "tree", "stat", "entries-only"]
+    )]
+    git_textconv: bool,
+
+    /// Prints a JSON listing of the groups and entries of INPUT-A, and of INPUT-B if it is given,
+    /// with the names of the fields of each entry but none of their values, instead of comparing
+    /// them
+    #[clap(
+        long,
+        conflicts_with_all = ["git-textconv", "base", "merge-into", "format", "json", "summary-only", "count", "tree", "stat", "entries-only", "order-by", "compare-only", "check"]
+    )]
+    manifest: bool,
+
+    /// Opens files given as symbolic links as they are, instead of following the links to the
+    /// files they link to first, which --verbose tells
+    #[clap(name = "no-follow-symlinks", long)]
+    no_follow_symlinks: bool,
 
-    /// Disables color output
-    #[clap(short = 'C', long = "no-color")]
+    /// Compares INPUT-A or INPUT-B as an empty database if the file does not exist, so everything
+    /// in the other one is reported as removed or added. Errors opening the other one still fail.
+    #[clap(
+        name = "treat-missing-as-empty",
+        long,
+        conflicts_with_all = ["git-textconv", "manifest", "merge-into"]
+    )]
+    treat_missing_as_empty: bool,
+
+    /// Sets when to color the output: only on a terminal, always or never
+    #[clap(long, value_enum, value_name = "WHEN", default_value_t = ColorWhen::Auto)]
+    color: ColorWhen,
+
+    /// Disables color output (same as --color never)
+    #[clap(short = 'C', long = "no-color", conflicts_with = "color")]
     no_color: bool,
 
+    /// Sets the color of added groups, entries and values: a name like green, a number from 0
+    /// to 255 or r,g,b
+    #[clap(
+        name = "color-added",
+        long,
+        value_name = "COLOR",
+        default_value = "green"
+    )]
+    color_added: Color,
+
+    /// Sets the color of removed groups, entries and values
+    #[clap(
+        name = "color-removed",
+        long,
+        value_name = "COLOR",
+        default_value = "red"
+    )]
+    color_removed: Color,
+
+    /// Sets the color of changed groups and entries
+    #[clap(
+        name = "color-changed",
+        long,
+        value_name = "COLOR",
+        default_value = "yellow"
+    )]
+    color_changed: Color,
+
+    /// Sets the color of moved entries
+    #[clap(
+        name = "color-moved",
+        long,
+        value_name = "COLOR",
+        default_value = "cyan"
+    )]
+    color_moved: Color,
+
+    /// Sets the color of unchanged groups and entries shown with --context and of times, which
+    /// are dimmed otherwise
+    #[clap(name = "color-unchanged", long, value_name = "COLOR")]
+    color_unchanged: Option<Color>,
+
     /// Enables verbose output
     #[clap(short = 'v', long)]
     verbose: bool,
 
-    /// Enables verbose output
+    /// Writes the differences to the given file instead of standard output
+    #[clap(short = 'o', long, value_name = "PATH")]
+    output: Option<Str>,
+
+    /// Masks passwords, other protected fields and OTP secrets (like --mask-fields Password)
     #[clap(short = 'm', long = "mask-passwords")]
     mask_passwords: bool,
 
+    /// Masks the values of the given fields, like UserName,URL,Notes (can be repeated)
+    #[clap(
+        name = "mask-fields",
+        long,
+        value_name = "NAME,...",
+        value_delimiter = ','
+    )]
+    mask_fields: Vec<String>,
+
+    /// Shows masked values as this text instead of ***
+    #[clap(
+        name = "mask-string",
+        long,
+        value_name = "TEXT",
+        value_parser = parse_mask_string
+    )]
+    mask_string: Option<String>,
+
+    /// Pads masked values with the last character of the mask to the length of the value
+    #[clap(name = "mask-show-length", long)]
+    mask_show_length: bool,
+
+    /// Tells for each entry of both files only whether each of its fields matches, without ever
+    /// showing or formatting a value, as all of them are only compared by their digests
+    #[clap(
+        name = "compare-only",
+        long,
+        conflicts_with_all = ["format", "json", "summary-only", "count", "base", "merge-into", "git-textconv", "tree", "stat", "entries-only", "order-by", "limit", "context", "verbose", "password-strength"]
+    )]
+    compare_only: bool,
+
+    /// Sets how the differences are printed
+    #[clap(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Prints the differences as a JSON document instead of text (same as --format json)
+    #[clap(long, conflicts_with = "format")]
+    json: bool,
+
+    /// Prints errors as a line of JSON with their category, also done for JSON output
+    #[clap(name = "json-errors", long)]
+    json_errors: bool,
+
+    /// Prints only the number of added, removed, changed and unchanged groups and entries
+    #[clap(name = "summary-only", long, conflicts_with_all = ["format", "json"])]
+    summary_only: bool,
+
+    /// Prints only the number of changes: the groups, entries and values that were added,
+    /// removed, changed or moved
+    #[clap(
+        long,
+        conflicts_with_all = ["format", "json", "summary-only", "base", "tree", "entries-only", "stat", "limit"]
+    )]
+    count: bool,
+
+    /// Prints nothing and only tells whether the databases differ by the exit code
+    #[clap(short = 'q', long, conflicts_with_all = ["format", "json", "summary-only", "output"])]
+    quiet: bool,
+
+    /// Only opens both files to check the passwords and key files, without comparing them
+    #[clap(long, conflicts_with_all = ["format", "json", "summary-only", "output"])]
+    check: bool,
+
+    /// Compares the databases again whenever INPUT-A, INPUT-B or the --base file changes,
+    /// clearing the screen before showing the differences, once the files have not changed for
+    /// 500 ms. Passwords are only asked for once. Stops on Ctrl-C.
+    #[clap(long, conflicts_with_all = ["git-textconv", "manifest", "check", "quiet"])]
+    watch: bool,
+
+    /// Shows only the added, removed, changed and moved entries with their path, including those
+    /// of added and removed groups, but not the groups themselves
+    #[clap(name = "entries-only", long, conflicts_with = "base")]
+    entries_only: bool,
+
+    /// Draws the changed groups and entries as a tree with branches, like tree(1)
+    #[clap(long, conflicts_with_all = ["base", "entries-only"])]
+    tree: bool,
+
+    /// Shows how many entries were added, removed, changed and moved in each group, like
+    /// git diff --stat
+    #[clap(
+        long,
+        conflicts_with_all = ["format", "json", "summary-only", "base", "tree", "entries-only", "limit"]
+    )]
+    stat: bool,
+
+    /// Writes text output with ASCII characters only, like -> instead of →, and draws the
+    /// branches of --tree with them (the default if the locale is not UTF-8)
+    #[clap(long)]
+    ascii: bool,
+
+    /// Shows only groups and entries that were added in the second file
+    #[clap(name = "only-additions", long, conflicts_with = "only-removals")]
+    only_additions: bool,
+
+    /// Shows only groups and entries that were removed from the first file
+    #[clap(name = "only-removals", long)]
+    only_removals: bool,
+
+    /// Shows only the first N changes, followed by how many more there are
+    #[clap(long, value_name = "N", conflicts_with_all = ["format", "json", "tree"])]
+    limit: Option<usize>,
+
+    /// Shortens the values shown in text output to N characters, and shows values with several
+    /// lines on one line with ↵ for the line breaks, as done for any length by default
+    #[clap(long, value_name = "N", conflicts_with = "no-truncate")]
+    truncate: Option<usize>,
+
+    /// Shows values in full in text output, with the lines after the first of multiline values
+    /// indented below
+    #[clap(name = "no-truncate", long)]
+    no_truncate: bool,
+
+    /// Shows up to N unchanged groups and entries before and after each changed one
+    #[clap(long, value_name = "N", default_value_t = 0)]
+    context: usize,
+
+    /// Shows all groups and entries, with the unchanged ones indented and everything in added
+    /// and removed groups marked like them, for an annotated listing of the whole database
+    #[clap(
+        name = "show-unchanged",
+        long,
+        conflicts_with_all = ["context", "limit", "tree", "stat", "entries-only", "order-by"]
+    )]
+    show_unchanged: bool,
+
+    /// Joins the groups and entries of each path with SEP, escaping it in names with a
+    /// backslash. Text lists the names of paths in brackets unless this is given, JSON and CSV
+    /// join them with / by default
+    #[clap(
+        name = "path-separator",
+        long,
+        value_name = "SEP",
+        value_parser = parse_path_separator
+    )]
+    path_separator: Option<String>,
+
+    /// Reads settings like ignored fields and excluded groups from the given TOML file instead of
+    /// .keepass-diff.toml in the current directory, with the arguments given taking precedence
+    #[clap(long, value_name = "PATH")]
+    config: Option<Str>,
+
+    /// Sets how entries of both files are paired up before they are compared
+    #[clap(name = "match-by", long, value_enum, default_value_t = MatchBy::Title)]
+    match_by: MatchBy,
+
+    /// Warns about entries of either file that are the same, or that are paired up by the same
+    /// key like the same title, before comparing them
+    #[clap(name = "warn-duplicates", long)]
+    warn_duplicates: bool,
+
+    /// Sets how the groups and entries of a group are ordered
+    #[clap(long, value_enum, default_value_t = SortBy::Title)]
+    sort: SortBy,
+
+    /// Lists the changes one per line with their full path in the given order, instead of
+    /// nested like the databases
+    #[clap(
+        name = "order-by",
+        long,
+        value_enum,
+        default_value_t = OrderBy::Tree,
+        conflicts_with_all = ["format", "json", "base", "tree", "stat", "entries-only", "verbose", "context"]
+    )]
+    order_by: OrderBy,
+
+    /// Leaves the field with the given name out of the comparison (can be repeated)
+    #[clap(name = "ignore-field", long, value_name = "NAME")]
+    ignore_field: Vec<String>,
+
+    /// Compares only the given fields, like Password,URL, and leaves out all others
+    #[clap(
+        long,
+        value_name = "NAME,...",
+        value_delimiter = ',',
+        conflicts_with = "ignore-field"
+    )]
+    fields: Vec<String>,
+
+    /// Matches the names given to --ignore-field regardless of their case
+    #[clap(name = "ignore-field-case-insensitive", long)]
+    ignore_field_case_insensitive: bool,
+
+    /// Pairs up entries and compares titles and user names regardless of their case
+    #[clap(name = "ignore-case", long)]
+    ignore_case: bool,
+
+    /// Compares the field with the given name regardless of its case (can be repeated)
+    #[clap(name = "ignore-case-field", long, value_name = "NAME")]
+    ignore_case_field: Vec<String>,
+
+    /// Normalizes the values of a field before comparing them, like URL=trim,strip-trailing-slash
+    /// (can be repeated). The rules are trim, lowercase, collapse-whitespace and
+    /// strip-trailing-slash, applied in their order.
+    #[clap(name = "field-rule", long, value_name = "FIELD=RULES")]
+    field_rule: Vec<FieldRule>,
+
+    /// Compares the history of prior versions of entries as well
+    #[clap(name = "with-history", long)]
+    with_history: bool,
+
+    /// Shows how the estimated strength of changed passwords changed, from 0 (very weak) to 4
+    /// (very strong), even if they are masked
+    #[clap(name = "password-strength", long)]
+    password_strength: bool,
+
+    /// Tells for each changed entry which file modified it last, like (B is newer), to help
+    /// decide which way to merge
+    #[clap(name = "newer-wins", long)]
+    newer_wins: bool,
+
+    /// Compares the icons of entries as well, which --verbose does too
+    #[clap(name = "show-icons", long)]
+    show_icons: bool,
+
+    /// Compares field references like {REF:P@I:<UUID>} by the values of the fields they refer to
+    /// instead of the references themselves
+    #[clap(name = "follow-references", long)]
+    follow_references: bool,
+
+    /// Only reports whether groups nested more than N levels below the root changed
+    #[clap(name = "max-depth", long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// Compares the recycle bin as well instead of leaving it out
+    #[clap(name = "include-recycle-bin", long)]
+    include_recycle_bin: bool,
+
+    /// Only compares the groups whose path below the root matches the given glob, like Work/*,
+    /// and their subgroups (can be repeated)
+    #[clap(name = "include-group", long, value_name = "GLOB")]
+    include_group: Vec<String>,
+
+    /// Only compares the group or entry at the given path below the root, like Work/Email/GitHub,
+    /// with the names joined by / or --path-separator (can be repeated)
+    #[clap(long, value_name = "PATH")]
+    only: Vec<String>,
+
+    /// Leaves the groups whose path below the root matches the given glob out of the comparison,
+    /// with their subgroups (can be repeated)
+    #[clap(name = "exclude-group", long, value_name = "GLOB")]
+    exclude_group: Vec<String>,
+
+    /// Only compares the entries that carry the given tag in either file (can be repeated)
+    #[clap(long, value_name = "NAME", conflicts_with = "ignore-all-metadata")]
+    tag: Vec<String>,
+
+    /// Only shows the changed entries whose password is all that changed, to confirm that a
+    /// password rotation changed nothing else
+    #[clap(
+        name = "changed-passwords-only",
+        long,
+        conflicts_with_all = ["only-additions", "only-removals"]
+    )]
+    changed_passwords_only: bool,
+
+    /// Reports entries whose creation, modification or expiry time differs as changed
+    #[clap(name = "compare-times", long)]
+    compare_times: bool,
+
+    /// Leaves all times out of the comparison, including when groups expire
+    #[clap(name = "ignore-times", long, conflicts_with = "compare-times")]
+    ignore_times: bool,
+
+    /// Leaves the times and all other metadata out of the comparison, like the icons of groups
+    /// and the tags of entries
+    #[clap(name = "ignore-all-metadata", long, conflicts_with = "compare-times")]
+    ignore_all_metadata: bool,
+
+    /// Sets the time zone that times of entries are shown in
+    #[clap(long, value_enum, value_name = "ZONE", default_value_t = TimeZone::Utc)]
+    timezone: TimeZone,
+
+    /// Shows entries that moved to another group as moved instead of as removed and added
+    #[clap(name = "detect-moves", long)]
+    detect_moves: bool,
+
+    /// Compares all entries regardless of their groups, as if both files had all entries in their
+    /// root, so only entries that are in one file or differ are shown. Entries are paired up by
+    /// their title and user name unless --match-by is given.
+    #[clap(
+        long,
+        conflicts_with_all = ["detect-moves", "base", "merge-into", "manifest", "git-textconv"]
+    )]
+    flatten: bool,
+
+    /// Pairs up entries only in one file with an entry only in the other if their titles and
+    /// --fuzzy-fields are at least this similar, from 0 to 1, and shows them as renamed
+    #[clap(
+        name = "fuzzy-match",
+        long,
+        value_name = "THRESHOLD",
+        value_parser = parse_threshold,
+        conflicts_with = "base"
+    )]
+    fuzzy_match: Option<f64>,
+
+    /// Sets the fields besides the title that --fuzzy-match compares
+    #[clap(
+        name = "fuzzy-fields",
+        long,
+        value_name = "NAME,...",
+        value_delimiter = ',',
+        default_value = "UserName,URL",
+        requires = "fuzzy-match"
+    )]
+    fuzzy_fields: Vec<String>,
+
+    /// Shows the differences from the second file to the first, so additions become removals.
+    /// Passwords and keyfiles still belong to the file they were given for.
+    #[clap(long)]
+    reverse: bool,
+
+    /// Compares both files with their common ancestor in the given file, telling apart the changes
+    /// made in only one of them, in both and conflicting changes (opened like the first file)
+    #[clap(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["format", "json", "summary-only", "detect-moves"]
+    )]
+    base: Option<Str>,
+
+    /// Writes a merge of both files to the given file: the first file with the groups and
+    /// entries added in the second, and the entries changed only in the second
+    #[clap(
+        name = "merge-into",
+        long,
+        value_name = "PATH",
+        requires = "merge-password",
+        conflicts_with_all = ["base", "reverse", "check"]
+    )]
+    merge_into: Option<Str>,
+
+    /// Sets the password of the file written by --merge-into
+    #[clap(
+        name = "merge-password",
+        long,
+        value_name = "PASSWORD",
+        requires = "merge-into"
+    )]
+    merge_password: Option<Str>,
+
+    /// Sets which version of an entry changed in both files --merge-into keeps
+    #[clap(long, value_enum, default_value_t = Prefer::A, requires = "merge-into")]
+    prefer: Prefer,
+
     /// Sets the password for the first file (will be asked for if omitted)
     #[clap(name = "password-a", long)]
     password_a: Option<Str>,
@@ -49,10 +540,71 @@ struct Args {
     #[clap(name = "password-b", long)]
     password_b: Option<Str>,
 
+    /// Reads the password for the first file from the given environment variable
+    #[clap(name = "password-a-env", long, value_name = "VAR")]
+    password_a_env: Option<Str>,
+
+    /// Reads the password for the second file from the given environment variable
+    #[clap(name = "password-b-env", long, value_name = "VAR")]
+    password_b_env: Option<Str>,
+
+    /// Reads the password for the first file from the first line of the given file
+    #[clap(
+        name = "password-a-file",
+        long,
+        value_name = "PATH",
+        conflicts_with = "password-a-env"
+    )]
+    password_a_file: Option<Str>,
+
+    /// Reads the password for the second file from the first line of the given file
+    #[clap(
+        name = "password-b-file",
+        long,
+        value_name = "PATH",
+        conflicts_with = "password-b-env"
+    )]
+    password_b_file: Option<Str>,
+
+    /// Reads the password for both files from the first line of the given file
+    #[clap(name = "passwords-file", long, value_name = "PATH")]
+    passwords_file: Option<Str>,
+
+    /// Reads password files even if other users are allowed to read them
+    #[clap(name = "insecure-password-file", long)]
+    insecure_password_file: bool,
+
+    /// Reads the password for the first file from a line of standard input (same as --password-a -)
+    #[clap(name = "password-a-stdin", long, conflicts_with_all = ["password-a", "password-a-env"])]
+    password_a_stdin: bool,
+
+    /// Reads the password for the second file from a line of standard input (same as --password-b -)
+    #[clap(name = "password-b-stdin", long, conflicts_with_all = ["password-b", "password-b-env"])]
+    password_b_stdin: bool,
+
     /// Sets the password for both files (if it's the same for both files)
     #[clap(name = "passwords", long, short)]
     passwords: Option<Str>,
 
+    /// Asks for a password again up to N times if the one typed at the prompt is incorrect
+    #[clap(name = "password-retries", long, value_name = "N", default_value_t = 3)]
+    password_retries: usize,
+
+    /// Sets how many files are opened at the same time, 1 opens one after another (defaults to
+    /// the number of CPUs)
+    #[clap(short = 'j', long, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+
+    /// Tells on stderr how long opening each database, comparing them and writing the
+    /// differences took
+    #[clap(long, conflicts_with = "git-textconv")]
+    timing: bool,
+
+    /// Asks for the passwords of both files before opening them, so their keys are derived at the
+    /// same time, instead of opening the first file before asking for the password of the second
+    #[clap(name = "prompt-both-first", long)]
+    prompt_both_first: bool,
+
     /// Asks for password only once, and tries to open both files with it
     #[clap(name = "same-password", long, short)]
     same_password: bool,
@@ -69,6 +621,11 @@ struct Args {
     #[clap(name = "no-passwords", long)]
     no_passwords: bool,
 
+    /// Never asks for passwords, and fails if no password (or --no-password-a or -b) is given for
+    /// a file instead
+    #[clap(name = "no-prompt", long, conflicts_with = "same-password")]
+    no_prompt: bool,
+
     /// Sets the key file for the first file
     #[clap(name = "keyfile-a", long)]
     keyfile_a: Option<Str>,
@@ -80,141 +637,1760 @@ struct Args {
     /// Sets the same key file for both files (keyfile-a and keyfile-b would take precedence if set as well)
     #[clap(name = "keyfiles", long)]
     keyfiles: Option<Str>,
+
+    /// Sets the content of the key file for the first file as base64, so it is never written to
+    /// a file
+    #[clap(
+        name = "keyfile-a-base64",
+        long,
+        value_name = "VALUE",
+        conflicts_with = "keyfile-a"
+    )]
+    keyfile_a_base64: Option<Str>,
+
+    /// Sets the content of the key file for the second file as base64
+    #[clap(
+        name = "keyfile-b-base64",
+        long,
+        value_name = "VALUE",
+        conflicts_with = "keyfile-b"
+    )]
+    keyfile_b_base64: Option<Str>,
+
+    /// Sets the content of the same key file for both files as base64 (the key files of either
+    /// file would take precedence if set as well)
+    #[clap(
+        name = "keyfiles-base64",
+        long,
+        value_name = "VALUE",
+        conflicts_with = "keyfiles"
+    )]
+    keyfiles_base64: Option<Str>,
+
+    /// Sets how the key file for the first file is read
+    #[clap(name = "keyfile-a-format", long, value_enum, default_value_t = KeyfileFormat::Auto)]
+    keyfile_a_format: KeyfileFormat,
+
+    /// Sets how the key file for the second file is read
+    #[clap(name = "keyfile-b-format", long, value_enum, default_value_t = KeyfileFormat::Auto)]
+    keyfile_b_format: KeyfileFormat,
 }
 
-fn main() -> Result<(), ()> {
-    let arguments = Args::parse();
-    let use_color = !arguments.no_color;
-    let use_verbose = arguments.verbose;
-    let mask_passwords = arguments.mask_passwords;
-
-    let (db_a, db_b) = {
-        let file_a = &arguments.input_a;
-        let file_b = &arguments.input_b;
-
-        let pass_a: Option<Cow<str>> = match (
-            arguments.password_a.as_deref(),
-            arguments.passwords.as_deref(),
-            arguments.same_password,
-            arguments.no_password_a,
-            arguments.no_passwords,
-        ) {
-            (Some(password), _, _, _, _) => Some(password.into()),
-            (_, Some(password), _, _, _) => Some(password.into()),
-            (_, _, true, _, _) => prompt_password(None).map(Into::into),
-            (_, _, _, true, _) => None,
-            (_, _, _, _, true) => None,
-            _ => prompt_password(Some(file_a)).map(Into::into),
-        };
-        let pass_b: Option<Cow<str>> = match (
-            arguments.password_b.as_deref(),
-            arguments.passwords.as_deref(),
-            arguments.same_password,
-            arguments.no_password_b,
-            arguments.no_passwords,
-        ) {
-            (Some(password), _, _, _, _) => Some(password.into()),
-            (_, Some(password), _, _, _) => Some(password.into()),
-            (_, _, true, _, _) => pass_a.clone(),
-            (_, _, _, true, _) => None,
-            (_, _, _, _, true) => None,
-            _ => prompt_password(Some(file_b)).map(Into::into),
-        };
+fn main() -> ExitCode {
+    let matches = Args::command().get_matches();
+    let mut arguments = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    // Without their groups, entries with the same title are most likely told apart by user name
+    if arguments.flatten && matches.value_source("match-by") == Some(ValueSource::DefaultValue) {
+        arguments.match_by = MatchBy::TitleUsername;
+    }
+    if let Some(conflict) = stdin_conflict(&arguments) {
+        Args::command()
+            .error(ErrorKind::ArgumentConflict, conflict)
+            .exit();
+    }
+    let json_errors = arguments.json_errors || arguments.json || arguments.format == Format::Json;
+    let files = [
+        Some(arguments.input_a.clone()),
+        arguments.input_b.clone(),
+        arguments.base.clone(),
+    ];
+    let result = apply_config(&mut arguments, &matches).and_then(|_| {
+        if arguments.watch {
+            watch(&arguments)
+        } else {
+            run(&arguments, &mut None)
+        }
+    });
+    match result {
+        Ok(false) => ExitCode::SUCCESS,
+        Ok(true) => ExitCode::from(EXIT_DIFFERENCES),
+        Err(e) if json_errors => {
+            eprintln!("{}", e.to_json(&files));
+            ExitCode::from(EXIT_ERROR)
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+/// Reads the config file given with --config, or .keepass-diff.toml in the current directory if
+/// there is one, into the arguments. Fields, groups and masked fields of the file are added to
+/// those given, and its other settings are only taken if the argument was not given.
+fn apply_config(arguments: &mut Args, matches: &ArgMatches) -> Result<(), Error> {
+    let path: Str = match &arguments.config {
+        Some(path) => path.clone(),
+        None if Path::new(config::DEFAULT_FILE).is_file() => config::DEFAULT_FILE.into(),
+        None => return Ok(()),
+    };
+    let config = Config::read(&path).map_err(|error| Error::Config {
+        path: path.clone(),
+        error,
+    })?;
+    let is_given = |id| {
+        matches
+            .value_source(id)
+            .is_some_and(|source| source != ValueSource::DefaultValue)
+    };
+    // Clap only checks the conflicts of the arguments given, so those with the settings of the
+    // file are checked here
+    let conflicts = [("ignore-field", !config.ignore_fields.is_empty(), "fields")];
+    for (setting, is_set, option) in conflicts {
+        if is_set && is_given(option) {
+            return Err(Error::Config {
+                path,
+                error: ConfigError::Conflict { setting, option },
+            });
+        }
+    }
+    let add = |given: &mut Vec<String>, from_config: Vec<String>| {
+        given.splice(0..0, from_config);
+    };
+    add(&mut arguments.ignore_field, config.ignore_fields);
+    add(&mut arguments.exclude_group, config.exclude_groups);
+    add(&mut arguments.mask_fields, config.mask_fields);
+    match config.match_by {
+        Some(match_by) if !is_given("match-by") => arguments.match_by = match_by,
+        _ => {}
+    }
+    match config.mask_passwords {
+        Some(mask) if !is_given("mask_passwords") => arguments.mask_passwords = mask,
+        _ => {}
+    }
+    Ok(())
+}
 
-        let keyfile_a = arguments.keyfile_a.as_deref();
-        let keyfile_b = arguments.keyfile_b.as_deref();
-        let keyfiles = arguments.keyfiles.as_deref();
+/// The passwords both databases were opened with, so --watch opens them again without asking.
+/// They are wiped from memory once they are dropped.
+struct Credentials {
+    password_a: Option<Zeroizing<String>>,
+    password_b: Option<Zeroizing<String>>,
+}
 
-        let keyfile_a = match (keyfile_a, keyfiles) {
-            (None, None) => None,
-            (Some(kfa), _) => Some(kfa),
-            (_, Some(kfs)) => Some(kfs),
-        };
-        let keyfile_b = match (keyfile_b, keyfiles) {
-            (None, None) => None,
-            (Some(kfb), _) => Some(kfb),
-            (_, Some(kfs)) => Some(kfs),
+/// Compares both databases and prints their differences. Returns whether they differ.
+///
+/// The databases are opened with the `credentials` if given, which are set to those that opened
+/// them otherwise.
+fn run(arguments: &Args, credentials: &mut Option<Credentials>) -> Result<bool, Error> {
+    let ascii = arguments.ascii || !locale_is_utf8();
+    let diff_options = diff_options(arguments, ascii);
+    if arguments.git_textconv {
+        return git_textconv(arguments, &diff_options).map(|_| false);
+    }
+    if arguments.manifest && arguments.input_b.is_none() {
+        let (_, db) = open_input_a(arguments, &diff_options, true)?;
+        return write_manifest(arguments, &[('A', &arguments.input_a, &db)]).map(|_| false);
+    }
+    let input_b = arguments
+        .input_b
+        .as_deref()
+        .expect("INPUT-B is required without --git-textconv or --manifest");
+
+    let mut timings = Timings::new(arguments.timing);
+    let Opened {
+        a: mut db_a,
+        b: mut db_b,
+        base: db_base,
+        merge_sources,
+    } = open_databases(arguments, input_b, &diff_options, credentials, &mut timings)?;
+    if !arguments.quiet {
+        print_warnings(arguments, &diff_options, &db_a, &db_b);
+    }
+    if arguments.check {
+        return Ok(false);
+    }
+    if arguments.manifest {
+        let databases = [('A', &*arguments.input_a, &db_a), ('B', input_b, &db_b)];
+        return write_manifest(arguments, &databases).map(|_| false);
+    }
+
+    let (mut input_a, mut input_b) = (&*arguments.input_a, input_b);
+    if arguments.reverse {
+        std::mem::swap(&mut db_a, &mut db_b);
+        std::mem::swap(&mut input_a, &mut input_b);
+    }
+    let comparing = Instant::now();
+    pair_up(arguments, &mut db_a, &mut db_b);
+    if arguments.compare_only {
+        let compared = compare_only(arguments, &db_a, &db_b);
+        timings
+            .phases
+            .push(("comparing and writing", comparing.elapsed()));
+        return compared;
+    }
+    if arguments.quiet && db_base.is_none() {
+        // Nothing is written, so comparing stops at the first difference
+        let has_changes = db_a.differs(&db_b);
+        timings.phases.push(("comparing", comparing.elapsed()));
+        if let (Some(path), Some((merged, b))) = (&arguments.merge_into, merge_sources) {
+            merge_databases(arguments, path, merged, &b)?;
+        }
+        return Ok(has_changes);
+    }
+    let filter = filter_of(arguments);
+    let delta = db_a.diff(&db_b);
+    let three_way = db_base.map(|base| {
+        let changes = |db: &Group| {
+            let mut changes = diff::collect_changes(&base.diff(db));
+            changes.retain(|c| filter.shows(c.item, c.kind));
+            changes
         };
+        let a_to_b = diff::collect_changes(&delta);
+        diff::three_way::three_way(&changes(&db_a), &changes(&db_b), &a_to_b)
+    });
+    let has_changes = match &three_way {
+        Some(changes) => !changes.is_empty(),
+        None => delta.has_changes(),
+    };
+    timings.phases.push(("comparing", comparing.elapsed()));
+    if let (Some(path), Some((merged, b))) = (&arguments.merge_into, merge_sources) {
+        merge_databases(arguments, path, merged, &b)?;
+    }
+
+    if arguments.quiet {
+        return Ok(has_changes);
+    }
+    let writing = Instant::now();
+    write_differences(
+        arguments,
+        delta,
+        three_way.as_deref(),
+        [input_a, input_b],
+        ascii,
+    )?;
+    timings
+        .phases
+        .push(("writing the differences", writing.elapsed()));
+
+    Ok(has_changes)
+}
+
+/// How the databases are compared, as the arguments tell
+fn diff_options(arguments: &Args, ascii: bool) -> DiffOptions {
+    DiffOptions {
+        use_verbose: arguments.verbose,
+        mask_passwords: arguments.mask_passwords,
+        mask_fields: arguments.mask_fields.clone(),
+        mask: Mask {
+            text: arguments
+                .mask_string
+                .clone()
+                .unwrap_or_else(|| Mask::default().text),
+            show_length: arguments.mask_show_length,
+        },
+        match_by: arguments.match_by,
+        ignore_fields: arguments.ignore_field.clone(),
+        ignore_fields_case_insensitive: arguments.ignore_field_case_insensitive,
+        only_fields: arguments.fields.clone(),
+        with_history: arguments.with_history,
+        ignore_case_fields: ignore_case_fields(arguments),
+        field_rules: arguments.field_rule.clone(),
+        include_recycle_bin: arguments.include_recycle_bin,
+        include_groups: arguments.include_group.clone(),
+        exclude_groups: arguments.exclude_group.clone(),
+        only_paths: arguments
+            .only
+            .iter()
+            .map(|path| stack::split_path(path, arguments.path_separator.as_deref().unwrap_or("/")))
+            .collect(),
+        sort_by: arguments.sort,
+        max_depth: arguments.max_depth,
+        compare_times: arguments.compare_times,
+        ignore_times: arguments.ignore_times || arguments.ignore_all_metadata,
+        ignore_metadata: arguments.ignore_all_metadata,
+        tags: arguments.tag.clone(),
+        changed_passwords_only: arguments.changed_passwords_only,
+        time_zone: arguments.timezone,
+        truncate: match (arguments.truncate, arguments.no_truncate) {
+            (Some(max), _) => Truncate::Length(max),
+            (None, true) => Truncate::Off,
+            (None, false) => Truncate::Collapse,
+        },
+        password_strength: arguments.password_strength,
+        newer_wins: arguments.newer_wins,
+        show_icons: arguments.show_icons,
+        follow_references: arguments.follow_references,
+        compare_only: arguments.compare_only,
+        ascii,
+    }
+}
+
+/// Which changes are shown, see --only-additions and --only-removals
+fn filter_of(arguments: &Args) -> Filter {
+    match (arguments.only_additions, arguments.only_removals) {
+        (true, _) => Filter::OnlyAdditions,
+        (_, true) => Filter::OnlyRemovals,
+        _ => Filter::All,
+    }
+}
+
+/// The databases to compare, as `open_databases` opens them
+struct Opened {
+    a: Group,
+    b: Group,
+    base: Option<Group>,
+    /// A and B as keepass reads them, to merge them with --merge-into
+    merge_sources: Option<(keepass::Database, keepass::Database)>,
+}
+
+/// A database given as INPUT-A or INPUT-B, before it is opened
+struct Input {
+    /// The database file, found in the directory that was given if it was one
+    file: String,
+    /// The key file found in the directory that was given, if any
+    dir_keyfile: Option<String>,
+    /// The database read from standard input, if the file is `-`
+    data: Option<Vec<u8>>,
+    /// Whether the file is missing and compared as an empty database, see --treat-missing-as-empty
+    missing: bool,
+}
+
+impl Input {
+    /// Whether the database is an XML export, which is not encrypted
+    fn is_export(&self) -> bool {
+        match &self.data {
+            Some(data) => is_xml_export(data),
+            None => is_xml_export_file(&self.file),
+        }
+    }
+}
 
-        (
-            kdbx_to_group(
+/// Finds the databases given as INPUT-A and INPUT-B, following symbolic links, looking into
+/// directories and reading a database from standard input
+fn find_inputs(arguments: &Args, input_b: &str) -> Result<(Input, Input), Error> {
+    let use_verbose = arguments.verbose && !arguments.quiet;
+    // Symbolic links are followed, so the files they link to are opened and named
+    let resolve = |input: &str, database| {
+        if arguments.no_follow_symlinks || input == STDIN_DATABASE {
+            return Ok(input.to_owned());
+        }
+        match resolve_symlink(input).map_err(|error| Error::Open { database, error })? {
+            Some(target) => {
+                if use_verbose {
+                    eprintln!("Database {}: {} links to {}", database, input, target);
+                }
+                Ok(target)
+            }
+            None => Ok(input.to_owned()),
+        }
+    };
+    let input_a = resolve(&arguments.input_a, 'A')?;
+    let input_b = resolve(input_b, 'B')?;
+    // A missing file stands for an empty database, unless both are missing
+    let is_missing = |input: &str| {
+        arguments.treat_missing_as_empty
+            && input != STDIN_DATABASE
+            && std::fs::symlink_metadata(input)
+                .is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound)
+    };
+    let (missing_a, missing_b) = match (is_missing(&input_a), is_missing(&input_b)) {
+        (true, true) => (false, false),
+        missing => missing,
+    };
+    let find = |input: &str, missing, database| {
+        if missing && use_verbose {
+            eprintln!(
+                "Database {}: {} does not exist, so it is compared as an empty database",
+                database, input
+            );
+        }
+        let open_error = |error| Error::Open { database, error };
+        // Directories stand for the database and the key file in them
+        let (file, dir_keyfile) = find_in_directory(input).map_err(open_error)?;
+        // A database from standard input is read once, as it is opened again for each password
+        let data = database_from_stdin(&file).map_err(open_error)?;
+        Ok(Input {
+            file,
+            dir_keyfile,
+            data,
+            missing,
+        })
+    };
+    Ok((
+        find(&input_a, missing_a, 'A')?,
+        find(&input_b, missing_b, 'B')?,
+    ))
+}
+
+/// The passwords to open both databases with, and where they came from
+struct Passwords<'a> {
+    a: Option<Zeroizing<String>>,
+    origin_a: Origin<'a>,
+    b: Option<Zeroizing<String>>,
+    origin_b: Origin<'a>,
+    /// Whether A is opened before the password for B is asked for
+    open_a_first: bool,
+}
+
+/// Takes the passwords of both databases from the arguments, or from the `credentials` that
+/// opened them before, asking for those that are not given
+fn passwords<'a>(
+    arguments: &'a Args,
+    credentials: &Option<Credentials>,
+    a: &'a Input,
+    b: &'a Input,
+) -> Result<Passwords<'a>, Error> {
+    // Watching opens the databases again with the passwords that opened them the first time
+    if let Some(cached) = credentials {
+        return Ok(Passwords {
+            a: cached.password_a.clone(),
+            origin_a: Origin::Given,
+            b: cached.password_b.clone(),
+            origin_b: Origin::Given,
+            open_a_first: false,
+        });
+    }
+    // Passwords from standard input are read line by line, first for A, then for B
+    let (stdin_a, stdin_b, stdin_both) = passwords_from_stdin(arguments).map_err(Error::Stdin)?;
+    let read_a = read_password(arguments, 'A', stdin_a, stdin_both.clone())?;
+    let read_b = read_password(arguments, 'B', stdin_b, stdin_both)?;
+
+    let (a_password, origin_a) = match resolve_password(arguments, 'A', read_a) {
+        _ if a.missing && !arguments.same_password => (None, Origin::Given),
+        Password::Given(password) => (password, Origin::Given),
+        Password::Same => (prompt_password(None), Origin::Prompt(None)),
+        // Exports are not encrypted
+        Password::Missing if a.is_export() => (None, Origin::Given),
+        Password::Missing if arguments.no_prompt => return Err(Error::NoPrompt('A')),
+        Password::Missing => (
+            prompt_password(Some(a.file.as_str())),
+            Origin::Prompt(Some(a.file.as_str())),
+        ),
+    };
+    let (mut b_password, origin_b) = match resolve_password(arguments, 'B', read_b) {
+        _ if b.missing => (None, Origin::Given),
+        Password::Given(password) => (password, Origin::Given),
+        Password::Same => (a_password.clone(), Origin::SameAsA),
+        Password::Missing if b.is_export() => (None, Origin::Given),
+        Password::Missing if arguments.no_prompt => return Err(Error::NoPrompt('B')),
+        Password::Missing => (None, Origin::Prompt(Some(b.file.as_str()))),
+    };
+    // If both passwords are typed, A is opened before the password for B is asked for, so a
+    // mistyped password for A shows up right away
+    let open_a_first = !arguments.prompt_both_first
+        && matches!(
+            (origin_a, origin_b),
+            (Origin::Prompt(Some(_)), Origin::Prompt(Some(_)))
+        );
+    if let (Origin::Prompt(file_name), false) = (origin_b, open_a_first) {
+        b_password = prompt_password(file_name);
+    }
+    Ok(Passwords {
+        a: a_password,
+        origin_a,
+        b: b_password,
+        origin_b,
+        open_a_first,
+    })
+}
+
+/// Opens both databases and the base, asking for their passwords as needed, and sets the
+/// `credentials` to the passwords that opened them
+fn open_databases(
+    arguments: &Args,
+    input_b: &str,
+    diff_options: &DiffOptions,
+    credentials: &mut Option<Credentials>,
+    timings: &mut Timings,
+) -> Result<Opened, Error> {
+    let ellipsis = Glyphs::new(diff_options.ascii).ellipsis;
+    let (a, b) = find_inputs(arguments, input_b)?;
+    let (file_a, file_b) = (a.file.as_str(), b.file.as_str());
+
+    // Key files given as base64 are decoded before asking for any password
+    let decode = |value: &Option<Str>, database| {
+        value
+            .as_deref()
+            .map(keyfile_from_base64)
+            .transpose()
+            .map_err(|error| Error::Open { database, error })
+    };
+    let base64_a = decode(&arguments.keyfile_a_base64, 'A')?;
+    let base64_b = decode(&arguments.keyfile_b_base64, 'B')?;
+    let base64_both = arguments
+        .keyfiles_base64
+        .as_deref()
+        .map(keyfile_from_base64)
+        .transpose()
+        .map_err(Error::KeyfilesBase64)?;
+
+    let Passwords {
+        a: mut pass_a,
+        origin_a,
+        b: mut pass_b,
+        origin_b,
+        open_a_first,
+    } = passwords(arguments, credentials, &a, &b)?;
+
+    let keyfile_a = keyfile_of(&arguments.keyfile_a, &base64_a);
+    let keyfile_b = keyfile_of(&arguments.keyfile_b, &base64_b);
+    let keyfiles = keyfile_of(&arguments.keyfiles, &base64_both);
+
+    let keyfile_a = match (keyfile_a, keyfiles) {
+        (None, None) => a.dir_keyfile.as_deref().map(Keyfile::Path),
+        (Some(kfa), _) => Some(kfa),
+        (_, Some(kfs)) => Some(kfs),
+    };
+    let keyfile_b = match (keyfile_b, keyfiles) {
+        (None, None) => b.dir_keyfile.as_deref().map(Keyfile::Path),
+        (Some(kfb), _) => Some(kfb),
+        (_, Some(kfs)) => Some(kfs),
+    };
+
+    let (time_a, time_b, time_base) = (Mutex::new(None), Mutex::new(None), Mutex::new(None));
+    let open_a = |password: Option<&str>| {
+        timed(&time_a, || match &a.data {
+            Some(data) => open_database_from(
+                data,
+                password,
+                keyfile_a,
+                arguments.keyfile_a_format,
+                diff_options,
+            ),
+            None => open_database(
                 file_a,
-                pass_a.as_deref(),
+                password,
                 keyfile_a,
-                use_verbose,
-                mask_passwords,
-            )
-            .expect("Error opening database A"),
-            kdbx_to_group(
+                arguments.keyfile_a_format,
+                diff_options,
+            ),
+        })
+    };
+    let open_b = |password: Option<&str>| {
+        timed(&time_b, || match &b.data {
+            Some(data) => open_database_from(
+                data,
+                password,
+                keyfile_b,
+                arguments.keyfile_b_format,
+                diff_options,
+            ),
+            None => open_database(
                 file_b,
-                pass_b.as_deref(),
+                password,
                 keyfile_b,
-                use_verbose,
-                mask_passwords,
+                arguments.keyfile_b_format,
+                diff_options,
+            ),
+        })
+    };
+    // The base is an ancestor of A, so it most likely still has the same credentials
+    let open_base = |password: Option<&str>| {
+        arguments
+            .base
+            .as_deref()
+            .map(|file| {
+                timed(&time_base, || {
+                    open_database(
+                        file,
+                        password,
+                        keyfile_a,
+                        arguments.keyfile_a_format,
+                        diff_options,
+                    )
+                    .map(|(_, base)| base)
+                })
+            })
+            .transpose()
+    };
+
+    // Progress is only shown on a terminal, so it stays out of logs and pipes
+    let show_progress = !arguments.quiet && std::io::stderr().is_terminal();
+    let spinner = |file: &str| {
+        let message = format!("Deriving the key for {}{}", file, ellipsis);
+        Spinner::start(message, show_progress)
+    };
+
+    // Deriving the keys takes most of the time, so the databases are opened at the same time
+    // first. Incorrect passwords are asked for again afterwards, one database after another.
+    let jobs = arguments
+        .jobs
+        .or_else(|| std::thread::available_parallelism().ok())
+        .map_or(1, NonZeroUsize::get);
+    let (first_a, mut first_b, first_base) = if jobs > 1 {
+        let message = if open_a_first {
+            format!("Deriving the key for {}{}", file_a, ellipsis)
+        } else {
+            format!(
+                "Deriving the keys for {} and {}{}",
+                file_a, file_b, ellipsis
             )
-            .expect("Error opening database B"),
+        };
+        let _spinner = Spinner::start(message, show_progress);
+        std::thread::scope(|scope| {
+            let b = (!open_a_first && !b.missing)
+                .then(|| scope.spawn(|| open_b(pass_b.as_deref().map(String::as_str))));
+            let base_job = if open_a_first { 2 } else { 3 };
+            let base = (jobs >= base_job)
+                .then(|| scope.spawn(|| open_base(pass_a.as_deref().map(String::as_str))));
+            let a = (!a.missing).then(|| open_a(pass_a.as_deref().map(String::as_str)));
+            (a, b.map(join), base.map(join))
+        })
+    } else {
+        (None, None, None)
+    };
+
+    let retries = arguments.password_retries;
+    let first_pass_a = pass_a.clone();
+    let open_a = |password: Option<&str>| {
+        let _spinner = spinner(file_a);
+        open_a(password)
+    };
+    let db_a = if a.missing {
+        None
+    } else {
+        Some(
+            open_with_retries(&mut pass_a, origin_a, retries, first_a, open_a).map_err(
+                |error| Error::Open {
+                    database: 'A',
+                    error,
+                },
+            )?,
+        )
+    };
+    timings.record("opening A", &time_a);
+    let pass_a_changed = pass_a != first_pass_a;
+    // The password for both files may have been asked for again to open A
+    if let Origin::SameAsA = origin_b {
+        if pass_a_changed {
+            first_b = None;
+        }
+        pass_b = pass_a.clone();
+    }
+    if open_a_first {
+        pass_b = prompt_password(Some(file_b));
+    }
+    let open_b = |password: Option<&str>| {
+        let _spinner = spinner(file_b);
+        open_b(password)
+    };
+    let db_b = if b.missing {
+        None
+    } else {
+        Some(
+            open_with_retries(&mut pass_b, origin_b, retries, first_b, open_b).map_err(
+                |error| Error::Open {
+                    database: 'B',
+                    error,
+                },
+            )?,
         )
     };
+    timings.record("opening B", &time_b);
+    // The empty database has the root of the other one, so only their contents differ
+    let (db_a, db_b, merge_sources) = match (db_a, db_b) {
+        // Merging needs the databases as keepass reads them
+        (Some((raw_a, db_a)), Some((raw_b, db_b))) => {
+            let merge_sources = arguments.merge_into.is_some().then_some((raw_a, raw_b));
+            (db_a, db_b, merge_sources)
+        }
+        (Some((_, db_a)), None) => {
+            let db_b = db_a.without_children();
+            (db_a, db_b, None)
+        }
+        (None, db_b) => {
+            let (_, db_b) = db_b.expect("Only one of the databases is missing");
+            (db_b.without_children(), db_b, None)
+        }
+    };
+    *credentials = Some(Credentials {
+        password_a: pass_a.clone(),
+        password_b: pass_b.clone(),
+    });
+    let db_base = match first_base.filter(|_| !pass_a_changed) {
+        Some(base) => base,
+        None => {
+            let _spinner = spinner(arguments.base.as_deref().unwrap_or_default());
+            open_base(pass_a.as_deref().map(String::as_str))
+        }
+    }
+    .map_err(Error::OpenBase)?;
+    timings.record("opening the base", &time_base);
 
-    let delta = db_a.diff(&db_b);
-    println!(
-        "{}",
-        DiffDisplay {
+    Ok(Opened {
+        a: db_a,
+        b: db_b,
+        base: db_base,
+        merge_sources,
+    })
+}
+
+/// Warns on stderr about what may make the differences of the databases unexpected, like entries
+/// without a title or --only paths that match nothing
+fn print_warnings(arguments: &Args, diff_options: &DiffOptions, db_a: &Group, db_b: &Group) {
+    if arguments.verbose {
+        print_security(db_a, db_b);
+    }
+    print_format_mismatch(db_a, db_b);
+    print_root_names(db_a, db_b);
+    if arguments.warn_duplicates {
+        print_duplicates(db_a, 'A', arguments.match_by);
+        print_duplicates(db_b, 'B', arguments.match_by);
+    }
+    print_untitled(db_a, 'A');
+    print_untitled(db_b, 'B');
+    for (only, path) in arguments.only.iter().zip(&diff_options.only_paths) {
+        if !db_a.contains_path(path) && !db_b.contains_path(path) {
+            eprintln!(
+                "Warning: --only {} matches no group or entry in either database",
+                only
+            );
+        }
+    }
+    if arguments.follow_references {
+        print_unresolved_references(db_a, 'A');
+        print_unresolved_references(db_b, 'B');
+    }
+}
+
+/// Pairs up the entries of both databases as the arguments ask before they are compared: in one
+/// flat list, by how alike they are, or by their UUID when they were moved
+fn pair_up(arguments: &Args, db_a: &mut Group, db_b: &mut Group) {
+    if arguments.flatten {
+        db_a.flatten();
+        db_b.flatten();
+    }
+    if let Some(threshold) = arguments.fuzzy_match {
+        let options = diff::fuzzy::FuzzyMatch {
+            threshold,
+            fields: arguments.fuzzy_fields.clone(),
+        };
+        diff::fuzzy::match_fuzzy(db_a, db_b, &options);
+    }
+    if arguments.detect_moves {
+        diff::moves::detect_moves(db_a, db_b);
+    }
+}
+
+/// Merges `b` into `merged` for --merge-into, telling the conflicts, and saves the result to
+/// `path`
+fn merge_databases(
+    arguments: &Args,
+    path: &str,
+    mut merged: keepass::Database,
+    b: &keepass::Database,
+) -> Result<(), Error> {
+    let merge_error = |error| Error::Merge {
+        path: path.into(),
+        error,
+    };
+    let conflicts = merge::merge(&mut merged, b, arguments.prefer).map_err(merge_error)?;
+    let kept = match arguments.prefer {
+        Prefer::A => 'A',
+        Prefer::B => 'B',
+    };
+    for conflict in conflicts.iter().filter(|_| !arguments.quiet) {
+        eprintln!(
+            "Conflict: [{}] was changed in both files, kept the version of {}",
+            conflict.path.join(", "),
+            kept
+        );
+    }
+    let password = arguments.merge_password.as_deref();
+    let key = get_database_key(password, None, KeyfileFormat::Auto)
+        .expect("A key of only a password is always valid");
+    merge::save(merged, path, key).map_err(merge_error)
+}
+
+/// Writes the differences in the format the arguments ask for, or the changes against the base
+/// if there is one. `inputs` are the names of the databases that were compared.
+fn write_differences(
+    arguments: &Args,
+    delta: diff::DiffResult<Group>,
+    three_way: Option<&[diff::three_way::ThreeWayChange]>,
+    [input_a, input_b]: [&str; 2],
+    ascii: bool,
+) -> Result<(), Error> {
+    let color = if arguments.no_color {
+        ColorWhen::Never
+    } else {
+        arguments.color
+    };
+    let filter = filter_of(arguments);
+    let format = if arguments.json {
+        Format::Json
+    } else {
+        arguments.format
+    };
+    let summary = diff::summarize(&delta).filtered(filter);
+    let separator = arguments.path_separator.as_deref().unwrap_or("/");
+    let theme = Theme {
+        added: arguments.color_added,
+        removed: arguments.color_removed,
+        changed: arguments.color_changed,
+        moved: arguments.color_moved,
+        unchanged: arguments.color_unchanged,
+    };
+
+    let (mut out, use_color) = open_output_or_err(arguments, color)?;
+    let entries_only = arguments.entries_only || arguments.stat;
+    let changes = |writer: &mut dyn output::ChangeWriter| {
+        output::write_changes(&delta, entries_only, filter, writer)
+    };
+    let written = match (format, three_way) {
+        (_, Some(changes)) => output::three_way::write(&mut out, changes, ascii),
+        // A limited preview of the changes is shown along with the summary
+        _ if arguments.count => writeln!(
+            out,
+            "{}",
+            output::count_changes(&delta, entries_only, filter)
+        ),
+        _ if arguments.summary_only && arguments.limit.is_none() => writeln!(out, "{}", summary),
+        (Format::Json, None) => output::json::Writer::new(&mut out, separator)
+            .and_then(|mut writer| changes(&mut writer)),
+        (Format::Csv, None) => output::csv::Writer::new(&mut out, separator)
+            .and_then(|mut writer| changes(&mut writer)),
+        (Format::Parseable, None) => {
+            changes(&mut output::parseable::Writer::new(&mut out, separator))
+        }
+        (Format::Xml, None) => {
+            output::xml::Writer::new(&mut out).and_then(|mut writer| changes(&mut writer))
+        }
+        (Format::Html, None) => output::html::Writer::new(&mut out, &summary, input_a, input_b)
+            .and_then(|mut writer| changes(&mut writer)),
+        (Format::Unified, None) => changes(&mut output::unified::Writer::new(
+            &mut out, input_a, input_b, &theme,
+        )),
+        (Format::Text, None) if arguments.stat => changes(&mut output::stat::Writer::new(
+            &mut out, separator, &theme, ascii,
+        ))
+        .and_then(|_| writeln!(out, "\n{}", summary)),
+        (Format::Text, None) if arguments.tree => {
+            changes(&mut output::tree::Writer::new(&mut out, &theme, ascii))
+                .and_then(|_| writeln!(out, "\n{}", summary))
+        }
+        (Format::Text, None) if arguments.order_by != OrderBy::Tree => {
+            changes(&mut output::ordered::Writer::new(
+                &mut out,
+                arguments.order_by,
+                arguments.limit,
+                arguments.path_separator.as_deref(),
+                &theme,
+                ascii,
+            ))
+            .and_then(|_| writeln!(out, "\n{}", summary))
+        }
+        (Format::Text, None) if arguments.entries_only => changes(
+            &mut output::entries::Writer::new(&mut out, &theme, ascii, arguments.limit),
+        )
+        .and_then(|_| writeln!(out, "\n{}", summary)),
+        (Format::Text, None) => DiffDisplay {
             inner: delta,
             path: stack::Stack::empty(),
-            use_color,
-            use_verbose,
-            mask_passwords,
+            options: DisplayOptions {
+                use_color,
+                use_verbose: arguments.verbose,
+                mask_passwords: arguments.mask_passwords,
+                filter,
+                theme,
+                context: arguments.context,
+                limit: arguments.limit.map(diff::Limit::new),
+                path_separator: arguments.path_separator.clone(),
+                ascii,
+                show_unchanged: arguments.show_unchanged,
+            },
         }
-    );
+        .write_to(&mut out)
+        .and_then(|_| writeln!(out, "\n{}", summary)),
+    };
+    ignore_broken_pipe(written.and_then(|_| out.flush()))
+}
 
-    Ok(())
+/// How long each phase of a comparison took, told on stderr with `--timing` once it is dropped,
+/// so the phases that were done are told even if a later one fails
+struct Timings {
+    enabled: bool,
+    phases: Vec<(&'static str, Duration)>,
 }
 
-fn prompt_password(file_name: Option<&str>) -> Option<String> {
-    let prompt = match file_name {
-        Some(fname) => format!("Password for file {}: ", fname),
-        None => "Password for both files: ".to_string(),
+impl Timings {
+    fn new(enabled: bool) -> Self {
+        Timings {
+            enabled,
+            phases: Vec::new(),
+        }
+    }
+
+    /// Adds the time taken by `phase`, as added up by [`timed`], if it was done
+    fn record(&mut self, phase: &'static str, total: &Mutex<Option<Duration>>) {
+        if let Some(duration) = *total.lock().expect("Timing a phase panicked") {
+            self.phases.push((phase, duration));
+        }
+    }
+}
+
+impl Drop for Timings {
+    fn drop(&mut self) {
+        if self.enabled {
+            for (phase, duration) in &self.phases {
+                eprintln!("Timing: {} took {:.1?}", phase, duration);
+            }
+        }
+    }
+}
+
+/// Runs `f` and adds how long it took to `total`, which may be shared by threads and sums up all
+/// the attempts of opening a database, like those with an incorrect password
+fn timed<T>(total: &Mutex<Option<Duration>>, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    let mut total = total.lock().expect("Timing a phase panicked");
+    *total = Some(total.unwrap_or_default() + start.elapsed());
+    result
+}
+
+/// Compares the databases like `run`, and again whenever INPUT-A, INPUT-B or the base changes,
+/// until it is stopped with Ctrl-C. The file system tells of the changes, and the files are only
+/// compared again once they have not changed for `WATCH_SETTLE`. Only errors before both
+/// databases are open end it, so a database that is read while it is being saved is reported and
+/// compared again once saving is done.
+fn watch(arguments: &Args) -> Result<bool, Error> {
+    let files: Vec<&str> = [
+        Some(&*arguments.input_a),
+        arguments.input_b.as_deref(),
+        arguments.base.as_deref(),
+    ]
+    .iter()
+    .flatten()
+    .copied()
+    .collect();
+    let watched = files
+        .iter()
+        .map(|file| watched_path(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(Error::Watch)?;
+    let (changed, changes) = mpsc::channel();
+    let mut debouncer = new_debouncer(WATCH_SETTLE, changed).map_err(Error::Watch)?;
+    // Databases are often saved by replacing the file, so the directories they are in are watched
+    for path in &watched {
+        let directory = path.parent().unwrap_or(path);
+        debouncer
+            .watcher()
+            .watch(directory, RecursiveMode::NonRecursive)
+            .map_err(Error::Watch)?;
+    }
+    let clear = arguments.output.is_none() && std::io::stdout().is_terminal();
+    let mut credentials = None;
+    loop {
+        let seen = modification_times(&files);
+        if clear {
+            // Clears the screen and moves the cursor to its top left corner
+            print!("\x1b[2J\x1b[H");
+        }
+        match run(arguments, &mut credentials) {
+            Err(e) if credentials.is_none() => return Err(e),
+            Err(e) => eprintln!("{}", e),
+            Ok(_) => {}
+        }
+        // Files that are still being written are only told of as changing continuously, so the
+        // files have to stop changing first
+        loop {
+            let events = changes
+                .recv()
+                .expect("The watcher only stops once it is dropped")
+                .map_err(Error::Watch)?;
+            let settled = events.iter().any(|event| {
+                event.kind == DebouncedEventKind::Any && watched.contains(&event.path)
+            });
+            if settled && modification_times(&files) != seen {
+                break;
+            }
+        }
+    }
+}
+
+/// The path that the file system tells of changes to `file` with: the file that it links to, or
+/// the file in the absolute path of its directory if it does not exist
+fn watched_path(file: &str) -> Result<PathBuf, notify::Error> {
+    if let Ok(path) = std::fs::canonicalize(file) {
+        return Ok(path);
+    }
+    let path = Path::new(file);
+    let directory = match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
     };
-    rpassword::prompt_password(prompt)
-        .map(|s| if s.is_empty() { None } else { Some(s) })
-        .unwrap_or(None)
+    let directory = std::fs::canonicalize(directory).map_err(notify::Error::io)?;
+    Ok(directory.join(path.file_name().unwrap_or_default()))
 }
 
-pub fn kdbx_to_group(
-    file: &str,
-    password: Option<&str>,
-    keyfile_path: Option<&str>,
-    use_verbose: bool,
-    mask_passwords: bool,
-) -> Result<Group, DatabaseOpenError> {
-    let db_key = get_database_key(password, keyfile_path)?;
-    let db = Database::open(&mut File::open(file)?, db_key)?;
-    Ok(Group::from_keepass(&db.root, use_verbose, mask_passwords))
-}
-
-fn get_database_key(
-    password: Option<&str>,
-    keyfile_path: Option<&str>,
-) -> Result<DatabaseKey, std::io::Error> {
-    let db_key = DatabaseKey::new();
-    let db_key = match password {
-        Some(pwd) => db_key.with_password(pwd),
-        _ => db_key,
-    };
-    if let Some(path) = keyfile_path {
-        db_key.with_keyfile(&mut File::open(path)?)
+/// When the files were last modified and their sizes, for those that can be read
+fn modification_times(files: &[&str]) -> Vec<Option<(SystemTime, u64)>> {
+    files
+        .iter()
+        .map(|file| {
+            let metadata = std::fs::metadata(file).ok()?;
+            Some((metadata.modified().ok()?, metadata.len()))
+        })
+        .collect()
+}
+
+/// Why the databases could not be compared
+#[derive(Debug)]
+enum Error {
+    /// The password for a database could not be read from the environment
+    Password {
+        database: &'static str,
+        reason: String,
+    },
+    /// The passwords could not be read from standard input
+    Stdin(String),
+    /// No password was given for the database, and --no-prompt forbids asking for it
+    NoPrompt(char),
+    Open {
+        database: char,
+        error: OpenError,
+    },
+    /// The database given with --base could not be opened
+    OpenBase(OpenError),
+    /// The key file of both databases given with --keyfiles-base64 could not be decoded
+    KeyfilesBase64(OpenError),
+    /// The config file could not be read
+    Config {
+        path: Str,
+        error: ConfigError,
+    },
+    /// The merged database could not be written to the file given with --merge-into
+    Merge {
+        path: Str,
+        error: MergeError,
+    },
+    /// The file given with --output could not be created
+    Output {
+        path: Str,
+        error: std::io::Error,
+    },
+    Write(std::io::Error),
+    /// The databases could not be watched for changes with --watch
+    Watch(notify::Error),
+}
+
+impl Error {
+    /// The error as a line of JSON for --json-errors, with its category (see
+    /// `OpenError::category`, or `password` if a password could not be read), the database and
+    /// file it is about, if any, and the message. `files` are the first, second and base file.
+    fn to_json(&self, files: &[Option<Str>; 3]) -> String {
+        let (category, database, file) = match self {
+            Error::Password { .. } | Error::Stdin(_) => ("password", None, None),
+            Error::NoPrompt(database) => {
+                let file = if *database == 'A' {
+                    &files[0]
+                } else {
+                    &files[1]
+                };
+                ("password", Some(database.to_string()), file.as_deref())
+            }
+            Error::Open { database, error } => {
+                let file = if *database == 'A' {
+                    &files[0]
+                } else {
+                    &files[1]
+                };
+                (
+                    error.category(),
+                    Some(database.to_string()),
+                    file.as_deref(),
+                )
+            }
+            Error::OpenBase(error) => (
+                error.category(),
+                Some("base".to_owned()),
+                files[2].as_deref(),
+            ),
+            Error::KeyfilesBase64(error) => (error.category(), None, None),
+            Error::Config { path, .. } => ("config", None, Some(&path[..])),
+            Error::Merge { path, .. } => ("merge", None, Some(&path[..])),
+            Error::Output { path, .. } => ("io", None, Some(&path[..])),
+            Error::Write(_) | Error::Watch(_) => ("io", None, None),
+        };
+        json!({
+            "error": category,
+            "database": database,
+            "file": file,
+            "message": self.to_string(),
+        })
+        .to_string()
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Password { database, reason } => {
+                write!(f, "Error reading the password for {}: {}", database, reason)
+            }
+            Error::Stdin(reason) => {
+                write!(f, "Error reading passwords from standard input: {}", reason)
+            }
+            Error::NoPrompt(database) => write!(
+                f,
+                "No password provided for file {} and prompting is disabled",
+                database
+            ),
+            Error::Open { database, error } => {
+                write!(f, "Error opening database {}: {}", database, error)
+            }
+            Error::OpenBase(error) => write!(f, "Error opening the base database: {}", error),
+            Error::KeyfilesBase64(error) => write!(f, "Error in --keyfiles-base64: {}", error),
+            Error::Config { path, error } => {
+                write!(f, "Error reading the config file {}: {}", path, error)
+            }
+            Error::Merge { path, error } => {
+                write!(f, "Error writing the merged database {}: {}", path, error)
+            }
+            Error::Output { path, error } => {
+                write!(f, "Error creating the output file {}: {}", path, error)
+            }
+            Error::Write(error) => write!(f, "Error writing the differences: {}", error),
+            Error::Watch(error) => write!(f, "Error watching the databases: {}", error),
+        }
+    }
+}
+
+/// Writes the listing of INPUT-A for --git-textconv. Its values are written on a single line each
+/// and with ASCII characters only, so the listing does not depend on the locale.
+fn git_textconv(arguments: &Args, diff_options: &DiffOptions) -> Result<(), Error> {
+    let options = DiffOptions {
+        ascii: true,
+        truncate: Truncate::Collapse,
+        ..diff_options.clone()
+    };
+    let (_, db) = open_input_a(arguments, &options, false)?;
+
+    let listing = output::dump::render(&db, arguments.path_separator.as_deref());
+    let (mut out, _) = open_output_or_err(arguments, ColorWhen::Never)?;
+    ignore_broken_pipe(out.write_all(listing.as_bytes()).and_then(|_| out.flush()))
+}
+
+/// Writes the manifest of the given databases for --manifest
+fn write_manifest(arguments: &Args, databases: &[(char, &str, &Group)]) -> Result<(), Error> {
+    let separator = arguments.path_separator.as_deref().unwrap_or("/");
+    let manifest = output::manifest::render(databases, separator);
+    let (mut out, _) = open_output_or_err(arguments, ColorWhen::Never)?;
+    ignore_broken_pipe(writeln!(out, "{}", manifest).and_then(|_| out.flush()))
+}
+
+/// Opens INPUT-A alone, returning the file it was read from. The password is taken from the
+/// options for database A or both databases, and otherwise asked for if `prompt` is set, or read
+/// from KEEPASS_DIFF_PASSWORD like for --git-textconv.
+fn open_input_a(
+    arguments: &Args,
+    options: &DiffOptions,
+    prompt: bool,
+) -> Result<(String, Group), Error> {
+    let open_error = |error| Error::Open {
+        database: 'A',
+        error,
+    };
+    let (file, dir_keyfile) = find_in_directory(&arguments.input_a).map_err(open_error)?;
+    let (stdin, _, stdin_both) = passwords_from_stdin(arguments).map_err(Error::Stdin)?;
+    let read = read_password(arguments, 'A', stdin, stdin_both)?;
+    let password = match resolve_password(arguments, 'A', read) {
+        Password::Given(password) => password,
+        _ if prompt => match is_xml_export_file(&file) {
+            true => None,
+            false if arguments.no_prompt => return Err(Error::NoPrompt('A')),
+            false => prompt_password(Some(&file)),
+        },
+        _ => match password_from_env(TEXTCONV_PASSWORD_ENV) {
+            Ok(password) => password,
+            // Databases that are only locked by a key file need no password
+            Err(_) if is_xml_export_file(&file) => None,
+            Err(_) if arguments.keyfile_a.is_some() || arguments.keyfiles.is_some() => None,
+            Err(_) if dir_keyfile.is_some() => None,
+            Err(_) => return Err(Error::NoPrompt('A')),
+        },
+    };
+
+    let decode = |value: &Option<Str>| {
+        value
+            .as_deref()
+            .map(keyfile_from_base64)
+            .transpose()
+            .map_err(open_error)
+    };
+    let base64_a = decode(&arguments.keyfile_a_base64)?;
+    let base64_both = arguments
+        .keyfiles_base64
+        .as_deref()
+        .map(keyfile_from_base64)
+        .transpose()
+        .map_err(Error::KeyfilesBase64)?;
+    let keyfile = keyfile_of(&arguments.keyfile_a, &base64_a)
+        .or_else(|| keyfile_of(&arguments.keyfiles, &base64_both))
+        .or_else(|| dir_keyfile.as_deref().map(Keyfile::Path));
+    let (_, db) = match database_from_stdin(&file).map_err(open_error)? {
+        Some(data) => open_database_from(
+            &data,
+            password.as_deref().map(String::as_str),
+            keyfile,
+            arguments.keyfile_a_format,
+            options,
+        ),
+        None => open_database(
+            &file,
+            password.as_deref().map(String::as_str),
+            keyfile,
+            arguments.keyfile_a_format,
+            options,
+        ),
+    }
+    .map_err(open_error)?;
+    Ok((file, db))
+}
+
+/// Prints whether the fields of the entries of both databases match, see `--compare-only`.
+/// Returns whether any of them differs, or only one database has it.
+fn compare_only(arguments: &Args, db_a: &Group, db_b: &Group) -> Result<bool, Error> {
+    let comparisons = diff::equality::compare(db_a, db_b);
+    let differs = comparisons
+        .iter()
+        .any(|c| c.equality != diff::equality::Equality::Match);
+    if arguments.quiet {
+        return Ok(differs);
+    }
+    let listing = output::equality::render(&comparisons, arguments.path_separator.as_deref());
+    let (mut out, _) = open_output_or_err(arguments, ColorWhen::Never)?;
+    ignore_broken_pipe(out.write_all(listing.as_bytes()).and_then(|_| out.flush()))?;
+    Ok(differs)
+}
+
+/// Opens the destination of the differences: the given file or standard output. Returns whether
+/// the output is colored, which for `--color auto` depends on it being a terminal. Both are
+/// buffered, as the differences are written to them piece by piece while they are found.
+fn open_output(
+    path: Option<&str>,
+    color: ColorWhen,
+) -> std::io::Result<(Box<dyn WriteColor>, bool)> {
+    let path = match path {
+        Some(path) => path,
+        None => {
+            let choice = match color {
+                ColorWhen::Auto if std::io::stdout().is_terminal() => ColorChoice::Auto,
+                ColorWhen::Always => ColorChoice::Always,
+                ColorWhen::Auto | ColorWhen::Never => ColorChoice::Never,
+            };
+            let stdout = BufferedStandardStream::stdout(choice);
+            let use_color = stdout.supports_color();
+            return Ok((Box::new(stdout), use_color));
+        }
+    };
+    let file = File::create(path)?;
+    let use_color = match color {
+        ColorWhen::Auto => file.is_terminal(),
+        ColorWhen::Always => true,
+        ColorWhen::Never => false,
+    };
+    let file = BufWriter::new(file);
+    if use_color {
+        Ok((Box::new(Ansi::new(file)), true))
     } else {
-        Ok(db_key)
+        Ok((Box::new(NoColor::new(file)), false))
+    }
+}
+
+/// Opens the destination of the differences like `open_output`, with the file given by --output
+fn open_output_or_err(
+    arguments: &Args,
+    color: ColorWhen,
+) -> Result<(Box<dyn WriteColor>, bool), Error> {
+    open_output(arguments.output.as_deref(), color).map_err(|error| Error::Output {
+        path: arguments.output.clone().unwrap_or_default(),
+        error,
+    })
+}
+
+/// Passes on an error writing the output, except once the reader of a pipe, like `head`, has
+/// seen enough, which stops the output quietly
+fn ignore_broken_pipe(written: std::io::Result<()>) -> Result<(), Error> {
+    match written {
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        written => written.map_err(Error::Write),
+    }
+}
+
+/// Warns on stderr if the databases have a different major version of their format, as some of
+/// the differences may come from how the formats store values rather than from their content
+fn print_format_mismatch(db_a: &Group, db_b: &Group) {
+    if let (Some(a), Some(b)) = (db_a.security(), db_b.security()) {
+        if a.is_other_format(b) {
+            eprintln!(
+                "Warning: database A is {} and database B is {}, so some differences may come \
+                 from the formats rather than the content",
+                a.version_name(),
+                b.version_name()
+            );
+        }
+    }
+}
+
+/// Notes on stderr if the root groups of the databases are named differently, like those of
+/// databases created apart. Their contents are compared with each other all the same.
+fn print_root_names(db_a: &Group, db_b: &Group) {
+    if db_a.name() != db_b.name() {
+        eprintln!(
+            "Note: the root group is named {} in database A and {} in database B, so their \
+             contents are compared with each other",
+            db_a.name(),
+            db_b.name()
+        );
+    }
+}
+
+/// Tells the format and encryption of both databases on stderr, so they can be checked against
+/// a policy, along with a warning if they are encrypted differently
+fn print_security(db_a: &Group, db_b: &Group) {
+    for (database, db) in [('A', db_a), ('B', db_b)] {
+        if let Some(security) = db.security() {
+            eprintln!("Database {}: {}", database, security);
+        }
+    }
+    if let (Some(a), Some(b)) = (db_a.security(), db_b.security()) {
+        let differences = a.differences(b);
+        if !differences.is_empty() {
+            eprintln!(
+                "Warning: the databases use a different {}",
+                differences.join(" and ")
+            );
+        }
     }
 }
 
-pub fn set_fg(color: Option<Color>) {
-    let mut stdout = StandardStream::stdout(ColorChoice::Always);
-    stdout.set_color(ColorSpec::new().set_fg(color)).expect("Setting colors in your console failed. Please use the --no-color flag to disable colors if the error persists.");
+/// Warns on standard error about the entries of a database that are the same or that have the
+/// same key, as they are easily confused with each other when the databases are compared
+fn print_duplicates(db: &Group, database: char, match_by: MatchBy) {
+    let key = match match_by {
+        MatchBy::Title => "title",
+        MatchBy::Uuid => "UUID",
+        MatchBy::TitleUsername => "title and user name",
+        MatchBy::TitleUrl => "title and URL",
+    };
+    for duplicates in diff::duplicates::find_duplicates(db) {
+        let mut path = duplicates.path;
+        path.push(duplicates.title);
+        let path = path.join(", ");
+        if duplicates.identical {
+            eprintln!(
+                "Warning: database {} has {} identical entries [{}]",
+                database, duplicates.count, path
+            );
+        } else {
+            eprintln!(
+                "Warning: database {} has {} entries [{}] with the same {}, which are paired up in order",
+                database, duplicates.count, path, key
+            );
+        }
+    }
 }
 
-pub fn reset_color() {
-    let mut stdout = StandardStream::stdout(ColorChoice::Always);
-    stdout.reset().expect("Resetting colors in your console failed. Please use the --no-color flag to disable colors if the error persists.");
+/// Warns on standard error about the entries of a database without a title, which cannot be
+/// paired up with the entries of the other database by their title
+fn print_untitled(db: &Group, database: char) {
+    for (path, has_uuid) in diff::duplicates::find_untitled(db) {
+        let paired_by = if has_uuid {
+            "its UUID"
+        } else {
+            "its order among the untitled entries"
+        };
+        eprintln!(
+            "Warning: entry [{}] in database {} has no title, so it is paired up by {}",
+            path.join(", "),
+            database,
+            paired_by
+        );
+    }
+}
+
+/// Warns on standard error about the field references of a database that refer to no entry, as
+/// they are compared as they are
+fn print_unresolved_references(db: &Group, database: char) {
+    for unresolved in db.unresolved_references() {
+        eprintln!(
+            "Warning: cannot resolve the reference {} in the field {} of [{}] in database {}, which is compared as it is",
+            unresolved.reference,
+            unresolved.field,
+            unresolved.path.join(", "),
+            database
+        );
+    }
+}
+
+/// Parses the text for --mask-string, which must not be empty so masked values stay visible
+fn parse_mask_string(value: &str) -> Result<String, String> {
+    match value {
+        "" => Err("must not be empty".to_owned()),
+        _ => Ok(value.to_owned()),
+    }
+}
+
+/// Parses the separator for --path-separator, which must not be empty or contain a backslash
+fn parse_path_separator(value: &str) -> Result<String, String> {
+    match value {
+        "" => Err("must not be empty".to_owned()),
+        _ if value.contains('\\') => Err("must not contain a backslash".to_owned()),
+        _ => Ok(value.to_owned()),
+    }
+}
+
+/// Parses the similarity for --fuzzy-match, a number from 0 to 1
+fn parse_threshold(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(threshold) if (0.0..=1.0).contains(&threshold) => Ok(threshold),
+        Ok(_) => Err("must be between 0 and 1".to_owned()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// The fields compared regardless of their case: title and user name for --ignore-case, and all
+/// fields given to --ignore-case-field
+fn ignore_case_fields(arguments: &Args) -> Vec<String> {
+    let mut fields = arguments.ignore_case_field.clone();
+    if arguments.ignore_case {
+        fields.extend(IGNORE_CASE_DEFAULT_FIELDS.iter().map(|f| f.to_string()));
+    }
+    fields
+}
+
+/// The key file given by its path or as base64, if either was given
+fn keyfile_of<'a>(
+    path: &'a Option<Str>,
+    base64: &'a Option<Zeroizing<Vec<u8>>>,
+) -> Option<Keyfile<'a>> {
+    match (path, base64) {
+        (Some(path), _) => Some(Keyfile::Path(path)),
+        (None, Some(contents)) => Some(Keyfile::Contents(contents)),
+        (None, None) => None,
+    }
+}
+
+/// Decodes the content of a key file given as base64, which may be wrapped over several lines
+/// like `base64` does
+fn keyfile_from_base64(value: &str) -> Result<Zeroizing<Vec<u8>>, OpenError> {
+    let value = Zeroizing::new(value.split_ascii_whitespace().collect::<String>());
+    general_purpose::STANDARD
+        .decode(value.as_bytes())
+        .map(Zeroizing::new)
+        .map_err(|e| {
+            let message = format!("invalid base64: {}", e);
+            OpenError::Keyfile(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                message,
+            ))
+        })
+}
+
+/// Reads a password from the first line of a file, without its line break. An empty line means
+/// no password. On Unix, files that the group or other users may read are rejected unless
+/// `insecure` is set.
+fn password_from_file(path: &str, insecure: bool) -> Result<Option<Zeroizing<String>>, String> {
+    let mut file = File::open(path).map_err(|e| format!("cannot open {}: {}", path, e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = file
+            .metadata()
+            .map_err(|e| format!("cannot read {}: {}", path, e))?
+            .permissions()
+            .mode();
+        if mode & 0o044 != 0 && !insecure {
+            return Err(format!(
+                "{} can be read by other users, restrict it with chmod 600 or pass --insecure-password-file",
+                path
+            ));
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = insecure;
+    // Files saved on Windows may be UTF-16 or start with a byte order mark
+    let mut data = Zeroizing::new(Vec::new());
+    file.read_to_end(&mut data)
+        .map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let text = decode_text(&data)
+        .map(Zeroizing::new)
+        .ok_or_else(|| format!("{} is neither UTF-8 nor UTF-16 text", path))?;
+    let line = text.split_inclusive('\n').next().unwrap_or_default();
+    let password = line.trim_end_matches(['\n', '\r']);
+    Ok(Some(Zeroizing::new(password.to_owned())).filter(|p| !p.is_empty()))
+}
+
+/// Reads a password from the environment variable `name`. An empty variable means no password,
+/// consistent with an empty answer to the password prompt.
+fn password_from_env(name: &str) -> Result<Option<Zeroizing<String>>, String> {
+    match std::env::var(name).map(Zeroizing::new) {
+        Ok(password) if password.is_empty() => Ok(None),
+        Ok(password) => Ok(Some(password)),
+        Err(std::env::VarError::NotPresent) => {
+            Err(format!("environment variable {} is not set", name))
+        }
+        Err(std::env::VarError::NotUnicode(_)) => Err(format!(
+            "environment variable {} is not valid unicode",
+            name
+        )),
+    }
+}
+
+/// A password read from standard input, the environment or a file, if one was requested.
+/// `Some(None)` stands for an empty one, which means no password.
+type StdinPassword = Option<Option<Zeroizing<String>>>;
+
+/// Reads the password of database `database`, A or B, from the first of these that is given:
+/// its environment variable, its password file, the line of standard input for it or for both
+/// databases, as read by `passwords_from_stdin`, and the password file for both databases
+fn read_password(
+    arguments: &Args,
+    database: char,
+    stdin: StdinPassword,
+    stdin_both: StdinPassword,
+) -> Result<StdinPassword, Error> {
+    let (env, file, name) = match database {
+        'A' => (
+            &arguments.password_a_env,
+            &arguments.password_a_file,
+            "database A",
+        ),
+        _ => (
+            &arguments.password_b_env,
+            &arguments.password_b_file,
+            "database B",
+        ),
+    };
+    let read_file = |path: &Option<Str>, database| {
+        path.as_deref()
+            .map(|path| password_from_file(path, arguments.insecure_password_file))
+            .transpose()
+            .map_err(|reason| Error::Password { database, reason })
+    };
+    let env = env
+        .as_deref()
+        .map(password_from_env)
+        .transpose()
+        .map_err(|reason| Error::Password {
+            database: name,
+            reason,
+        })?;
+    let file = read_file(file, name)?;
+    let both = read_file(&arguments.passwords_file, "both databases")?;
+    Ok(env.or(file).or(stdin).or(stdin_both).or(both))
+}
+
+/// How the password of a database is given
+enum Password {
+    /// On the command line or as `read_password` reads it, or no password is wanted
+    Given(Option<Zeroizing<String>>),
+    /// Asked for once for both databases, with --same-password
+    Same,
+    /// Not given, so it is asked for unless the database needs none
+    Missing,
+}
+
+/// The password of database `database`, A or B, as the first of these gives it: the password for
+/// it or for both databases on the command line, the one that was `read`, --same-password, and
+/// --no-password-a, --no-password-b or --no-passwords
+fn resolve_password(arguments: &Args, database: char, read: StdinPassword) -> Password {
+    let (password, no_password) = match database {
+        'A' => (&arguments.password_a, arguments.no_password_a),
+        _ => (&arguments.password_b, arguments.no_password_b),
+    };
+    // Passwords from standard input were read already
+    let not_stdin = |password: &&str| *password != STDIN_PASSWORD;
+    let given = password
+        .as_deref()
+        .filter(not_stdin)
+        .or(arguments.passwords.as_deref().filter(not_stdin));
+    match (given, read) {
+        (Some(password), _) => Password::Given(Some(Zeroizing::new(password.to_owned()))),
+        (None, Some(password)) => Password::Given(password),
+        _ if arguments.same_password => Password::Same,
+        _ if no_password || arguments.no_passwords => Password::Given(None),
+        _ => Password::Missing,
+    }
+}
+
+/// Why the inputs cannot be read as given, if standard input is asked to hold more than one of
+/// them: both databases, or a database and passwords, or a database that --watch would read again
+fn stdin_conflict(arguments: &Args) -> Option<&'static str> {
+    let is_stdin = |input: &Option<Str>| input.as_deref() == Some(STDIN_PASSWORD);
+    let databases = [Some(&arguments.input_a), arguments.input_b.as_ref()]
+        .iter()
+        .flatten()
+        .filter(|input| input.as_ref() == STDIN_DATABASE)
+        .count();
+    let passwords = arguments.password_a_stdin
+        || arguments.password_b_stdin
+        || is_stdin(&arguments.password_a)
+        || is_stdin(&arguments.password_b)
+        || is_stdin(&arguments.passwords);
+    match (databases, passwords) {
+        (1.., _) if arguments.watch => {
+            Some("--watch cannot watch a database read from standard input")
+        }
+        (2, _) => Some("only one of INPUT-A and INPUT-B can be read from standard input"),
+        (1, true) => Some("passwords cannot be read from standard input along with a database"),
+        _ => None,
+    }
+}
+
+/// Reads the whole database from standard input if `file` asks for it
+fn database_from_stdin(file: &str) -> Result<Option<Vec<u8>>, OpenError> {
+    if file != STDIN_DATABASE {
+        return Ok(None);
+    }
+    let mut data = Vec::new();
+    std::io::stdin()
+        .read_to_end(&mut data)
+        .map_err(OpenError::File)?;
+    Ok(Some(data))
+}
+
+/// Reads the requested passwords for A, for B and for both files from standard input, one line
+/// each and in this order. Line breaks are stripped and an empty line means no password,
+/// consistent with an empty answer to the password prompt.
+fn passwords_from_stdin(
+    arguments: &Args,
+) -> Result<(StdinPassword, StdinPassword, StdinPassword), String> {
+    let mut lines = std::io::stdin().lock().lines();
+    let mut read_if = |requested: bool| {
+        if !requested {
+            return Ok(None);
+        }
+        match lines.next() {
+            Some(Ok(line)) if line.is_empty() => Ok(Some(None)),
+            Some(Ok(line)) => Ok(Some(Some(Zeroizing::new(line)))),
+            Some(Err(e)) => Err(e.to_string()),
+            None => Err("not enough lines".to_owned()),
+        }
+    };
+    let is_stdin = |password: &Option<Str>| password.as_deref() == Some(STDIN_PASSWORD);
+    Ok((
+        read_if(arguments.password_a_stdin || is_stdin(&arguments.password_a))?,
+        read_if(arguments.password_b_stdin || is_stdin(&arguments.password_b))?,
+        read_if(is_stdin(&arguments.passwords))?,
+    ))
+}
+
+/// Where the password for a database came from
+#[derive(Clone, Copy)]
+enum Origin<'a> {
+    /// From the command line, the environment or standard input, or no password was wanted
+    Given,
+    /// From the prompt for the given file, or for both files
+    Prompt(Option<&'a str>),
+    /// The password for the first file, with --same-password
+    SameAsA,
+}
+
+/// Waits for a thread to finish, passing on its panic
+fn join<T>(handle: std::thread::ScopedJoinHandle<'_, T>) -> T {
+    handle
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+/// Shows a message with a spinner on standard error until it is dropped, as deriving the key of a
+/// database can take a few seconds. It only shows up if that takes longer than a moment, and
+/// clears its line when it is done.
+struct Spinner {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Spinner {
+    const FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+
+    /// Starts the spinner, unless it is not `enabled`
+    fn start(message: String, enabled: bool) -> Spinner {
+        if !enabled {
+            return Spinner {
+                stop: None,
+                thread: None,
+            };
+        }
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = std::thread::spawn(move || {
+            let mut shown = false;
+            for frame in Spinner::FRAMES.iter().cycle() {
+                // Dropping the sender stops the spinner
+                if stopped.recv_timeout(SPINNER_INTERVAL) != Err(mpsc::RecvTimeoutError::Timeout) {
+                    break;
+                }
+                eprint!("\r{} {}", frame, message);
+                shown = true;
+            }
+            if shown {
+                eprint!("\r{}\r", " ".repeat(message.chars().count() + 2));
+            }
+        });
+        Spinner {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Opens a database with `password`, asking for the password again up to `retries` times if it
+/// came from the prompt and turns out to be incorrect. Other errors are returned right away.
+/// `first` is the result of opening the database with `password` already, if it was opened.
+fn open_with_retries<T>(
+    password: &mut Option<Zeroizing<String>>,
+    origin: Origin,
+    retries: usize,
+    first: Option<Result<T, OpenError>>,
+    open: impl Fn(Option<&str>) -> Result<T, OpenError>,
+) -> Result<T, OpenError> {
+    let mut retries_left = match origin {
+        Origin::Prompt(_) => retries,
+        Origin::Given | Origin::SameAsA => 0,
+    };
+    let mut result = first.unwrap_or_else(|| open(password.as_deref().map(String::as_str)));
+    loop {
+        match (result, origin) {
+            (Err(e @ OpenError::IncorrectKey { .. }), Origin::Prompt(file_name))
+                if retries_left > 0 =>
+            {
+                eprintln!("{}, please try again", e);
+                retries_left -= 1;
+                *password = prompt_password(file_name);
+                result = open(password.as_deref().map(String::as_str));
+            }
+            (result, _) => return result,
+        }
+    }
+}
+
+/// Whether the locale, as set by the first of `LC_ALL`, `LC_CTYPE` and `LANG` that is set, uses
+/// UTF-8. Without any of them, the C locale is used, which does not. Windows consoles are assumed
+/// to handle Unicode.
+fn locale_is_utf8() -> bool {
+    if cfg!(windows) {
+        return true;
+    }
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|locale| !locale.is_empty())
+        .is_some_and(|locale| {
+            let locale = locale.to_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        })
+}
+
+fn prompt_password(file_name: Option<&str>) -> Option<Zeroizing<String>> {
+    let prompt = match file_name {
+        Some(fname) => format!("Password for file {}: ", fname),
+        None => "Password for both files: ".to_string(),
+    };
+    rpassword::prompt_password(prompt)
+        .map(Zeroizing::new)
+        .ok()
+        .filter(|s| !s.is_empty())
 }
@@ -0,0 +1,234 @@
+//! Merges the groups and entries of one database into another, for --merge-into
+
+use keepass::config::DatabaseVersion;
+use keepass::db::{Entry, Group, Node};
+use keepass::{Database, DatabaseKey};
+
+use std::fs::File;
+
+use crate::error::MergeError;
+
+/// Which version of an entry is kept if it was changed in both databases
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Prefer {
+    /// Keeps the version of the first database
+    #[default]
+    A,
+    /// Takes the version of the second database
+    B,
+}
+
+/// An entry that was changed in both databases, so neither version could be told to be newer
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Conflict {
+    /// Names of the groups containing the entry, starting at the root, and its title
+    pub path: Vec<String>,
+}
+
+/// Merges `b` into `a`: groups and entries only in `b` are added, and entries that `b` changed
+/// since its version in `a` replace it. An entry was changed in `b` if its version in `a` is one
+/// of the prior versions in its history in `b`, and the other way around. Entries that neither
+/// database has the other's version of in its history were changed in both, so they are conflicts,
+/// which keep the version of `prefer`. Groups and entries only in `a` are kept.
+///
+/// Groups and entries are paired by their UUID, or by their name and title if there is none with
+/// the same UUID. Databases with attachments are not merged, as keepass does not keep track of
+/// which entry an attachment belongs to.
+pub fn merge(a: &mut Database, b: &Database, prefer: Prefer) -> Result<Vec<Conflict>, MergeError> {
+    if has_attachments(a) || has_attachments(b) {
+        return Err(MergeError::Attachments);
+    }
+    let mut conflicts = Vec::new();
+    let mut path = vec![a.root.name.clone()];
+    merge_groups(&mut a.root, &b.root, prefer, &mut path, &mut conflicts);
+    Ok(conflicts)
+}
+
+/// Writes the merged database to `file` as KDBX 4, the only version keepass can write
+pub fn save(mut db: Database, file: &str, key: DatabaseKey) -> Result<(), MergeError> {
+    if !matches!(db.config.version, DatabaseVersion::KDB4(_)) {
+        db.config.version = DatabaseVersion::KDB4(0);
+    }
+    let mut out = File::create(file).map_err(MergeError::File)?;
+    db.save(&mut out, key).map_err(MergeError::Save)
+}
+
+fn has_attachments(db: &Database) -> bool {
+    !db.header_attachments.is_empty() || !db.meta.binaries.binaries.is_empty()
+}
+
+fn merge_groups(
+    a: &mut Group,
+    b: &Group,
+    prefer: Prefer,
+    path: &mut Vec<String>,
+    conflicts: &mut Vec<Conflict>,
+) {
+    for node in &b.children {
+        match node {
+            Node::Group(group) => match find_group(a, group) {
+                Some(index) => {
+                    path.push(group.name.clone());
+                    if let Node::Group(existing) = &mut a.children[index] {
+                        merge_groups(existing, group, prefer, path, conflicts);
+                    }
+                    path.pop();
+                }
+                None => a.children.push(node.clone()),
+            },
+            Node::Entry(entry) => match find_entry(a, entry) {
+                Some(index) => {
+                    if let Node::Entry(existing) = &mut a.children[index] {
+                        let title = existing.get_title().unwrap_or_default().to_owned();
+                        if merge_entries(existing, entry, prefer) {
+                            let mut path = path.clone();
+                            path.push(title);
+                            conflicts.push(Conflict { path });
+                        }
+                    }
+                }
+                None => a.children.push(node.clone()),
+            },
+        }
+    }
+}
+
+/// Replaces the entry `a` with `b` if `b` is newer. Returns whether both were changed.
+fn merge_entries(a: &mut Entry, b: &Entry, prefer: Prefer) -> bool {
+    if same_content(a, b) || is_in_history(a, b) {
+        return false;
+    }
+    let is_conflict = !is_in_history(b, a);
+    if !is_conflict || prefer == Prefer::B {
+        *a = b.clone();
+    }
+    is_conflict
+}
+
+/// Whether `entry` has a prior version with the same content as `version`
+fn is_in_history(entry: &Entry, version: &Entry) -> bool {
+    entry
+        .history
+        .as_ref()
+        .is_some_and(|h| h.get_entries().iter().any(|e| same_content(e, version)))
+}
+
+/// Whether the entries have the same fields and tags, regardless of their times and history
+fn same_content(a: &Entry, b: &Entry) -> bool {
+    a.fields == b.fields && a.tags == b.tags
+}
+
+fn find_group(a: &Group, group: &Group) -> Option<usize> {
+    let is_group = |f: &dyn Fn(&Group) -> bool| {
+        a.children
+            .iter()
+            .position(|node| matches!(node, Node::Group(g) if f(g)))
+    };
+    is_group(&|g| g.uuid == group.uuid).or_else(|| is_group(&|g| g.name == group.name))
+}
+
+fn find_entry(a: &Group, entry: &Entry) -> Option<usize> {
+    let is_entry = |f: &dyn Fn(&Entry) -> bool| {
+        a.children
+            .iter()
+            .position(|node| matches!(node, Node::Entry(e) if f(e)))
+    };
+    is_entry(&|e| e.uuid == entry.uuid)
+        .or_else(|| is_entry(&|e| e.get_title().is_some() && e.get_title() == entry.get_title()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use keepass::config::DatabaseConfig;
+    use keepass::db::{History, Value};
+
+    fn entry(title: &str, password: &str) -> Entry {
+        let mut entry = Entry::new();
+        let field = |value: &str| Value::Unprotected(value.to_owned());
+        entry.fields.insert("Title".to_owned(), field(title));
+        entry.fields.insert("Password".to_owned(), field(password));
+        entry
+    }
+
+    /// The entry with a new password and its prior version in its history
+    fn changed(entry: &Entry, password: &str) -> Entry {
+        let mut changed = entry.clone();
+        let mut history = History::default();
+        history.add_entry(entry.clone());
+        changed.history = Some(history);
+        changed.fields.insert(
+            "Password".to_owned(),
+            Value::Unprotected(password.to_owned()),
+        );
+        changed
+    }
+
+    fn database(entries: Vec<Entry>) -> Database {
+        let mut db = Database::new(DatabaseConfig::default());
+        db.root.children = entries.into_iter().map(Node::Entry).collect();
+        db
+    }
+
+    fn passwords(db: &Database) -> Vec<(String, String)> {
+        db.root
+            .children
+            .iter()
+            .filter_map(|node| match node {
+                Node::Entry(e) => Some((
+                    e.get_title().unwrap_or_default().to_owned(),
+                    e.get_password().unwrap_or_default().to_owned(),
+                )),
+                Node::Group(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn takes_additions_and_newer_entries_and_reports_conflicts() {
+        let (github, mail, wiki) = (
+            entry("github", "old"),
+            entry("mail", "old"),
+            entry("wiki", "pw"),
+        );
+        let mut a = database(vec![github.clone(), changed(&mail, "from a"), wiki.clone()]);
+        let b = database(vec![
+            changed(&github, "new"),
+            changed(&mail, "from b"),
+            wiki,
+            entry("gitlab", "pw"),
+        ]);
+        let conflicts = merge(&mut a, &b, Prefer::A).unwrap();
+
+        assert_eq!(
+            vec![Conflict {
+                path: vec!["Root".to_owned(), "mail".to_owned()]
+            }],
+            conflicts
+        );
+        let expected = [
+            ("github", "new"),
+            ("mail", "from a"),
+            ("wiki", "pw"),
+            ("gitlab", "pw"),
+        ];
+        let expected: Vec<(String, String)> = expected
+            .iter()
+            .map(|(title, password)| (title.to_string(), password.to_string()))
+            .collect();
+        assert_eq!(expected, passwords(&a));
+    }
+
+    #[test]
+    fn prefers_the_second_database_if_asked_to() {
+        let mail = entry("mail", "old");
+        let mut a = database(vec![changed(&mail, "from a")]);
+        let b = database(vec![changed(&mail, "from b")]);
+        merge(&mut a, &b, Prefer::B).unwrap();
+
+        assert_eq!(
+            vec![("mail".to_owned(), "from b".to_owned())],
+            passwords(&a)
+        );
+    }
+}
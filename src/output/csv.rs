@@ -0,0 +1,124 @@
+use std::io::Write;
+
+use crate::diff::attachment::SHORT_HASH_LENGTH;
+use crate::diff::{Change, ChangeKind, ItemKind};
+use crate::output::json::change_kind_name;
+use crate::output::ChangeWriter;
+
+/// Column names of the header row
+const HEADER: [&str; 5] = ["path", "change", "field", "old", "new"];
+
+/// Writes the flattened differences as CSV with one row per change, below a header row.
+/// Added and removed groups and entries get a row without a field, changed ones only get rows
+/// for their fields and attachments. Paths are joined with `separator`.
+pub struct Writer<'a> {
+    out: &'a mut dyn Write,
+    separator: &'a str,
+}
+
+impl<'a> Writer<'a> {
+    /// Writes the header row
+    pub fn new(out: &'a mut dyn Write, separator: &'a str) -> std::io::Result<Writer<'a>> {
+        write_row(out, &HEADER)?;
+        Ok(Writer { out, separator })
+    }
+}
+
+impl ChangeWriter for Writer<'_> {
+    fn write(&mut self, change: &Change) -> std::io::Result<()> {
+        match cells(change, self.separator) {
+            Some(cells) => write_row(self.out, &cells.each_ref().map(String::as_str)),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The path, kind of change, field, old and new value of a change as they are written in a row,
+/// or `None` for the changed groups and entries that only contain the changes that follow
+pub(crate) fn cells(change: &Change, separator: &str) -> Option<[String; 5]> {
+    let field = match (change.kind, change.item) {
+        // Changed groups and entries are only containers of the changes that follow, unless
+        // they are compared beyond the maximum depth
+        (ChangeKind::Changed, ItemKind::Group | ItemKind::Entry | ItemKind::History)
+            if change.old.is_none() =>
+        {
+            return None
+        }
+        (_, ItemKind::Group | ItemKind::Entry | ItemKind::History) => String::new(),
+        (_, ItemKind::Field) => change.name.clone(),
+        (_, ItemKind::Attachment) => format!("Attachment {}", change.name),
+        (_, ItemKind::Tag) => format!("tag:{}", change.name),
+    };
+    let (old, new) = change.values(separator);
+    let value = |value: &Option<String>| match (change.item, value) {
+        (ItemKind::Attachment, Some(hash)) => {
+            format!("sha256 {}", &hash[..hash.len().min(SHORT_HASH_LENGTH)])
+        }
+        (_, value) => value.clone().unwrap_or_default(),
+    };
+    Some([
+        change.location(separator),
+        change_kind_name(change.kind).to_owned(),
+        field,
+        value(&old),
+        value(&new),
+    ])
+}
+
+fn write_row(out: &mut dyn Write, cells: &[&str]) -> std::io::Result<()> {
+    for (index, cell) in cells.iter().enumerate() {
+        if index > 0 {
+            write!(out, ",")?;
+        }
+        write!(out, "{}", quote(cell))?;
+    }
+    writeln!(out)
+}
+
+/// Quotes a cell if it contains a separator, a quote or a line break, as described in RFC 4180
+fn quote(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn quotes_cells_with_separators_and_line_breaks() {
+        let notes = Change {
+            path: vec!["Root".to_owned(), "Walked".to_owned()],
+            item: ItemKind::Field,
+            name: "Notes".to_owned(),
+            kind: ChangeKind::Changed,
+            old: Some("one, two".to_owned()),
+            new: Some("say \"three\"\nfour".to_owned()),
+        };
+        let entry = Change {
+            path: vec!["Root".to_owned()],
+            item: ItemKind::Entry,
+            name: "Walked".to_owned(),
+            kind: ChangeKind::Changed,
+            old: None,
+            new: None,
+        };
+
+        let mut out = Vec::new();
+        Writer::new(&mut out, "/")
+            .and_then(|mut writer| writer.write_each(&[entry, notes]))
+            .unwrap();
+        assert_eq!(
+            "path,change,field,old,new\n\
+             Root/Walked,changed,Notes,\"one, two\",\"say \"\"three\"\"\nfour\"\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! A listing of a single database with one line per group, entry and value, for `git diff` to
+//! compare as the output of a `textconv` driver
+
+use crate::diff::entry::Entry;
+use crate::diff::group::Group;
+use crate::stack::join_path;
+
+/// Lists the groups of a database, their entries and the values of both, one per line like
+/// `[Root, Email, Mailbox, UserName = jane]`, or with the path joined by `separator` if one is
+/// given. Groups, entries and values are listed by their name rather than in the order of the
+/// database, so the listing only changes when they do. Entries of the same name keep their
+/// order. Times and prior versions are left out, as they change with every edit.
+pub fn render(root: &Group, separator: Option<&str>) -> String {
+    let mut lines = Vec::new();
+    list_group(root, &mut Vec::new(), separator, &mut lines);
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+fn list_group(
+    group: &Group,
+    path: &mut Vec<String>,
+    separator: Option<&str>,
+    lines: &mut Vec<String>,
+) {
+    path.push(group.name.clone());
+    lines.push(line(path, None, separator));
+    let mut attributes: Vec<_> = group.attributes.values().collect();
+    attributes.sort_by(|a, b| a.name.cmp(&b.name));
+    lines.extend(
+        attributes
+            .iter()
+            .map(|field| line(path, Some(field.to_string()), separator)),
+    );
+
+    let mut entries: Vec<&Entry> = group
+        .entry_keys
+        .iter()
+        .filter_map(|key| group.entries.get(key))
+        .flatten()
+        .collect();
+    entries.sort_by(|a, b| a.display_title().cmp(&b.display_title()));
+    for entry in entries {
+        path.push(entry.display_title().into_owned());
+        list_entry(entry, path, separator, lines);
+        path.pop();
+    }
+
+    let mut groups: Vec<&Group> = group
+        .group_keys
+        .iter()
+        .filter_map(|key| group.child_groups.get(key))
+        .flatten()
+        .collect();
+    groups.sort_by(|a, b| a.name.cmp(&b.name));
+    for child in groups {
+        list_group(child, path, separator, lines);
+    }
+    path.pop();
+}
+
+fn list_entry(entry: &Entry, path: &[String], separator: Option<&str>, lines: &mut Vec<String>) {
+    lines.push(line(path, None, separator));
+    let mut fields: Vec<_> = entry.fields.values().chain(&entry.icon).collect();
+    fields.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut attachments: Vec<_> = entry.attachments.values().collect();
+    attachments.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut tags: Vec<_> = entry.tags.values().collect();
+    tags.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let values = fields
+        .iter()
+        .map(|f| f.to_string())
+        .chain(attachments.iter().map(|a| a.to_string()))
+        .chain(tags.iter().map(|t| t.to_string()));
+    lines.extend(values.map(|value| line(path, Some(value), separator)));
+}
+
+/// A line like `[Root, Email, Mailbox, UserName = jane]` for the value at the end of the path,
+/// or for the group or entry at its end if there is no value
+fn line(path: &[String], value: Option<String>, separator: Option<&str>) -> String {
+    match (separator, value) {
+        (Some(separator), Some(value)) => {
+            format!("{}{}{}", join_path(path, separator), separator, value)
+        }
+        (Some(separator), None) => join_path(path, separator),
+        (None, Some(value)) => format!("[{}, {}]", path.join(", "), value),
+        (None, None) => format!("[{}]", path.join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::diff::attachment::Attachments;
+    use crate::diff::DiffOptions;
+    use keepass::db::{Entry, Group as KeepassGroup, Node, Value};
+
+    fn keepass_entry(title: &str, user_name: &str) -> Entry {
+        let mut entry = Entry::new();
+        for (name, value) in [("Title", title), ("UserName", user_name)] {
+            entry
+                .fields
+                .insert(name.to_owned(), Value::Unprotected(value.to_owned()));
+        }
+        entry
+    }
+
+    #[test]
+    fn lists_groups_entries_and_values_by_name() {
+        let mut work = KeepassGroup::new("Work");
+        work.children
+            .push(Node::Entry(keepass_entry("VPN", "jane")));
+        let mut root = KeepassGroup::new("Root");
+        root.children.push(Node::Group(work));
+        root.children
+            .push(Node::Entry(keepass_entry("wiki", "jane")));
+        root.children
+            .push(Node::Entry(keepass_entry("mail", "j@example.com")));
+        let root = Group::from_keepass(&root, &DiffOptions::default(), &Attachments::new());
+
+        assert_eq!(
+            "[Root]\n\
+             [Root, mail]\n\
+             [Root, mail, Title = mail]\n\
+             [Root, mail, UserName = j@example.com]\n\
+             [Root, wiki]\n\
+             [Root, wiki, Title = wiki]\n\
+             [Root, wiki, UserName = jane]\n\
+             [Root, Work]\n\
+             [Root, Work, VPN]\n\
+             [Root, Work, VPN, Title = VPN]\n\
+             [Root, Work, VPN, UserName = jane]\n",
+            render(&root, None)
+        );
+        assert!(render(&root, Some("/")).contains("Root/Work/VPN/UserName = jane\n"));
+    }
+}
@@ -0,0 +1,67 @@
+use termcolor::{ColorSpec, WriteColor};
+
+use crate::diff::{Change, ChangeKind};
+use crate::output::theme::Theme;
+use crate::output::{write_more, ChangeWriter, Glyphs};
+
+/// Writes the changes of entries, as collected by `collect_entry_changes`, one line per entry
+/// with its full path. Only the first `limit` changes are written, followed by how many were
+/// left out. `ascii` marks moved entries with `->` instead of `→`.
+pub struct Writer<'a> {
+    out: &'a mut dyn WriteColor,
+    theme: &'a Theme,
+    glyphs: &'static Glyphs,
+    limit: Option<usize>,
+    seen: usize,
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(
+        out: &'a mut dyn WriteColor,
+        theme: &'a Theme,
+        ascii: bool,
+        limit: Option<usize>,
+    ) -> Writer<'a> {
+        Writer {
+            out,
+            theme,
+            glyphs: Glyphs::new(ascii),
+            limit,
+            seen: 0,
+        }
+    }
+}
+
+impl ChangeWriter for Writer<'_> {
+    fn write(&mut self, change: &Change) -> std::io::Result<()> {
+        self.seen += 1;
+        if self.limit.is_some_and(|limit| self.seen > limit) {
+            return Ok(());
+        }
+        let mut path = change.path.clone();
+        path.push(change.name.clone());
+        let path = format!("[{}]", path.join(", "));
+        let line = match change.kind {
+            ChangeKind::Added => format!("+ {}", path),
+            ChangeKind::Removed => format!("- {}", path),
+            ChangeKind::Changed => format!("~ {}", path),
+            ChangeKind::Moved => {
+                let to = change.new.as_deref().unwrap_or_default();
+                format!("{} {} moved to {}", self.glyphs.arrow, path, to)
+            }
+        };
+        let out = &mut *self.out;
+        out.set_color(ColorSpec::new().set_fg(Some(self.theme.color_of(change.kind))))?;
+        write!(out, "{}", line)?;
+        out.reset()?;
+        writeln!(out)
+    }
+
+    /// Tells how many changes were left out
+    fn finish(&mut self) -> std::io::Result<()> {
+        let more = self
+            .limit
+            .map_or(0, |limit| self.seen.saturating_sub(limit));
+        write_more(self.out, more, self.glyphs.ellipsis)
+    }
+}
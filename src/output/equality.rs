@@ -0,0 +1,68 @@
+use crate::diff::equality::{Comparison, Equality};
+use crate::stack::join_path;
+
+/// Renders whether the fields of each entry match, as compared by `equality::compare`, with a
+/// line like `[Root, github]` for each entry followed by one indented line per field like
+/// `  Password: MATCH`. Entries that only one database has get a line like
+/// `[Root, github]: only in A`. The names of paths are joined with `separator` if given.
+pub fn render(comparisons: &[Comparison], separator: Option<&str>) -> String {
+    let mut out = String::new();
+    let mut last_path: Option<&Vec<String>> = None;
+    for comparison in comparisons {
+        let path = match separator {
+            None => format!("[{}]", comparison.path.join(", ")),
+            Some(separator) => join_path(&comparison.path, separator),
+        };
+        let equality = describe(comparison.equality);
+        match &comparison.field {
+            None => out.push_str(&format!("{}: {}\n", path, equality)),
+            Some(field) => {
+                if last_path != Some(&comparison.path) {
+                    out.push_str(&format!("{}\n", path));
+                }
+                out.push_str(&format!("  {}: {}\n", field, equality));
+            }
+        }
+        last_path = Some(&comparison.path);
+    }
+    out
+}
+
+fn describe(equality: Equality) -> &'static str {
+    match equality {
+        Equality::Match => "MATCH",
+        Equality::Differs => "DIFFERS",
+        Equality::OnlyInA => "only in A",
+        Equality::OnlyInB => "only in B",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lists_the_fields_of_each_entry() {
+        let comparison = |title: &str, field: Option<&str>, equality| Comparison {
+            path: vec!["Root".to_owned(), title.to_owned()],
+            field: field.map(str::to_owned),
+            equality,
+        };
+        let comparisons = vec![
+            comparison("github", Some("Password"), Equality::Match),
+            comparison("github", Some("URL"), Equality::Differs),
+            comparison("github", Some("otp"), Equality::OnlyInB),
+            comparison("gitlab", None, Equality::OnlyInA),
+        ];
+
+        assert_eq!(
+            "[Root, github]\n  \
+               Password: MATCH\n  \
+               URL: DIFFERS\n  \
+               otp: only in B\n\
+             [Root, gitlab]: only in A\n",
+            render(&comparisons, None)
+        );
+        assert!(render(&comparisons, Some("/")).starts_with("Root/github\n"));
+    }
+}
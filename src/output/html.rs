@@ -0,0 +1,241 @@
+use std::io::Write;
+
+use crate::diff::attachment::SHORT_HASH_LENGTH;
+use crate::diff::summary::Summary;
+use crate::diff::{Change, ChangeKind, ItemKind};
+use crate::output::ChangeWriter;
+
+/// Styles of the report, inlined so the page needs no other files
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em; color: #222; }
+details { margin-left: 1.5em; }
+summary { cursor: pointer; font-weight: bold; }
+ul { margin: 0.2em 0 0.2em 1.5em; padding: 0; list-style: none; }
+li, .node { margin: 0.2em 0 0.2em 1.5em; font-family: monospace; }
+li { margin-left: 0; }
+.added { color: #1a7f37; }
+.removed { color: #cf222e; }
+.changed { color: #9a6700; }
+.moved { color: #0969da; }
+del { background: #ffebe9; color: #cf222e; }
+ins { background: #dafbe1; color: #1a7f37; text-decoration: none; }
+.summary { padding: 0.5em 1em; background: #f6f8fa; border-radius: 6px; }
+";
+
+/// Writes the flattened differences as a self-contained HTML page: a header with the names of
+/// both files and the summary, followed by a collapsible section per changed group and entry
+/// with its changes, colored by their kind.
+pub struct Writer<'a> {
+    out: &'a mut dyn Write,
+    /// Paths of the open sections, the outermost first, whether they list the contents of an
+    /// entry, and the tags that close them
+    open: Vec<(Vec<String>, bool, String)>,
+    is_empty: bool,
+}
+
+impl<'a> Writer<'a> {
+    /// Writes the header of the page
+    pub fn new(
+        out: &'a mut dyn Write,
+        summary: &Summary,
+        file_a: &str,
+        file_b: &str,
+    ) -> std::io::Result<Writer<'a>> {
+        write!(out, "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n")?;
+        writeln!(out, "<meta charset=\"utf-8\">")?;
+        write!(
+            out,
+            "<title>Differences between {} and {}</title>\n<style>\n{}</style>\n",
+            escape(file_a),
+            escape(file_b),
+            STYLE
+        )?;
+        write!(out, "</head>\n<body>\n")?;
+        writeln!(
+            out,
+            "<h1>Differences between <code>{}</code> and <code>{}</code></h1>",
+            escape(file_a),
+            escape(file_b)
+        )?;
+        writeln!(
+            out,
+            "<p class=\"summary\">{}</p>",
+            escape(&summary.to_string())
+        )?;
+        Ok(Writer {
+            out,
+            open: Vec::new(),
+            is_empty: true,
+        })
+    }
+}
+
+impl ChangeWriter for Writer<'_> {
+    fn write(&mut self, change: &Change) -> std::io::Result<()> {
+        self.is_empty = false;
+        let Writer { out, open, .. } = self;
+        while let Some((path, _, closing)) = open.last() {
+            if change.path.starts_with(path) {
+                break;
+            }
+            write!(out, "{}", closing)?;
+            open.pop();
+        }
+        // Containers are listed before their changes, unless they were filtered out
+        while open.len() < change.path.len() {
+            let path = change.path[..=open.len()].to_vec();
+            writeln!(
+                out,
+                "<details open><summary>{}</summary>",
+                escape(&path[open.len()])
+            )?;
+            open.push((path, false, "</details>\n".to_owned()));
+        }
+
+        let class = class_name(change.kind);
+        // Lists of the contents of an entry only contain list items
+        let in_list = open.last().is_some_and(|(_, is_list, _)| *is_list);
+        let tag = if in_list { "li" } else { "div" };
+        match (change.kind, change.item) {
+            (ChangeKind::Changed, ItemKind::Group | ItemKind::Entry | ItemKind::History)
+                if change.old.is_none() =>
+            {
+                let is_list = change.item != ItemKind::Group;
+                writeln!(
+                    out,
+                    "{}<details open><summary class=\"{}\">{}</summary>{}",
+                    if in_list { "<li>" } else { "" },
+                    class,
+                    escape(&change.name),
+                    if is_list { "<ul>" } else { "" }
+                )?;
+                let closing = format!(
+                    "{}</details>{}\n",
+                    if is_list { "</ul>" } else { "" },
+                    if in_list { "</li>" } else { "" }
+                );
+                let mut path = change.path.clone();
+                path.push(change.name.clone());
+                open.push((path, is_list, closing));
+            }
+            _ => writeln!(
+                out,
+                "<{} class=\"{} node\">{}</{}>",
+                tag,
+                class,
+                describe(change),
+                tag
+            )?,
+        }
+        Ok(())
+    }
+
+    /// Closes the open sections and the page
+    fn finish(&mut self) -> std::io::Result<()> {
+        if self.is_empty {
+            writeln!(self.out, "<p>The databases are identical.</p>")?;
+        }
+        while let Some((_, _, closing)) = self.open.pop() {
+            write!(self.out, "{}", closing)?;
+        }
+        write!(self.out, "</body>\n</html>\n")
+    }
+}
+
+fn class_name(kind: ChangeKind) -> &'static str {
+    match kind {
+        ChangeKind::Added => "added",
+        ChangeKind::Removed => "removed",
+        ChangeKind::Changed => "changed",
+        ChangeKind::Moved => "moved",
+    }
+}
+
+/// Describes a change with its sign, name and values, already escaped
+fn describe(change: &Change) -> String {
+    let value = |value: &Option<String>| {
+        let value = match (change.item, value) {
+            (ItemKind::Attachment, Some(hash)) => {
+                format!("sha256 {}", &hash[..hash.len().min(SHORT_HASH_LENGTH)])
+            }
+            (_, value) => value.clone().unwrap_or_default(),
+        };
+        escape(&value)
+    };
+    let name = match change.item {
+        ItemKind::Attachment => escape(&format!("Attachment {}", change.name)),
+        ItemKind::Tag => escape(&format!("tag:{}", change.name)),
+        _ => escape(&change.name),
+    };
+    let has_value = change.item.has_value();
+    match change.kind {
+        ChangeKind::Added if has_value => format!("+ {} = <ins>{}</ins>", name, value(&change.new)),
+        ChangeKind::Added => format!("+ {}", name),
+        ChangeKind::Removed if has_value => {
+            format!("- {} = <del>{}</del>", name, value(&change.old))
+        }
+        ChangeKind::Removed => format!("- {}", name),
+        ChangeKind::Changed => format!(
+            "~ {} = <del>{}</del> <ins>{}</ins>",
+            name,
+            value(&change.old),
+            value(&change.new)
+        ),
+        ChangeKind::Moved => format!("→ {} moved to {}", name, value(&change.new)),
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nests_sections_and_escapes_values() {
+        let container = |path: &[&str], item, name: &str| Change {
+            path: path.iter().map(|p| p.to_string()).collect(),
+            item,
+            name: name.to_owned(),
+            kind: ChangeKind::Changed,
+            old: None,
+            new: None,
+        };
+        let notes = Change {
+            old: Some("a < b".to_owned()),
+            new: Some("a & b".to_owned()),
+            ..container(&["Root", "Walked"], ItemKind::Field, "Notes")
+        };
+        let changes = [
+            container(&[], ItemKind::Group, "Root"),
+            container(&["Root"], ItemKind::Entry, "Walked"),
+            notes,
+        ];
+        let mut out = Vec::new();
+        Writer::new(&mut out, &Summary::default(), "a.kdbx", "b.kdbx")
+            .and_then(|mut writer| writer.write_each(&changes))
+            .unwrap();
+        let html = String::from_utf8(out).unwrap();
+
+        assert!(html.contains(
+            "<details open><summary class=\"changed\">Root</summary>\n\
+             <details open><summary class=\"changed\">Walked</summary><ul>\n\
+             <li class=\"changed node\">~ Notes = <del>a &lt; b</del> <ins>a &amp; b</ins></li>\n\
+             </ul></details>\n\
+             </details>\n\
+             </body>"
+        ));
+    }
+}
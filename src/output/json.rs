@@ -0,0 +1,126 @@
+use serde_json::{json, Map, Value};
+
+use std::io::Write;
+
+use crate::diff::{Change, ChangeKind, ItemKind};
+use crate::output::ChangeWriter;
+
+/// Version of the JSON document layout. Bump it whenever existing keys change their meaning.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Indents each change in the `changes` array, two levels deep
+const CHANGE_INDENT: &str = "    ";
+
+/// Writes the flattened differences as a pretty-printed JSON document, one change at a time. The
+/// location of each change and the groups of moved objects are joined with `separator`.
+pub struct Writer<'a> {
+    out: &'a mut dyn Write,
+    separator: &'a str,
+    written: usize,
+}
+
+impl<'a> Writer<'a> {
+    /// Writes the start of the document, up to the array of the changes
+    pub fn new(out: &'a mut dyn Write, separator: &'a str) -> std::io::Result<Writer<'a>> {
+        write!(
+            out,
+            "{{\n  \"schemaVersion\": {},\n  \"changes\": ",
+            SCHEMA_VERSION
+        )?;
+        Ok(Writer {
+            out,
+            separator,
+            written: 0,
+        })
+    }
+}
+
+impl ChangeWriter for Writer<'_> {
+    fn write(&mut self, change: &Change) -> std::io::Result<()> {
+        let object = serde_json::to_string_pretty(&change_to_json(change, self.separator))
+            .expect("Serializing the differences failed");
+        // Strings are escaped, so each line of the object is a line of the document
+        let object = object.replace('\n', &format!("\n{}", CHANGE_INDENT));
+        let start = if self.written == 0 { "[" } else { "," };
+        write!(self.out, "{}\n{}{}", start, CHANGE_INDENT, object)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        if self.written == 0 {
+            writeln!(self.out, "[]\n}}")
+        } else {
+            writeln!(self.out, "\n  ]\n}}")
+        }
+    }
+}
+
+fn change_to_json(change: &Change, separator: &str) -> Value {
+    let mut object = Map::new();
+    object.insert("path".to_owned(), json!(change.path));
+    object.insert("location".to_owned(), json!(change.location(separator)));
+    object.insert("kind".to_owned(), json!(item_kind_name(change.item)));
+    object.insert("name".to_owned(), json!(change.name));
+    object.insert("change".to_owned(), json!(change_kind_name(change.kind)));
+    let (old, new) = change.values(separator);
+    if let Some(old) = old {
+        object.insert("old".to_owned(), json!(old));
+    }
+    if let Some(new) = new {
+        object.insert("new".to_owned(), json!(new));
+    }
+    Value::Object(object)
+}
+
+fn item_kind_name(kind: ItemKind) -> &'static str {
+    match kind {
+        ItemKind::Group => "group",
+        ItemKind::Entry => "entry",
+        ItemKind::Field => "field",
+        ItemKind::Attachment => "attachment",
+        ItemKind::History => "history",
+        ItemKind::Tag => "tag",
+    }
+}
+
+pub(crate) fn change_kind_name(kind: ChangeKind) -> &'static str {
+    match kind {
+        ChangeKind::Added => "added",
+        ChangeKind::Removed => "removed",
+        ChangeKind::Changed => "changed",
+        ChangeKind::Moved => "moved",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn writes_the_same_document_as_serializing_it_whole() {
+        let changes = [
+            Change::new(&[], ItemKind::Group, "Root", ChangeKind::Changed),
+            Change::new(
+                &["Root", "Walked"],
+                ItemKind::Field,
+                "Notes",
+                ChangeKind::Changed,
+            )
+            .with_values("one\ntwo", "\"three\""),
+        ];
+        for count in 0..=changes.len() {
+            let mut out = Vec::new();
+            Writer::new(&mut out, "/")
+                .and_then(|mut writer| writer.write_each(&changes[..count]))
+                .unwrap();
+            let written: Vec<Value> = changes[..count]
+                .iter()
+                .map(|change| change_to_json(change, "/"))
+                .collect();
+            let document = json!({ "schemaVersion": SCHEMA_VERSION, "changes": written });
+            let expected = serde_json::to_string_pretty(&document).unwrap() + "\n";
+            assert_eq!(expected, String::from_utf8(out).unwrap());
+        }
+    }
+}
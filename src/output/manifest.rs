@@ -0,0 +1,114 @@
+//! A JSON listing of the structure of databases: their groups and entries with the names of the
+//! fields of each entry, but none of their values
+
+use serde_json::{json, Value};
+
+use crate::diff::entry::Entry;
+use crate::diff::group::Group;
+use crate::stack::join_path;
+
+/// Version of the manifest layout. Bump it whenever existing keys change their meaning.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Renders a pretty-printed JSON document with the groups and entries of each database, given
+/// by its label like `A` and the file it was read from. Each group and entry is listed with its
+/// path, and its location joined with `separator`, in the order of the database. Entries list the
+/// names of their fields, attachments and tags sorted by name.
+pub fn render(databases: &[(char, &str, &Group)], separator: &str) -> String {
+    let databases: Vec<Value> = databases
+        .iter()
+        .map(|(label, file, root)| {
+            let mut items = Vec::new();
+            list_group(root, &mut Vec::new(), separator, &mut items);
+            json!({
+                "database": label.to_string(),
+                "file": file,
+                "items": items,
+            })
+        })
+        .collect();
+    let document = json!({
+        "schemaVersion": SCHEMA_VERSION,
+        "databases": databases,
+    });
+    serde_json::to_string_pretty(&document).expect("Serializing the manifest failed")
+}
+
+fn list_group(group: &Group, path: &mut Vec<String>, separator: &str, items: &mut Vec<Value>) {
+    path.push(group.name.clone());
+    items.push(json!({
+        "kind": "group",
+        "path": path,
+        "location": join_path(path, separator),
+    }));
+    let entries = group
+        .entry_keys
+        .iter()
+        .filter_map(|key| group.entries.get(key))
+        .flatten();
+    for entry in entries {
+        path.push(entry.display_title().into_owned());
+        items.push(entry_item(entry, path, separator));
+        path.pop();
+    }
+    let groups = group
+        .group_keys
+        .iter()
+        .filter_map(|key| group.child_groups.get(key))
+        .flatten();
+    for child in groups {
+        list_group(child, path, separator, items);
+    }
+    path.pop();
+}
+
+fn sorted(names: Vec<&String>) -> Vec<&String> {
+    let mut names = names;
+    names.sort();
+    names
+}
+
+fn entry_item(entry: &Entry, path: &[String], separator: &str) -> Value {
+    json!({
+        "kind": "entry",
+        "path": path,
+        "location": join_path(path, separator),
+        "fields": sorted(entry.fields.keys().collect()),
+        "attachments": sorted(entry.attachments.keys().collect()),
+        "tags": sorted(entry.tags.keys().collect()),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::diff::attachment::Attachments;
+    use crate::diff::DiffOptions;
+    use keepass::db::{Entry, Node, Value as KeepassValue};
+
+    #[test]
+    fn lists_the_names_of_fields_without_their_values() {
+        let mut entry = Entry::new();
+        entry.fields.insert(
+            "Title".to_owned(),
+            KeepassValue::Unprotected("github".to_owned()),
+        );
+        entry.fields.insert(
+            "Password".to_owned(),
+            KeepassValue::Protected("secret".as_bytes().into()),
+        );
+        let mut work = keepass::db::Group::new("Work");
+        work.children.push(Node::Entry(entry));
+        let mut root = keepass::db::Group::new("Root");
+        root.children.push(Node::Group(work));
+        let root = Group::from_keepass(&root, &DiffOptions::default(), &Attachments::new());
+
+        let manifest = render(&[('A', "vault.kdbx", &root)], "/");
+        assert!(!manifest.contains("secret"));
+        let manifest: Value = serde_json::from_str(&manifest).unwrap();
+        let items = &manifest["databases"][0]["items"];
+        assert_eq!("Root/Work", items[1]["location"]);
+        assert_eq!(json!(["Root", "Work", "github"]), items[2]["path"]);
+        assert_eq!(json!(["Password", "Title"]), items[2]["fields"]);
+    }
+}
@@ -0,0 +1,157 @@
+//! Alternative renderers for a diff, next to the default text output of `DiffDisplay`
+
+use termcolor::WriteColor;
+
+use std::ops::ControlFlow;
+
+use crate::diff::{Change, DiffResultFormat, Filter};
+use crate::stack::Stack;
+
+pub mod csv;
+pub mod dump;
+pub mod entries;
+pub mod equality;
+pub mod html;
+pub mod json;
+pub mod manifest;
+pub mod ordered;
+pub mod parseable;
+pub mod stat;
+pub mod theme;
+pub mod three_way;
+pub mod tree;
+pub mod unified;
+pub mod xml;
+
+/// The characters that text output marks moves, shortened values and line breaks with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Glyphs {
+    pub arrow: &'static str,
+    pub ellipsis: &'static str,
+    pub line_break: &'static str,
+}
+
+impl Glyphs {
+    pub const UNICODE: Glyphs = Glyphs {
+        arrow: "→",
+        ellipsis: "…",
+        line_break: "↵",
+    };
+
+    /// For terminals and logs without Unicode, see `--ascii`
+    pub const ASCII: Glyphs = Glyphs {
+        arrow: "->",
+        ellipsis: "...",
+        line_break: "\\n",
+    };
+
+    pub fn new(ascii: bool) -> &'static Glyphs {
+        if ascii {
+            &Glyphs::ASCII
+        } else {
+            &Glyphs::UNICODE
+        }
+    }
+}
+
+/// How the differences are printed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// Tree of the changed groups and entries
+    #[default]
+    Text,
+    /// JSON document of all changes
+    Json,
+    /// Removed and added values below a header with their path, like `diff -u`
+    Unified,
+    /// One row per change with its path, field and old and new value
+    Csv,
+    /// XML document of all changes, laid out like a KeePass XML export
+    Xml,
+    /// Self-contained HTML page with a collapsible section per changed group and entry
+    Html,
+    /// One record per change like csv, ended by NUL and with its fields separated by the ASCII
+    /// unit separator, for scripts
+    Parseable,
+}
+
+/// Writes the changes of a diff one at a time as the differences are walked, so they are not
+/// collected first, like `DiffDisplay::write_to` does for the text output
+pub trait ChangeWriter {
+    /// Writes a single change
+    fn write(&mut self, change: &Change) -> std::io::Result<()>;
+
+    /// Writes what follows the last change, like the end of the document
+    fn finish(&mut self) -> std::io::Result<()>;
+
+    /// Writes the given changes and finishes
+    fn write_each(&mut self, changes: &[Change]) -> std::io::Result<()> {
+        for change in changes {
+            self.write(change)?;
+        }
+        self.finish()
+    }
+}
+
+/// Walks the differences and passes each change that `filter` shows to `writer` as soon as it is
+/// found, then finishes it. Only the changed entries are walked if `entries_only` is set, see
+/// `collect_entry_changes`. The walk stops at the first error, like when the reader of a pipe
+/// has seen enough.
+pub fn write_changes(
+    delta: &dyn DiffResultFormat,
+    entries_only: bool,
+    filter: Filter,
+    writer: &mut dyn ChangeWriter,
+) -> std::io::Result<()> {
+    let mut written = Ok(());
+    let mut visit = |change: Change| {
+        if !filter.shows(change.item, change.kind) {
+            return ControlFlow::Continue(());
+        }
+        match writer.write(&change) {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(error) => {
+                written = Err(error);
+                ControlFlow::Break(())
+            }
+        }
+    };
+    let _ = if entries_only {
+        delta.visit_entry_changes(&Stack::empty(), &mut visit)
+    } else {
+        delta.visit_changes(&Stack::empty(), &mut visit)
+    };
+    written?;
+    writer.finish()
+}
+
+/// The number of changes that `filter` shows, without the groups and entries that only contain
+/// them, counted while the differences are walked
+pub fn count_changes(delta: &dyn DiffResultFormat, entries_only: bool, filter: Filter) -> usize {
+    let mut count = 0;
+    let mut visit = |change: Change| {
+        if !change.is_container() && filter.shows(change.item, change.kind) {
+            count += 1;
+        }
+        ControlFlow::Continue(())
+    };
+    let _ = if entries_only {
+        delta.visit_entry_changes(&Stack::empty(), &mut visit)
+    } else {
+        delta.visit_changes(&Stack::empty(), &mut visit)
+    };
+    count
+}
+
+/// Tells how many changes were left out of text output by `--limit`, if any
+pub(crate) fn write_more(
+    out: &mut dyn WriteColor,
+    more: usize,
+    ellipsis: &str,
+) -> std::io::Result<()> {
+    match more {
+        0 => Ok(()),
+        1 => writeln!(out, "{} and 1 more change", ellipsis),
+        more => writeln!(out, "{} and {} more changes", ellipsis, more),
+    }
+}
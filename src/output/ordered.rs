@@ -0,0 +1,294 @@
+use termcolor::{ColorSpec, WriteColor};
+
+use std::cmp::Ordering;
+
+use crate::diff::attachment::SHORT_HASH_LENGTH;
+use crate::diff::{Change, ChangeKind, ItemKind};
+use crate::output::theme::Theme;
+use crate::output::{write_more, ChangeWriter, Glyphs};
+use crate::stack::{join_path, split_path};
+
+/// The order in which text output lists the changes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OrderBy {
+    /// Nested like the groups and entries of the databases
+    #[default]
+    Tree,
+    /// Removals first, then changed passwords, then other changes and moves, then additions
+    Type,
+    /// By the path of the changed group, entry or value
+    Path,
+}
+
+/// Compares changes in the given order, equal ones are listed in the order they are found in
+fn compare(order: OrderBy, a: &Change, b: &Change) -> Ordering {
+    match order {
+        OrderBy::Tree => Ordering::Equal,
+        OrderBy::Type => rank(a).cmp(&rank(b)),
+        OrderBy::Path => path_key(a).cmp(&path_key(b)),
+    }
+}
+
+/// The names of the changed group or entry, or of the entry containing the changed value,
+/// followed by the name of the value, so entries come before their values
+fn path_key(change: &Change) -> (Vec<&str>, Option<&str>) {
+    let mut names: Vec<&str> = change.path.iter().map(String::as_str).collect();
+    if change.item.is_node() || change.item == ItemKind::History {
+        names.push(&change.name);
+        (names, None)
+    } else {
+        (names, Some(&change.name))
+    }
+}
+
+/// How severe a change is, with the most severe first
+fn rank(change: &Change) -> u8 {
+    match change.kind {
+        ChangeKind::Removed => 0,
+        ChangeKind::Changed if change.item == ItemKind::Field && change.name == "Password" => 1,
+        ChangeKind::Changed | ChangeKind::Moved => 2,
+        ChangeKind::Added => 3,
+    }
+}
+
+/// Writes the changes, as collected by `collect_changes`, one per line with their full path, like
+/// the text output shows them when they are not nested, such as `+ [Root, Work, Email]` or
+/// `- [Root, github, Password = old]` followed by `+ [Root, github, Password = new]`. The groups
+/// and entries that only contain changes are left out, as their changes are listed with their
+/// full path. The names of paths are joined with `separator` if given. `ascii` marks moves with
+/// `->` instead of `→`.
+///
+/// The changes are sorted in `order` once they have all been found, so they are kept until then.
+/// Only the first `limit` of them are kept if given, followed by how many were left out.
+pub struct Writer<'a> {
+    out: &'a mut dyn WriteColor,
+    order: OrderBy,
+    limit: Option<usize>,
+    separator: Option<&'a str>,
+    theme: &'a Theme,
+    glyphs: &'static Glyphs,
+    kept: Vec<Change>,
+    written: usize,
+    more: usize,
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(
+        out: &'a mut dyn WriteColor,
+        order: OrderBy,
+        limit: Option<usize>,
+        separator: Option<&'a str>,
+        theme: &'a Theme,
+        ascii: bool,
+    ) -> Writer<'a> {
+        Writer {
+            out,
+            order,
+            limit,
+            separator,
+            theme,
+            glyphs: Glyphs::new(ascii),
+            kept: Vec::new(),
+            written: 0,
+            more: 0,
+        }
+    }
+
+    /// Sorts the kept changes and leaves out those beyond the limit
+    fn truncate(&mut self) {
+        let order = self.order;
+        self.kept.sort_by(|a, b| compare(order, a, b));
+        if let Some(limit) = self.limit.filter(|limit| self.kept.len() > *limit) {
+            self.more += self.kept.len() - limit;
+            self.kept.truncate(limit);
+        }
+    }
+}
+
+impl ChangeWriter for Writer<'_> {
+    fn write(&mut self, change: &Change) -> std::io::Result<()> {
+        if change.is_container() {
+            return Ok(());
+        }
+        if self.order == OrderBy::Tree {
+            // Already in order, so there is no need to wait for the others
+            if self.limit.is_some_and(|limit| self.written >= limit) {
+                self.more += 1;
+                return Ok(());
+            }
+            self.written += 1;
+            return write_change(self.out, change, self.separator, self.theme, self.glyphs);
+        }
+        self.kept.push(change.clone());
+        // Sorting once there are twice as many as will be written keeps at most that many
+        if self
+            .limit
+            .is_some_and(|limit| self.kept.len() >= limit.max(1) * 2)
+        {
+            self.truncate();
+        }
+        Ok(())
+    }
+
+    /// Writes the kept changes in order and tells how many were left out
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.truncate();
+        for change in &self.kept {
+            write_change(self.out, change, self.separator, self.theme, self.glyphs)?;
+        }
+        write_more(self.out, self.more, self.glyphs.ellipsis)
+    }
+}
+
+fn write_change(
+    out: &mut dyn WriteColor,
+    change: &Change,
+    separator: Option<&str>,
+    theme: &Theme,
+    glyphs: &Glyphs,
+) -> std::io::Result<()> {
+    let path = |names: &[String], label: Option<String>| {
+        let joined = match separator {
+            None => names.join(", "),
+            Some(separator) => join_path(names, separator),
+        };
+        match (separator, label) {
+            (None, None) => format!("[{}]", joined),
+            (None, Some(label)) => format!("[{}, {}]", joined, label),
+            (Some(_), None) => joined,
+            (Some(separator), Some(label)) => format!("{}{}{}", joined, separator, label),
+        }
+    };
+    // Groups and entries are part of the path, values are labelled after it
+    let line = |value: Option<&String>| match change.item {
+        ItemKind::Field => {
+            let value = value.map_or("", String::as_str);
+            path(&change.path, Some(format!("{} = {}", change.name, value)))
+        }
+        ItemKind::Attachment => {
+            let hash = value.map_or("", |v| &v[..SHORT_HASH_LENGTH.min(v.len())]);
+            let label = format!("Attachment {} (sha256 {})", change.name, hash);
+            path(&change.path, Some(label))
+        }
+        ItemKind::Tag => path(&change.path, Some(format!("tag:{}", change.name))),
+        _ => {
+            let mut names = change.path.clone();
+            names.push(change.name.clone());
+            path(&names, None)
+        }
+    };
+    let lines = match change.kind {
+        ChangeKind::Added => vec![format!("+ {}", line(change.new.as_ref()))],
+        ChangeKind::Removed => vec![format!("- {}", line(change.old.as_ref()))],
+        ChangeKind::Changed if change.item.has_value() => vec![
+            format!("- {}", line(change.old.as_ref())),
+            format!("+ {}", line(change.new.as_ref())),
+        ],
+        ChangeKind::Changed => vec![format!("~ {}", line(None))],
+        ChangeKind::Moved => {
+            let moved =
+                |p: &Option<String>| path(&split_path(p.as_deref().unwrap_or_default(), "/"), None);
+            vec![format!(
+                "{} {} moved from {} to {}",
+                glyphs.arrow,
+                change.name,
+                moved(&change.old),
+                moved(&change.new)
+            )]
+        }
+    };
+    for (line, kind) in lines.iter().zip(line_kinds(change.kind)) {
+        out.set_color(ColorSpec::new().set_fg(Some(theme.color_of(kind))))?;
+        write!(out, "{}", line)?;
+        out.reset()?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// The colors of the lines of a change: a changed value is removed, then added
+fn line_kinds(kind: ChangeKind) -> [ChangeKind; 2] {
+    match kind {
+        ChangeKind::Changed => [ChangeKind::Removed, ChangeKind::Added],
+        kind => [kind, kind],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use termcolor::Buffer;
+
+    fn change(path: &[&str], item: ItemKind, name: &str, kind: ChangeKind) -> Change {
+        let change = Change::new(path, item, name, kind);
+        if item.has_value() {
+            change.with_values("old", "new")
+        } else {
+            change
+        }
+    }
+
+    #[test]
+    fn lists_the_changes_by_type_or_path() {
+        let changes = vec![
+            change(&["Root"], ItemKind::Entry, "Wiki", ChangeKind::Added),
+            change(
+                &["Root", "mail"],
+                ItemKind::Field,
+                "URL",
+                ChangeKind::Changed,
+            ),
+            change(&["Root"], ItemKind::Group, "Old", ChangeKind::Removed),
+            change(
+                &["Root", "bank"],
+                ItemKind::Field,
+                "Password",
+                ChangeKind::Changed,
+            ),
+            change(
+                &["Root", "mail"],
+                ItemKind::Tag,
+                "work",
+                ChangeKind::Removed,
+            ),
+        ];
+        let render_in = |order, limit| {
+            let mut out = Buffer::no_color();
+            Writer::new(&mut out, order, limit, None, &Theme::default(), true)
+                .write_each(&changes)
+                .unwrap();
+            String::from_utf8(out.into_inner()).unwrap()
+        };
+
+        assert_eq!(
+            "- [Root, Old]\n\
+             - [Root, mail, tag:work]\n\
+             - [Root, bank, Password = old]\n\
+             + [Root, bank, Password = new]\n\
+             - [Root, mail, URL = old]\n\
+             + [Root, mail, URL = new]\n\
+             + [Root, Wiki]\n",
+            render_in(OrderBy::Type, None)
+        );
+        assert_eq!(
+            "- [Root, Old]\n\
+             + [Root, Wiki]\n\
+             - [Root, bank, Password = old]\n\
+             + [Root, bank, Password = new]\n\
+             - [Root, mail, URL = old]\n\
+             + [Root, mail, URL = new]\n\
+             - [Root, mail, tag:work]\n",
+            render_in(OrderBy::Path, None)
+        );
+        assert_eq!(
+            "- [Root, Old]\n\
+             - [Root, mail, tag:work]\n\
+             ... and 3 more changes\n",
+            render_in(OrderBy::Type, Some(2))
+        );
+        assert_eq!(
+            "+ [Root, Wiki]\n... and 4 more changes\n",
+            render_in(OrderBy::Tree, Some(1))
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use std::io::Write;
+
+use crate::diff::Change;
+use crate::output::{csv, ChangeWriter};
+
+/// Ends each record. KeePass stores values as XML, which cannot contain it.
+const RECORD_END: char = '\0';
+/// Separates the fields of a record, the ASCII unit separator, which XML cannot contain either
+const FIELD_SEPARATOR: char = '\u{1f}';
+
+/// Writes the flattened differences for scripts, with one record per change like the rows of
+/// `csv::Writer`, each ended by a NUL character like `find -print0` does. The fields of a record
+/// are the kind of change, the path, the field, the old and the new value, separated by the
+/// ASCII unit separator, so names and values with spaces, commas or line breaks need no quoting.
+/// Paths are joined with `separator`.
+pub struct Writer<'a> {
+    out: &'a mut dyn Write,
+    separator: &'a str,
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(out: &'a mut dyn Write, separator: &'a str) -> Writer<'a> {
+        Writer { out, separator }
+    }
+}
+
+impl ChangeWriter for Writer<'_> {
+    fn write(&mut self, change: &Change) -> std::io::Result<()> {
+        if let Some([path, kind, field, old, new]) = csv::cells(change, self.separator) {
+            let fields = [kind, path, field, old, new];
+            write!(
+                self.out,
+                "{}{}",
+                fields.join(&FIELD_SEPARATOR.to_string()),
+                RECORD_END
+            )?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::diff::{ChangeKind, ItemKind};
+
+    #[test]
+    fn ends_records_with_nul() {
+        let notes = Change {
+            path: vec!["Root".to_owned(), "Walked, slowly".to_owned()],
+            item: ItemKind::Field,
+            name: "Notes".to_owned(),
+            kind: ChangeKind::Changed,
+            old: Some("one two".to_owned()),
+            new: Some("three\nfour".to_owned()),
+        };
+        let added = Change {
+            path: vec!["Root".to_owned()],
+            item: ItemKind::Entry,
+            name: "a/b".to_owned(),
+            kind: ChangeKind::Added,
+            old: None,
+            new: None,
+        };
+
+        let mut out = Vec::new();
+        Writer::new(&mut out, "/")
+            .write_each(&[notes, added])
+            .unwrap();
+        assert_eq!(
+            "changed\u{1f}Root/Walked, slowly\u{1f}Notes\u{1f}one two\u{1f}three\nfour\0\
+             added\u{1f}Root/a\\/b\u{1f}\u{1f}\u{1f}\0",
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
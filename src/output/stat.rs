@@ -0,0 +1,144 @@
+use termcolor::{ColorSpec, WriteColor};
+
+use crate::diff::{Change, ChangeKind};
+use crate::output::theme::Theme;
+use crate::output::{ChangeWriter, Glyphs};
+use crate::stack::join_path;
+
+/// The kinds of changes in the order they are counted, with their markers
+const KINDS: [(ChangeKind, &str, &str); 4] = [
+    (ChangeKind::Added, "+", "+"),
+    (ChangeKind::Removed, "-", "-"),
+    (ChangeKind::Changed, "~", "~"),
+    (
+        ChangeKind::Moved,
+        Glyphs::UNICODE.arrow,
+        Glyphs::ASCII.arrow,
+    ),
+];
+
+/// Writes how many entries were added, removed, changed and moved in each group, as collected
+/// by `collect_entry_changes`, like `git diff --stat` does for files. Each group with changed
+/// entries gets a line like `Root/Work/Email | 2 +, 1 -, 3 ~`, in the order the groups are
+/// found in, with their paths joined by `separator` and padded so the counts line up. Moved
+/// entries are counted in the group they were moved out of. `ascii` marks them with `->`
+/// instead of `→`.
+///
+/// Only the counts are kept while the changes are found, the lines are written once the widest
+/// path is known.
+pub struct Writer<'a> {
+    out: &'a mut dyn WriteColor,
+    separator: &'a str,
+    theme: &'a Theme,
+    ascii: bool,
+    groups: Vec<(String, [usize; 4])>,
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(
+        out: &'a mut dyn WriteColor,
+        separator: &'a str,
+        theme: &'a Theme,
+        ascii: bool,
+    ) -> Writer<'a> {
+        Writer {
+            out,
+            separator,
+            theme,
+            ascii,
+            groups: Vec::new(),
+        }
+    }
+}
+
+impl ChangeWriter for Writer<'_> {
+    fn write(&mut self, change: &Change) -> std::io::Result<()> {
+        let path = join_path(&change.path, self.separator);
+        let groups = &mut self.groups;
+        let index = match groups.iter().position(|(p, _)| *p == path) {
+            Some(index) => index,
+            None => {
+                groups.push((path, [0; 4]));
+                groups.len() - 1
+            }
+        };
+        let kind = KINDS.iter().position(|(k, _, _)| *k == change.kind);
+        groups[index].1[kind.expect("Every kind of change is counted")] += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        let width = self
+            .groups
+            .iter()
+            .map(|(path, _)| path.chars().count())
+            .max()
+            .unwrap_or(0);
+        for (path, counts) in &self.groups {
+            write_group(self.out, path, width, counts, self.theme, self.ascii)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_group(
+    out: &mut dyn WriteColor,
+    path: &str,
+    width: usize,
+    counts: &[usize; 4],
+    theme: &Theme,
+    ascii: bool,
+) -> std::io::Result<()> {
+    write!(out, "{:width$} |", path, width = width)?;
+    let counted = KINDS.iter().zip(counts).filter(|(_, count)| **count > 0);
+    for (i, ((kind, marker, ascii_marker), count)) in counted.enumerate() {
+        let separator = if i == 0 { " " } else { ", " };
+        let marker = if ascii { ascii_marker } else { marker };
+        write!(out, "{}", separator)?;
+        out.set_color(ColorSpec::new().set_fg(Some(theme.color_of(*kind))))?;
+        write!(out, "{} {}", count, marker)?;
+        out.reset()?;
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::diff::ItemKind;
+    use termcolor::Buffer;
+
+    fn entry(path: &[&str], name: &str, kind: ChangeKind) -> Change {
+        Change::new(path, ItemKind::Entry, name, kind)
+    }
+
+    fn render(changes: &[Change], ascii: bool) -> String {
+        let mut out = Buffer::no_color();
+        Writer::new(&mut out, "/", &Theme::default(), ascii)
+            .write_each(changes)
+            .unwrap();
+        String::from_utf8(out.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn counts_the_changed_entries_of_each_group() {
+        let changes = vec![
+            entry(&["Root", "Work", "Email"], "Mailbox", ChangeKind::Added),
+            entry(&["Root"], "github", ChangeKind::Changed),
+            entry(&["Root", "Work", "Email"], "Webmail", ChangeKind::Removed),
+            entry(&["Root", "Work", "Email"], "Calendar", ChangeKind::Added),
+            entry(&["Root"], "VPN", ChangeKind::Moved),
+        ];
+
+        assert_eq!(
+            "Root/Work/Email | 2 +, 1 -\n\
+             Root            | 1 ~, 1 →\n",
+            render(&changes, false)
+        );
+        assert_eq!(
+            "Root            | 1 ~, 1 ->\n\
+             Root/Work/Email | 1 +, 1 -\n",
+            render(&changes[1..], true)
+        );
+    }
+}
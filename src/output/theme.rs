@@ -0,0 +1,52 @@
+use termcolor::{Color, ColorSpec};
+
+use crate::diff::ChangeKind;
+
+/// The colors of the changes in colored output, so they can be remapped to colors that are
+/// easier to tell apart
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Theme {
+    pub added: Color,
+    pub removed: Color,
+    pub changed: Color,
+    pub moved: Color,
+    /// The color of unchanged groups and entries shown for context and of other lines that are
+    /// no change, like times. They are dimmed without a color.
+    pub unchanged: Option<Color>,
+}
+
+impl Theme {
+    /// The color of changes of the given kind
+    pub fn color_of(&self, kind: ChangeKind) -> Color {
+        match kind {
+            ChangeKind::Added => self.added,
+            ChangeKind::Removed => self.removed,
+            ChangeKind::Changed => self.changed,
+            ChangeKind::Moved => self.moved,
+        }
+    }
+
+    /// The style of lines that are no change, so they recede behind the changes around them
+    pub fn unchanged_spec(&self) -> ColorSpec {
+        let mut spec = ColorSpec::new();
+        match self.unchanged {
+            Some(color) => spec.set_fg(Some(color)),
+            None => spec.set_dimmed(true),
+        };
+        spec
+    }
+}
+
+/// Green for added, red for removed, yellow for changed and cyan for moved objects, with
+/// unchanged ones dimmed
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            added: Color::Green,
+            removed: Color::Red,
+            changed: Color::Yellow,
+            moved: Color::Cyan,
+            unchanged: None,
+        }
+    }
+}
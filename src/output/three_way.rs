@@ -0,0 +1,108 @@
+use termcolor::{Color, ColorSpec, WriteColor};
+
+use crate::diff::attachment::SHORT_HASH_LENGTH;
+use crate::diff::three_way::{Side, ThreeWayChange};
+use crate::diff::{Change, ChangeKind, ItemKind};
+use crate::output::Glyphs;
+
+/// Writes the changes of a three-way comparison, one line per change and side, marked with the
+/// side that made it: `A`, `B`, `AB` for both, or `!A` and `!B` for conflicting changes. A count
+/// of the changes per side follows. `ascii` marks changed values and moves with `->` instead of
+/// `→`.
+pub fn write(
+    out: &mut dyn WriteColor,
+    changes: &[ThreeWayChange],
+    ascii: bool,
+) -> std::io::Result<()> {
+    let glyphs = Glyphs::new(ascii);
+    for change in changes {
+        let lines = match (change.side, &change.a, &change.b) {
+            (Side::Conflict, a, b) => vec![("!A", a.as_ref()), ("!B", b.as_ref())],
+            (Side::A, a, _) => vec![("A ", a.as_ref())],
+            (Side::B, _, b) => vec![("B ", b.as_ref())],
+            (Side::Both, a, _) => vec![("AB", a.as_ref())],
+        };
+        let color = match change.side {
+            Side::A => Color::Yellow,
+            Side::B => Color::Cyan,
+            Side::Both => Color::Green,
+            Side::Conflict => Color::Red,
+        };
+        for (marker, side) in lines {
+            if let Some(side) = side {
+                out.set_color(ColorSpec::new().set_fg(Some(color)))?;
+                write!(out, "{} {}", marker, describe(side, glyphs))?;
+                out.reset()?;
+                writeln!(out)?;
+            }
+        }
+    }
+
+    let count = |side| changes.iter().filter(|c| c.side == side).count();
+    let conflicts = count(Side::Conflict);
+    writeln!(
+        out,
+        "\n{} {} only in A, {} only in B, {} in both; {} {}",
+        count(Side::A),
+        if count(Side::A) == 1 {
+            "change"
+        } else {
+            "changes"
+        },
+        count(Side::B),
+        count(Side::Both),
+        conflicts,
+        if conflicts == 1 {
+            "conflict"
+        } else {
+            "conflicts"
+        }
+    )
+}
+
+/// Describes a change like the text output does, with the old and new value on one line
+fn describe(change: &Change, glyphs: &Glyphs) -> String {
+    let label = match change.item {
+        ItemKind::Attachment => format!("Attachment {}", change.name),
+        ItemKind::Tag => format!("tag:{}", change.name),
+        _ => change.name.clone(),
+    };
+    let value = |value: &Option<String>| match (change.item, value) {
+        (ItemKind::Attachment, Some(hash)) => {
+            format!("sha256 {}", &hash[..hash.len().min(SHORT_HASH_LENGTH)])
+        }
+        (_, value) => value.clone().unwrap_or_default(),
+    };
+    let path = |last: String| {
+        let mut path = change.path.clone();
+        path.push(last);
+        format!("[{}]", path.join(", "))
+    };
+    let with_value = |sign, value: String| {
+        if change.item.is_node() || change.item == ItemKind::Tag {
+            format!("{} {}", sign, path(label.clone()))
+        } else {
+            format!("{} {}", sign, path(format!("{} = {}", label, value)))
+        }
+    };
+    match change.kind {
+        ChangeKind::Added => with_value("+", value(&change.new)),
+        ChangeKind::Removed => with_value("-", value(&change.old)),
+        ChangeKind::Changed => format!(
+            "~ {}",
+            path(format!(
+                "{} = {} {} {}",
+                label,
+                value(&change.old),
+                glyphs.arrow,
+                value(&change.new)
+            ))
+        ),
+        ChangeKind::Moved => format!(
+            "{} {} moved to [{}]",
+            glyphs.arrow,
+            path(label),
+            value(&change.new)
+        ),
+    }
+}
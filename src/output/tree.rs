@@ -0,0 +1,255 @@
+use termcolor::{ColorSpec, WriteColor};
+
+use crate::diff::attachment::SHORT_HASH_LENGTH;
+use crate::diff::{Change, ChangeKind, ItemKind};
+use crate::output::theme::Theme;
+use crate::output::{ChangeWriter, Glyphs};
+
+/// The connectors that draw the branches of the tree
+struct Connectors {
+    branch: &'static str,
+    last_branch: &'static str,
+    trunk: &'static str,
+    arrow: &'static str,
+}
+
+const UNICODE: Connectors = Connectors {
+    branch: "├─ ",
+    last_branch: "└─ ",
+    trunk: "│  ",
+    arrow: Glyphs::UNICODE.arrow,
+};
+
+const ASCII: Connectors = Connectors {
+    branch: "|- ",
+    last_branch: "`- ",
+    trunk: "|  ",
+    arrow: Glyphs::ASCII.arrow,
+};
+
+/// A group, entry or value in the tree, with the change it was read from. Groups that only lead
+/// to changes, without being reported themselves, have no change.
+#[derive(Default)]
+struct Node<'a> {
+    name: String,
+    change: Option<&'a Change>,
+    children: Vec<Node<'a>>,
+}
+
+impl<'a> Node<'a> {
+    /// Whether the node is a group, entry or prior version of an entry that other nodes can be
+    /// nested in, rather than a value
+    fn is_container(&self) -> bool {
+        self.change
+            .is_none_or(|c| c.item.is_node() || c.item == ItemKind::History)
+    }
+
+    /// The node at `path` below this one, added if there is none. If there are several nodes of
+    /// the same name, like a group and an entry, the last one is taken, which is the one the
+    /// changes that follow it belong to.
+    fn descend(&mut self, path: &[String]) -> &mut Node<'a> {
+        match path.split_first() {
+            None => self,
+            Some((name, rest)) => {
+                let found = self
+                    .children
+                    .iter()
+                    .rposition(|c| c.is_container() && c.name == *name);
+                let index = match found {
+                    Some(index) => index,
+                    None => {
+                        self.children.push(Node {
+                            name: name.clone(),
+                            ..Default::default()
+                        });
+                        self.children.len() - 1
+                    }
+                };
+                self.children[index].descend(rest)
+            }
+        }
+    }
+}
+
+/// Writes the changes as a tree like `tree(1)` draws directories, with each group and entry
+/// below the group containing it and the markers of the changes inline. `ascii` draws the
+/// branches with plain ASCII characters instead of box-drawing characters.
+///
+/// The branch drawn before a node depends on whether any of its siblings follow, which changes
+/// found later can still add, so the changes are kept and the tree is written once all of them
+/// have been found.
+pub struct Writer<'a> {
+    out: &'a mut dyn WriteColor,
+    theme: &'a Theme,
+    connectors: &'static Connectors,
+    changes: Vec<Change>,
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(out: &'a mut dyn WriteColor, theme: &'a Theme, ascii: bool) -> Writer<'a> {
+        Writer {
+            out,
+            theme,
+            connectors: if ascii { &ASCII } else { &UNICODE },
+            changes: Vec::new(),
+        }
+    }
+}
+
+impl ChangeWriter for Writer<'_> {
+    fn write(&mut self, change: &Change) -> std::io::Result<()> {
+        self.changes.push(change.clone());
+        Ok(())
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        let mut root = Node::default();
+        for change in &self.changes {
+            let parent = root.descend(&change.path);
+            let node = Node {
+                name: change.name.clone(),
+                change: Some(change),
+                children: Vec::new(),
+            };
+            // A group may have been added already on the way to a change within it, if the
+            // group itself was left out, like a moved entry's new group
+            let placeholder = parent
+                .children
+                .iter_mut()
+                .rfind(|c| c.change.is_none() && c.name == change.name);
+            match placeholder {
+                Some(existing) if node.is_container() => existing.change = Some(change),
+                _ => parent.children.push(node),
+            }
+        }
+
+        for node in &root.children {
+            write_node(self.out, node, "", None, self.connectors, self.theme)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a node and its children. `is_last` tells whether the node is the last child of its
+/// parent, or is `None` for the nodes at the top, which are drawn without a branch.
+fn write_node(
+    out: &mut dyn WriteColor,
+    node: &Node,
+    prefix: &str,
+    is_last: Option<bool>,
+    connectors: &Connectors,
+    theme: &Theme,
+) -> std::io::Result<()> {
+    let (branch, child_prefix) = match is_last {
+        None => ("", prefix.to_owned()),
+        Some(false) => (connectors.branch, format!("{}{}", prefix, connectors.trunk)),
+        Some(true) => (connectors.last_branch, format!("{}   ", prefix)),
+    };
+    write!(out, "{}{}", prefix, branch)?;
+    match node.change {
+        Some(change) => {
+            out.set_color(ColorSpec::new().set_fg(Some(theme.color_of(change.kind))))?;
+            write!(out, "{}", describe(change, connectors.arrow))?;
+            out.reset()?;
+        }
+        None => {
+            out.set_color(&theme.unchanged_spec())?;
+            write!(out, "{}", node.name)?;
+            out.reset()?;
+        }
+    }
+    writeln!(out)?;
+
+    for (i, child) in node.children.iter().enumerate() {
+        let is_last = i + 1 == node.children.len();
+        write_node(out, child, &child_prefix, Some(is_last), connectors, theme)?;
+    }
+    Ok(())
+}
+
+/// Describes a change with its marker, name and values
+fn describe(change: &Change, arrow: &str) -> String {
+    let label = match change.item {
+        ItemKind::Attachment => format!("Attachment {}", change.name),
+        ItemKind::Tag => format!("tag:{}", change.name),
+        _ => change.name.clone(),
+    };
+    let value = |value: &Option<String>| match (change.item, value) {
+        (ItemKind::Attachment, Some(hash)) => {
+            format!("sha256 {}", &hash[..hash.len().min(SHORT_HASH_LENGTH)])
+        }
+        (_, value) => value.clone().unwrap_or_default(),
+    };
+    let has_value = change.item.has_value();
+    match change.kind {
+        ChangeKind::Added if has_value => format!("+ {} = {}", label, value(&change.new)),
+        ChangeKind::Added => format!("+ {}", label),
+        ChangeKind::Removed if has_value => format!("- {} = {}", label, value(&change.old)),
+        ChangeKind::Removed => format!("- {}", label),
+        // Groups compared beyond the maximum depth are changed without their changes
+        ChangeKind::Changed if change.old.is_none() => format!("~ {}", label),
+        ChangeKind::Changed if !has_value => format!("~ {} ({})", label, value(&change.old)),
+        ChangeKind::Changed => format!(
+            "~ {} = {} {} {}",
+            label,
+            value(&change.old),
+            arrow,
+            value(&change.new)
+        ),
+        ChangeKind::Moved => format!("{} {} moved to [{}]", arrow, label, value(&change.new)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use termcolor::Buffer;
+
+    #[test]
+    fn draws_nested_changes_as_branches() {
+        let password = Change::new(
+            &["Root", "Walked"],
+            ItemKind::Field,
+            "Password",
+            ChangeKind::Changed,
+        )
+        .with_values("line", "plank");
+        let changes = vec![
+            Change::new(&[], ItemKind::Group, "Root", ChangeKind::Changed),
+            Change::new(&["Root"], ItemKind::Entry, "Walked", ChangeKind::Changed),
+            password,
+            Change::new(
+                &["Root", "Walked"],
+                ItemKind::Tag,
+                "prod",
+                ChangeKind::Added,
+            ),
+            Change::new(&["Root"], ItemKind::Entry, "One more", ChangeKind::Added),
+        ];
+
+        let render = |ascii| {
+            let mut out = Buffer::no_color();
+            Writer::new(&mut out, &Theme::default(), ascii)
+                .write_each(&changes)
+                .unwrap();
+            String::from_utf8(out.into_inner()).unwrap()
+        };
+
+        assert_eq!(
+            "~ Root\n\
+             ├─ ~ Walked\n\
+             │  ├─ ~ Password = line → plank\n\
+             │  └─ + tag:prod\n\
+             └─ + One more\n",
+            render(false)
+        );
+        assert_eq!(
+            "~ Root\n\
+             |- ~ Walked\n\
+             |  |- ~ Password = line -> plank\n\
+             |  `- + tag:prod\n\
+             `- + One more\n",
+            render(true)
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use termcolor::{Color, ColorSpec, WriteColor};
+
+use crate::diff::attachment::SHORT_HASH_LENGTH;
+use crate::diff::{Change, ChangeKind, ItemKind};
+use crate::output::theme::Theme;
+use crate::output::ChangeWriter;
+
+/// Writes the flattened differences like `diff -u` does: a `---`/`+++` header naming both
+/// files, then the removed and added lines of each changed group or entry below an `@@` header
+/// with its path. Removed and added lines are colored like removals and additions of `theme`.
+/// Nothing is written if there are no differences.
+pub struct Writer<'a> {
+    out: &'a mut dyn WriteColor,
+    file_a: &'a str,
+    file_b: &'a str,
+    theme: &'a Theme,
+    /// The path of the last `@@` header, if any was written
+    current_path: Option<Vec<String>>,
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(
+        out: &'a mut dyn WriteColor,
+        file_a: &'a str,
+        file_b: &'a str,
+        theme: &'a Theme,
+    ) -> Writer<'a> {
+        Writer {
+            out,
+            file_a,
+            file_b,
+            theme,
+            current_path: None,
+        }
+    }
+}
+
+impl ChangeWriter for Writer<'_> {
+    fn write(&mut self, change: &Change) -> std::io::Result<()> {
+        // Changed groups and entries are only containers, their children are listed instead,
+        // unless groups are compared beyond the maximum depth
+        let (old, new) = match (change.kind, change.item) {
+            (ChangeKind::Changed, ItemKind::Group | ItemKind::Entry | ItemKind::History)
+                if change.old.is_none() =>
+            {
+                return Ok(())
+            }
+            (ChangeKind::Added, _) => (None, Some(line(change, change.new.as_deref()))),
+            (ChangeKind::Removed, _) => (Some(line(change, change.old.as_deref())), None),
+            (ChangeKind::Moved, _) => {
+                let to = change.new.as_deref().unwrap_or_default();
+                (Some(format!("{} (moved to {})", change.name, to)), None)
+            }
+            (ChangeKind::Changed, _) => (
+                Some(line(change, change.old.as_deref())),
+                Some(line(change, change.new.as_deref())),
+            ),
+        };
+
+        let theme = self.theme;
+        if self.current_path.is_none() {
+            let file_a = format!("--- {}", self.file_a);
+            write_colored(self.out, Some(theme.removed), &file_a)?;
+            let file_b = format!("+++ {}", self.file_b);
+            write_colored(self.out, Some(theme.added), &file_b)?;
+        }
+        if self.current_path.as_ref() != Some(&change.path) {
+            let header = format!("@@ {} @@", change.path.join("/"));
+            write_colored(self.out, Some(Color::Cyan), &header)?;
+            self.current_path = Some(change.path.clone());
+        }
+        if let Some(old) = old {
+            write_colored(self.out, Some(theme.removed), &format!("-{}", old))?;
+        }
+        if let Some(new) = new {
+            write_colored(self.out, Some(theme.added), &format!("+{}", new))?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Describes the item of a change with one of its values
+fn line(change: &Change, value: Option<&str>) -> String {
+    match change.item {
+        ItemKind::Field => format!("{} = {}", change.name, value.unwrap_or_default()),
+        ItemKind::Attachment => {
+            let hash = value.unwrap_or_default();
+            let short_hash = &hash[..hash.len().min(SHORT_HASH_LENGTH)];
+            format!("Attachment {} (sha256 {})", change.name, short_hash)
+        }
+        ItemKind::Group => match value {
+            Some(nested) => format!("{} ({})", change.name, nested),
+            None => change.name.clone(),
+        },
+        ItemKind::Entry | ItemKind::History => change.name.clone(),
+        ItemKind::Tag => format!("tag:{}", change.name),
+    }
+}
+
+fn write_colored(
+    out: &mut dyn WriteColor,
+    color: Option<Color>,
+    text: &str,
+) -> std::io::Result<()> {
+    out.set_color(ColorSpec::new().set_fg(color))?;
+    write!(out, "{}", text)?;
+    out.reset()?;
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use termcolor::Buffer;
+
+    #[test]
+    fn groups_changes_below_their_path() {
+        let password = Change::new(
+            &["Root", "Walked"],
+            ItemKind::Field,
+            "Password",
+            ChangeKind::Changed,
+        )
+        .with_values("line", "plank");
+        let changes = vec![
+            Change::new(&[], ItemKind::Group, "Root", ChangeKind::Changed),
+            Change::new(&["Root"], ItemKind::Entry, "Walked", ChangeKind::Changed),
+            password,
+            Change::new(&["Root"], ItemKind::Entry, "One more", ChangeKind::Added),
+        ];
+
+        let render = |changes: &[Change]| {
+            let mut out = Buffer::no_color();
+            Writer::new(&mut out, "a.kdbx", "b.kdbx", &Theme::default())
+                .write_each(changes)
+                .unwrap();
+            String::from_utf8(out.into_inner()).unwrap()
+        };
+
+        assert_eq!(
+            "--- a.kdbx\n+++ b.kdbx\n\
+             @@ Root/Walked @@\n-Password = line\n+Password = plank\n\
+             @@ Root @@\n+One more\n",
+            render(&changes)
+        );
+        assert_eq!("", render(&changes[..2]));
+    }
+}
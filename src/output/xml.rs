@@ -0,0 +1,192 @@
+use std::io::Write;
+
+use crate::diff::{Change, ItemKind};
+use crate::output::json::change_kind_name;
+use crate::output::ChangeWriter;
+
+/// Writes the flattened differences as an XML document laid out like a KeePass XML export:
+/// changed groups and entries are nested `Group` and `Entry` elements, and their fields and
+/// attachments are `String` and `Binary` elements with their old and new value. Every element of
+/// a change has a `Change` attribute, groups that only lead to a change have none.
+pub struct Writer<'a> {
+    out: &'a mut dyn Write,
+    /// Paths and tags of the open elements, the outermost first
+    open: Vec<(Vec<String>, &'static str)>,
+}
+
+impl<'a> Writer<'a> {
+    /// Writes the start of the document
+    pub fn new(out: &'a mut dyn Write) -> std::io::Result<Writer<'a>> {
+        writeln!(
+            out,
+            "<?xml version=\"1.0\" encoding=\"utf-8\" standalone=\"yes\"?>"
+        )?;
+        write!(out, "<KeePassFile>\n\t<Root>\n")?;
+        Ok(Writer {
+            out,
+            open: Vec::new(),
+        })
+    }
+}
+
+impl ChangeWriter for Writer<'_> {
+    fn write(&mut self, change: &Change) -> std::io::Result<()> {
+        let Writer { out, open } = self;
+        while let Some((path, tag)) = open.last() {
+            if change.path.starts_with(path) {
+                break;
+            }
+            writeln!(out, "{}</{}>", indent(open.len()), tag)?;
+            open.pop();
+        }
+        // Changes are listed after their parents, so only unchanged groups can be missing
+        while open.len() < change.path.len() {
+            let path = change.path[..=open.len()].to_vec();
+            writeln!(out, "{}<Group>", indent(open.len() + 1))?;
+            write_name(*out, open.len() + 2, "Group", &path[open.len()])?;
+            open.push((path, "Group"));
+        }
+
+        let depth = open.len() + 1;
+        let tag = match change.item {
+            ItemKind::Group => "Group",
+            ItemKind::Entry => "Entry",
+            ItemKind::History => "History",
+            ItemKind::Field => "String",
+            ItemKind::Attachment => "Binary",
+            ItemKind::Tag => "Tag",
+        };
+        writeln!(
+            out,
+            "{}<{} Change=\"{}\">",
+            indent(depth),
+            tag,
+            change_kind_name(change.kind)
+        )?;
+        write_name(*out, depth + 1, tag, &change.name)?;
+        for (element, value) in [("OldValue", &change.old), ("NewValue", &change.new)] {
+            if let Some(value) = value {
+                write_element(*out, depth + 1, element, value)?;
+            }
+        }
+        match change.item {
+            ItemKind::Group | ItemKind::Entry | ItemKind::History => {
+                let mut path = change.path.clone();
+                path.push(change.name.clone());
+                open.push((path, tag));
+            }
+            ItemKind::Field | ItemKind::Attachment | ItemKind::Tag => {
+                writeln!(out, "{}</{}>", indent(depth), tag)?
+            }
+        }
+        Ok(())
+    }
+
+    /// Closes the open elements and the document
+    fn finish(&mut self) -> std::io::Result<()> {
+        while let Some((_, tag)) = self.open.pop() {
+            writeln!(self.out, "{}</{}>", indent(self.open.len() + 1), tag)?;
+        }
+        write!(self.out, "\t</Root>\n</KeePassFile>\n")
+    }
+}
+
+/// Writes the element naming an element with the given tag, the way KeePass names it
+fn write_name(out: &mut dyn Write, depth: usize, tag: &str, name: &str) -> std::io::Result<()> {
+    match tag {
+        "Entry" => {
+            writeln!(out, "{}<String>", indent(depth))?;
+            write_element(out, depth + 1, "Key", "Title")?;
+            write_element(out, depth + 1, "Value", name)?;
+            writeln!(out, "{}</String>", indent(depth))
+        }
+        "String" | "Binary" => write_element(out, depth, "Key", name),
+        _ => write_element(out, depth, "Name", name),
+    }
+}
+
+fn write_element(out: &mut dyn Write, depth: usize, tag: &str, text: &str) -> std::io::Result<()> {
+    writeln!(out, "{}<{}>{}</{}>", indent(depth), tag, escape(text), tag)
+}
+
+fn indent(depth: usize) -> String {
+    "\t".repeat(depth + 1)
+}
+
+/// Escapes text for the content of an element. Carriage returns are kept as character
+/// references, as parsers would turn them into line feeds otherwise. Control characters that
+/// XML 1.0 cannot represent, even as character references, become U+FFFD.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\r' => escaped.push_str("&#13;"),
+            '\t' | '\n' => escaped.push(c),
+            '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => escaped.push('\u{fffd}'),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::diff::ChangeKind;
+
+    #[test]
+    fn nests_changes_and_escapes_values() {
+        let notes = Change {
+            path: vec!["Root".to_owned(), "Work".to_owned(), "Walked".to_owned()],
+            item: ItemKind::Field,
+            name: "Notes".to_owned(),
+            kind: ChangeKind::Changed,
+            old: Some("a < b & c".to_owned()),
+            new: Some("bell\u{7}\r\nnext".to_owned()),
+        };
+        let entry = Change {
+            path: vec!["Root".to_owned(), "Work".to_owned()],
+            item: ItemKind::Entry,
+            name: "Walked".to_owned(),
+            kind: ChangeKind::Changed,
+            old: None,
+            new: None,
+        };
+
+        let mut out = Vec::new();
+        Writer::new(&mut out)
+            .and_then(|mut writer| writer.write_each(&[entry, notes]))
+            .unwrap();
+
+        assert_eq!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\" standalone=\"yes\"?>\n\
+             <KeePassFile>\n\
+             \t<Root>\n\
+             \t\t<Group>\n\
+             \t\t\t<Name>Root</Name>\n\
+             \t\t\t<Group>\n\
+             \t\t\t\t<Name>Work</Name>\n\
+             \t\t\t\t<Entry Change=\"changed\">\n\
+             \t\t\t\t\t<String>\n\
+             \t\t\t\t\t\t<Key>Title</Key>\n\
+             \t\t\t\t\t\t<Value>Walked</Value>\n\
+             \t\t\t\t\t</String>\n\
+             \t\t\t\t\t<String Change=\"changed\">\n\
+             \t\t\t\t\t\t<Key>Notes</Key>\n\
+             \t\t\t\t\t\t<OldValue>a &lt; b &amp; c</OldValue>\n\
+             \t\t\t\t\t\t<NewValue>bell\u{fffd}&#13;\nnext</NewValue>\n\
+             \t\t\t\t\t</String>\n\
+             \t\t\t\t</Entry>\n\
+             \t\t\t</Group>\n\
+             \t\t</Group>\n\
+             \t</Root>\n\
+             </KeePassFile>\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
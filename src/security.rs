@@ -0,0 +1,156 @@
+//! The format and encryption settings of a database, which `--verbose` shows for both databases
+
+use keepass::config::{DatabaseConfig, DatabaseVersion, KdfConfig, OuterCipherConfig};
+
+use std::fmt;
+
+/// Bytes in a kibibyte and a mebibyte, the units KeePass shows the memory of Argon2 in
+const KIB: u64 = 1024;
+const MIB: u64 = 1024 * KIB;
+
+/// The version of a database and how its key is derived and its content encrypted
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Security {
+    pub version: DatabaseVersion,
+    pub kdf: KdfConfig,
+    pub cipher: OuterCipherConfig,
+}
+
+impl Security {
+    pub fn of(config: &DatabaseConfig) -> Self {
+        Security {
+            version: config.version.clone(),
+            kdf: config.kdf_config.clone(),
+            cipher: config.outer_cipher_config.clone(),
+        }
+    }
+
+    /// The format of the database, like `KDBX 4.0`
+    pub fn version_name(&self) -> String {
+        match self.version {
+            DatabaseVersion::KDB(_) => "KeePass 1.x".to_owned(),
+            DatabaseVersion::KDB2(minor) => format!("KDBX 2.{}", minor),
+            DatabaseVersion::KDB3(minor) => format!("KDBX 3.{}", minor),
+            DatabaseVersion::KDB4(minor) => format!("KDBX 4.{}", minor),
+        }
+    }
+
+    /// Whether the databases have a different major version of their format, like KDBX 3 and
+    /// KDBX 4, which store some values differently, like protected fields
+    pub fn is_other_format(&self, other: &Security) -> bool {
+        let major = |security: &Security| match security.version {
+            DatabaseVersion::KDB(_) => 1,
+            DatabaseVersion::KDB2(_) => 2,
+            DatabaseVersion::KDB3(_) => 3,
+            DatabaseVersion::KDB4(_) => 4,
+        };
+        major(self) != major(other)
+    }
+
+    /// The name of the key derivation function, without its parameters
+    pub fn kdf_name(&self) -> &'static str {
+        match self.kdf {
+            KdfConfig::Aes { .. } => "AES-KDF",
+            KdfConfig::Argon2 { .. } => "Argon2d",
+            KdfConfig::Argon2id { .. } => "Argon2id",
+        }
+    }
+
+    pub fn cipher_name(&self) -> &'static str {
+        match self.cipher {
+            OuterCipherConfig::AES256 => "AES-256",
+            OuterCipherConfig::Twofish => "Twofish",
+            OuterCipherConfig::ChaCha20 => "ChaCha20",
+        }
+    }
+
+    /// What the databases are encrypted with differently, like `key derivation function`, if
+    /// anything. Different parameters of the same function are not told.
+    pub fn differences(&self, other: &Security) -> Vec<&'static str> {
+        let mut differences = Vec::new();
+        if self.kdf_name() != other.kdf_name() {
+            differences.push("key derivation function");
+        }
+        if self.cipher != other.cipher {
+            differences.push("cipher");
+        }
+        differences
+    }
+}
+
+/// Shows the settings like `KDBX 4.0, Argon2id (2 iterations, 64 MiB, 2 threads), AES-256`
+impl fmt::Display for Security {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, {}", self.version_name(), self.kdf_name())?;
+        match self.kdf {
+            KdfConfig::Aes { rounds } => write!(f, " ({} rounds)", rounds)?,
+            KdfConfig::Argon2 {
+                iterations,
+                memory,
+                parallelism,
+                ..
+            }
+            | KdfConfig::Argon2id {
+                iterations,
+                memory,
+                parallelism,
+                ..
+            } => {
+                let memory = match memory {
+                    memory if memory >= MIB => format!("{} MiB", memory / MIB),
+                    memory => format!("{} KiB", memory / KIB),
+                };
+                let threads = if parallelism == 1 {
+                    "thread"
+                } else {
+                    "threads"
+                };
+                write!(
+                    f,
+                    " ({} iterations, {}, {} {})",
+                    iterations, memory, parallelism, threads
+                )?
+            }
+        }
+        write!(f, ", {}", self.cipher_name())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn security(kdf: KdfConfig, cipher: OuterCipherConfig) -> Security {
+        Security {
+            version: DatabaseVersion::KDB4(0),
+            kdf,
+            cipher,
+        }
+    }
+
+    #[test]
+    fn shows_the_version_kdf_and_cipher() {
+        let aes = security(KdfConfig::Aes { rounds: 60000 }, OuterCipherConfig::AES256);
+        assert_eq!("KDBX 4.0, AES-KDF (60000 rounds), AES-256", aes.to_string());
+
+        let argon2 = Security::of(&DatabaseConfig::default());
+        assert_eq!(
+            "KDBX 4.0, Argon2d (50 iterations, 1 MiB, 4 threads), AES-256",
+            argon2.to_string()
+        );
+        assert_eq!(vec!["key derivation function"], aes.differences(&argon2));
+        let chacha20 = security(KdfConfig::Aes { rounds: 1 }, OuterCipherConfig::ChaCha20);
+        assert_eq!(vec!["cipher"], aes.differences(&chacha20));
+        assert!(aes.differences(&aes.clone()).is_empty());
+
+        let kdbx3 = Security {
+            version: DatabaseVersion::KDB3(1),
+            ..aes.clone()
+        };
+        assert!(aes.is_other_format(&kdbx3));
+        assert!(!aes.is_other_format(&Security {
+            version: DatabaseVersion::KDB4(1),
+            ..aes.clone()
+        }));
+    }
+}
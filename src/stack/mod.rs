@@ -42,6 +42,24 @@ impl<T> Stack<T> {
             None => 0,
         }
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+}
+
+impl<T: Clone> Stack<T> {
+    /// Returns the elements of the stack from the bottom to the top
+    pub fn to_vec(&self) -> Vec<T> {
+        let mut result = Vec::new();
+        let mut link = self.head.as_ref();
+        while let Some(node) = link {
+            result.push(node.value.clone());
+            link = node.next.as_ref();
+        }
+        result.reverse();
+        result
+    }
 }
 
 impl<T: Display> Display for Stack<T> {
@@ -83,6 +101,67 @@ impl<T: Display> Stack<T> {
     }
 }
 
+impl<T: Display> Stack<T> {
+    /// Joins the elements from the bottom to the top into a path like `Root/Work/Email`, see
+    /// [`join_path`]
+    pub fn to_path(&self, separator: &str) -> String {
+        let mut segments = Vec::new();
+        let mut link = self.head.as_ref();
+        while let Some(node) = link {
+            segments.push(node.value.to_string());
+            link = node.next.as_ref();
+        }
+        segments.reverse();
+        join_path(&segments, separator)
+    }
+}
+
+/// Joins the names of a path with `separator`, which must not be empty or contain a backslash.
+/// A separator or backslash within a name is escaped with a backslash, so the names can be told
+/// apart again with [`split_path`].
+pub fn join_path<S: AsRef<str>>(segments: &[S], separator: &str) -> String {
+    segments
+        .iter()
+        .map(|segment| escape(segment.as_ref(), separator))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// Splits a path joined by [`join_path`] into its names
+pub fn split_path(path: &str, separator: &str) -> Vec<String> {
+    if path.is_empty() {
+        return Vec::new();
+    }
+    let mut segments = vec![String::new()];
+    let mut rest = path;
+    while let Some(c) = rest.chars().next() {
+        let segment = segments.last_mut().expect("There always is a segment");
+        if let Some(escaped) = rest.strip_prefix('\\') {
+            let length = match escaped.strip_prefix(separator) {
+                Some(_) => separator.len(),
+                None => escaped.chars().next().map_or(0, char::len_utf8),
+            };
+            segment.push_str(&escaped[..length]);
+            rest = &escaped[length..];
+        } else if let Some(next) = rest.strip_prefix(separator) {
+            segments.push(String::new());
+            rest = next;
+        } else {
+            segment.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    segments
+}
+
+/// Escapes backslashes and the separator within a name. The separator must not be empty or
+/// contain a backslash itself.
+fn escape(segment: &str, separator: &str) -> String {
+    segment
+        .replace('\\', "\\\\")
+        .replace(separator, &format!("\\{}", separator))
+}
+
 impl<T> Drop for Stack<T> {
     fn drop(&mut self) {
         let mut head = self.head.take();
@@ -130,6 +209,7 @@ mod test {
     fn correct_len() {
         let stack: Stack<&str> = Stack::empty();
         assert_eq!(0, stack.len());
+        assert!(stack.is_empty());
 
         assert_eq!(2, stack.append("one").append("two").len())
     }
@@ -137,33 +217,57 @@ mod test {
     #[test]
     fn empty_stack() {
         let stack: Stack<&str> = Stack::empty();
-        assert_eq!("Stack()", format!("{}", stack.to_string()))
+        assert_eq!("Stack()", stack.to_string())
     }
 
     #[test]
     fn single_element_stack() {
         let stack = Stack::empty().append("hello");
-        assert_eq!("Stack(hello)", format!("{}", stack.to_string()))
+        assert_eq!("Stack(hello)", stack.to_string())
     }
 
     #[test]
     fn two_elements_stack() {
         let stack = Stack::empty().append("hello").append("bye");
-        assert_eq!("Stack(hello, bye)", format!("{}", stack.to_string()))
+        assert_eq!("Stack(hello, bye)", stack.to_string())
     }
 
     #[test]
     fn shows_its_strings() {
         let stack = stack_abcd();
-        assert_eq!("Stack(a, b, c, d)", format!("{}", stack.to_string()))
+        assert_eq!("Stack(a, b, c, d)", stack.to_string())
     }
 
     #[test]
     fn mk_string_shows_correct() {
         let stack = stack_abcd();
+        assert_eq!("[a, b, c, d]", stack.mk_string("[", ", ", "]"))
+    }
+
+    #[test]
+    fn to_path_joins_from_the_bottom() {
+        assert_eq!("a/b/c/d", stack_abcd().to_path("/"));
+        assert_eq!("a > b > c > d", stack_abcd().to_path(" > "));
+        assert_eq!("", Stack::<&str>::empty().to_path("/"));
+    }
+
+    #[test]
+    fn join_path_escapes_separators_in_names() {
+        let segments = ["Root", "Work/Home", "C:\\Temp"];
+        let path = join_path(&segments, "/");
+
+        assert_eq!("Root/Work\\/Home/C:\\\\Temp", path);
+        assert_eq!(segments.to_vec(), split_path(&path, "/"));
         assert_eq!(
-            "[a, b, c, d]",
-            format!("{}", stack.mk_string("[", ", ", "]"))
-        )
+            segments.to_vec(),
+            split_path(&join_path(&segments, "::"), "::")
+        );
+    }
+
+    #[test]
+    fn to_vec_starts_at_the_bottom() {
+        let stack: Stack<&str> = Stack::empty();
+        assert!(stack.to_vec().is_empty());
+        assert_eq!(vec!["a", "b", "c", "d"], stack_abcd().to_vec())
     }
 }
@@ -0,0 +1,345 @@
+//! Plaintext XML exports of KeePass databases, as written by KeePass' "Export" feature or by
+//! `keepassxc-cli export`, read into the same shape as a decrypted database
+
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use flate2::read::GzDecoder;
+use keepass::config::DatabaseConfig;
+use keepass::db::{BinaryAttachment, Entry, Group, History, Node, Times, Value};
+use keepass::error::XmlParseError;
+use keepass::Database;
+use secstr::SecStr;
+use uuid::Uuid;
+use xml::reader::{EventReader, XmlEvent};
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::Read;
+
+use crate::xml_tags::inside;
+
+/// UTF-8 byte order mark, which exports may start with
+pub(crate) const BYTE_ORDER_MARK: &[u8] = b"\xef\xbb\xbf";
+
+/// Number of bytes read to tell whether a file is an XML export
+const SNIFF_LENGTH: u64 = 64;
+
+/// Whether the data looks like an XML document rather than an encrypted database. Databases
+/// start with a binary signature, so the first character tells them apart.
+pub fn is_xml_export(data: &[u8]) -> bool {
+    let data = data.strip_prefix(BYTE_ORDER_MARK).unwrap_or(data);
+    data.iter()
+        .find(|b| !b.is_ascii_whitespace())
+        .is_some_and(|b| *b == b'<')
+}
+
+/// Whether the file looks like an XML export, see [`is_xml_export`]. Files that cannot be read
+/// are not, which leaves reporting the error to opening them.
+pub fn is_xml_export_file(file: &str) -> bool {
+    let mut data = Vec::new();
+    File::open(file)
+        .and_then(|f| f.take(SNIFF_LENGTH).read_to_end(&mut data))
+        .is_ok_and(|_| is_xml_export(&data))
+}
+
+/// Reads an XML export into a database with its groups, entries, the recycle bin settings and
+/// the binaries that attachments refer to. Returns `None` if the document is not a KeePass file.
+///
+/// Attachments are read from the XML with `read_attachments`, like for databases. Protected values
+/// are in plain text in exports and only marked with `ProtectInMemory`.
+pub fn read_xml_export(xml: &[u8]) -> Result<Option<Database>, XmlParseError> {
+    let mut db = Database::new(DatabaseConfig::default());
+    let mut tags: Vec<String> = Vec::new();
+    let mut groups: Vec<Group> = Vec::new();
+    // Entries being read, the outermost first, with the versions of their history read so far
+    let mut entries: Vec<(Entry, Vec<Entry>)> = Vec::new();
+    let mut root = None;
+    let mut key = String::new();
+    let mut value = String::new();
+    let mut protected = false;
+    let mut binary = BinaryAttachment::default();
+
+    for event in EventReader::new(xml) {
+        match event? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                let attribute = |name: &str| {
+                    attributes
+                        .iter()
+                        .find(|a| a.name.local_name == name)
+                        .map(|a| a.value.to_lowercase())
+                };
+                if tags.is_empty() && name.local_name != "KeePassFile" {
+                    return Ok(None);
+                }
+                match &name.local_name[..] {
+                    "Group" if tags.iter().any(|t| t == "Root") => groups.push(Group::default()),
+                    "Entry" if !groups.is_empty() => entries.push(Default::default()),
+                    "Value" if inside(&tags, &["Entry", "String"]) => {
+                        protected = attribute("ProtectInMemory").as_deref() == Some("true")
+                            || attribute("Protected").as_deref() == Some("true");
+                    }
+                    "Binary" if inside(&tags, &["Meta", "Binaries"]) => {
+                        binary = BinaryAttachment {
+                            identifier: attribute("ID"),
+                            compressed: attribute("Compressed").as_deref() == Some("true"),
+                            content: Vec::new(),
+                        };
+                    }
+                    _ => {}
+                }
+                tags.push(name.local_name);
+            }
+            XmlEvent::Characters(text) => {
+                let Some((tag, parent)) = tags.split_last() else {
+                    continue;
+                };
+                let tag = tag.as_str();
+                if inside(parent, &["Entry", "String"]) {
+                    match tag {
+                        "Key" => key = text,
+                        "Value" => value = text,
+                        _ => {}
+                    }
+                } else if inside(parent, &["Entry", "Times"]) || inside(parent, &["Group", "Times"])
+                {
+                    let times = if inside(parent, &["Entry", "Times"]) {
+                        entries.last_mut().map(|(e, _)| &mut e.times)
+                    } else {
+                        groups.last_mut().map(|g| &mut g.times)
+                    };
+                    if let Some(times) = times {
+                        set_time(times, tag, &text)?;
+                    }
+                } else if let (true, Some((entry, _))) =
+                    (inside(parent, &["Entry"]), entries.last_mut())
+                {
+                    match tag {
+                        "UUID" => entry.uuid = parse_uuid(&text)?,
+                        "IconID" => entry.icon_id = Some(text.parse()?),
+                        "Tags" => {
+                            entry.tags = text.split([';', ',']).map(|t| t.to_owned()).collect()
+                        }
+                        _ => {}
+                    }
+                } else if let (true, Some(group)) = (inside(parent, &["Group"]), groups.last_mut())
+                {
+                    match tag {
+                        "UUID" => group.uuid = parse_uuid(&text)?,
+                        "Name" => group.name = text,
+                        "Notes" => group.notes = Some(text),
+                        "IconID" => group.icon_id = Some(text.parse()?),
+                        _ => {}
+                    }
+                } else if inside(parent, &["Meta"]) {
+                    match tag {
+                        "RecycleBinEnabled" => {
+                            db.meta.recyclebin_enabled = Some(text.to_lowercase().parse()?)
+                        }
+                        "RecycleBinUUID" => db.meta.recyclebin_uuid = Some(parse_uuid(&text)?),
+                        _ => {}
+                    }
+                } else if inside(&tags, &["Meta", "Binaries", "Binary"]) {
+                    binary.content = general_purpose::STANDARD.decode(text.trim())?;
+                }
+            }
+            XmlEvent::EndElement { .. } => {
+                let tag = tags.pop().unwrap_or_default();
+                match &tag[..] {
+                    // Empty fields are left out, like keepass does for databases
+                    "String" if inside(&tags, &["Entry"]) => {
+                        let key = std::mem::take(&mut key);
+                        let value = std::mem::take(&mut value);
+                        if let (Some((entry, _)), false) = (entries.last_mut(), value.is_empty()) {
+                            let value = if protected {
+                                Value::Protected(SecStr::from(value))
+                            } else {
+                                Value::Unprotected(value)
+                            };
+                            entry.fields.insert(key, value);
+                        }
+                        protected = false;
+                    }
+                    "Entry" => {
+                        let Some((mut entry, history)) = entries.pop() else {
+                            continue;
+                        };
+                        if !history.is_empty() {
+                            // Versions are added to the front, so add them in reverse to keep their
+                            // order
+                            let mut versions = History::default();
+                            for version in history.into_iter().rev() {
+                                versions.add_entry(version);
+                            }
+                            entry.history = Some(versions);
+                        }
+                        match (tags.last().map(String::as_str), entries.last_mut()) {
+                            (Some("History"), Some((_, history))) => history.push(entry),
+                            _ => {
+                                if let Some(group) = groups.last_mut() {
+                                    group.children.push(Node::Entry(entry));
+                                }
+                            }
+                        }
+                    }
+                    "Group" if !groups.is_empty() => {
+                        let group = groups.pop().unwrap_or_default();
+                        match groups.last_mut() {
+                            Some(parent) => parent.children.push(Node::Group(group)),
+                            None => root = root.or(Some(group)),
+                        }
+                    }
+                    "Binary" if inside(&tags, &["Meta", "Binaries"]) => {
+                        let mut binary = std::mem::take(&mut binary);
+                        if binary.compressed {
+                            let mut content = Vec::new();
+                            GzDecoder::new(binary.content.as_slice())
+                                .read_to_end(&mut content)
+                                .map_err(XmlParseError::Compression)?;
+                            binary.content = content;
+                        }
+                        db.meta.binaries.binaries.push(binary);
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(root.map(|root| {
+        db.root = root;
+        db
+    }))
+}
+
+/// UUIDs are base64-encoded, like in databases
+fn parse_uuid(text: &str) -> Result<Uuid, XmlParseError> {
+    Ok(Uuid::from_slice(
+        &general_purpose::STANDARD.decode(text.trim())?,
+    )?)
+}
+
+/// Sets the time of the given tag, like `CreationTime`. Times are either ISO 8601 timestamps, or
+/// base64-encoded seconds since the year 1 like in KDBX 4 databases.
+fn set_time(times: &mut Times, tag: &str, text: &str) -> Result<(), XmlParseError> {
+    match tag {
+        "Expires" => times.expires = text.to_lowercase().parse()?,
+        "UsageCount" => times.usage_count = text.parse()?,
+        _ => {
+            let time = match NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%SZ") {
+                Ok(time) => time,
+                Err(e) => {
+                    let seconds = general_purpose::STANDARD.decode(text)?;
+                    let seconds = <[u8; 8]>::try_from(seconds.as_slice()).map_err(|_| e)?;
+                    NaiveDate::from_ymd_opt(1, 1, 1)
+                        .unwrap_or_default()
+                        .and_hms_opt(0, 0, 0)
+                        .unwrap_or_default()
+                        + Duration::seconds(i64::from_le_bytes(seconds))
+                }
+            };
+            times.times.insert(tag.to_owned(), time);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const EXPORT: &str = r#"<?xml version="1.0" encoding="utf-8" standalone="yes"?>
+<KeePassFile>
+	<Meta>
+		<RecycleBinEnabled>True</RecycleBinEnabled>
+		<RecycleBinUUID>AAAAAAAAAAAAAAAAAAAAAA==</RecycleBinUUID>
+		<Binaries>
+			<Binary ID="0" Compressed="True">H4sIAAAAAAAC/8tIzcnJBwCGphA2BQAAAA==</Binary>
+		</Binaries>
+	</Meta>
+	<Root>
+		<Group>
+			<UUID>AQIDBAUGBwgJCgsMDQ4PEA==</UUID>
+			<Name>Root</Name>
+			<Entry>
+				<UUID>EBESExQVFhcYGRobHB0eHw==</UUID>
+				<Times>
+					<CreationTime>2023-06-01T10:00:00Z</CreationTime>
+					<Expires>False</Expires>
+				</Times>
+				<String>
+					<Key>Title</Key>
+					<Value>Walked</Value>
+				</String>
+				<String>
+					<Key>Password</Key>
+					<Value ProtectInMemory="True">secret &amp; more</Value>
+				</String>
+				<String>
+					<Key>URL</Key>
+					<Value />
+				</String>
+				<History>
+					<Entry>
+						<String>
+							<Key>Title</Key>
+							<Value>First</Value>
+						</String>
+					</Entry>
+					<Entry>
+						<String>
+							<Key>Title</Key>
+							<Value>Second</Value>
+						</String>
+					</Entry>
+				</History>
+			</Entry>
+			<Group>
+				<Name>Work</Name>
+			</Group>
+		</Group>
+	</Root>
+</KeePassFile>
+"#;
+
+    #[test]
+    fn tells_xml_from_databases() {
+        assert!(is_xml_export(b"\xef\xbb\xbf\n<?xml version=\"1.0\"?>"));
+        assert!(!is_xml_export(b"\x03\xd9\xa2\x9a\x67\xfb\x4b\xb5"));
+        assert!(!is_xml_export(b""));
+    }
+
+    #[test]
+    fn reads_groups_entries_and_their_history() {
+        let db = read_xml_export(EXPORT.as_bytes()).unwrap().unwrap();
+        assert_eq!(Some(Uuid::nil()), db.meta.recyclebin_uuid);
+        assert_eq!("Root", db.root.name);
+        let Node::Entry(entry) = &db.root.children[0] else {
+            panic!("expected an entry, got {:?}", db.root.children[0]);
+        };
+        assert_eq!(Some("Walked"), entry.get_title());
+        assert!(matches!(entry.fields["Password"], Value::Protected(_)));
+        assert_eq!(Some("secret & more"), entry.get_password());
+        assert_eq!(None, entry.get_url());
+        assert!(entry.times.get_creation().is_some());
+        let history = entry.history.as_ref().unwrap().get_entries();
+        let titles: Vec<_> = history.iter().map(|e| e.get_title().unwrap()).collect();
+        assert_eq!(vec!["First", "Second"], titles);
+        assert!(matches!(&db.root.children[1], Node::Group(g) if g.name == "Work"));
+    }
+
+    #[test]
+    fn decompresses_binaries() {
+        let db = read_xml_export(EXPORT.as_bytes()).unwrap().unwrap();
+        let binary = &db.meta.binaries.binaries[0];
+        assert_eq!(Some("0"), binary.identifier.as_deref());
+        assert_eq!(b"hello".to_vec(), binary.content);
+    }
+
+    #[test]
+    fn rejects_other_xml_documents() {
+        assert!(read_xml_export(b"<html></html>").unwrap().is_none());
+    }
+}
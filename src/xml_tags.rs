@@ -0,0 +1,6 @@
+//! Helpers for walking the XML of databases and exports tag by tag
+
+/// Whether the innermost open tags are `path`, like `["Entry", "String", "Key"]`
+pub(crate) fn inside(tags: &[String], path: &[&str]) -> bool {
+    tags.len() >= path.len() && tags[tags.len() - path.len()..] == *path
+}
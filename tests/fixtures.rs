@@ -0,0 +1,176 @@
+//! Diffs pairs of the demo databases in `test/__fixtures__` and checks the resulting changes
+
+extern crate keepass_diff;
+
+use keepass_diff::diff::fuzzy::{match_fuzzy, FuzzyMatch};
+use keepass_diff::diff::group::Group;
+use keepass_diff::diff::moves::detect_moves;
+use keepass_diff::diff::{collect_changes, summarize, Change, ChangeKind, Diff, DiffOptions};
+use keepass_diff::diff::{DiffResult, ItemKind};
+use keepass_diff::{open_database, KeyfileFormat};
+
+fn open(name: &str) -> Group {
+    let file = format!("test/__fixtures__/{}.kdbx", name);
+    let options = DiffOptions::default();
+    open_database(&file, Some("demopass"), None, KeyfileFormat::Auto, &options)
+        .unwrap()
+        .1
+}
+
+/// The kind, path and name of each change, which is enough to tell most changes apart
+fn outline(changes: &[Change]) -> Vec<(ChangeKind, String)> {
+    changes
+        .iter()
+        .map(|c| {
+            let mut path = c.path.clone();
+            path.push(c.name.clone());
+            (c.kind, path.join("/"))
+        })
+        .collect()
+}
+
+#[test]
+fn identical_databases_have_no_changes() {
+    let (a, b) = (open("test"), open("test"));
+    let delta = a.diff(&b);
+
+    assert!(matches!(delta, DiffResult::Identical { .. }));
+    assert!(collect_changes(&delta).is_empty());
+    assert_eq!(0, summarize(&delta).entries.added);
+}
+
+#[test]
+fn new_groups_are_added_with_their_entries() {
+    let (a, b) = (open("empty"), open("test"));
+    let delta = a.diff(&b);
+    let changes = collect_changes(&delta);
+
+    assert_eq!(
+        vec![
+            (ChangeKind::Changed, "Root".to_owned()),
+            (ChangeKind::Added, "Root/Another group".to_owned()),
+            (ChangeKind::Added, "Root/Some group".to_owned()),
+            (ChangeKind::Added, "Root/Test".to_owned()),
+        ],
+        outline(&changes)
+    );
+    assert_eq!(ItemKind::Group, changes[1].item);
+    assert_eq!(ItemKind::Entry, changes[3].item);
+    let summary = summarize(&delta);
+    assert_eq!((4, 5), (summary.groups.added, summary.entries.added));
+}
+
+#[test]
+fn changed_fields_carry_both_values() {
+    let (a, b) = (open("test"), open("test2"));
+    let changes = collect_changes(&a.diff(&b));
+
+    let password = changes
+        .iter()
+        .find(|c| c.item == ItemKind::Field && c.name == "Password")
+        .unwrap();
+    assert_eq!(ChangeKind::Changed, password.kind);
+    assert_eq!(
+        vec!["Root", "Some group", "Sub-Group 2 of group", "Walked"],
+        password.path
+    );
+    assert_eq!(Some("line"), password.old.as_deref());
+    assert_eq!(Some("plank"), password.new.as_deref());
+}
+
+#[test]
+fn renamed_entries_are_paired_by_similarity() {
+    let (a, mut b) = (open("fuzzy"), open("fuzzy-2"));
+    let removed_and_added = outline(&collect_changes(&a.diff(&b)));
+    assert!(removed_and_added.contains(&(ChangeKind::Removed, "Root/Work/Mail".to_owned())));
+    assert!(removed_and_added.contains(&(ChangeKind::Added, "Root/Work/Mailbox".to_owned())));
+
+    let options = FuzzyMatch {
+        threshold: 0.5,
+        fields: vec!["UserName".to_owned(), "URL".to_owned()],
+    };
+    match_fuzzy(&a, &mut b, &options);
+    let changes = collect_changes(&a.diff(&b));
+
+    let title = changes
+        .iter()
+        .find(|c| c.path == ["Root", "Work", "Mail"] && c.name == "Title")
+        .unwrap();
+    assert_eq!(ChangeKind::Changed, title.kind);
+    assert_eq!(Some("Mailbox"), title.new.as_deref());
+    assert!(!outline(&changes).contains(&(ChangeKind::Added, "Root/Work/Mailbox".to_owned())));
+}
+
+#[test]
+fn moved_entries_are_reported_with_their_new_group() {
+    let (mut a, mut b) = (open("moved-entries"), open("moved-entries-2"));
+    detect_moves(&mut a, &mut b);
+    let changes = collect_changes(&a.diff(&b));
+
+    let moved: Vec<(&str, Option<&str>)> = changes
+        .iter()
+        .filter(|c| c.kind == ChangeKind::Moved)
+        .map(|c| (c.name.as_str(), c.new.as_deref()))
+        .collect();
+    assert_eq!(
+        vec![
+            ("Mailbox", Some("Root/Work/Email")),
+            ("Wiki", Some("Root/Work"))
+        ],
+        moved
+    );
+    assert!(outline(&changes).contains(&(ChangeKind::Removed, "Root/Old forum".to_owned())));
+
+    let mailbox = changes.iter().find(|c| c.name == "Mailbox").unwrap();
+    assert_eq!("Root > Work > Mailbox", mailbox.location(" > "));
+    assert_eq!(
+        (
+            Some("Root > Work".to_owned()),
+            Some("Root > Work > Email".to_owned())
+        ),
+        mailbox.values(" > ")
+    );
+}
+
+#[test]
+fn differs_tells_the_same_as_has_changes() {
+    let pairs = [
+        ("test", "test"),
+        ("test", "test2"),
+        ("empty", "test"),
+        ("custom-fields", "custom-fields-2"),
+        ("multiple-entries", "multiple-entries-2"),
+        ("same-groups", "same-groups-2"),
+        ("moved-entries", "moved-entries-2"),
+    ];
+    for (a, b) in pairs {
+        let (db_a, db_b) = (open(a), open(b));
+        assert_eq!(
+            db_a.diff(&db_b).has_changes(),
+            db_a.differs(&db_b),
+            "{} and {}",
+            a,
+            b
+        );
+    }
+}
+
+#[test]
+fn roots_with_other_names_are_compared_by_their_contents() {
+    let (a, b) = (open("renamed-root"), open("renamed-root-2"));
+    let delta = a.diff(&b);
+
+    assert_eq!(
+        vec![
+            (ChangeKind::Changed, "Root".to_owned()),
+            (ChangeKind::Changed, "Root/Email".to_owned()),
+            (ChangeKind::Changed, "Root/Email/Mailbox".to_owned()),
+            (
+                ChangeKind::Changed,
+                "Root/Email/Mailbox/Password".to_owned()
+            ),
+            (ChangeKind::Added, "Root/VPN".to_owned()),
+        ],
+        outline(&collect_changes(&delta))
+    );
+}
@@ -0,0 +1,172 @@
+//! Writes the differences of large databases in each format and checks that they are written as
+//! they are found, rather than being collected or rendered in memory first
+
+extern crate keepass;
+extern crate keepass_diff;
+extern crate termcolor;
+
+use keepass::db::{Entry, Node, Value};
+use keepass_diff::diff::attachment::Attachments;
+use keepass_diff::diff::group::Group;
+use keepass_diff::diff::{summarize, Diff, DiffOptions, Filter};
+use keepass_diff::output::ordered::OrderBy;
+use keepass_diff::output::theme::Theme;
+use keepass_diff::output::{self, ChangeWriter};
+use termcolor::NoColor;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts the bytes allocated at the moment and the most that were allocated at once
+struct Counting;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let current = CURRENT.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+        PEAK.fetch_max(current, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        CURRENT.fetch_sub(layout.size(), Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Throws away what is written to it, counting the bytes
+#[derive(Default)]
+struct Counted(usize);
+
+impl Write for Counted {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+const ENTRIES: usize = 10_000;
+
+/// The most that writing the differences may allocate on top of the compared databases, a small
+/// fraction of what is written
+const MOST_ALLOCATED: usize = 64 * 1024;
+
+fn database(password: &str) -> Group {
+    let mut group = keepass::db::Group::new("Root");
+    for i in 0..ENTRIES {
+        let mut entry = Entry::new();
+        entry.fields.insert(
+            "Title".to_owned(),
+            Value::Unprotected(format!("entry {}", i)),
+        );
+        entry.fields.insert(
+            "Password".to_owned(),
+            Value::Protected(password.repeat(50).as_bytes().into()),
+        );
+        group.children.push(Node::Entry(entry));
+    }
+    Group::from_keepass(&group, &DiffOptions::default(), &Attachments::new())
+}
+
+/// How many bytes `write` writes, and the most it allocated at once while doing so
+fn measure(write: impl FnOnce(&mut NoColor<Counted>) -> std::io::Result<()>) -> (usize, usize) {
+    let mut out = NoColor::new(Counted::default());
+    let before = CURRENT.load(Ordering::SeqCst);
+    PEAK.store(before, Ordering::SeqCst);
+    write(&mut out).unwrap();
+    let allocated = PEAK.load(Ordering::SeqCst) - before;
+    (out.into_inner().0, allocated)
+}
+
+#[test]
+fn formats_are_written_without_collecting_the_differences() {
+    let (a, b) = (database("old"), database("new"));
+    let delta = a.diff(&b);
+    let summary = summarize(&delta);
+    let theme = Theme::default();
+    let changes = |entries_only, writer: &mut dyn ChangeWriter| {
+        output::write_changes(&delta, entries_only, Filter::All, writer)
+    };
+
+    let measured = [
+        (
+            "json",
+            measure(|out| {
+                output::json::Writer::new(out, "/").and_then(|mut w| changes(false, &mut w))
+            }),
+        ),
+        (
+            "csv",
+            measure(|out| {
+                output::csv::Writer::new(out, "/").and_then(|mut w| changes(false, &mut w))
+            }),
+        ),
+        (
+            "parseable",
+            measure(|out| changes(false, &mut output::parseable::Writer::new(out, "/"))),
+        ),
+        (
+            "xml",
+            measure(|out| output::xml::Writer::new(out).and_then(|mut w| changes(false, &mut w))),
+        ),
+        (
+            "html",
+            measure(|out| {
+                output::html::Writer::new(out, &summary, "a.kdbx", "b.kdbx")
+                    .and_then(|mut w| changes(false, &mut w))
+            }),
+        ),
+        (
+            "unified",
+            measure(|out| {
+                changes(
+                    false,
+                    &mut output::unified::Writer::new(out, "a.kdbx", "b.kdbx", &theme),
+                )
+            }),
+        ),
+        (
+            "entries",
+            measure(|out| {
+                changes(
+                    true,
+                    &mut output::entries::Writer::new(out, &theme, false, None),
+                )
+            }),
+        ),
+        (
+            "ordered with a limit",
+            measure(|out| {
+                let mut writer =
+                    output::ordered::Writer::new(out, OrderBy::Path, Some(10), None, &theme, false);
+                changes(false, &mut writer)
+            }),
+        ),
+    ];
+
+    for (format, (written, allocated)) in measured {
+        assert!(
+            written > 2 * MOST_ALLOCATED || format == "ordered with a limit",
+            "{} wrote only {} bytes",
+            format,
+            written
+        );
+        assert!(
+            allocated < MOST_ALLOCATED,
+            "{} allocated {} bytes to write {} bytes",
+            format,
+            allocated,
+            written
+        );
+    }
+}